@@ -0,0 +1,80 @@
+// Compares backends on the same workloads through `Storage`, run with `cargo bench`. Each
+// backend is a benchmark group and each workload a benchmark in it, so
+// `cargo bench -- dashmap/get` runs one. Redis is benched when `REDIS_URL` is set, on keys
+// starting with `workload:` that it deletes afterwards.
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use storage_trait::workload::{run, Mix, Workload};
+use storage_trait::{DashMapStorageBuilder, DynStorage, RedisStorageBuilder, Storage};
+
+fn workloads() -> Vec<(&'static str, Workload)> {
+    let workload = Workload::new().seed(42);
+    vec![
+        ("get", workload.clone().mix(Mix::only_get())),
+        ("set", workload.clone().mix(Mix::only_set())),
+        ("del", workload.clone().mix(Mix::only_del())),
+        ("batches", workload.clone().mix(Mix::only_batches())),
+        ("read_heavy", workload.clone().mix(Mix::READ_HEAVY)),
+        ("write_heavy", workload.clone().mix(Mix::WRITE_HEAVY)),
+        (
+            "read_heavy_hot",
+            workload.mix(Mix::READ_HEAVY).hot_keys(0.01, 0.9),
+        ),
+    ]
+}
+
+// Every iteration replays the same `ops` operations on freshly loaded keys, so deletes keep
+// finding values. Only the operations are timed, not the loading.
+fn bench_backend<S: Storage<String, String> + ?Sized>(
+    c: &mut Criterion,
+    backend: &str,
+    storage: &S,
+    ops: usize,
+    sample_size: usize,
+) {
+    let mut group = c.benchmark_group(backend);
+    group.sample_size(sample_size);
+    group.throughput(Throughput::Elements(ops as u64));
+    for (name, workload) in workloads() {
+        let ops = workload.ops(ops);
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    workload.preload(storage).unwrap();
+                    let start = Instant::now();
+                    run(storage, &ops).unwrap();
+                    elapsed += start.elapsed();
+                }
+                elapsed
+            })
+        });
+        workload.cleanup(storage).unwrap();
+    }
+    group.finish();
+}
+
+fn dashmap(c: &mut Criterion) {
+    let dashmap = DashMapStorageBuilder::<String, String>::new().build();
+    bench_backend(c, "dashmap", &dashmap, 10_000, 20);
+    // The same map behind `dyn Storage`, for the cost of dynamic dispatch.
+    let dynamic: DynStorage<String, String> = std::sync::Arc::new(dashmap);
+    bench_backend(c, "dashmap_dyn", &*dynamic, 10_000, 20);
+}
+
+fn redis(c: &mut Criterion) {
+    match RedisStorageBuilder::<String, String>::from_env() {
+        Ok(builder) => {
+            let redis = builder
+                .connect_timeout(Duration::from_secs(5))
+                .try_build()
+                .unwrap();
+            bench_backend(c, "redis", &redis, 1_000, 10);
+        }
+        Err(_) => println!("redis: skipped, set REDIS_URL to bench it"),
+    }
+}
+
+criterion_group!(benches, dashmap, redis);
+criterion_main!(benches);
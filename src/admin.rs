@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use crate::storage::Err;
+
+// Whole-store operations, e.g. to reset state between integration tests.
+pub trait AdminStorage {
+    // Removes every key the storage can reach.
+    fn clear(&self) -> Result<(), Err>;
+
+    // Number of live keys, backends that expire lazily may still count expired ones.
+    fn len(&self) -> Result<usize, Err>;
+
+    fn is_empty(&self) -> Result<bool, Err> {
+        Ok(self.len()? == 0)
+    }
+}
+
+macro_rules! forward_admin {
+    ($($ty:ty),*) => {
+        $(
+            impl<S: AdminStorage + ?Sized> AdminStorage for $ty {
+                fn clear(&self) -> Result<(), Err> {
+                    (**self).clear()
+                }
+
+                fn len(&self) -> Result<usize, Err> {
+                    (**self).len()
+                }
+
+                fn is_empty(&self) -> Result<bool, Err> {
+                    (**self).is_empty()
+                }
+            }
+        )*
+    };
+}
+
+forward_admin!(&S, Box<S>, Arc<S>);
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use crate::storage::{Err, Storage};
+
+// Values that grow at the end, e.g. log lines accumulated in a string.
+pub trait Append {
+    fn concat(&mut self, tail: Self);
+
+    // What `append` reports, in bytes for strings like redis APPEND.
+    fn size(&self) -> usize;
+}
+
+impl Append for String {
+    fn concat(&mut self, tail: Self) {
+        self.push_str(&tail);
+    }
+
+    fn size(&self) -> usize {
+        String::len(self)
+    }
+}
+
+impl Append for Vec<u8> {
+    fn concat(&mut self, mut tail: Self) {
+        self.append(&mut tail);
+    }
+
+    fn size(&self) -> usize {
+        Vec::len(self)
+    }
+}
+
+// Appends in one step on the backend, so concurrent appends to a key all land, in some order.
+// A missing key starts empty, an existing one keeps its TTL.
+pub trait AppendStorage<K, V: Append>: Storage<K, V> {
+    // Returns the length of the value after appending. Not `append`, which `StreamStorage`
+    // already has.
+    fn append_to(&self, key: K, value: V) -> Result<usize, Err>;
+}
+
+impl<K, V: Append, S: AppendStorage<K, V> + ?Sized> AppendStorage<K, V> for &S {
+    fn append_to(&self, key: K, value: V) -> Result<usize, Err> {
+        (**self).append_to(key, value)
+    }
+}
+
+impl<K, V: Append, S: AppendStorage<K, V> + ?Sized> AppendStorage<K, V> for Arc<S> {
+    fn append_to(&self, key: K, value: V) -> Result<usize, Err> {
+        (**self).append_to(key, value)
+    }
+}
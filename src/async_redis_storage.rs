@@ -0,0 +1,119 @@
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, FromRedisValue, ToRedisArgs};
+
+use crate::storage::{AsyncErr, AsyncStorage, CloseFlag};
+
+// Clones share one multiplexed connection, which is re-established after failures.
+#[derive(Clone)]
+pub struct AsyncRedisStorage<K, V> {
+    manager: ConnectionManager,
+    pub(crate) closed: CloseFlag,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> AsyncRedisStorage<K, V> {
+    pub async fn from_client(client: redis::Client) -> Result<Self, AsyncErr> {
+        Ok(AsyncRedisStorage::from_manager(
+            ConnectionManager::new(client).await?,
+        ))
+    }
+
+    pub fn from_manager(manager: ConnectionManager) -> Self {
+        AsyncRedisStorage {
+            manager,
+            closed: CloseFlag::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn manager(&self) -> &ConnectionManager {
+        &self.manager
+    }
+}
+
+impl<K, V> Debug for AsyncRedisStorage<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncRedisStorage")
+            .field("closed", &self.closed.is_closed())
+            .finish()
+    }
+}
+
+impl<K, V> AsyncStorage<K, V> for AsyncRedisStorage<K, V>
+where
+    K: ToRedisArgs + Send + Sync,
+    V: ToRedisArgs + FromRedisValue + Send + Sync,
+{
+    async fn set(&self, key: K, value: V) -> Result<(), AsyncErr> {
+        self.closed.check_async()?;
+        let mut conn = self.manager.clone();
+        conn.set::<K, V, ()>(key, value).await?;
+        Ok(())
+    }
+
+    async fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), AsyncErr> {
+        self.closed.check_async()?;
+        let mut conn = self.manager.clone();
+        conn.set_ex::<K, V, ()>(key, value, expire.as_secs())
+            .await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: K) -> Result<Option<V>, AsyncErr> {
+        self.closed.check_async()?;
+        let mut conn = self.manager.clone();
+        Ok(conn.get(key).await?)
+    }
+
+    async fn del(&self, key: K) -> Result<Option<K>, AsyncErr> {
+        self.closed.check_async()?;
+        let mut conn = self.manager.clone();
+        conn.del::<&K, ()>(&key).await?;
+        Ok(Some(key))
+    }
+
+    async fn contains(&self, key: K) -> Result<bool, AsyncErr> {
+        self.closed.check_async()?;
+        let mut conn = self.manager.clone();
+        Ok(conn.exists(key).await?)
+    }
+
+    async fn close(&self) -> Result<(), AsyncErr> {
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis_storage::RedisStorageBuilder;
+
+    #[test]
+    fn test_get() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let storage = RedisStorageBuilder::<&str, String>::new()
+                .addr("redis://127.0.0.1:6379")
+                .try_build_async()
+                .await
+                .unwrap();
+
+            let (key, value) = ("async_name", "Ferris".to_string());
+            storage.set(key, value.clone()).await.unwrap();
+            assert_eq!(storage.get(key).await.unwrap(), Some(value));
+            assert!(storage.contains(key).await.unwrap());
+
+            let _ = storage.del(key).await.unwrap();
+            assert_eq!(storage.get(key).await.unwrap(), None);
+        });
+    }
+}
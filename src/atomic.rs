@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use crate::storage::{Err, Storage};
+
+// Optimistic concurrency for read-modify-write cycles that a `get` followed by a `set` would
+// race on, e.g. counters and session state.
+pub trait AtomicStorage<K, V: PartialEq>: Storage<K, V> {
+    // Replaces the value with `new` only if it currently equals `expected`, `None` standing
+    // for a missing key on either side. A swap drops the key's TTL. Returns whether it
+    // happened.
+    fn compare_and_swap(&self, key: K, expected: Option<V>, new: Option<V>) -> Result<bool, Err>;
+
+    // Applies `f` to the current value until the result is swapped in, and returns the value
+    // `f` last saw. `f` may run more than once under contention.
+    fn fetch_update<F>(&self, key: K, mut f: F) -> Result<Option<V>, Err>
+    where
+        K: Clone,
+        V: Clone,
+        F: FnMut(Option<&V>) -> Option<V>,
+    {
+        loop {
+            let current = self.get(key.clone())?;
+            let new = f(current.as_ref());
+            if self.compare_and_swap(key.clone(), current.clone(), new)? {
+                return Ok(current);
+            }
+        }
+    }
+
+    // Stores `value` and returns what it replaced, like GETSET. Drops the key's TTL.
+    fn swap(&self, key: K, value: V) -> Result<Option<V>, Err>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.fetch_update(key, |_| Some(value.clone()))
+    }
+
+    // Deletes the key and returns its value, like GETDEL. Of concurrent takes only one gets
+    // the value, e.g. to claim a job token.
+    fn take(&self, key: K) -> Result<Option<V>, Err>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.fetch_update(key, |_| None)
+    }
+}
+
+impl<K, V: PartialEq, S: AtomicStorage<K, V> + ?Sized> AtomicStorage<K, V> for &S {
+    fn compare_and_swap(&self, key: K, expected: Option<V>, new: Option<V>) -> Result<bool, Err> {
+        (**self).compare_and_swap(key, expected, new)
+    }
+
+    fn fetch_update<F>(&self, key: K, f: F) -> Result<Option<V>, Err>
+    where
+        K: Clone,
+        V: Clone,
+        F: FnMut(Option<&V>) -> Option<V>,
+    {
+        (**self).fetch_update(key, f)
+    }
+
+    fn swap(&self, key: K, value: V) -> Result<Option<V>, Err>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        (**self).swap(key, value)
+    }
+
+    fn take(&self, key: K) -> Result<Option<V>, Err>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        (**self).take(key)
+    }
+}
+
+impl<K, V: PartialEq, S: AtomicStorage<K, V> + ?Sized> AtomicStorage<K, V> for Arc<S> {
+    fn compare_and_swap(&self, key: K, expected: Option<V>, new: Option<V>) -> Result<bool, Err> {
+        (**self).compare_and_swap(key, expected, new)
+    }
+
+    fn fetch_update<F>(&self, key: K, f: F) -> Result<Option<V>, Err>
+    where
+        K: Clone,
+        V: Clone,
+        F: FnMut(Option<&V>) -> Option<V>,
+    {
+        (**self).fetch_update(key, f)
+    }
+
+    fn swap(&self, key: K, value: V) -> Result<Option<V>, Err>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        (**self).swap(key, value)
+    }
+
+    fn take(&self, key: K) -> Result<Option<V>, Err>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        (**self).take(key)
+    }
+}
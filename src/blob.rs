@@ -0,0 +1,80 @@
+use std::io::{self, Read};
+use std::sync::Arc;
+
+use crate::storage::Err;
+
+// Values too large to hold in memory at once, e.g. rendered reports, moved in chunks. A blob
+// is replaced whole, readers never see half of a write, and is removed with the storage's
+// usual `del`.
+pub trait BlobStorage<K> {
+    // `None` for a missing key.
+    fn get_reader(&self, key: K) -> Result<Option<BlobReader<'_>>, Err>;
+
+    // Stores exactly `len` bytes from `reader`, failing if it ends early. The blob has no TTL.
+    fn set_from_reader(&self, key: K, reader: &mut dyn Read, len: u64) -> Result<(), Err>;
+}
+
+impl<K, S: BlobStorage<K> + ?Sized> BlobStorage<K> for &S {
+    fn get_reader(&self, key: K) -> Result<Option<BlobReader<'_>>, Err> {
+        (**self).get_reader(key)
+    }
+
+    fn set_from_reader(&self, key: K, reader: &mut dyn Read, len: u64) -> Result<(), Err> {
+        (**self).set_from_reader(key, reader, len)
+    }
+}
+
+impl<K, S: BlobStorage<K> + ?Sized> BlobStorage<K> for Arc<S> {
+    fn get_reader(&self, key: K) -> Result<Option<BlobReader<'_>>, Err> {
+        (**self).get_reader(key)
+    }
+
+    fn set_from_reader(&self, key: K, reader: &mut dyn Read, len: u64) -> Result<(), Err> {
+        (**self).set_from_reader(key, reader, len)
+    }
+}
+
+// A blob being read, with its length known up front, e.g. for a `Content-Length`.
+pub struct BlobReader<'a> {
+    len: u64,
+    inner: Box<dyn Read + Send + 'a>,
+}
+
+impl<'a> BlobReader<'a> {
+    pub fn new<R: Read + Send + 'a>(len: u64, reader: R) -> Self {
+        BlobReader {
+            len,
+            inner: Box::new(reader),
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Read for BlobReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+// Fills `buf` from `reader` until it is full or `left` is used up, failing on an early end.
+// Returns the bytes read.
+#[cfg(feature = "redis")]
+pub(crate) fn read_chunk(reader: &mut dyn Read, buf: &mut Vec<u8>, left: u64) -> io::Result<usize> {
+    let want = (buf.capacity() as u64).min(left) as usize;
+    buf.clear();
+    let read = reader.take(want as u64).read_to_end(buf)?;
+    if read < want {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "blob reader ended before its length",
+        ));
+    }
+    Ok(read)
+}
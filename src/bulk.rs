@@ -0,0 +1,174 @@
+use crate::storage::Err;
+
+// Rough in-memory size of a value, used to cut bulk loads into chunks of similar weight.
+pub trait ValueSize {
+    fn value_size(&self) -> usize;
+}
+
+impl ValueSize for String {
+    fn value_size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl ValueSize for &str {
+    fn value_size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl ValueSize for Vec<u8> {
+    fn value_size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl ValueSize for &[u8] {
+    fn value_size(&self) -> usize {
+        self.len()
+    }
+}
+
+macro_rules! impl_value_size {
+    ($($ty:ty),*) => {
+        $(
+            impl ValueSize for $ty {
+                fn value_size(&self) -> usize {
+                    std::mem::size_of::<$ty>()
+                }
+            }
+        )*
+    };
+}
+
+impl_value_size!(bool, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+// A chunk is written once it holds `max_items` entries or `max_bytes` of values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtendOptions {
+    pub max_items: usize,
+    pub max_bytes: usize,
+}
+
+impl Default for ExtendOptions {
+    fn default() -> Self {
+        ExtendOptions {
+            max_items: 1000,
+            max_bytes: 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ChunkError {
+    pub chunk: usize,
+    // Entries of the chunk that were not written.
+    pub skipped: usize,
+    pub error: Err,
+}
+
+#[derive(Debug, Default)]
+pub struct ExtendReport {
+    pub chunks: usize,
+    pub written: usize,
+    pub failed: Vec<ChunkError>,
+}
+
+impl ExtendReport {
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+// Cuts `entries` into chunks according to `options` and hands each one to `write`, which
+// returns how many entries it wrote before failing (0 when the chunk is all or nothing). Later chunks are still attempted.
+pub(crate) fn chunked<K, V, I, F>(entries: I, options: ExtendOptions, mut write: F) -> ExtendReport
+where
+    I: IntoIterator<Item = (K, V)>,
+    V: ValueSize,
+    F: FnMut(Vec<(K, V)>) -> Result<(), (usize, Err)>,
+{
+    let mut report = ExtendReport::default();
+    let mut chunk = Vec::new();
+    let mut bytes = 0;
+    let mut entries = entries.into_iter().peekable();
+    while let Some((key, value)) = entries.next() {
+        bytes += value.value_size();
+        chunk.push((key, value));
+        if chunk.len() < options.max_items.max(1)
+            && bytes < options.max_bytes
+            && entries.peek().is_some()
+        {
+            continue;
+        }
+
+        let len = chunk.len();
+        match write(std::mem::take(&mut chunk)) {
+            Ok(()) => report.written += len,
+            Err((written, error)) => {
+                report.written += written;
+                report.failed.push(ChunkError {
+                    chunk: report.chunks,
+                    skipped: len - written,
+                    error,
+                });
+            }
+        }
+        report.chunks += 1;
+        bytes = 0;
+    }
+    report
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::{DashMapStorageBuilder, Storage};
+
+    #[test]
+    fn test_extend() {
+        let storage = DashMapStorageBuilder::<u32, String>::new().build();
+        let entries = (0..10).map(|i| (i, "x".repeat(i as usize * 10)));
+        let report = storage.extend_with(
+            entries,
+            ExtendOptions {
+                max_items: 4,
+                max_bytes: 100,
+            },
+        );
+
+        // Chunks close at 4 items or once they reach 100 bytes: [0..4], [4..7], [7, 8], [9].
+        assert!(report.is_ok());
+        assert_eq!(report.chunks, 4);
+        assert_eq!(report.written, 10);
+        assert_eq!(storage.get(9).unwrap(), Some("x".repeat(90)));
+    }
+
+    #[test]
+    fn test_extend_errors() {
+        let storage = DashMapStorageBuilder::<u32, u32>::new().build();
+        let report = chunked(
+            (0..6).map(|i| (i, i)),
+            ExtendOptions {
+                max_items: 2,
+                max_bytes: usize::MAX,
+            },
+            |chunk| {
+                if chunk[0].0 == 2 {
+                    return Err((1, "backend unavailable".into()));
+                }
+                chunk
+                    .into_iter()
+                    .try_for_each(|(k, v)| storage.set(k, v))
+                    .map_err(|e| (0, e))
+            },
+        );
+
+        assert!(!report.is_ok());
+        assert_eq!(report.chunks, 3);
+        assert_eq!(report.written, 5);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].chunk, 1);
+        assert_eq!(report.failed[0].skipped, 1);
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt::Display;
+
+use crate::storage::Err;
+
+// How a byte-oriented backend stores keys of type `K`, so application code only needs
+// `Storage<K, V>` and no backend traits on its key types. Equal keys must encode to equal
+// bytes, and a borrowed key, e.g. a `&str` for a `String`, to the bytes of its owned key.
+pub trait KeyCodec<K: ?Sized> {
+    fn encode_key(&self, key: &K) -> Result<Vec<u8>, Err>;
+}
+
+// How a byte-oriented backend stores values of type `V`. Values that fail to decode are
+// returned as errors, not as misses.
+pub trait ValueCodec<V> {
+    fn encode_value(&self, value: &V) -> Result<Vec<u8>, Err>;
+    fn decode_value(&self, raw: Vec<u8>) -> Result<V, Err>;
+}
+
+// Keys written with their `Display` form, e.g. UUIDs or enums with a stable name.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DisplayKeys;
+
+impl<K: Display + ?Sized> KeyCodec<K> for DisplayKeys {
+    fn encode_key(&self, key: &K) -> Result<Vec<u8>, Err> {
+        Ok(key.to_string().into_bytes())
+    }
+}
+
+// Keys written as the string a closure builds, e.g. `|key: &UserKey| format!(..)`.
+impl<K: ?Sized, F: Fn(&K) -> String> KeyCodec<K> for F {
+    fn encode_key(&self, key: &K) -> Result<Vec<u8>, Err> {
+        Ok(self(key).into_bytes())
+    }
+}
+
+// Joins the parts of a tuple key, optionally prefixing each part with a label:
+// `JoinedKeys::new(":").labels(["t", "u"])` encodes `(42, 7)` as `t:42:u:7`.
+#[derive(Debug, Clone)]
+pub struct JoinedKeys {
+    separator: String,
+    labels: Vec<String>,
+}
+
+impl JoinedKeys {
+    pub fn new(separator: &str) -> Self {
+        JoinedKeys {
+            separator: separator.to_string(),
+            labels: Vec::new(),
+        }
+    }
+
+    pub fn labels<I, L>(mut self, labels: I) -> Self
+    where
+        I: IntoIterator<Item = L>,
+        L: Into<String>,
+    {
+        self.labels = labels.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn join(&self, parts: &[&dyn Display]) -> String {
+        let mut key = String::new();
+        for (i, part) in parts.iter().enumerate() {
+            if i > 0 {
+                key.push_str(&self.separator);
+            }
+            if let Some(label) = self.labels.get(i) {
+                key.push_str(label);
+                key.push_str(&self.separator);
+            }
+            key.push_str(&part.to_string());
+        }
+        key
+    }
+}
+
+impl Default for JoinedKeys {
+    fn default() -> Self {
+        JoinedKeys::new(":")
+    }
+}
+
+macro_rules! impl_joined_keys {
+    ($($name:ident: $index:tt),+) => {
+        impl<$($name: Display),+> KeyCodec<($($name,)+)> for JoinedKeys {
+            fn encode_key(&self, key: &($($name,)+)) -> Result<Vec<u8>, Err> {
+                Ok(self.join(&[$(&key.$index),+]).into_bytes())
+            }
+        }
+    };
+}
+
+impl_joined_keys!(A: 0);
+impl_joined_keys!(A: 0, B: 1);
+impl_joined_keys!(A: 0, B: 1, C: 2);
+impl_joined_keys!(A: 0, B: 1, C: 2, D: 3);
+
+#[cfg(feature = "json")]
+impl<V> ValueCodec<V> for crate::typed_storage::Json
+where
+    V: serde::Serialize + serde::de::DeserializeOwned,
+{
+    fn encode_value(&self, value: &V) -> Result<Vec<u8>, Err> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode_value(&self, raw: Vec<u8>) -> Result<V, Err> {
+        Ok(serde_json::from_slice(&raw)?)
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<V> ValueCodec<V> for crate::typed_storage::Bincode
+where
+    V: serde::Serialize + serde::de::DeserializeOwned,
+{
+    fn encode_value(&self, value: &V) -> Result<Vec<u8>, Err> {
+        crate::typed_storage::Codec::encode(self, value)
+    }
+
+    fn decode_value(&self, raw: Vec<u8>) -> Result<V, Err> {
+        crate::typed_storage::Codec::decode(self, raw)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl<V> ValueCodec<V> for crate::typed_storage::MsgPack
+where
+    V: serde::Serialize + serde::de::DeserializeOwned,
+{
+    fn encode_value(&self, value: &V) -> Result<Vec<u8>, Err> {
+        crate::typed_storage::Codec::encode(self, value)
+    }
+
+    fn decode_value(&self, raw: Vec<u8>) -> Result<V, Err> {
+        crate::typed_storage::Codec::decode(self, raw)
+    }
+}
+
+// Pairs a key codec with a value codec, e.g. `(DisplayKeys, Json)`.
+impl<K: ?Sized, A: KeyCodec<K>, B> KeyCodec<K> for (A, B) {
+    fn encode_key(&self, key: &K) -> Result<Vec<u8>, Err> {
+        self.0.encode_key(key)
+    }
+}
+
+impl<V, A, B: ValueCodec<V>> ValueCodec<V> for (A, B) {
+    fn encode_value(&self, value: &V) -> Result<Vec<u8>, Err> {
+        self.1.encode_value(value)
+    }
+
+    fn decode_value(&self, raw: Vec<u8>) -> Result<V, Err> {
+        self.1.decode_value(raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    enum Region {
+        Eu,
+    }
+
+    impl Display for Region {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self)
+        }
+    }
+
+    #[test]
+    fn test_key_codecs() {
+        assert_eq!(DisplayKeys.encode_key(&Region::Eu).unwrap(), b"Eu");
+        let joined = JoinedKeys::new(":").labels(["t", "u"]);
+        assert_eq!(joined.encode_key(&(42, 7)).unwrap(), b"t:42:u:7");
+        assert_eq!(
+            JoinedKeys::default().encode_key(&("a", 1, 'b')).unwrap(),
+            b"a:1:b"
+        );
+        let closure = |key: &u32| format!("user/{}", key);
+        assert_eq!(closure.encode_key(&7).unwrap(), b"user/7");
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_pair() {
+        use crate::typed_storage::Json;
+
+        let codec = (DisplayKeys, Json);
+        assert_eq!(codec.encode_key(&7).unwrap(), b"7");
+        let raw = codec.encode_value(&vec![1, 2]).unwrap();
+        assert_eq!(raw, b"[1,2]");
+        let value: Vec<u32> = codec.decode_value(raw).unwrap();
+        assert_eq!(value, [1, 2]);
+        assert!(ValueCodec::<Vec<u32>>::decode_value(&codec, b"{".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,337 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::health::HealthStatus;
+use crate::jitter_storage::random_unit;
+use crate::options::OpOptions;
+use crate::storage::{Err, Storage};
+
+type MakeError = Box<dyn Fn() -> Err + Send + Sync>;
+
+// Which calls a fault applies to, batch calls count as their single-key kind and the
+// `*_with_opts` variants as theirs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChaosOp {
+    Get,
+    Set,
+    Del,
+    Contains,
+    All,
+}
+
+impl ChaosOp {
+    fn slots(self) -> &'static [usize] {
+        match self {
+            ChaosOp::Get => &[0],
+            ChaosOp::Set => &[1],
+            ChaosOp::Del => &[2],
+            ChaosOp::Contains => &[3],
+            ChaosOp::All => &[0, 1, 2, 3],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Faults {
+    error_rate: f64,
+    latency: (Duration, Duration),
+    partial_rate: f64,
+}
+
+// Breaks the storage it wraps on purpose, to test retries, breakers and failovers against.
+// Calls are delayed by a latency picked between the configured bounds, then fail at the
+// error rate of their kind without reaching the inner storage. With a partial failure rate,
+// `set_many` and `del_many` apply a random prefix of the batch and then fail, like a
+// connection that dropped halfway. Injected errors are `io::Error`s with `ConnectionReset`
+// unless `error_with` says otherwise, so `is_connection_error` treats them as transient.
+//
+// The same `seed` injects the same faults for the same sequence of calls, calls from several
+// threads interleave their draws.
+pub struct ChaosStorage<S> {
+    inner: S,
+    faults: [Faults; 4],
+    rng: AtomicU64,
+    enabled: AtomicBool,
+    injected: AtomicU64,
+    make_error: Option<MakeError>,
+}
+
+impl<S> ChaosStorage<S> {
+    // Injects nothing until faults are configured.
+    pub fn new(inner: S) -> Self {
+        ChaosStorage {
+            inner,
+            faults: [Faults::default(); 4],
+            rng: AtomicU64::new((random_unit() * (1u64 << 53) as f64) as u64),
+            enabled: AtomicBool::new(true),
+            injected: AtomicU64::new(0),
+            make_error: None,
+        }
+    }
+
+    pub fn seed(self, seed: u64) -> Self {
+        self.rng.store(seed, Ordering::SeqCst);
+        self
+    }
+
+    // The share of calls of `op` that fail, clamped to `0.0..=1.0`.
+    pub fn error_rate(mut self, op: ChaosOp, rate: f64) -> Self {
+        for slot in op.slots() {
+            self.faults[*slot].error_rate = rate.clamp(0.0, 1.0);
+        }
+        self
+    }
+
+    // Delays every call of `op` by between `min` and `max`.
+    pub fn latency(mut self, op: ChaosOp, min: Duration, max: Duration) -> Self {
+        for slot in op.slots() {
+            self.faults[*slot].latency = (min, max.max(min));
+        }
+        self
+    }
+
+    // The share of `set_many`/`del_many` calls that are applied only in part, on top of the
+    // error rate. Only `Set` and `Del` have batches to cut short.
+    pub fn partial_failure_rate(mut self, op: ChaosOp, rate: f64) -> Self {
+        for slot in op.slots() {
+            self.faults[*slot].partial_rate = rate.clamp(0.0, 1.0);
+        }
+        self
+    }
+
+    // Builds the injected errors, e.g. `|| StorageError::Timeout.into()`.
+    pub fn error_with<F: Fn() -> Err + Send + Sync + 'static>(mut self, make_error: F) -> Self {
+        self.make_error = Some(Box::new(make_error));
+        self
+    }
+
+    // Turns the faults off and on again, e.g. to check that a breaker closes after an outage.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    // Errors injected so far, partial failures included.
+    pub fn injected(&self) -> u64 {
+        self.injected.load(Ordering::SeqCst)
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    // SplitMix64, so a seed replays without a rand dependency.
+    fn draw(&self) -> f64 {
+        let mut z = self
+            .rng
+            .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::SeqCst)
+            .wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        ((z ^ (z >> 31)) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn error(&self) -> Err {
+        self.injected.fetch_add(1, Ordering::SeqCst);
+        match &self.make_error {
+            Some(make_error) => make_error(),
+            None => Box::new(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "chaos: injected failure",
+            )),
+        }
+    }
+
+    // Sleeps the call's latency, then fails it at its error rate.
+    fn inject(&self, op: ChaosOp) -> Result<(), Err> {
+        if !self.enabled.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let faults = self.faults[op.slots()[0]];
+        let (min, max) = faults.latency;
+        if !max.is_zero() {
+            thread::sleep(min + (max - min).mul_f64(self.draw()));
+        }
+        if faults.error_rate > 0.0 && self.draw() < faults.error_rate {
+            return Err(self.error());
+        }
+        Ok(())
+    }
+
+    // How much of a batch of `len` to apply before failing, `None` to apply it all.
+    fn cut(&self, op: ChaosOp, len: usize) -> Option<usize> {
+        let rate = self.faults[op.slots()[0]].partial_rate;
+        if len == 0 || rate == 0.0 || !self.enabled.load(Ordering::SeqCst) {
+            return None;
+        }
+        (self.draw() < rate).then(|| (self.draw() * len as f64) as usize)
+    }
+}
+
+impl<K, V, S: Storage<K, V>> Storage<K, V> for ChaosStorage<S> {
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.inject(ChaosOp::Set)?;
+        self.inner.set(key, value)
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.inject(ChaosOp::Set)?;
+        self.inner.set_ex(key, value, expire)
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.inject(ChaosOp::Get)?;
+        self.inner.get(key)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.inject(ChaosOp::Del)?;
+        self.inner.del(key)
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.inject(ChaosOp::Contains)?;
+        self.inner.contains(key)
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.inner.close()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.inner.health_check()
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        self.inject(ChaosOp::Get)?;
+        self.inner.get_many(keys)
+    }
+
+    fn set_many(&self, mut entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.inject(ChaosOp::Set)?;
+        if let Some(applied) = self.cut(ChaosOp::Set, entries.len()) {
+            entries.truncate(applied);
+            self.inner.set_many(entries)?;
+            return Err(self.error());
+        }
+        self.inner.set_many(entries)
+    }
+
+    fn del_many(&self, mut keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        self.inject(ChaosOp::Del)?;
+        if let Some(applied) = self.cut(ChaosOp::Del, keys.len()) {
+            keys.truncate(applied);
+            self.inner.del_many(keys)?;
+            return Err(self.error());
+        }
+        self.inner.del_many(keys)
+    }
+
+    fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+        self.inject(ChaosOp::Set)?;
+        self.inner.set_with_opts(key, value, opts)
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        self.inject(ChaosOp::Get)?;
+        self.inner.get_with_opts(key, opts)
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        self.inject(ChaosOp::Del)?;
+        self.inner.del_with_opts(key, opts)
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        self.inject(ChaosOp::Contains)?;
+        self.inner.contains_with_opts(key, opts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::StorageError;
+    use crate::failover_storage::is_connection_error;
+    use crate::mock_storage::{Call, MockStorage};
+
+    type Mock = MockStorage<u32, u32>;
+
+    fn failures(storage: &ChaosStorage<Mock>) -> Vec<bool> {
+        (0..64).map(|i| storage.set(i, i).is_err()).collect()
+    }
+
+    #[test]
+    fn test_seeded() {
+        let chaos = || {
+            ChaosStorage::new(Mock::new())
+                .seed(7)
+                .error_rate(ChaosOp::Set, 0.5)
+        };
+        let (first, second) = (chaos(), chaos());
+        let seen = failures(&first);
+        assert_eq!(seen, failures(&second));
+        assert!(seen.contains(&true) && seen.contains(&false));
+        assert_eq!(
+            first.injected(),
+            seen.iter().filter(|failed| **failed).count() as u64
+        );
+        // Failed calls never reached the mock, and reads are unaffected.
+        assert_eq!(first.inner().call_count() as u64, 64 - first.injected());
+        assert!((0..64).all(|i| first.get(i).is_ok()));
+    }
+
+    #[test]
+    fn test_injected_error() {
+        let storage = ChaosStorage::new(Mock::new()).error_rate(ChaosOp::All, 1.0);
+        let e = storage.get(1).unwrap_err();
+        assert!(is_connection_error(&e));
+        assert_eq!(storage.inner().call_count(), 0);
+
+        storage.set_enabled(false);
+        storage.set(1, 1).unwrap();
+        assert_eq!(storage.get(1).unwrap(), Some(1));
+
+        let storage = ChaosStorage::new(Mock::new())
+            .error_rate(ChaosOp::Del, 1.0)
+            .error_with(|| StorageError::Timeout.into());
+        let e = storage.del(1).unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<StorageError>(),
+            Some(&StorageError::Timeout)
+        );
+    }
+
+    #[test]
+    fn test_partial_failure() {
+        let storage = ChaosStorage::new(Mock::new())
+            .seed(1)
+            .partial_failure_rate(ChaosOp::Set, 1.0);
+        let entries: Vec<(u32, u32)> = (0..10).map(|i| (i, i)).collect();
+        assert!(storage.set_many(entries).is_err());
+        assert_eq!(storage.injected(), 1);
+        // A prefix made it, the rest didn't.
+        let calls = storage.inner().calls();
+        assert!(calls.len() < 10);
+        for (i, call) in calls.into_iter().enumerate() {
+            assert_eq!(call, Call::Set(i as u32, i as u32));
+        }
+    }
+
+    #[test]
+    fn test_latency() {
+        let storage = ChaosStorage::new(Mock::new()).latency(
+            ChaosOp::Get,
+            Duration::from_millis(20),
+            Duration::from_millis(30),
+        );
+        let start = std::time::Instant::now();
+        storage.get(1).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
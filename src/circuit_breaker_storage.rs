@@ -0,0 +1,307 @@
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::error::{BuilderError, StorageError};
+use crate::health::HealthStatus;
+use crate::options::OpOptions;
+use crate::storage::{Err, Storage};
+
+type FailOn = Box<dyn Fn(&Err) -> bool + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    // Calls reach the backend.
+    Closed,
+    // Calls fail fast with `StorageError::CircuitOpen` until the cooldown ends.
+    Open,
+    // One probe call is testing whether the backend recovered, the others fail fast.
+    HalfOpen,
+}
+
+enum State {
+    Closed { failures: usize },
+    Open { until: Instant },
+    HalfOpen,
+}
+
+// Stops calling a backend that keeps failing. After `failure_threshold` failures in a row
+// the circuit opens and calls fail with `StorageError::CircuitOpen` for `cooldown`; the next
+// call then probes the backend, closing the circuit on success and reopening it on failure.
+pub struct CircuitBreakerStorage<S> {
+    inner: S,
+    state: Mutex<State>,
+    failure_threshold: usize,
+    cooldown: Duration,
+    fail_on: Option<FailOn>,
+}
+
+impl<S> CircuitBreakerStorage<S> {
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn state(&self) -> CircuitState {
+        match *self.lock() {
+            State::Closed { .. } => CircuitState::Closed,
+            State::Open { until } if until <= Instant::now() => CircuitState::HalfOpen,
+            State::Open { .. } => CircuitState::Open,
+            State::HalfOpen => CircuitState::HalfOpen,
+        }
+    }
+
+    // Closes the circuit by hand, e.g. after an operator fixed the backend.
+    pub fn reset(&self) {
+        *self.lock() = State::Closed { failures: 0 };
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn call<T, F>(&self, op: F) -> Result<T, Err>
+    where
+        F: FnOnce() -> Result<T, Err>,
+    {
+        self.acquire()?;
+        let resp = op();
+        let failed = match (&resp, &self.fail_on) {
+            (Ok(_), _) => false,
+            (Err(e), Some(fail_on)) => fail_on(e),
+            (Err(_), None) => true,
+        };
+        self.record(failed);
+        resp
+    }
+
+    fn acquire(&self) -> Result<(), Err> {
+        let mut state = self.lock();
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if until <= Instant::now() => {
+                *state = State::HalfOpen;
+                Ok(())
+            }
+            State::Open { .. } | State::HalfOpen => Err(StorageError::CircuitOpen.into()),
+        }
+    }
+
+    fn record(&self, failed: bool) {
+        let mut state = self.lock();
+        let open = State::Open {
+            until: Instant::now() + self.cooldown,
+        };
+        match (&mut *state, failed) {
+            (State::Closed { failures }, true) => {
+                *failures += 1;
+                if *failures >= self.failure_threshold {
+                    *state = open;
+                }
+            }
+            (State::Closed { failures }, false) => *failures = 0,
+            (State::HalfOpen, true) => *state = open,
+            (State::HalfOpen, false) => *state = State::Closed { failures: 0 },
+            // A call that started before the circuit opened.
+            (State::Open { .. }, _) => {}
+        }
+    }
+}
+
+impl<K, V, S> Storage<K, V> for CircuitBreakerStorage<S>
+where
+    S: Storage<K, V>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.call(|| self.inner.set(key, value))
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.call(|| self.inner.set_ex(key, value, expire))
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.call(|| self.inner.get(key))
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.call(|| self.inner.del(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.call(|| self.inner.contains(key))
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.inner.close()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.inner.health_check()
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        self.call(|| self.inner.get_many(keys))
+    }
+
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.call(|| self.inner.set_many(entries))
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        self.call(|| self.inner.del_many(keys))
+    }
+
+    fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+        self.call(|| self.inner.set_with_opts(key, value, opts))
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        self.call(|| self.inner.get_with_opts(key, opts))
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        self.call(|| self.inner.del_with_opts(key, opts))
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        self.call(|| self.inner.contains_with_opts(key, opts))
+    }
+}
+
+pub struct CircuitBreakerStorageBuilder<S> {
+    inner: S,
+    failure_threshold: usize,
+    cooldown: Duration,
+    fail_on: Option<FailOn>,
+}
+
+impl<S> CircuitBreakerStorageBuilder<S> {
+    pub fn new(inner: S) -> Self {
+        CircuitBreakerStorageBuilder {
+            inner,
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+            fail_on: None,
+        }
+    }
+
+    pub fn failure_threshold(mut self, failures: usize) -> Self {
+        self.failure_threshold = failures;
+        self
+    }
+
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    // Only errors matching `fail_on` count as failures, e.g. `is_connection_error`, so a
+    // rejected value doesn't open the circuit. By default every error counts.
+    pub fn fail_on<F>(mut self, fail_on: F) -> Self
+    where
+        F: Fn(&Err) -> bool + Send + Sync + 'static,
+    {
+        self.fail_on = Some(Box::new(fail_on));
+        self
+    }
+
+    // # Panics
+    // Panics if the failure threshold is zero.
+    pub fn build(self) -> CircuitBreakerStorage<S> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build circuit breaker storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<CircuitBreakerStorage<S>, BuilderError> {
+        if self.failure_threshold == 0 {
+            return Err(BuilderError::invalid(
+                "failure_threshold",
+                "must be at least 1",
+            ));
+        }
+
+        Ok(CircuitBreakerStorage {
+            inner: self.inner,
+            state: Mutex::new(State::Closed { failures: 0 }),
+            failure_threshold: self.failure_threshold,
+            cooldown: self.cooldown,
+            fail_on: self.fail_on,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::failover_storage::is_connection_error;
+    use crate::mock_storage::MockStorage;
+
+    fn breaker(cooldown: Duration) -> CircuitBreakerStorage<MockStorage<&'static str, u32>> {
+        let mock = MockStorage::new().with_entry("a", 1);
+        for n in 0..2 {
+            mock.fail_nth(n, StorageError::Timeout);
+        }
+        CircuitBreakerStorageBuilder::new(mock)
+            .failure_threshold(2)
+            .cooldown(cooldown)
+            .build()
+    }
+
+    fn is_open(e: Err) -> bool {
+        e.downcast_ref() == Some(&StorageError::CircuitOpen)
+    }
+
+    #[test]
+    fn test_opens_and_fails_fast() {
+        let storage = breaker(Duration::from_secs(60));
+        assert!(storage.get("a").is_err());
+        assert_eq!(storage.state(), CircuitState::Closed);
+        assert!(storage.get("a").is_err());
+        assert_eq!(storage.state(), CircuitState::Open);
+
+        assert!(is_open(storage.get("a").unwrap_err()));
+        assert_eq!(storage.inner().call_count(), 2);
+
+        storage.reset();
+        assert_eq!(storage.get("a").unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_half_open() {
+        let storage = breaker(Duration::from_millis(20));
+        assert!(storage.get("a").is_err());
+        assert!(storage.get("a").is_err());
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(storage.state(), CircuitState::HalfOpen);
+
+        // A failed probe reopens the circuit.
+        storage.inner().fail_nth(2, StorageError::Timeout);
+        assert!(!is_open(storage.get("a").unwrap_err()));
+        assert_eq!(storage.state(), CircuitState::Open);
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(storage.get("a").unwrap(), Some(1));
+        assert_eq!(storage.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_fail_on() {
+        let mock = MockStorage::<&str, u32>::new();
+        for n in 0..3 {
+            mock.fail_nth(n, "rejected");
+        }
+        let storage = CircuitBreakerStorageBuilder::new(mock)
+            .failure_threshold(1)
+            .fail_on(is_connection_error)
+            .build();
+        for _ in 0..3 {
+            assert!(!is_open(storage.set("a", 1).unwrap_err()));
+        }
+        assert_eq!(storage.state(), CircuitState::Closed);
+    }
+}
@@ -0,0 +1,432 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::Read;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+use crate::error::BuilderError;
+use crate::hooks::{Hooks, StorageHook};
+use crate::secret::SecretString;
+use crate::storage::{CloseFlag, Err, Storage};
+
+const API_BASE: &str = "https://api.cloudflare.com/client/v4";
+// Workers KV rejects expirations shorter than one minute.
+const MIN_EXPIRATION_TTL: u64 = 60;
+
+pub trait CloudflareTransport {
+    fn get(&self, url: &str, token: &str) -> Result<Option<Vec<u8>>, Err>;
+    fn put(&self, url: &str, token: &str, body: &[u8]) -> Result<(), Err>;
+    fn delete(&self, url: &str, token: &str) -> Result<(), Err>;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct UreqTransport {
+    agent: ureq::Agent,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for UreqTransport {
+    fn default() -> Self {
+        Self {
+            agent: ureq::Agent::new(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CloudflareTransport for UreqTransport {
+    fn get(&self, url: &str, token: &str) -> Result<Option<Vec<u8>>, Err> {
+        match self
+            .agent
+            .get(url)
+            .set("Authorization", &format!("Bearer {}", token))
+            .call()
+        {
+            Ok(resp) => {
+                let mut body = Vec::new();
+                resp.into_reader().read_to_end(&mut body)?;
+                Ok(Some(body))
+            }
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put(&self, url: &str, token: &str, body: &[u8]) -> Result<(), Err> {
+        self.agent
+            .put(url)
+            .set("Authorization", &format!("Bearer {}", token))
+            .send_bytes(body)?;
+        Ok(())
+    }
+
+    fn delete(&self, url: &str, token: &str) -> Result<(), Err> {
+        match self
+            .agent
+            .delete(url)
+            .set("Authorization", &format!("Bearer {}", token))
+            .call()
+        {
+            Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub type DefaultTransport = UreqTransport;
+
+pub struct CloudflareKvStorage<K, V, T>
+where
+    V: Into<String>,
+{
+    transport: T,
+    base_url: String,
+    token: SecretString,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V, T> CloudflareKvStorage<K, V, T>
+where
+    K: AsRef<str>,
+    V: Into<String>,
+    T: CloudflareTransport,
+{
+    fn url(&self, key: &str) -> String {
+        format!(
+            "{}/values/{}",
+            self.base_url,
+            utf8_percent_encode(key, NON_ALPHANUMERIC)
+        )
+    }
+}
+
+impl<K, V, T> Storage<K, V> for CloudflareKvStorage<K, V, T>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+    T: CloudflareTransport,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        self.transport.put(
+            &self.url(key.as_ref()),
+            self.token.expose_secret(),
+            value.as_bytes(),
+        )?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        let url = format!(
+            "{}?expiration_ttl={}",
+            self.url(key.as_ref()),
+            expire.as_secs().max(MIN_EXPIRATION_TTL)
+        );
+        self.transport
+            .put(&url, self.token.expose_secret(), value.as_bytes())?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let value = match self
+            .transport
+            .get(&self.url(key.as_ref()), self.token.expose_secret())?
+        {
+            Some(body) => Some(String::from_utf8(body)?.into()),
+            None => None,
+        };
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let url = self.url(key.as_ref());
+        let found = self
+            .transport
+            .get(&url, self.token.expose_secret())?
+            .is_some();
+        if found {
+            self.transport.delete(&url, self.token.expose_secret())?;
+        }
+        self.hooks.del(&key);
+        Ok(found.then_some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        Ok(self
+            .transport
+            .get(&self.url(key.as_ref()), self.token.expose_secret())?
+            .is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CloudflareKvStorageBuilder<K, V, T>
+where
+    V: Into<String>,
+{
+    #[cfg_attr(feature = "serde", serde(default))]
+    account_id: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    namespace_id: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    token: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default = "default_api_base"))]
+    api_base: String,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    transport: Option<T>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V, T> CloudflareKvStorageBuilder<K, V, T>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+    T: CloudflareTransport,
+{
+    pub fn with_transport(transport: T) -> Self {
+        Self {
+            account_id: None,
+            namespace_id: None,
+            token: None,
+            api_base: API_BASE.to_string(),
+            transport: Some(transport),
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn transport(mut self, transport: T) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    pub fn account_id(mut self, account_id: &str) -> Self {
+        self.account_id = Some(account_id.to_string());
+        self
+    }
+
+    pub fn namespace_id(mut self, namespace_id: &str) -> Self {
+        self.namespace_id = Some(namespace_id.to_string());
+        self
+    }
+
+    pub fn token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    pub fn api_base(mut self, api_base: &str) -> Self {
+        self.api_base = api_base.trim_end_matches('/').to_string();
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their TTL variants. Writes
+    // from other clients don't fire them.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if the account id, namespace id or token is missing or empty.
+    pub fn build(self) -> CloudflareKvStorage<K, V, T> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build cloudflare kv storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<CloudflareKvStorage<K, V, T>, BuilderError> {
+        let account_id = required("account_id", self.account_id)?;
+        let namespace_id = required("namespace_id", self.namespace_id)?;
+        let token = required("token", self.token)?;
+        let transport = self
+            .transport
+            .ok_or(BuilderError::MissingField("transport"))?;
+
+        Ok(CloudflareKvStorage {
+            transport,
+            base_url: format!(
+                "{}/accounts/{}/storage/kv/namespaces/{}",
+                self.api_base, account_id, namespace_id
+            ),
+            token: token.into(),
+            closed: CloseFlag::default(),
+            hooks: self.hooks,
+            _marker: PhantomData,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+fn default_api_base() -> String {
+    API_BASE.to_string()
+}
+
+fn required(field: &'static str, value: Option<String>) -> Result<String, BuilderError> {
+    match value {
+        Some(value) if value.is_empty() => Err(BuilderError::invalid(field, "must not be empty")),
+        Some(value) => Ok(value),
+        None => Err(BuilderError::MissingField(field)),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<K, V> CloudflareKvStorageBuilder<K, V, DefaultTransport>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    pub fn new() -> Self {
+        CloudflareKvStorageBuilder::default()
+    }
+
+    // Reads `CLOUDFLARE_ACCOUNT_ID`, `CLOUDFLARE_KV_NAMESPACE_ID` and `CLOUDFLARE_API_TOKEN`.
+    pub fn from_env() -> Result<Self, BuilderError> {
+        Ok(CloudflareKvStorageBuilder::new()
+            .account_id(&crate::env::required("CLOUDFLARE_ACCOUNT_ID")?)
+            .namespace_id(&crate::env::required("CLOUDFLARE_KV_NAMESPACE_ID")?)
+            .token(&crate::env::required("CLOUDFLARE_API_TOKEN")?))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<K, V> Default for CloudflareKvStorageBuilder<K, V, DefaultTransport>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn default() -> Self {
+        Self::with_transport(UreqTransport::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MemoryTransport {
+        values: Mutex<HashMap<String, Vec<u8>>>,
+    }
+
+    impl CloudflareTransport for MemoryTransport {
+        fn get(&self, url: &str, _token: &str) -> Result<Option<Vec<u8>>, Err> {
+            Ok(self.values.lock().unwrap().get(url).cloned())
+        }
+
+        fn put(&self, url: &str, _token: &str, body: &[u8]) -> Result<(), Err> {
+            let url = url.split('?').next().unwrap().to_string();
+            self.values.lock().unwrap().insert(url, body.to_vec());
+            Ok(())
+        }
+
+        fn delete(&self, url: &str, _token: &str) -> Result<(), Err> {
+            self.values.lock().unwrap().remove(url);
+            Ok(())
+        }
+    }
+
+    fn build_memory() -> CloudflareKvStorage<&'static str, String, MemoryTransport> {
+        CloudflareKvStorageBuilder::with_transport(MemoryTransport::default())
+            .account_id("account")
+            .namespace_id("namespace")
+            .token("token")
+            .build()
+    }
+
+    #[test]
+    fn test_get() {
+        let storage = build_memory();
+
+        let (key, value) = ("user/name", "Ferris".to_string());
+        let _ = storage.set(key, value.clone());
+        let resp = storage.get(key).unwrap();
+        assert_eq!(resp, Some(value));
+
+        let _ = storage.del(key).unwrap();
+        let resp = storage.get(key).unwrap();
+        assert_eq!(resp, None);
+    }
+
+    #[test]
+    fn test_url() {
+        let storage = build_memory();
+        assert_eq!(
+            storage.url("user/name"),
+            "https://api.cloudflare.com/client/v4/accounts/account/storage/kv/namespaces/namespace/values/user%2Fname"
+        );
+    }
+
+    #[test]
+    fn test_from_env() {
+        std::env::set_var("CLOUDFLARE_ACCOUNT_ID", "account");
+        std::env::set_var("CLOUDFLARE_KV_NAMESPACE_ID", "namespace");
+        std::env::remove_var("CLOUDFLARE_API_TOKEN");
+        let resp = CloudflareKvStorageBuilder::<&str, String, _>::from_env();
+        assert!(matches!(
+            resp,
+            Err(BuilderError::MissingEnv("CLOUDFLARE_API_TOKEN"))
+        ));
+
+        std::env::set_var("CLOUDFLARE_API_TOKEN", "token");
+        let storage = CloudflareKvStorageBuilder::<&str, String, _>::from_env()
+            .unwrap()
+            .build();
+        assert!(storage.url("name").contains("/accounts/account/"));
+
+        for name in [
+            "CLOUDFLARE_ACCOUNT_ID",
+            "CLOUDFLARE_KV_NAMESPACE_ID",
+            "CLOUDFLARE_API_TOKEN",
+        ] {
+            std::env::remove_var(name);
+        }
+    }
+
+    #[test]
+    fn test_try_build() {
+        let resp = CloudflareKvStorageBuilder::<&str, String, _>::new()
+            .account_id("account")
+            .try_build();
+        assert!(matches!(
+            resp,
+            Err(BuilderError::MissingField("namespace_id"))
+        ));
+
+        let resp = CloudflareKvStorageBuilder::<&str, String, _>::new()
+            .account_id("account")
+            .namespace_id("namespace")
+            .token("")
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(_))));
+    }
+}
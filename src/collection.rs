@@ -0,0 +1,141 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+
+use crate::storage::Err;
+
+// Lists and sets stored under a key, like redis LPUSH/RPOP or SADD/SMEMBERS. A key holds one
+// kind of collection, using it as the other kind is an error. Collections are created by the
+// first push or add and deleted with their last element, so an empty one reads like a
+// missing key. Pushes and adds keep the key's TTL.
+pub trait CollectionStorage<K, V> {
+    // Appends to the list, returns its new length.
+    fn push(&self, key: K, value: V) -> Result<usize, Err>;
+
+    fn push_front(&self, key: K, value: V) -> Result<usize, Err>;
+
+    // Takes from the front, so `push` and `pop` make a queue.
+    fn pop(&self, key: K) -> Result<Option<V>, Err>;
+
+    // Takes from the back, so `push` and `pop_back` make a stack.
+    fn pop_back(&self, key: K) -> Result<Option<V>, Err>;
+
+    // The whole list, front first.
+    fn list(&self, key: K) -> Result<Vec<V>, Err>;
+
+    fn list_len(&self, key: K) -> Result<usize, Err>;
+
+    // `true` if the member is new.
+    fn add_member(&self, key: K, member: V) -> Result<bool, Err>;
+
+    // `true` if the member was there.
+    fn remove_member(&self, key: K, member: V) -> Result<bool, Err>;
+
+    fn is_member(&self, key: K, member: V) -> Result<bool, Err>;
+
+    // In no particular order.
+    fn members(&self, key: K) -> Result<Vec<V>, Err>;
+}
+
+// How in-memory backends keep collections, as the values of their map.
+#[derive(Debug, Clone)]
+pub enum Collection<V> {
+    List(VecDeque<V>),
+    Set(HashSet<V>),
+}
+
+impl<V> Collection<V> {
+    pub fn len(&self) -> usize {
+        match self {
+            Collection::List(list) => list.len(),
+            Collection::Set(set) => set.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V, S: CollectionStorage<K, V> + ?Sized> CollectionStorage<K, V> for &S {
+    fn push(&self, key: K, value: V) -> Result<usize, Err> {
+        (**self).push(key, value)
+    }
+
+    fn push_front(&self, key: K, value: V) -> Result<usize, Err> {
+        (**self).push_front(key, value)
+    }
+
+    fn pop(&self, key: K) -> Result<Option<V>, Err> {
+        (**self).pop(key)
+    }
+
+    fn pop_back(&self, key: K) -> Result<Option<V>, Err> {
+        (**self).pop_back(key)
+    }
+
+    fn list(&self, key: K) -> Result<Vec<V>, Err> {
+        (**self).list(key)
+    }
+
+    fn list_len(&self, key: K) -> Result<usize, Err> {
+        (**self).list_len(key)
+    }
+
+    fn add_member(&self, key: K, member: V) -> Result<bool, Err> {
+        (**self).add_member(key, member)
+    }
+
+    fn remove_member(&self, key: K, member: V) -> Result<bool, Err> {
+        (**self).remove_member(key, member)
+    }
+
+    fn is_member(&self, key: K, member: V) -> Result<bool, Err> {
+        (**self).is_member(key, member)
+    }
+
+    fn members(&self, key: K) -> Result<Vec<V>, Err> {
+        (**self).members(key)
+    }
+}
+
+impl<K, V, S: CollectionStorage<K, V> + ?Sized> CollectionStorage<K, V> for Arc<S> {
+    fn push(&self, key: K, value: V) -> Result<usize, Err> {
+        (**self).push(key, value)
+    }
+
+    fn push_front(&self, key: K, value: V) -> Result<usize, Err> {
+        (**self).push_front(key, value)
+    }
+
+    fn pop(&self, key: K) -> Result<Option<V>, Err> {
+        (**self).pop(key)
+    }
+
+    fn pop_back(&self, key: K) -> Result<Option<V>, Err> {
+        (**self).pop_back(key)
+    }
+
+    fn list(&self, key: K) -> Result<Vec<V>, Err> {
+        (**self).list(key)
+    }
+
+    fn list_len(&self, key: K) -> Result<usize, Err> {
+        (**self).list_len(key)
+    }
+
+    fn add_member(&self, key: K, member: V) -> Result<bool, Err> {
+        (**self).add_member(key, member)
+    }
+
+    fn remove_member(&self, key: K, member: V) -> Result<bool, Err> {
+        (**self).remove_member(key, member)
+    }
+
+    fn is_member(&self, key: K, member: V) -> Result<bool, Err> {
+        (**self).is_member(key, member)
+    }
+
+    fn members(&self, key: K) -> Result<Vec<V>, Err> {
+        (**self).members(key)
+    }
+}
@@ -0,0 +1,224 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::health::HealthStatus;
+use crate::options::OpOptions;
+use crate::storage::{Err, Storage};
+
+// Stored values starting with this are framed: one more byte names the encoding of the rest.
+// Anything else is a plain value, so values written before the wrapper stay readable.
+const MAGIC: [u8; 3] = [0xc5, 0x7a, 0x01];
+const GZIP: u8 = b'g';
+// A plain value that happens to start with `MAGIC`.
+const RAW: u8 = b'r';
+
+// Gzips values of at least `threshold` bytes on their way to a `Vec<u8>` backend, smaller ones
+// and ones that don't shrink are stored as they are.
+pub struct CompressedStorage<S> {
+    inner: S,
+    threshold: usize,
+    level: Compression,
+}
+
+impl<S> CompressedStorage<S> {
+    pub fn new(inner: S, threshold: usize) -> Self {
+        CompressedStorage {
+            inner,
+            threshold,
+            level: Compression::default(),
+        }
+    }
+
+    // Gzip level from 0 (store) to 9 (smallest), 6 by default.
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = Compression::new(level.min(9));
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub fn encode(&self, value: Vec<u8>) -> Result<Vec<u8>, Err> {
+        if value.len() >= self.threshold {
+            let mut framed = frame(GZIP, value.len() / 2);
+            let mut encoder = GzEncoder::new(&mut framed, self.level);
+            encoder.write_all(&value)?;
+            encoder.finish()?;
+            if framed.len() < value.len() {
+                return Ok(framed);
+            }
+        }
+        if value.starts_with(&MAGIC) {
+            let mut framed = frame(RAW, value.len());
+            framed.extend_from_slice(&value);
+            return Ok(framed);
+        }
+        Ok(value)
+    }
+
+    pub fn decode(&self, stored: Vec<u8>) -> Result<Vec<u8>, Err> {
+        let Some(rest) = stored.strip_prefix(&MAGIC) else {
+            return Ok(stored);
+        };
+        match rest.split_first() {
+            Some((&GZIP, data)) => {
+                let mut value = Vec::with_capacity(data.len() * 2);
+                GzDecoder::new(data).read_to_end(&mut value)?;
+                Ok(value)
+            }
+            Some((&RAW, value)) => Ok(value.to_vec()),
+            Some((encoding, _)) => Err(format!("unknown value encoding {:#04x}", encoding).into()),
+            None => Err("truncated compressed value".into()),
+        }
+    }
+
+    fn decode_opt(&self, stored: Option<Vec<u8>>) -> Result<Option<Vec<u8>>, Err> {
+        stored.map(|stored| self.decode(stored)).transpose()
+    }
+}
+
+fn frame(encoding: u8, capacity: usize) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(MAGIC.len() + 1 + capacity);
+    framed.extend_from_slice(&MAGIC);
+    framed.push(encoding);
+    framed
+}
+
+impl<K, S> Storage<K, Vec<u8>> for CompressedStorage<S>
+where
+    S: Storage<K, Vec<u8>>,
+{
+    fn set(&self, key: K, value: Vec<u8>) -> Result<(), Err> {
+        self.inner.set(key, self.encode(value)?)
+    }
+
+    fn set_ex(&self, key: K, value: Vec<u8>, expire: Duration) -> Result<(), Err> {
+        self.inner.set_ex(key, self.encode(value)?, expire)
+    }
+
+    fn get(&self, key: K) -> Result<Option<Vec<u8>>, Err> {
+        self.decode_opt(self.inner.get(key)?)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.inner.del(key)
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.inner.contains(key)
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.inner.close()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.inner.health_check()
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<Vec<u8>>>, Err> {
+        self.inner
+            .get_many(keys)?
+            .into_iter()
+            .map(|stored| self.decode_opt(stored))
+            .collect()
+    }
+
+    fn set_many(&self, entries: Vec<(K, Vec<u8>)>) -> Result<(), Err> {
+        let entries = entries
+            .into_iter()
+            .map(|(key, value)| Ok((key, self.encode(value)?)))
+            .collect::<Result<_, Err>>()?;
+        self.inner.set_many(entries)
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        self.inner.del_many(keys)
+    }
+
+    fn set_with_opts(&self, key: K, value: Vec<u8>, opts: &OpOptions) -> Result<(), Err> {
+        self.inner.set_with_opts(key, self.encode(value)?, opts)
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<Vec<u8>>, Err> {
+        self.decode_opt(self.inner.get_with_opts(key, opts)?)
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        self.inner.del_with_opts(key, opts)
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        self.inner.contains_with_opts(key, opts)
+    }
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::{DashMapStorage, DashMapStorageBuilder};
+
+    fn compressed() -> CompressedStorage<DashMapStorage<&'static str, Vec<u8>>> {
+        CompressedStorage::new(DashMapStorageBuilder::new().build(), 64)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let storage = compressed();
+        let blob = br#"{"name":"Ferris","roles":["admin"]}"#.repeat(100);
+        storage.set("blob", blob.clone()).unwrap();
+        storage.set("small", b"tiny".to_vec()).unwrap();
+
+        let stored = storage.inner().get("blob").unwrap().unwrap();
+        assert!(stored.starts_with(&MAGIC) && stored.len() < blob.len() / 5);
+        assert_eq!(
+            storage.inner().get("small").unwrap(),
+            Some(b"tiny".to_vec())
+        );
+
+        assert_eq!(storage.get("blob").unwrap(), Some(blob.clone()));
+        assert_eq!(
+            storage.get_many(vec!["small", "blob", "missing"]).unwrap(),
+            [Some(b"tiny".to_vec()), Some(blob), None]
+        );
+    }
+
+    #[test]
+    fn test_plain_values() {
+        let storage = compressed();
+        // Written before the wrapper.
+        storage.inner().set("old", b"legacy".to_vec()).unwrap();
+        assert_eq!(storage.get("old").unwrap(), Some(b"legacy".to_vec()));
+
+        let tricky = [&MAGIC[..], b"g not gzip"].concat();
+        storage.set("tricky", tricky.clone()).unwrap();
+        assert_eq!(storage.get("tricky").unwrap(), Some(tricky));
+
+        // Random bytes don't shrink and are kept as they are.
+        let noise: Vec<u8> = (0..256u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        storage.set("noise", noise.clone()).unwrap();
+        assert_eq!(storage.inner().get("noise").unwrap(), Some(noise.clone()));
+        assert_eq!(storage.get("noise").unwrap(), Some(noise));
+
+        storage
+            .inner()
+            .set("broken", [&MAGIC[..], b"x"].concat())
+            .unwrap();
+        assert!(storage.get("broken").is_err());
+    }
+}
@@ -0,0 +1,344 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::durability::{Durability, SyncState};
+use crate::error::BuilderError;
+use crate::hooks::{Hooks, StorageHook};
+use crate::storage::{CloseFlag, Err, Storage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum ConfigFormat {
+    #[cfg(feature = "toml")]
+    Toml,
+    #[cfg(feature = "yaml")]
+    Yaml,
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            #[cfg(feature = "toml")]
+            Some("toml") => Some(ConfigFormat::Toml),
+            #[cfg(feature = "yaml")]
+            Some("yaml") | Some("yml") => Some(ConfigFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn parse(&self, content: &str) -> Result<Value, Err> {
+        match self {
+            #[cfg(feature = "toml")]
+            ConfigFormat::Toml => Ok(toml::from_str(content)?),
+            #[cfg(feature = "yaml")]
+            ConfigFormat::Yaml => Ok(serde_yaml::from_str(content)?),
+        }
+    }
+
+    fn render(&self, value: &Value) -> Result<String, Err> {
+        match self {
+            #[cfg(feature = "toml")]
+            ConfigFormat::Toml => Ok(toml::to_string_pretty(value)?),
+            #[cfg(feature = "yaml")]
+            ConfigFormat::Yaml => Ok(serde_yaml::to_string(value)?),
+        }
+    }
+}
+
+// Keys are dotted paths into the document, e.g. `server.port`.
+pub struct ConfigFileStorage<K, V> {
+    path: PathBuf,
+    format: ConfigFormat,
+    writable: bool,
+    document: RwLock<Value>,
+    sync: Mutex<SyncState>,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> ConfigFileStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Serialize + DeserializeOwned,
+{
+    pub fn reload(&self) -> Result<(), Err> {
+        let document = load(&self.path, self.format)?;
+        *self.document.write().unwrap() = document;
+        Ok(())
+    }
+
+    fn update<F: FnOnce(&mut Value) -> Result<bool, Err>>(&self, f: F) -> Result<bool, Err> {
+        if !self.writable {
+            return Err("ConfigFileStorage is read-only, use `writable` on the builder".into());
+        }
+
+        let mut document = self.document.write().unwrap();
+        let mut updated = document.clone();
+        if !f(&mut updated)? {
+            return Ok(false);
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(self.format.render(&updated)?.as_bytes())?;
+        if self.sync.lock().unwrap().record_write() {
+            file.sync_all()?;
+        }
+        drop(file);
+        fs::rename(&tmp, &self.path)?;
+        *document = updated;
+        Ok(true)
+    }
+}
+
+impl<K, V> Storage<K, V> for ConfigFileStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Serialize + DeserializeOwned,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        let json = serde_json::to_value(&value)?;
+        self.update(|document| {
+            let mut node = document;
+            for segment in key.as_ref().split('.') {
+                if !node.is_object() {
+                    *node = Value::Object(Map::new());
+                }
+                node = node
+                    .as_object_mut()
+                    .unwrap()
+                    .entry(segment)
+                    .or_insert(Value::Null);
+            }
+            *node = json;
+            Ok(true)
+        })?;
+        self.hooks.set(&key, &value);
+        Ok(())
+    }
+
+    fn set_ex(&self, _key: K, _value: V, _expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        Err("ConfigFileStorage does not support expiration".into())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let value = match lookup(&self.document.read().unwrap(), key.as_ref()) {
+            Some(value) => Some(serde_json::from_value(value.clone())?),
+            None => None,
+        };
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let removed = self.update(|document| {
+            let (parent, last) = match key.as_ref().rsplit_once('.') {
+                Some((parent, last)) => (lookup_mut(document, parent), last),
+                None => (Some(document), key.as_ref()),
+            };
+            Ok(parent
+                .and_then(|node| node.as_object_mut())
+                .and_then(|map| map.remove(last))
+                .is_some())
+        })?;
+        self.hooks.del(&key);
+        Ok(if removed { Some(key) } else { None })
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        Ok(lookup(&self.document.read().unwrap(), key.as_ref()).is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+}
+
+fn lookup<'a>(document: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.')
+        .try_fold(document, |node, segment| node.get(segment))
+}
+
+fn lookup_mut<'a>(document: &'a mut Value, key: &str) -> Option<&'a mut Value> {
+    key.split('.')
+        .try_fold(document, |node, segment| node.get_mut(segment))
+}
+
+fn load(path: &Path, format: ConfigFormat) -> Result<Value, Err> {
+    match fs::read_to_string(path) {
+        Ok(content) => match format.parse(&content)? {
+            Value::Null => Ok(Value::Object(Map::new())),
+            document => Ok(document),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Value::Object(Map::new())),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ConfigFileStorageBuilder<K, V> {
+    path: Option<PathBuf>,
+    format: Option<ConfigFormat>,
+    writable: bool,
+    durability: Durability,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> ConfigFileStorageBuilder<K, V>
+where
+    K: AsRef<str>,
+    V: Serialize + DeserializeOwned,
+{
+    pub fn new() -> Self {
+        ConfigFileStorageBuilder::default()
+    }
+
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn format(mut self, format: ConfigFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    pub fn writable(mut self, writable: bool) -> Self {
+        self.writable = writable;
+        self
+    }
+
+    // Applies to the rewritten file before it replaces the original.
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del`, in the order they were added.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if the path is missing, the format cannot be inferred from the extension,
+    // or the existing file fails to parse.
+    pub fn build(self) -> ConfigFileStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build config file storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<ConfigFileStorage<K, V>, BuilderError> {
+        let path = self
+            .path
+            .clone()
+            .ok_or(BuilderError::MissingField("path"))?;
+        let format = self
+            .format
+            .or_else(|| ConfigFormat::from_path(&path))
+            .ok_or(BuilderError::MissingField("format"))?;
+        self.durability.validate()?;
+        let document = load(&path, format).map_err(BuilderError::Backend)?;
+
+        Ok(ConfigFileStorage {
+            document: RwLock::new(document),
+            sync: Mutex::new(SyncState::new(self.durability)),
+            path,
+            format,
+            writable: self.writable,
+            closed: CloseFlag::default(),
+            hooks: self.hooks,
+            _marker: self._marker,
+        })
+    }
+}
+
+impl<K, V> Default for ConfigFileStorageBuilder<K, V> {
+    fn default() -> Self {
+        Self {
+            path: None,
+            format: None,
+            writable: false,
+            durability: Durability::Never,
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = ConfigFileStorageBuilder::<&str, String>::new()
+            .path("settings.ini")
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::MissingField("format"))));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.toml");
+        fs::write(&path, "[server]\nhost = \"127.0.0.1\"\nport = 8080\n").unwrap();
+
+        let storage = ConfigFileStorageBuilder::new()
+            .path(&path)
+            .writable(true)
+            .build();
+        assert_eq!(storage.get("server.port").unwrap(), Some(8080));
+        assert!(!storage.contains("server.user").unwrap());
+
+        storage.set("server.port", 9090).unwrap();
+        storage.set("log.level", 3).unwrap();
+        let _ = storage.del("server.host").unwrap();
+
+        let reloaded = ConfigFileStorageBuilder::<&str, u16>::new()
+            .path(&path)
+            .build();
+        assert_eq!(reloaded.get("server.port").unwrap(), Some(9090));
+        assert_eq!(reloaded.get("log.level").unwrap(), Some(3));
+        assert!(!reloaded.contains("server.host").unwrap());
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml_read_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.yaml");
+        fs::write(&path, "name: Ferris\n").unwrap();
+
+        let storage = ConfigFileStorageBuilder::new().path(&path).build();
+        assert_eq!(storage.get("name").unwrap(), Some("Ferris".to_string()));
+        assert!(storage.set("name", "Corro".to_string()).is_err());
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::health::HealthStatus;
+use crate::options::OpOptions;
+use crate::storage::{Err, Storage};
+
+// Request identity carried in `OpOptions::context`, for wrappers such as audit logs, metrics
+// or tenant isolation to act on. Backends ignore it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Context {
+    pub trace_id: Option<String>,
+    pub span_id: Option<String>,
+    pub tenant: Option<String>,
+    pub principal: Option<String>,
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Context::default()
+    }
+
+    pub fn trace(mut self, trace_id: &str, span_id: &str) -> Self {
+        self.trace_id = Some(trace_id.to_string());
+        self.span_id = Some(span_id.to_string());
+        self
+    }
+
+    pub fn tenant(mut self, tenant: &str) -> Self {
+        self.tenant = Some(tenant.to_string());
+        self
+    }
+
+    pub fn principal(mut self, principal: &str) -> Self {
+        self.principal = Some(principal.to_string());
+        self
+    }
+
+    pub fn attribute(mut self, key: &str, value: &str) -> Self {
+        self.attributes.insert(key.to_string(), value.to_string());
+        self
+    }
+}
+
+// Attaches a context to every call on this handle, a context passed with the call wins.
+pub struct ContextStorage<S> {
+    inner: S,
+    context: Context,
+}
+
+impl<S> ContextStorage<S> {
+    pub fn new(inner: S, context: Context) -> Self {
+        ContextStorage { inner, context }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    fn with_context(&self, opts: &OpOptions) -> OpOptions {
+        let mut opts = opts.clone();
+        if opts.context.is_none() {
+            opts.context = Some(self.context.clone());
+        }
+        opts
+    }
+}
+
+impl<K, V, S> Storage<K, V> for ContextStorage<S>
+where
+    S: Storage<K, V>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.set_with_opts(key, value, &OpOptions::default())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.set_with_opts(key, value, &OpOptions::new().ttl(expire))
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.get_with_opts(key, &OpOptions::default())
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.del_with_opts(key, &OpOptions::default())
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.contains_with_opts(key, &OpOptions::default())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.inner.close()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.inner.health_check()
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        self.inner.get_many(keys)
+    }
+
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.inner.set_many(entries)
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        self.inner.del_many(keys)
+    }
+
+    fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+        self.inner
+            .set_with_opts(key, value, &self.with_context(opts))
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        self.inner.get_with_opts(key, &self.with_context(opts))
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        self.inner.del_with_opts(key, &self.with_context(opts))
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        self.inner.contains_with_opts(key, &self.with_context(opts))
+    }
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::{DashMapStorage, DashMapStorageBuilder};
+    use crate::key_encoder::KeyEncodedStorage;
+    use std::sync::Mutex;
+
+    // Records the tenant of every write it sees.
+    struct Audited {
+        inner: DashMapStorage<String, String>,
+        tenants: Mutex<Vec<Option<String>>>,
+    }
+
+    impl Storage<String, String> for Audited {
+        fn set(&self, key: String, value: String) -> Result<(), Err> {
+            self.set_with_opts(key, value, &OpOptions::default())
+        }
+
+        fn set_ex(&self, key: String, value: String, expire: Duration) -> Result<(), Err> {
+            self.inner.set_ex(key, value, expire)
+        }
+
+        fn get(&self, key: String) -> Result<Option<String>, Err> {
+            self.inner.get(key)
+        }
+
+        fn del(&self, key: String) -> Result<Option<String>, Err> {
+            self.inner.del(key)
+        }
+
+        fn contains(&self, key: String) -> Result<bool, Err> {
+            self.inner.contains(key)
+        }
+
+        fn set_with_opts(&self, key: String, value: String, opts: &OpOptions) -> Result<(), Err> {
+            let tenant = opts.context.as_ref().and_then(|ctx| ctx.tenant.clone());
+            self.tenants.lock().unwrap().push(tenant);
+            self.inner.set(key, value)
+        }
+    }
+
+    #[test]
+    fn test_context() {
+        let audited = Audited {
+            inner: DashMapStorageBuilder::new().build(),
+            tenants: Mutex::new(Vec::new()),
+        };
+        let storage = ContextStorage::new(
+            KeyEncodedStorage::new(&audited, |key: &u32| format!("user:{}", key)),
+            Context::new().tenant("acme").principal("svc-billing"),
+        );
+
+        storage.set(7, "Ferris".to_string()).unwrap();
+        let opts = OpOptions::new().context(Context::new().tenant("globex"));
+        storage
+            .set_with_opts(8, "Corro".to_string(), &opts)
+            .unwrap();
+        audited
+            .set("user:9".to_string(), "Rusty".to_string())
+            .unwrap();
+
+        assert_eq!(
+            *audited.tenants.lock().unwrap(),
+            [Some("acme".to_string()), Some("globex".to_string()), None]
+        );
+        assert_eq!(storage.get(7).unwrap(), Some("Ferris".to_string()));
+    }
+}
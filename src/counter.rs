@@ -0,0 +1,70 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::storage::Err;
+
+// Counters updated atomically by the backend, e.g. for rate limits and quotas. A missing key
+// counts from 0, an existing TTL is kept.
+pub trait CounterStorage<K> {
+    // Adds `delta` and returns the new value, overflowing is an error.
+    fn incr(&self, key: K, delta: i64) -> Result<i64, Err>;
+
+    fn decr(&self, key: K, delta: i64) -> Result<i64, Err> {
+        match delta.checked_neg() {
+            Some(delta) => self.incr(key, delta),
+            None => Err("decrement would overflow".into()),
+        }
+    }
+
+    // Adds `delta` unless the result would be above `max`, checked and written in one step,
+    // e.g. to count against a limit without taking a denied call back out. A key it creates
+    // expires after `expire`, an existing TTL is kept. Returns the value after the call and
+    // whether `delta` was added.
+    fn incr_within(
+        &self,
+        key: K,
+        delta: i64,
+        max: i64,
+        expire: Duration,
+    ) -> Result<(i64, bool), Err>;
+}
+
+impl<K, S: CounterStorage<K> + ?Sized> CounterStorage<K> for &S {
+    fn incr(&self, key: K, delta: i64) -> Result<i64, Err> {
+        (**self).incr(key, delta)
+    }
+
+    fn decr(&self, key: K, delta: i64) -> Result<i64, Err> {
+        (**self).decr(key, delta)
+    }
+
+    fn incr_within(
+        &self,
+        key: K,
+        delta: i64,
+        max: i64,
+        expire: Duration,
+    ) -> Result<(i64, bool), Err> {
+        (**self).incr_within(key, delta, max, expire)
+    }
+}
+
+impl<K, S: CounterStorage<K> + ?Sized> CounterStorage<K> for Arc<S> {
+    fn incr(&self, key: K, delta: i64) -> Result<i64, Err> {
+        (**self).incr(key, delta)
+    }
+
+    fn decr(&self, key: K, delta: i64) -> Result<i64, Err> {
+        (**self).decr(key, delta)
+    }
+
+    fn incr_within(
+        &self,
+        key: K,
+        delta: i64,
+        max: i64,
+        expire: Duration,
+    ) -> Result<(i64, bool), Err> {
+        (**self).incr_within(key, delta, max, expire)
+    }
+}
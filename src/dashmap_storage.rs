@@ -1,68 +1,1629 @@
-use std::hash::Hash;
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashSet, VecDeque};
+use std::hash::{BuildHasher, Hash};
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+#[cfg(feature = "serde")]
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::RecvTimeoutError,
+    sync::Mutex,
+    thread::{self, JoinHandle},
+};
 
+use dashmap::mapref::entry::Entry;
+use dashmap::mapref::one::Ref;
 use dashmap::DashMap;
-use std::time::Duration;
 
-use crate::storage::{Err, Storage};
+use crate::admin::AdminStorage;
+use crate::append::{Append, AppendStorage};
+use crate::atomic::AtomicStorage;
+use crate::collection::{Collection, CollectionStorage};
+use crate::counter::CounterStorage;
+use crate::error::BuilderError;
+use crate::expiry::ExpiryStorage;
+use crate::export::{EntryIter, ExportEntry, ExportIter, ExportStorage};
+use crate::flush::{FlushConfirmation, FlushNamespace};
+use crate::gc::GarbageCollect;
+use crate::get_or_insert::GetOrInsertStorage;
+use crate::hooks::{Hooks, StorageHook};
+use crate::jitter_storage::{Jitter, TtlPolicy};
+use crate::lock::LockStorage;
+use crate::lookup::LookupStorage;
+use crate::merge::{Merge, MergeableStorage};
+use crate::scan::{KeyIter, ScanStorage};
+use crate::stats::{StorageInfo, StorageStats};
+use crate::storage::{CloseFlag, Err, Storage};
+use crate::stream::{no_group, Stream, StreamEntry, StreamStorage};
+#[cfg(feature = "tracing")]
+use crate::trace;
+use crate::trace::traced;
+use crate::update::UpdateStorage;
+use crate::versioned::{Version, VersionedStorage};
+use crate::wait::{wait_watched, WaitStorage};
+use crate::watch::{ChangeEvent, Changes, WatchableStorage};
 
-pub struct DashMapStorage<K, V> {
-    dash: DashMap<K, V>,
+type ExpireHook<K, V> = Arc<dyn Fn(&K, &V) + Send + Sync>;
+
+// Deadlines live next to the shared map, so `from_arc`/`shared` keep exposing a plain
+// `DashMap<K, V>`. Writes lock the value's entry before its deadline, expired entries are
+// purged when read and by `gc_now`.
+pub struct DashMapStorage<K, V, S = RandomState> {
+    dash: Arc<DashMap<K, V, S>>,
+    deadlines: Arc<DashMap<K, Instant, S>>,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    on_expire: Option<ExpireHook<K, V>>,
+    watchers: DashMap<K, Vec<Sender<ChangeEvent>>, S>,
+    // Saves looking up watchers before anything was ever watched.
+    watched: AtomicBool,
+    // Token and deadline of every held lock, apart from the values.
+    locks: DashMap<K, (String, Instant), S>,
+    // The version `get_versioned` handed out per key, dropped by the next write to it. They
+    // come from `next_version`, so a key's versions only grow, even across deletes.
+    versions: DashMap<K, u64, S>,
+    versioned: AtomicBool,
+    next_version: AtomicU64,
+    ttl: TtlPolicy,
+    #[cfg(feature = "serde")]
+    persistence: Option<Persistence>,
+}
+
+impl<K: Hash + Eq, V: Clone, S: BuildHasher + Clone> DashMapStorage<K, V, S> {
+    pub fn from_map(map: DashMap<K, V, S>) -> Self {
+        DashMapStorage::from_arc(Arc::new(map))
+    }
+
+    // Shares the map with the caller, writes through either handle are visible to both.
+    pub fn from_arc(map: Arc<DashMap<K, V, S>>) -> Self {
+        let hasher = map.hasher().clone();
+        DashMapStorage {
+            dash: map,
+            deadlines: Arc::new(DashMap::with_hasher(hasher.clone())),
+            closed: CloseFlag::default(),
+            hooks: Hooks::default(),
+            on_expire: None,
+            watchers: DashMap::with_hasher(hasher.clone()),
+            watched: AtomicBool::new(false),
+            locks: DashMap::with_hasher(hasher.clone()),
+            versions: DashMap::with_hasher(hasher),
+            versioned: AtomicBool::new(false),
+            next_version: AtomicU64::new(0),
+            ttl: TtlPolicy::default(),
+            #[cfg(feature = "serde")]
+            persistence: None,
+        }
+    }
+
+    pub fn shared(&self) -> Arc<DashMap<K, V, S>> {
+        self.dash.clone()
+    }
+
+    // The helpers that only look a key up take it borrowed, for `LookupStorage`.
+    fn is_expired<Q>(&self, key: &Q, now: Instant) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.deadlines
+            .get(key)
+            .is_some_and(|deadline| *deadline <= now)
+    }
+
+    // Removes the entry only if it is still expired, a concurrent `set` wins.
+    fn purge<Q>(&self, key: &Q, now: Instant) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let removed = self
+            .dash
+            .remove_if(key, |key, _| self.is_expired::<K>(key, now));
+        self.deadlines
+            .remove_if(key, |_, deadline| *deadline <= now);
+        let Some((key, value)) = removed else {
+            return false;
+        };
+        self.notify::<K>(&key, ChangeEvent::Expired);
+        self.expired(&key, &value);
+        true
+    }
+
+    // Runs the `on_expire` callback, the entry has left the map by then.
+    fn expired(&self, key: &K, value: &V) {
+        if let Some(on_expire) = &self.on_expire {
+            on_expire(key, value);
+        }
+    }
+
+    // Copies out an expired value that a write is about to replace or remove, so `on_expire`
+    // can see it once the entry's lock is released. Only with a callback, to spare the clone.
+    fn expiring(&self, entry: &Entry<'_, K, V, S>, now: Instant) -> Option<(K, V)>
+    where
+        K: Clone,
+    {
+        match entry {
+            Entry::Occupied(entry)
+                if self.on_expire.is_some() && self.is_expired(entry.key(), now) =>
+            {
+                Some((entry.key().clone(), entry.get().clone()))
+            }
+            _ => None,
+        }
+    }
+
+    fn replaced(&self, expiring: Option<(K, V)>) {
+        if let Some((key, value)) = expiring {
+            self.expired(&key, &value);
+        }
+    }
+
+    fn lookup<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = Instant::now();
+        if self.is_expired(key, now) {
+            self.purge(key, now);
+            return None;
+        }
+        self.dash.get(key).map(|v| v.value().clone())
+    }
+
+    fn has<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = Instant::now();
+        if self.is_expired(key, now) {
+            self.purge(key, now);
+            return false;
+        }
+        self.dash.contains_key(key)
+    }
+
+    fn insert(&self, key: K, value: V, deadline: Option<Instant>)
+    where
+        K: Clone,
+    {
+        let entry = self.dash.entry(key);
+        let expiring = self.expiring(&entry, Instant::now());
+        match deadline {
+            Some(deadline) => self.deadlines.insert(entry.key().clone(), deadline),
+            None => self
+                .deadlines
+                .remove(entry.key())
+                .map(|(_, deadline)| deadline),
+        };
+        self.notify(entry.insert(value).key(), ChangeEvent::Set);
+        self.replaced(expiring);
+    }
+
+    // Keys and values are only cloned for the hooks when there are any.
+    // When a write with `ttl` expires, after the builder's default TTL and jitter.
+    fn deadline(&self, ttl: Option<Duration>) -> Option<Instant> {
+        self.ttl.apply(ttl).map(|ttl| Instant::now() + ttl)
+    }
+
+    fn insert_hooked(&self, key: K, value: V, deadline: Option<Instant>)
+    where
+        K: Clone,
+    {
+        if self.hooks.is_empty() {
+            return self.insert(key, value, deadline);
+        }
+        self.insert(key.clone(), value.clone(), deadline);
+        self.hooks.set(&key, &value);
+    }
+
+    fn remove<Q>(&self, key: &Q) -> Option<K>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = Instant::now();
+        let expired = self.is_expired(key, now);
+        let removed = self.dash.remove(key);
+        self.deadlines.remove(key);
+        let (key, value) = removed?;
+        if expired {
+            self.notify::<K>(&key, ChangeEvent::Expired);
+            self.expired(&key, &value);
+            return None;
+        }
+        self.notify::<K>(&key, ChangeEvent::Del);
+        Some(key)
+    }
+
+    // An expired value counts as missing and is replaced.
+    fn get_or_insert_entry<F>(&self, key: K, expire: Option<Duration>, f: F) -> V
+    where
+        K: Clone,
+        F: FnOnce() -> V,
+    {
+        let now = Instant::now();
+        let entry = self.dash.entry(key);
+        let expiring = self.expiring(&entry, now);
+        match entry {
+            Entry::Occupied(entry) if !self.is_expired(entry.key(), now) => entry.get().clone(),
+            entry => {
+                match expire {
+                    Some(expire) => self.deadlines.insert(entry.key().clone(), now + expire),
+                    None => self
+                        .deadlines
+                        .remove(entry.key())
+                        .map(|(_, deadline)| deadline),
+                };
+                let value = {
+                    let value = entry.insert(f());
+                    self.notify(value.key(), ChangeEvent::Set);
+                    value.clone()
+                };
+                self.replaced(expiring);
+                value
+            }
+        }
+    }
+
+    // Holds the entry's lock while `f` decides on the new value, `None` leaves the entry alone.
+    // Returns the value `f` saw and whether it was replaced.
+    fn update_entry<F>(&self, key: K, f: F) -> (Option<V>, bool)
+    where
+        K: Clone,
+        F: FnOnce(Option<&V>) -> Option<Option<V>>,
+    {
+        let now = Instant::now();
+        let entry = self.dash.entry(key);
+        let expiring = self.expiring(&entry, now);
+        let current = match &entry {
+            Entry::Occupied(entry) if !self.is_expired(entry.key(), now) => {
+                Some(entry.get().clone())
+            }
+            _ => None,
+        };
+        let Some(new) = f(current.as_ref()) else {
+            return (current, false);
+        };
+        match (new, entry) {
+            (Some(new), entry) => {
+                self.deadlines.remove(entry.key());
+                self.notify(entry.insert(new).key(), ChangeEvent::Set);
+            }
+            (None, Entry::Occupied(entry)) => {
+                self.deadlines.remove(entry.key());
+                self.notify(entry.key(), ChangeEvent::Del);
+                entry.remove();
+            }
+            (None, Entry::Vacant(_)) => {}
+        }
+        self.replaced(expiring);
+        (current, true)
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> DashMapStorage<K, V, S> {
+    // Sends `event` to the key's watchers and forgets the ones whose receiver is gone. Every
+    // write ends up here, so it also drops the key's version.
+    fn notify<Q>(&self, key: &Q, event: ChangeEvent)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.versioned.load(Ordering::Relaxed) {
+            self.versions.remove(key);
+        }
+        if !self.watched.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(mut senders) = self.watchers.get_mut(key) {
+            senders.retain(|tx| tx.send(event).is_ok());
+        }
+        self.watchers
+            .remove_if(key, |_, senders| senders.is_empty());
+    }
 }
 
-impl<K: Hash + Eq, V: Clone> Storage<K, V> for DashMapStorage<K, V> {
+impl<K: Hash + Eq, V: Clone, S: BuildHasher + Clone> From<DashMap<K, V, S>>
+    for DashMapStorage<K, V, S>
+{
+    fn from(map: DashMap<K, V, S>) -> Self {
+        DashMapStorage::from_map(map)
+    }
+}
+
+impl<K: Hash + Eq, V: Clone, S: BuildHasher + Clone> From<Arc<DashMap<K, V, S>>>
+    for DashMapStorage<K, V, S>
+{
+    fn from(map: Arc<DashMap<K, V, S>>) -> Self {
+        DashMapStorage::from_arc(map)
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, S: BuildHasher + Clone> Storage<K, V>
+    for DashMapStorage<K, V, S>
+{
     fn get(&self, key: K) -> Result<Option<V>, Err> {
-        Ok(self.dash.get(&key).map(|v| (*v.value()).clone()))
+        traced!("dashmap", "get", trace::hashed_key(&key), trace::found, {
+            self.closed.check()?;
+            let value = self.lookup(&key);
+            self.hooks.get(&key, value.as_ref());
+            Ok(value)
+        })
     }
 
     fn set(&self, key: K, value: V) -> Result<(), Err> {
-        Ok(self.dash.insert(key, value).map_or((), |_| ()))
+        traced!("dashmap", "set", trace::hashed_key(&key), trace::ok, {
+            self.closed.check()?;
+            self.insert_hooked(key, value, self.deadline(None));
+            Ok(())
+        })
     }
 
-    fn set_ex(&self, _key: K, _value: V, _expire: Duration) -> Result<(), Err> {
-        unimplemented!()
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        traced!("dashmap", "set_ex", trace::hashed_key(&key), trace::ok, {
+            self.closed.check()?;
+            self.insert_hooked(key, value, self.deadline(Some(expire)));
+            Ok(())
+        })
     }
 
     fn del(&self, key: K) -> Result<Option<K>, Err> {
-        Ok(self.dash.remove(&key).map(|p| p.0))
+        traced!("dashmap", "del", trace::hashed_key(&key), trace::found, {
+            self.closed.check()?;
+            let removed = self.remove(&key);
+            self.hooks.del(&key);
+            Ok(removed)
+        })
     }
 
     fn contains(&self, key: K) -> Result<bool, Err> {
-        Ok(self.dash.contains_key(&key))
+        traced!("dashmap", "contains", trace::hashed_key(&key), trace::ok, {
+            self.closed.check()?;
+            Ok(self.has(&key))
+        })
+    }
+
+    // With `persist_to`, writes the file one last time first.
+    fn close(&self) -> Result<(), Err> {
+        #[cfg(feature = "serde")]
+        let persisted = match &self.persistence {
+            Some(persistence) if !self.closed.is_closed() => persistence.stop(),
+            _ => Ok(()),
+        };
+        self.closed.close();
+        #[cfg(feature = "serde")]
+        persisted?;
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        traced!("dashmap", "get_many", None::<String>, trace::ok, {
+            self.closed.check()?;
+            let values: Vec<_> = keys.iter().map(|key| self.lookup(key)).collect();
+            for (key, value) in keys.iter().zip(&values) {
+                self.hooks.get(key, value.as_ref());
+            }
+            Ok(values)
+        })
+    }
+
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        traced!("dashmap", "set_many", None::<String>, trace::ok, {
+            self.closed.check()?;
+            for (key, value) in entries {
+                self.insert_hooked(key, value, self.deadline(None));
+            }
+            Ok(())
+        })
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        traced!("dashmap", "del_many", None::<String>, trace::ok, {
+            self.closed.check()?;
+            let removed = keys.iter().map(|key| self.remove(key)).collect();
+            keys.iter().for_each(|key| self.hooks.del(key));
+            Ok(removed)
+        })
+    }
+}
+
+impl<K, V, Q, S> LookupStorage<K, V, Q> for DashMapStorage<K, V, S>
+where
+    K: Hash + Eq + Clone + Borrow<Q>,
+    V: Clone,
+    Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    S: BuildHasher + Clone,
+{
+    fn get_borrowed(&self, key: &Q) -> Result<Option<V>, Err> {
+        traced!("dashmap", "get", trace::hashed_key(key), trace::found, {
+            self.closed.check()?;
+            let value = self.lookup(key);
+            if !self.hooks.is_empty() {
+                self.hooks.get(&key.to_owned(), value.as_ref());
+            }
+            Ok(value)
+        })
+    }
+
+    fn contains_borrowed(&self, key: &Q) -> Result<bool, Err> {
+        traced!("dashmap", "contains", trace::hashed_key(key), trace::ok, {
+            self.closed.check()?;
+            Ok(self.has(key))
+        })
+    }
+
+    fn del_borrowed(&self, key: &Q) -> Result<bool, Err> {
+        traced!("dashmap", "del", trace::hashed_key(key), trace::ok, {
+            self.closed.check()?;
+            let removed = self.remove(key);
+            if !self.hooks.is_empty() {
+                self.hooks.del(&key.to_owned());
+            }
+            Ok(removed.is_some())
+        })
+    }
+}
+
+// Walks the map shard by shard, only the shard being read is locked.
+impl<K: Hash + Eq + Clone, V: Clone, S: BuildHasher + Clone> ExportStorage<K, V>
+    for DashMapStorage<K, V, S>
+{
+    fn export_iter(&self) -> Result<ExportIter<'_, K, V>, Err> {
+        Ok(Box::new(
+            self.export_entries()?
+                .map(|entry| entry.map(|entry| (entry.key, entry.value))),
+        ))
+    }
+
+    fn export_entries<'a>(&'a self) -> Result<EntryIter<'a, K, V>, Err>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.closed.check()?;
+        let now = Instant::now();
+        Ok(Box::new(self.dash.iter().filter_map(move |entry| {
+            let ttl = match self.deadlines.get(entry.key()) {
+                Some(deadline) if *deadline <= now => return None,
+                Some(deadline) => Some(*deadline - now),
+                None => None,
+            };
+            Some(Ok(ExportEntry {
+                key: entry.key().clone(),
+                value: entry.value().clone(),
+                ttl,
+            }))
+        })))
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, S: BuildHasher + Clone> DashMapStorage<K, V, S> {
+    // Copies every live entry, e.g. to look at in a debugger. TTLs are left out, see
+    // `export_entries` for them.
+    pub fn snapshot(&self) -> Result<Vec<(K, V)>, Err> {
+        self.export_iter()?.collect()
+    }
+
+    // Borrows the value in place instead of cloning it. The guard holds a read lock on the
+    // value's shard, so writers to that shard wait until it is dropped, and writing to the
+    // storage from the thread holding it may deadlock.
+    pub fn get_ref(&self, key: &K) -> Result<Option<Ref<'_, K, V, S>>, Err> {
+        self.closed.check()?;
+        let now = Instant::now();
+        if self.is_expired(key, now) {
+            self.purge(key, now);
+            self.hooks.get(key, None);
+            return Ok(None);
+        }
+        let value = self.dash.get(key);
+        self.hooks
+            .get(key, value.as_ref().map(|value| value.value()));
+        Ok(value)
+    }
+
+    // Writes every live entry as a line of JSON, `{"key":..,"value":..,"expires_at":..}`
+    // with the expiry in milliseconds since the epoch, so TTLs keep running while the
+    // snapshot sits on disk. Shards are read one at a time, not as one atomic snapshot.
+    // Returns how many entries were written.
+    #[cfg(feature = "serde")]
+    pub fn export_to_writer<W: std::io::Write>(&self, writer: W) -> Result<usize, Err>
+    where
+        K: serde::Serialize,
+        V: serde::Serialize,
+    {
+        use std::io::Write;
+
+        let mut writer = std::io::BufWriter::new(writer);
+        let now = epoch_millis();
+        let mut written = 0;
+        for entry in self.export_entries()? {
+            let entry = entry?;
+            let record = SnapshotRecord {
+                key: &entry.key,
+                value: &entry.value,
+                expires_at: entry.ttl.map(|ttl| now + ttl.as_millis() as u64),
+            };
+            serde_json::to_writer(&mut writer, &record)?;
+            writer.write_all(b"\n")?;
+            written += 1;
+        }
+        writer.flush()?;
+        Ok(written)
+    }
+
+    // Writes the `persist_to` file now, e.g. before a planned restart.
+    #[cfg(feature = "serde")]
+    pub fn persist_now(&self) -> Result<usize, Err>
+    where
+        K: serde::Serialize,
+        V: serde::Serialize,
+    {
+        match &self.persistence {
+            Some(persistence) => persist(self, &persistence.path, &persistence.writing),
+            None => Err("storage was built without `persist_to`".into()),
+        }
+    }
+
+    // Loads entries written by `export_to_writer`, overwriting keys that already exist.
+    // Entries that expired in the meantime are skipped. Stops at the first malformed line,
+    // keeping the entries before it, and returns how many entries were restored.
+    #[cfg(feature = "serde")]
+    pub fn restore_from_reader<R: std::io::Read>(&self, reader: R) -> Result<usize, Err>
+    where
+        K: serde::de::DeserializeOwned,
+        V: serde::de::DeserializeOwned,
+    {
+        use std::io::BufRead;
+
+        self.closed.check()?;
+        let now = epoch_millis();
+        let mut restored = 0;
+        for (n, line) in std::io::BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: SnapshotRecord<K, V> = serde_json::from_str(&line)
+                .map_err(|e| format!("snapshot line {}: {}", n + 1, e))?;
+            let deadline = match record.expires_at {
+                Some(at) if at <= now => continue,
+                Some(at) => Some(Instant::now() + Duration::from_millis(at - now)),
+                None => None,
+            };
+            self.insert_hooked(record.key, record.value, deadline);
+            restored += 1;
+        }
+        Ok(restored)
+    }
+}
+
+// Shares the entries and close flag, not the hooks, watchers and locks.
+#[cfg(feature = "serde")]
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> DashMapStorage<K, V, S> {
+    fn view(&self) -> Self {
+        DashMapStorage {
+            dash: self.dash.clone(),
+            deadlines: self.deadlines.clone(),
+            closed: self.closed.clone(),
+            hooks: Hooks::default(),
+            on_expire: None,
+            watchers: DashMap::with_hasher(self.dash.hasher().clone()),
+            watched: AtomicBool::new(false),
+            locks: DashMap::with_hasher(self.dash.hasher().clone()),
+            versions: DashMap::with_hasher(self.dash.hasher().clone()),
+            versioned: AtomicBool::new(false),
+            next_version: AtomicU64::new(0),
+            ttl: self.ttl,
+            persistence: None,
+        }
+    }
+}
+
+// Replaces the file through a temporary next to it, so a crash mid-write keeps the last one.
+#[cfg(feature = "serde")]
+fn persist<K, V, S>(
+    storage: &DashMapStorage<K, V, S>,
+    path: &Path,
+    lock: &Mutex<()>,
+) -> Result<usize, Err>
+where
+    K: Hash + Eq + Clone + serde::Serialize,
+    V: Clone + serde::Serialize,
+    S: BuildHasher + Clone,
+{
+    let _writing = lock.lock().unwrap();
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut file = std::fs::File::create(&tmp)?;
+    let written = storage.export_to_writer(&mut file)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(written)
+}
+
+// The background writer of `persist_to`, stopped by `close` or when the storage drops.
+#[cfg(feature = "serde")]
+struct Persistence {
+    path: PathBuf,
+    // Keeps `persist_now` and the thread from writing the file at once.
+    writing: Arc<Mutex<()>>,
+    stop: Sender<()>,
+    handle: Mutex<Option<JoinHandle<Result<(), String>>>>,
+}
+
+#[cfg(feature = "serde")]
+impl Persistence {
+    // Waits for the final write.
+    fn stop(&self) -> Result<(), Err> {
+        let _ = self.stop.send(());
+        match self.handle.lock().unwrap().take() {
+            Some(handle) => handle
+                .join()
+                .map_err(|_| "persistence thread panicked")?
+                .map_err(Err::from),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Drop for Persistence {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(feature = "serde")]
+type StartPersistence<K, V, S> =
+    Box<dyn FnOnce(&DashMapStorage<K, V, S>, Duration) -> Result<Persistence, BuilderError>>;
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SnapshotRecord<K, V> {
+    key: K,
+    value: V,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+}
+
+#[cfg(feature = "serde")]
+fn epoch_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+// Holds the value's entry while touching its deadline, like `insert`.
+impl<K: Hash + Eq + Clone, V: Clone, S: BuildHasher + Clone> ExpiryStorage<K>
+    for DashMapStorage<K, V, S>
+{
+    fn ttl(&self, key: K) -> Result<Option<Duration>, Err> {
+        self.closed.check()?;
+        let now = Instant::now();
+        match self.deadlines.get(&key).map(|deadline| *deadline) {
+            Some(deadline) if deadline > now => Ok(Some(deadline - now)),
+            Some(_) => {
+                self.purge(&key, now);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn expire(&self, key: K, expire: Duration) -> Result<bool, Err> {
+        self.closed.check()?;
+        let now = Instant::now();
+        match self.dash.entry(key) {
+            Entry::Occupied(entry) if !self.is_expired(entry.key(), now) => {
+                self.deadlines.insert(entry.key().clone(), now + expire);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn persist(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        let now = Instant::now();
+        match self.dash.entry(key) {
+            Entry::Occupied(entry) if !self.is_expired(entry.key(), now) => {
+                Ok(self.deadlines.remove(entry.key()).is_some())
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+// `f` runs under the entry's lock, so concurrent misses of one key compute it once.
+impl<K: Hash + Eq + Clone, V: Clone, S: BuildHasher + Clone> GetOrInsertStorage<K, V>
+    for DashMapStorage<K, V, S>
+{
+    fn set_nx(&self, key: K, value: V, expire: Option<Duration>) -> Result<bool, Err> {
+        self.closed.check()?;
+        let mut inserted = false;
+        self.get_or_insert_entry(key, expire, || {
+            inserted = true;
+            value
+        });
+        Ok(inserted)
+    }
+
+    fn get_or_insert_with<F>(&self, key: K, f: F) -> Result<V, Err>
+    where
+        F: FnOnce() -> V,
+    {
+        self.closed.check()?;
+        Ok(self.get_or_insert_entry(key, None, f))
+    }
+
+    fn get_or_insert_with_ex<F>(&self, key: K, expire: Duration, f: F) -> Result<V, Err>
+    where
+        F: FnOnce() -> V,
+    {
+        self.closed.check()?;
+        Ok(self.get_or_insert_entry(key, Some(expire), f))
+    }
+}
+
+// `clear` also empties a map shared through `from_arc`.
+impl<K: Hash + Eq + Clone, V: Clone, S: BuildHasher + Clone> AdminStorage
+    for DashMapStorage<K, V, S>
+{
+    fn clear(&self) -> Result<(), Err> {
+        self.closed.check()?;
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        if self.watched.load(Ordering::Relaxed) || self.on_expire.is_some() {
+            self.dash.retain(|key, value| {
+                if self.on_expire.is_some() && self.is_expired(key, now) {
+                    expired.push((key.clone(), value.clone()));
+                }
+                self.notify(key, ChangeEvent::Del);
+                false
+            });
+        }
+        self.dash.clear();
+        self.deadlines.clear();
+        self.versions.clear();
+        for (key, value) in &expired {
+            self.expired(key, value);
+        }
+        Ok(())
+    }
+
+    fn len(&self) -> Result<usize, Err> {
+        self.closed.check()?;
+        let now = Instant::now();
+        let expired = self
+            .deadlines
+            .iter()
+            .filter(|deadline| *deadline.value() <= now)
+            .count();
+        Ok(self.dash.len().saturating_sub(expired))
+    }
+}
+
+// The keys are collected up front, so the caller may write to the map while iterating.
+impl<K: Hash + Eq + Clone + AsRef<str>, V: Clone, S: BuildHasher + Clone> ScanStorage<K>
+    for DashMapStorage<K, V, S>
+{
+    fn scan_prefix(&self, prefix: &str) -> Result<KeyIter<'_, K>, Err> {
+        self.closed.check()?;
+        let now = Instant::now();
+        let keys: Vec<K> = self
+            .dash
+            .iter()
+            .map(|entry| entry.key().clone())
+            .filter(|key| key.as_ref().starts_with(prefix) && !self.is_expired(key, now))
+            .collect();
+        Ok(Box::new(keys.into_iter().map(Ok)))
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, S: BuildHasher + Clone> GarbageCollect
+    for DashMapStorage<K, V, S>
+{
+    fn gc_now(&self, batch_size: usize) -> Result<usize, Err> {
+        self.closed.check()?;
+        let now = Instant::now();
+        // Collected first, purging while iterating would lock the maps in the wrong order.
+        let expired: Vec<K> = self
+            .deadlines
+            .iter()
+            .filter(|deadline| *deadline.value() <= now)
+            .map(|deadline| deadline.key().clone())
+            .take(batch_size)
+            .collect();
+        Ok(expired.iter().filter(|key| self.purge(key, now)).count())
+    }
+}
+
+impl<K: Hash + Eq + Clone + AsRef<str>, V: Clone, S: BuildHasher + Clone> FlushNamespace
+    for DashMapStorage<K, V, S>
+{
+    fn flush_namespace(&self, prefix: &str, confirm: &FlushConfirmation) -> Result<usize, Err> {
+        self.closed.check()?;
+        confirm.check(prefix)?;
+        let now = Instant::now();
+        let before = self.dash.len();
+        let mut expired = Vec::new();
+        self.dash.retain(|key, value| {
+            let flushed = key.as_ref().starts_with(prefix);
+            if flushed {
+                if self.on_expire.is_some() && self.is_expired(key, now) {
+                    expired.push((key.clone(), value.clone()));
+                }
+                self.notify(key, ChangeEvent::Del);
+            }
+            !flushed
+        });
+        self.deadlines
+            .retain(|key, _| !key.as_ref().starts_with(prefix));
+        for (key, value) in &expired {
+            self.expired(key, value);
+        }
+        Ok(before.saturating_sub(self.dash.len()))
+    }
+}
+
+// Merges under the entry's lock, so concurrent merges of one key are never lost. An expired
+// value is replaced rather than merged into.
+impl<K: Hash + Eq + Clone, V: Clone + Merge, S: BuildHasher + Clone> MergeableStorage<K, V>
+    for DashMapStorage<K, V, S>
+{
+    fn merge(&self, key: K, value: V) -> Result<V, Err> {
+        self.closed.check()?;
+        let now = Instant::now();
+        let entry = self.dash.entry(key);
+        let expiring = self.expiring(&entry, now);
+        let merged = match entry {
+            Entry::Occupied(mut entry) if !self.is_expired(entry.key(), now) => {
+                entry.get_mut().merge(value);
+                self.notify(entry.key(), ChangeEvent::Set);
+                entry.get().clone()
+            }
+            entry => {
+                self.deadlines.remove(entry.key());
+                let value = entry.insert(value);
+                self.notify(value.key(), ChangeEvent::Set);
+                value.clone()
+            }
+        };
+        self.replaced(expiring);
+        Ok(merged)
+    }
+}
+
+// Appends under the entry's lock. An expired value is replaced, the new one has no TTL.
+impl<K: Hash + Eq + Clone, V: Clone + Append, S: BuildHasher + Clone> AppendStorage<K, V>
+    for DashMapStorage<K, V, S>
+{
+    fn append_to(&self, key: K, value: V) -> Result<usize, Err> {
+        self.closed.check()?;
+        let now = Instant::now();
+        let entry = self.dash.entry(key);
+        let expiring = self.expiring(&entry, now);
+        let len = match entry {
+            Entry::Occupied(mut entry) if !self.is_expired(entry.key(), now) => {
+                entry.get_mut().concat(value);
+                self.notify(entry.key(), ChangeEvent::Set);
+                entry.get().size()
+            }
+            entry => {
+                self.deadlines.remove(entry.key());
+                let value = entry.insert(value);
+                self.notify(value.key(), ChangeEvent::Set);
+                value.value().size()
+            }
+        };
+        self.replaced(expiring);
+        Ok(len)
+    }
+}
+
+// Both run under the entry's lock, so `fetch_update` never retries. Expired values count as
+// missing.
+impl<K, V, S: BuildHasher + Clone> AtomicStorage<K, V> for DashMapStorage<K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone + PartialEq,
+{
+    fn compare_and_swap(&self, key: K, expected: Option<V>, new: Option<V>) -> Result<bool, Err> {
+        self.closed.check()?;
+        let (_, swapped) =
+            self.update_entry(key, |current| (current == expected.as_ref()).then_some(new));
+        Ok(swapped)
+    }
+
+    fn fetch_update<F>(&self, key: K, mut f: F) -> Result<Option<V>, Err>
+    where
+        F: FnMut(Option<&V>) -> Option<V>,
+    {
+        self.closed.check()?;
+        Ok(self.update_entry(key, |current| Some(f(current))).0)
+    }
+}
+
+// `f` runs once, holding the entry's lock, so it must not use the storage itself.
+impl<K, V, S: BuildHasher + Clone> UpdateStorage<K, V> for DashMapStorage<K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    fn update<F>(&self, key: K, mut f: F) -> Result<bool, Err>
+    where
+        F: FnMut(&mut V),
+    {
+        self.closed.check()?;
+        let now = Instant::now();
+        let Some(mut value) = self.dash.get_mut(&key) else {
+            return Ok(false);
+        };
+        if self.is_expired(&key, now) {
+            drop(value);
+            self.purge(&key, now);
+            return Ok(false);
+        }
+        f(value.value_mut());
+        self.notify(&key, ChangeEvent::Set);
+        Ok(true)
+    }
+}
+
+// A key gets the next number of a storage-wide counter when it is read with its version, and
+// loses it on any write through the storage. The version is handed out while the value's
+// shard is locked and checked under the entry's lock, so no write slips in between. A value
+// without a version was written since it was read, and matches no `expected`.
+impl<K, V, S: BuildHasher + Clone> VersionedStorage<K, V> for DashMapStorage<K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    fn get_versioned(&self, key: K) -> Result<Option<(V, Version)>, Err> {
+        self.closed.check()?;
+        self.versioned.store(true, Ordering::Relaxed);
+        let now = Instant::now();
+        if self.is_expired(&key, now) {
+            self.purge(&key, now);
+            return Ok(None);
+        }
+        let Some(value) = self.dash.get(&key) else {
+            return Ok(None);
+        };
+        let version = *self
+            .versions
+            .entry(key)
+            .or_insert_with(|| self.next_version.fetch_add(1, Ordering::Relaxed) + 1);
+        Ok(Some((value.value().clone(), Version::from_u64(version))))
+    }
+
+    fn set_if_version(&self, key: K, value: V, expected: Option<Version>) -> Result<bool, Err> {
+        self.closed.check()?;
+        self.versioned.store(true, Ordering::Relaxed);
+        let versions = &self.versions;
+        let versioned = key.clone();
+        let (_, written) = self.update_entry(key, |current| {
+            let current = current.map(|_| versions.get(&versioned).map(|v| *v));
+            let matches = match expected {
+                Some(expected) => current == Some(Some(expected.as_u64())),
+                None => current.is_none(),
+            };
+            matches.then_some(Some(value))
+        });
+        Ok(written)
+    }
+}
+
+// Updated under the entry's lock. An expired counter restarts from 0 without a TTL.
+impl<K: Hash + Eq + Clone, S: BuildHasher + Clone> CounterStorage<K> for DashMapStorage<K, i64, S> {
+    fn incr(&self, key: K, delta: i64) -> Result<i64, Err> {
+        self.closed.check()?;
+        let now = Instant::now();
+        let entry = self.dash.entry(key);
+        let expiring = self.expiring(&entry, now);
+        match entry {
+            Entry::Occupied(mut entry) if !self.is_expired(entry.key(), now) => {
+                let value = entry
+                    .get()
+                    .checked_add(delta)
+                    .ok_or("increment would overflow")?;
+                entry.insert(value);
+                self.notify(entry.key(), ChangeEvent::Set);
+                Ok(value)
+            }
+            entry => {
+                self.deadlines.remove(entry.key());
+                self.notify(entry.insert(delta).key(), ChangeEvent::Set);
+                self.replaced(expiring);
+                Ok(delta)
+            }
+        }
+    }
+
+    fn incr_within(
+        &self,
+        key: K,
+        delta: i64,
+        max: i64,
+        expire: Duration,
+    ) -> Result<(i64, bool), Err> {
+        self.closed.check()?;
+        let now = Instant::now();
+        let entry = self.dash.entry(key);
+        let expiring = self.expiring(&entry, now);
+        match entry {
+            Entry::Occupied(mut entry) if !self.is_expired(entry.key(), now) => {
+                let current = *entry.get();
+                let value = current
+                    .checked_add(delta)
+                    .ok_or("increment would overflow")?;
+                if value > max {
+                    return Ok((current, false));
+                }
+                entry.insert(value);
+                self.notify(entry.key(), ChangeEvent::Set);
+                Ok((value, true))
+            }
+            // An expired entry above the cap is left for the next purge.
+            _ if delta > max => Ok((0, false)),
+            entry => {
+                self.deadlines.insert(entry.key().clone(), now + expire);
+                self.notify(entry.insert(delta).key(), ChangeEvent::Set);
+                self.replaced(expiring);
+                Ok((delta, true))
+            }
+        }
+    }
+}
+
+// Helpers for values that are structures of their own, e.g. lists or streams.
+impl<K: Hash + Eq + Clone, V: Clone, S: BuildHasher + Clone> DashMapStorage<K, V, S> {
+    // Runs `f` on the key's value under the entry's lock, keeping its TTL. `empty` stands in
+    // for a missing or expired value, without it that is `Ok(None)`. A value `drop` is true for
+    // afterwards is removed.
+    fn modify<T, F>(
+        &self,
+        key: K,
+        empty: Option<V>,
+        drop: fn(&V) -> bool,
+        f: F,
+    ) -> Result<Option<T>, Err>
+    where
+        F: FnOnce(&mut V) -> Result<T, Err>,
+    {
+        self.closed.check()?;
+        let now = Instant::now();
+        let mut expiring = None;
+        // Scoped, so the entry is unlocked before `on_expire` runs.
+        let result = {
+            let mut entry = match self.dash.entry(key) {
+                Entry::Occupied(entry) if !self.is_expired(entry.key(), now) => entry,
+                Entry::Occupied(mut entry) => {
+                    self.deadlines.remove(entry.key());
+                    self.notify(entry.key(), ChangeEvent::Expired);
+                    let Some(empty) = empty else {
+                        let (key, value) = entry.remove_entry();
+                        self.expired(&key, &value);
+                        return Ok(None);
+                    };
+                    let value = entry.insert(empty);
+                    if self.on_expire.is_some() {
+                        expiring = Some((entry.key().clone(), value));
+                    }
+                    entry
+                }
+                Entry::Vacant(entry) => match empty {
+                    Some(empty) => entry.insert_entry(empty),
+                    None => return Ok(None),
+                },
+            };
+            let result = f(entry.get_mut());
+            if drop(entry.get()) {
+                self.deadlines.remove(entry.key());
+                self.notify(entry.key(), ChangeEvent::Del);
+                entry.remove();
+            } else if result.is_ok() {
+                self.notify(entry.key(), ChangeEvent::Set);
+            }
+            result
+        };
+        self.replaced(expiring);
+        result.map(Some)
+    }
+
+    fn read<T, F>(&self, key: K, f: F) -> Result<Option<T>, Err>
+    where
+        F: FnOnce(&V) -> Result<T, Err>,
+    {
+        self.closed.check()?;
+        self.purge(&key, Instant::now());
+        self.dash
+            .get(&key)
+            .map(|entry| f(entry.value()))
+            .transpose()
+    }
+}
+
+fn as_list<V>(collection: &mut Collection<V>) -> Result<&mut VecDeque<V>, Err> {
+    match collection {
+        Collection::List(list) => Ok(list),
+        Collection::Set(_) => Err("key holds a set, not a list".into()),
+    }
+}
+
+fn as_set<V>(collection: &mut Collection<V>) -> Result<&mut HashSet<V>, Err> {
+    match collection {
+        Collection::Set(set) => Ok(set),
+        Collection::List(_) => Err("key holds a list, not a set".into()),
+    }
+}
+
+// Collections are values of the map, so `get`, `del` and `expire` work on them as a whole.
+// Reads clone nothing but what they return.
+impl<K, V, S> CollectionStorage<K, V> for DashMapStorage<K, Collection<V>, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone + Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    fn push(&self, key: K, value: V) -> Result<usize, Err> {
+        let empty = Collection::List(VecDeque::new());
+        let len = self.modify(key, Some(empty), Collection::is_empty, |collection| {
+            let list = as_list(collection)?;
+            list.push_back(value);
+            Ok(list.len())
+        })?;
+        Ok(len.unwrap_or_default())
+    }
+
+    fn push_front(&self, key: K, value: V) -> Result<usize, Err> {
+        let empty = Collection::List(VecDeque::new());
+        let len = self.modify(key, Some(empty), Collection::is_empty, |collection| {
+            let list = as_list(collection)?;
+            list.push_front(value);
+            Ok(list.len())
+        })?;
+        Ok(len.unwrap_or_default())
+    }
+
+    fn pop(&self, key: K) -> Result<Option<V>, Err> {
+        let popped = self.modify(key, None, Collection::is_empty, |collection| {
+            Ok(as_list(collection)?.pop_front())
+        })?;
+        Ok(popped.flatten())
+    }
+
+    fn pop_back(&self, key: K) -> Result<Option<V>, Err> {
+        let popped = self.modify(key, None, Collection::is_empty, |collection| {
+            Ok(as_list(collection)?.pop_back())
+        })?;
+        Ok(popped.flatten())
+    }
+
+    fn list(&self, key: K) -> Result<Vec<V>, Err> {
+        let list = self.read(key, |collection| match collection {
+            Collection::List(list) => Ok(list.iter().cloned().collect()),
+            Collection::Set(_) => Err("key holds a set, not a list".into()),
+        })?;
+        Ok(list.unwrap_or_default())
+    }
+
+    fn list_len(&self, key: K) -> Result<usize, Err> {
+        let len = self.read(key, |collection| match collection {
+            Collection::List(list) => Ok(list.len()),
+            Collection::Set(_) => Err("key holds a set, not a list".into()),
+        })?;
+        Ok(len.unwrap_or_default())
+    }
+
+    fn add_member(&self, key: K, member: V) -> Result<bool, Err> {
+        let empty = Collection::Set(HashSet::new());
+        let added = self.modify(key, Some(empty), Collection::is_empty, |collection| {
+            Ok(as_set(collection)?.insert(member))
+        })?;
+        Ok(added.unwrap_or_default())
+    }
+
+    fn remove_member(&self, key: K, member: V) -> Result<bool, Err> {
+        let removed = self.modify(key, None, Collection::is_empty, |collection| {
+            Ok(as_set(collection)?.remove(&member))
+        })?;
+        Ok(removed.unwrap_or_default())
+    }
+
+    fn is_member(&self, key: K, member: V) -> Result<bool, Err> {
+        let found = self.read(key, |collection| match collection {
+            Collection::Set(set) => Ok(set.contains(&member)),
+            Collection::List(_) => Err("key holds a list, not a set".into()),
+        })?;
+        Ok(found.unwrap_or_default())
+    }
+
+    fn members(&self, key: K) -> Result<Vec<V>, Err> {
+        let members = self.read(key, |collection| match collection {
+            Collection::Set(set) => Ok(set.iter().cloned().collect()),
+            Collection::List(_) => Err("key holds a list, not a set".into()),
+        })?;
+        Ok(members.unwrap_or_default())
+    }
+}
+
+// Streams are values of the map and aren't removed when they run empty, their groups stay.
+impl<K, S> StreamStorage<K> for DashMapStorage<K, Stream, S>
+where
+    K: Hash + Eq + Clone,
+    S: BuildHasher + Clone,
+{
+    fn append(&self, stream: K, fields: Vec<(String, String)>) -> Result<String, Err> {
+        let id = self.modify(
+            stream,
+            Some(Stream::default()),
+            |_| false,
+            |stream| Ok(stream.append(fields)),
+        )?;
+        Ok(id.unwrap_or_default())
+    }
+
+    fn read_from(&self, stream: K, after: &str, count: usize) -> Result<Vec<StreamEntry>, Err> {
+        let entries = self.read(stream, |stream| stream.read_from(after, count))?;
+        Ok(entries.unwrap_or_default())
+    }
+
+    fn stream_len(&self, stream: K) -> Result<usize, Err> {
+        Ok(self
+            .read(stream, |stream| Ok(stream.len()))?
+            .unwrap_or_default())
+    }
+
+    fn trim(&self, stream: K, max_len: usize) -> Result<usize, Err> {
+        let trimmed = self.modify(stream, None, |_| false, |stream| Ok(stream.trim(max_len)))?;
+        Ok(trimmed.unwrap_or_default())
+    }
+
+    fn create_group(&self, stream: K, group: &str, from: &str) -> Result<bool, Err> {
+        let created = self.modify(
+            stream,
+            Some(Stream::default()),
+            |_| false,
+            |stream| stream.create_group(group, from),
+        )?;
+        Ok(created.unwrap_or_default())
+    }
+
+    fn read_group(
+        &self,
+        stream: K,
+        group: &str,
+        consumer: &str,
+        count: usize,
+    ) -> Result<Vec<StreamEntry>, Err> {
+        self.modify(
+            stream,
+            None,
+            |_| false,
+            |stream| stream.read_group(group, consumer, count),
+        )?
+        .ok_or_else(|| no_group(group))
+    }
+
+    fn read_pending(
+        &self,
+        stream: K,
+        group: &str,
+        consumer: &str,
+        count: usize,
+    ) -> Result<Vec<StreamEntry>, Err> {
+        self.read(stream, |stream| stream.read_pending(group, consumer, count))?
+            .ok_or_else(|| no_group(group))
+    }
+
+    fn ack(&self, stream: K, group: &str, ids: Vec<String>) -> Result<usize, Err> {
+        let acked = self.modify(stream, None, |_| false, |stream| stream.ack(group, &ids))?;
+        Ok(acked.unwrap_or_default())
+    }
+}
+
+// Locks are only shared by handles of this storage, not by a map shared through `from_arc`.
+// An expired lock is free to take and can't be renewed or released.
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> LockStorage<K> for DashMapStorage<K, V, S> {
+    fn try_acquire(&self, key: K, token: &str, ttl: Duration) -> Result<bool, Err> {
+        self.closed.check()?;
+        let now = Instant::now();
+        match self.locks.entry(key) {
+            Entry::Occupied(entry) if entry.get().1 > now => Ok(false),
+            entry => {
+                entry.insert((token.to_string(), now + ttl));
+                Ok(true)
+            }
+        }
+    }
+
+    fn renew(&self, key: K, token: &str, ttl: Duration) -> Result<bool, Err> {
+        self.closed.check()?;
+        let now = Instant::now();
+        match self.locks.get_mut(&key) {
+            Some(mut lock) if lock.0 == token && lock.1 > now => {
+                lock.1 = now + ttl;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn release(&self, key: K, token: &str) -> Result<bool, Err> {
+        self.closed.check()?;
+        let now = Instant::now();
+        let removed = self.locks.remove_if(&key, |_, lock| lock.0 == token);
+        Ok(removed.is_some_and(|(_, lock)| lock.1 > now))
     }
 }
 
-pub struct DashMapStorageBuilder<K, V> {
+// Every write through this storage notifies, including merges, counters and `clear`. Expired
+// entries are reported once they are purged, when read or by `gc_now`, and writes to a map
+// shared through `from_arc` that bypass the storage aren't seen.
+impl<K: Hash + Eq + Clone, V: Clone, S: BuildHasher + Clone> WatchableStorage<K>
+    for DashMapStorage<K, V, S>
+{
+    fn watch(&self, key: K) -> Result<Changes, Err> {
+        self.closed.check()?;
+        let (tx, rx) = mpsc::channel();
+        self.watchers.entry(key).or_default().push(tx);
+        self.watched.store(true, Ordering::Relaxed);
+        Ok(Changes::new(rx))
+    }
+}
+
+// Woken by the storage's own writes. Writes through `shared` aren't seen until the next
+// re-read, at most a second later.
+impl<K: Hash + Eq + Clone, V: Clone, S: BuildHasher + Clone> WaitStorage<K, V>
+    for DashMapStorage<K, V, S>
+{
+    fn wait_for(&self, key: K, timeout: Duration) -> Result<Option<V>, Err> {
+        wait_watched(self, key, timeout)
+    }
+}
+
+// Values live on the heap of the process, their memory isn't known. Shards are read one at a
+// time.
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> StorageStats for DashMapStorage<K, V, S> {
+    fn stats(&self) -> Result<StorageInfo, Err> {
+        self.closed.check()?;
+        let mut info = StorageInfo {
+            keys: self.dash.len() as u64,
+            ..StorageInfo::default()
+        };
+        info.detail("expiring", self.deadlines.len());
+        info.detail("locks", self.locks.len());
+        info.detail("watched_keys", self.watchers.len());
+        info.detail("shards", self.dash.shards().len());
+        for (i, shard) in self.dash.shards().iter().enumerate() {
+            info.detail(&format!("shard.{}.len", i), shard.read().len());
+        }
+        Ok(info)
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DashMapStorageBuilder<K, V, S = RandomState> {
     capacity: Option<usize>,
+    shard_amount: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hasher: S,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    on_expire: Option<ExpireHook<K, V>>,
+    #[cfg(feature = "serde")]
+    #[serde(skip)]
+    persist_to: Option<StartPersistence<K, V, S>>,
+    #[cfg(feature = "serde")]
+    #[serde(with = "crate::config::secs")]
+    flush_interval: Duration,
+    #[cfg_attr(feature = "serde", serde(with = "crate::config::secs::option"))]
+    default_ttl: Option<Duration>,
+    ttl_jitter: Option<Jitter>,
+    #[cfg_attr(feature = "serde", serde(skip))]
     _marker: PhantomData<(K, V)>,
 }
 
-#[allow(unused)]
 impl<K: Hash + Eq, V: Clone> DashMapStorageBuilder<K, V> {
     pub fn new() -> Self {
         DashMapStorageBuilder::default()
     }
+}
+
+impl<K: Hash + Eq, V: Clone, S: BuildHasher + Clone> DashMapStorageBuilder<K, V, S> {
+    // Hashes keys with `hasher` instead of the standard SipHash, e.g. a faster one for small
+    // integer keys. Only pick a hasher without DoS resistance if callers can't choose keys.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self {
+            capacity: None,
+            shard_amount: None,
+            hasher,
+            hooks: Hooks::default(),
+            on_expire: None,
+            #[cfg(feature = "serde")]
+            persist_to: None,
+            #[cfg(feature = "serde")]
+            flush_interval: Duration::from_secs(30),
+            default_ttl: None,
+            ttl_jitter: None,
+            _marker: PhantomData,
+        }
+    }
 
     pub fn capacity(mut self, capacity: usize) -> Self {
         self.capacity = Some(capacity);
         self
     }
 
-    pub fn build(self) -> DashMapStorage<K, V> {
-        DashMapStorage {
-            dash: self.capacity.map_or(DashMap::<K, V>::new(), |c| {
-                DashMap::<K, V>::with_capacity(c)
-            }),
+    // Number of independently locked shards, a power of two above 1. DashMap picks four per
+    // CPU by default; more shards mean writers contend less.
+    pub fn shard_amount(mut self, shard_amount: usize) -> Self {
+        self.shard_amount = Some(shard_amount);
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their batch and TTL
+    // variants, in the order they were added. The capability traits (`merge`, `incr`,
+    // compare-and-swap, ...) don't fire them.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // Called with each expired entry as the storage removes it, which happens when it is read
+    // after its deadline, collected by `gc_now`, e.g. from a `GcTask`, or overwritten by any
+    // write. A `clear` or `flush_namespace` reports the expired entries it drops too. Writes
+    // to a map shared through `from_arc` from outside the storage aren't seen. Runs outside
+    // the map's locks, so it may use the storage.
+    pub fn on_expire<F: Fn(&K, &V) + Send + Sync + 'static>(mut self, on_expire: F) -> Self {
+        self.on_expire = Some(Arc::new(on_expire));
+        self
+    }
+
+    // TTL of `set` and `set_many`, which otherwise keep keys until they are deleted.
+    pub fn default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = Some(ttl);
+        self
+    }
+
+    // Spreads every TTL written through `set`, `set_ex` and `set_many`, the default one
+    // included, so keys written together don't expire together.
+    pub fn ttl_jitter(mut self, jitter: Jitter) -> Self {
+        self.ttl_jitter = Some(jitter);
+        self
+    }
+
+    // # Panics
+    // If the shard amount isn't a power of two above 1, or with `persist_to`, if the file
+    // exists but can't be read back. Use `try_build` to get the error instead.
+    pub fn build(self) -> DashMapStorage<K, V, S> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build dashmap storage: {}", e),
         }
     }
+
+    // Restores before the hooks are set, so they don't fire for the restored entries.
+    pub fn try_build(self) -> Result<DashMapStorage<K, V, S>, BuilderError> {
+        let capacity = self.capacity.unwrap_or(0);
+        let map = match self.shard_amount {
+            Some(shard_amount) if shard_amount < 2 || !shard_amount.is_power_of_two() => {
+                return Err(BuilderError::invalid(
+                    "shard_amount",
+                    "must be a power of two greater than 1",
+                ));
+            }
+            Some(shard_amount) => DashMap::with_capacity_and_hasher_and_shard_amount(
+                capacity,
+                self.hasher,
+                shard_amount,
+            ),
+            None => DashMap::with_capacity_and_hasher(capacity, self.hasher),
+        };
+        let storage = DashMapStorage::from_map(map);
+        #[cfg(feature = "serde")]
+        let storage = match self.persist_to {
+            Some(start) => DashMapStorage {
+                persistence: Some(start(&storage, self.flush_interval)?),
+                ..storage
+            },
+            None => storage,
+        };
+        Ok(DashMapStorage {
+            hooks: self.hooks,
+            on_expire: self.on_expire,
+            ttl: TtlPolicy {
+                default_ttl: self.default_ttl,
+                jitter: self.ttl_jitter,
+            },
+            ..storage
+        })
+    }
 }
 
-impl<K, V> Default for DashMapStorageBuilder<K, V> {
+#[cfg(feature = "serde")]
+impl<K, V, S> DashMapStorageBuilder<K, V, S>
+where
+    K: Hash + Eq + Clone + Send + Sync + serde::Serialize + serde::de::DeserializeOwned + 'static,
+    V: Clone + Send + Sync + serde::Serialize + serde::de::DeserializeOwned + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    // Loads the file written by a previous run on build, if there is one, then rewrites it
+    // from a background thread every `flush_interval` and once more on `close` or drop. The
+    // format is `export_to_writer`'s. Writes that happen after the last flush are lost when
+    // the process dies; errors of background flushes are dropped, call `persist_now` to see
+    // them.
+    pub fn persist_to<P: AsRef<Path>>(mut self, path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        self.persist_to = Some(Box::new(move |storage, interval| {
+            match std::fs::File::open(&path) {
+                Ok(file) => {
+                    storage
+                        .restore_from_reader(file)
+                        .map_err(BuilderError::Backend)?;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(BuilderError::backend(e)),
+            }
+            if interval.is_zero() {
+                return Err(BuilderError::invalid("flush_interval", "must be positive"));
+            }
+
+            let view = storage.view();
+            let writing = Arc::new(Mutex::new(()));
+            let lock = writing.clone();
+            let file = path.clone();
+            let (stop, stopped) = mpsc::channel::<()>();
+            let handle = thread::Builder::new()
+                .name("storage-persist".to_string())
+                .spawn(move || loop {
+                    match stopped.recv_timeout(interval) {
+                        Err(RecvTimeoutError::Timeout) => {
+                            let _ = persist(&view, &file, &lock);
+                        }
+                        _ => {
+                            return persist(&view, &file, &lock)
+                                .map(|_| ())
+                                .map_err(|e| e.to_string())
+                        }
+                    }
+                })
+                .map_err(BuilderError::backend)?;
+            Ok(Persistence {
+                path,
+                writing,
+                stop,
+                handle: Mutex::new(Some(handle)),
+            })
+        }));
+        self
+    }
+
+    // How often `persist_to` rewrites the file, every 30 seconds by default.
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+}
+
+impl<K: Hash + Eq, V: Clone, S: BuildHasher + Clone + Default> Default
+    for DashMapStorageBuilder<K, V, S>
+{
     fn default() -> Self {
-        Self {
-            capacity: None,
-            _marker: PhantomData,
-        }
+        DashMapStorageBuilder::with_hasher(S::default())
     }
 }
 
@@ -70,11 +1631,16 @@ impl<K, V> Default for DashMapStorageBuilder<K, V> {
 mod tests {
     use super::*;
 
+    crate::storage_conformance_tests!(
+        conformance,
+        DashMapStorageBuilder::<String, String>::new().build()
+    );
+
     #[test]
     fn test_contains() {
         let storage = DashMapStorageBuilder::new().build();
 
-        let _ = storage
+        storage
             .set("name".to_string(), "Ferris".to_string())
             .unwrap();
         let _ = storage.contains("name".into()).unwrap();
@@ -93,4 +1659,752 @@ mod tests {
         let resp = storage.get(key).unwrap();
         assert_eq!(resp, None);
     }
+
+    #[test]
+    fn test_borrowed() {
+        let storage: DashMapStorage<String, u32> = DashMapStorageBuilder::new().build();
+        storage.set("name".to_string(), 1).unwrap();
+        storage
+            .set_ex("short".to_string(), 2, Duration::from_millis(10))
+            .unwrap();
+
+        assert_eq!(storage.get_borrowed("name").unwrap(), Some(1));
+        assert!(storage.contains_borrowed("name").unwrap());
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!storage.contains_borrowed("short").unwrap());
+        assert_eq!(storage.get_borrowed("short").unwrap(), None);
+
+        assert!(storage.del_borrowed("name").unwrap());
+        assert!(!storage.del_borrowed("name").unwrap());
+        assert_eq!(storage.get("name".to_string()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_export_iter() {
+        let storage = DashMapStorageBuilder::new().build();
+        for i in 0..100 {
+            storage.set(i, i.to_string()).unwrap();
+        }
+
+        let mut entries: Vec<_> = storage.export_iter().unwrap().map(Result::unwrap).collect();
+        entries.sort();
+        assert_eq!(entries.len(), 100);
+        assert_eq!(entries[42], (42, "42".to_string()));
+    }
+
+    #[test]
+    fn test_flush_namespace() {
+        let storage = DashMapStorageBuilder::new().build();
+        for key in ["tenant:1:a", "tenant:1:b", "tenant:2:a"] {
+            storage.set(key, 1).unwrap();
+        }
+
+        let wrong = FlushConfirmation::for_prefix("tenant:");
+        assert!(storage.flush_namespace("tenant:1:", &wrong).is_err());
+        let confirm = FlushConfirmation::for_prefix("tenant:1:");
+        assert_eq!(storage.flush_namespace("tenant:1:", &confirm).unwrap(), 2);
+        assert!(storage.contains("tenant:2:a").unwrap());
+    }
+
+    #[test]
+    fn test_many() {
+        let storage = DashMapStorageBuilder::new().build();
+        storage.set_many(vec![("a", 1), ("b", 2)]).unwrap();
+        assert_eq!(
+            storage.get_many(vec!["a", "b", "c"]).unwrap(),
+            [Some(1), Some(2), None]
+        );
+        assert_eq!(storage.del_many(vec!["a", "c"]).unwrap(), [Some("a"), None]);
+        assert!(!storage.contains("a").unwrap());
+    }
+
+    #[test]
+    fn test_set_ex() {
+        let storage = DashMapStorageBuilder::new().build();
+        let (key, value) = ("set_ex_test", "ok!".to_string());
+        storage
+            .set_ex(key, value.clone(), Duration::from_millis(50))
+            .unwrap();
+        storage
+            .set_ex("renewed", value.clone(), Duration::from_millis(50))
+            .unwrap();
+        storage.set("renewed", value.clone()).unwrap();
+        assert_eq!(storage.get(key).unwrap(), Some(value.clone()));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(storage.get(key).unwrap(), None);
+        assert!(storage.contains("renewed").unwrap());
+        assert!(storage.shared().get(key).is_none());
+    }
+
+    #[test]
+    fn test_gc_now() {
+        let storage = DashMapStorageBuilder::new().build();
+        for i in 0..3 {
+            storage.set_ex(i, i, Duration::from_millis(10)).unwrap();
+        }
+        storage.set(3, 3).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(storage.gc_now(2).unwrap(), 2);
+        assert_eq!(storage.gc_now(2).unwrap(), 1);
+        assert_eq!(storage.shared().len(), 1);
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let storage = DashMapStorageBuilder::new().build();
+        assert!(storage.compare_and_swap("a", None, Some(1)).unwrap());
+        assert!(!storage.compare_and_swap("a", Some(2), Some(3)).unwrap());
+        assert!(storage.compare_and_swap("a", Some(1), None).unwrap());
+        assert!(!storage.contains("a").unwrap());
+
+        storage.set_ex("b", 1, Duration::from_millis(10)).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!storage.compare_and_swap("b", Some(1), Some(2)).unwrap());
+        assert!(storage.compare_and_swap("b", None, Some(2)).unwrap());
+    }
+
+    #[test]
+    fn test_default_ttl() {
+        let storage = DashMapStorageBuilder::new()
+            .default_ttl(Duration::from_secs(60))
+            .ttl_jitter(Jitter::Ratio(0.5))
+            .build();
+        storage.set("a", 1).unwrap();
+        storage.set_many(vec![("b", 2)]).unwrap();
+        storage.set_ex("c", 3, Duration::from_secs(600)).unwrap();
+        for (key, max) in [("a", 90), ("b", 90), ("c", 900)] {
+            let ttl = storage.ttl(key).unwrap().unwrap();
+            assert!(ttl <= Duration::from_secs(max) && ttl >= Duration::from_secs(max / 3));
+        }
+        let plain = DashMapStorageBuilder::new().build();
+        plain.set("a", 1).unwrap();
+        assert!(plain.ttl("a").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_swap_take() {
+        let storage = Arc::new(DashMapStorageBuilder::<&str, u32>::new().build());
+        assert_eq!(storage.swap("a", 1).unwrap(), None);
+        assert_eq!(storage.swap("a", 2).unwrap(), Some(1));
+
+        // Only one of the racing takes claims the token.
+        storage.set("token", 7).unwrap();
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let storage = storage.clone();
+                std::thread::spawn(move || storage.take("token").unwrap())
+            })
+            .collect();
+        let claimed: Vec<u32> = handles
+            .into_iter()
+            .filter_map(|handle| handle.join().unwrap())
+            .collect();
+        assert_eq!(claimed, [7]);
+        assert!(!storage.contains("token").unwrap());
+    }
+
+    #[test]
+    fn test_append() {
+        let storage = DashMapStorageBuilder::new().build();
+        assert_eq!(storage.append_to("log", "a".to_string()).unwrap(), 1);
+        storage.expire("log", Duration::from_secs(60)).unwrap();
+        assert_eq!(storage.append_to("log", "bc".to_string()).unwrap(), 3);
+        assert_eq!(storage.get("log").unwrap(), Some("abc".to_string()));
+        assert!(storage.ttl("log").unwrap().is_some());
+
+        let bytes = DashMapStorageBuilder::<&str, Vec<u8>>::new().build();
+        bytes.append_to("buf", vec![1]).unwrap();
+        assert_eq!(bytes.append_to("buf", vec![2, 3]).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_versioned() {
+        let storage = DashMapStorageBuilder::new().build();
+        assert!(storage.set_if_version("a", 1, None).unwrap());
+        let (value, version) = storage.get_versioned("a").unwrap().unwrap();
+        assert_eq!(value, 1);
+        // A plain write in between changes the version too, even of an equal value.
+        storage.set("a", 1).unwrap();
+        assert!(!storage.set_if_version("a", 3, Some(version)).unwrap());
+        let (_, newer) = storage.get_versioned("a").unwrap().unwrap();
+        assert!(newer.as_u64() > version.as_u64());
+        assert_eq!(storage.get_versioned("a").unwrap().unwrap().1, newer);
+        assert!(storage.set_if_version("a", 3, Some(newer)).unwrap());
+        assert!(!storage.set_if_version("a", 4, Some(newer)).unwrap());
+        assert!(!storage.set_if_version("a", 4, None).unwrap());
+        assert_eq!(storage.get("a").unwrap(), Some(3));
+
+        // Versions keep growing after a delete.
+        storage.del("a").unwrap();
+        assert!(storage.set_if_version("a", 5, None).unwrap());
+        let (_, recreated) = storage.get_versioned("a").unwrap().unwrap();
+        assert!(recreated.as_u64() > newer.as_u64());
+    }
+
+    #[test]
+    fn test_fetch_update() {
+        let storage = Arc::new(DashMapStorageBuilder::<&str, u32>::new().build());
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let storage = storage.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        storage
+                            .fetch_update("hits", |n| Some(n.map_or(1, |n| n + 1)))
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(storage.get("hits").unwrap(), Some(400));
+    }
+
+    #[test]
+    fn test_update() {
+        let storage = Arc::new(DashMapStorageBuilder::<&str, Vec<u32>>::new().build());
+        assert!(!storage.update("list", |list| list.push(1)).unwrap());
+        assert_eq!(storage.get("list").unwrap(), None);
+
+        storage
+            .set_ex("list", Vec::new(), Duration::from_secs(60))
+            .unwrap();
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let storage = storage.clone();
+                std::thread::spawn(move || {
+                    for j in 0..100 {
+                        assert!(storage
+                            .update("list", |list| list.push(i * 100 + j))
+                            .unwrap());
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(storage.get("list").unwrap().unwrap().len(), 400);
+        // The TTL is kept.
+        assert!(storage.ttl("list").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_incr() {
+        let storage = DashMapStorageBuilder::new().build();
+        assert_eq!(storage.incr("hits", 2).unwrap(), 2);
+        assert_eq!(storage.decr("hits", 5).unwrap(), -3);
+        storage.set("max", i64::MAX).unwrap();
+        assert!(storage.incr("max", 1).is_err());
+        assert_eq!(storage.get("max").unwrap(), Some(i64::MAX));
+
+        storage
+            .set_ex("window", 7, Duration::from_millis(10))
+            .unwrap();
+        assert_eq!(storage.incr("window", 1).unwrap(), 8);
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(storage.incr("window", 1).unwrap(), 1);
+
+        let expire = Duration::from_millis(10);
+        assert_eq!(
+            storage.incr_within("capped", 2, 3, expire).unwrap(),
+            (2, true)
+        );
+        assert_eq!(
+            storage.incr_within("capped", 2, 3, expire).unwrap(),
+            (2, false)
+        );
+        assert_eq!(
+            storage.incr_within("capped", 1, 3, expire).unwrap(),
+            (3, true)
+        );
+        assert_eq!(
+            storage.incr_within("empty", 4, 3, expire).unwrap(),
+            (0, false)
+        );
+        assert!(!storage.contains("empty").unwrap());
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(storage.get("capped").unwrap(), None);
+    }
+
+    #[test]
+    fn test_scan_prefix() {
+        let storage = DashMapStorageBuilder::new().build();
+        for key in ["user:1", "user:2", "order:1"] {
+            storage.set(key, 1).unwrap();
+        }
+        storage
+            .set_ex("user:3", 1, Duration::from_millis(10))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+
+        let mut keys: Vec<&str> = storage
+            .scan_prefix("user:")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        keys.sort();
+        assert_eq!(keys, ["user:1", "user:2"]);
+        for key in storage.scan_prefix("").unwrap() {
+            storage.del(key.unwrap()).unwrap();
+        }
+        assert_eq!(storage.scan_prefix("").unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_expire() {
+        let storage = DashMapStorageBuilder::new().build();
+        storage.set("a", 1).unwrap();
+        assert_eq!(storage.ttl("a").unwrap(), None);
+        assert!(!storage.persist("a").unwrap());
+
+        assert!(storage.expire("a", Duration::from_secs(60)).unwrap());
+        assert!(storage.ttl("a").unwrap().unwrap() > Duration::from_secs(59));
+        assert!(storage.persist("a").unwrap());
+        assert_eq!(storage.ttl("a").unwrap(), None);
+
+        assert!(storage.expire("a", Duration::from_millis(10)).unwrap());
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!storage.contains("a").unwrap());
+        assert!(!storage.expire("a", Duration::from_secs(60)).unwrap());
+        assert!(!storage.expire("missing", Duration::from_secs(60)).unwrap());
+    }
+
+    #[test]
+    fn test_clear() {
+        let storage = DashMapStorageBuilder::new().build();
+        assert!(storage.is_empty().unwrap());
+        storage.set("a", 1).unwrap();
+        storage.set_ex("b", 2, Duration::from_millis(10)).unwrap();
+        assert_eq!(storage.len().unwrap(), 2);
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(storage.len().unwrap(), 1);
+
+        storage.clear().unwrap();
+        assert!(storage.is_empty().unwrap());
+        assert!(storage.shared().is_empty());
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        let storage = Arc::new(DashMapStorageBuilder::<&str, u32>::new().build());
+        let computed = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let (storage, computed) = (storage.clone(), computed.clone());
+                std::thread::spawn(move || {
+                    storage
+                        .get_or_insert_with("config", || {
+                            computed.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                            i
+                        })
+                        .unwrap()
+                })
+            })
+            .collect();
+        let values: Vec<u32> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert!(values.iter().all(|value| *value == values[0]));
+        assert_eq!(computed.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        assert!(!storage.set_nx("config", 9, None).unwrap());
+        let value = storage
+            .get_or_insert_with_ex("session", Duration::from_millis(10), || 1)
+            .unwrap();
+        assert_eq!(value, 1);
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(storage.set_nx("session", 2, None).unwrap());
+    }
+
+    #[test]
+    fn test_from_arc() {
+        let map = Arc::new(DashMap::new());
+        map.insert("name", "Ferris".to_string());
+
+        let storage = DashMapStorage::from_arc(map.clone());
+        assert_eq!(storage.get("name").unwrap(), Some("Ferris".to_string()));
+
+        storage.set("lang", "Rust".to_string()).unwrap();
+        assert_eq!(map.get("lang").map(|v| v.clone()), Some("Rust".to_string()));
+    }
+
+    #[test]
+    fn test_hooks() {
+        // Broadcasts invalidations: every write or delete is sent on.
+        struct Invalidate(std::sync::Mutex<std::sync::mpsc::Sender<&'static str>>);
+
+        impl StorageHook<&'static str, u32> for Invalidate {
+            fn on_set(&self, key: &&'static str, _value: &u32) {
+                self.0.lock().unwrap().send(key).unwrap();
+            }
+
+            fn on_del(&self, key: &&'static str) {
+                self.0.lock().unwrap().send(key).unwrap();
+            }
+        }
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let storage = DashMapStorageBuilder::new()
+            .hook(Invalidate(std::sync::Mutex::new(tx)))
+            .build();
+        storage.set("a", 1).unwrap();
+        storage.get("a").unwrap();
+        storage.set_many(vec![("b", 2), ("c", 3)]).unwrap();
+        storage.del("a").unwrap();
+        storage.close().unwrap();
+        assert!(storage.set("d", 4).is_err());
+
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), ["a", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn test_on_expire() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let tx = std::sync::Mutex::new(tx);
+        let storage = DashMapStorageBuilder::new()
+            .on_expire(move |key: &&str, value: &u32| {
+                tx.lock().unwrap().send((*key, *value)).unwrap()
+            })
+            .build();
+        let ttl = Duration::from_millis(10);
+        storage.set_ex("session:a", 1, ttl).unwrap();
+        storage.set_ex("session:b", 2, ttl).unwrap();
+        storage.set_ex("session:c", 3, ttl).unwrap();
+        storage.set_ex("session:d", 4, ttl).unwrap();
+        storage.set_ex("session:e", 5, ttl).unwrap();
+        storage.set("kept", 6).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+
+        // Read, deleted, overwritten and swept expirations are all reported, once each.
+        assert_eq!(storage.get("session:a").unwrap(), None);
+        assert_eq!(storage.del("session:b").unwrap(), None);
+        storage.set("session:d", 7).unwrap();
+        assert!(storage.set_nx("session:e", 8, None).unwrap());
+        assert_eq!(storage.gc_now(10).unwrap(), 1);
+        assert_eq!(storage.gc_now(10).unwrap(), 0);
+        storage.del("kept").unwrap();
+        storage.set("session:d", 9).unwrap();
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            [
+                ("session:a", 1),
+                ("session:b", 2),
+                ("session:d", 4),
+                ("session:e", 5),
+                ("session:c", 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_watch() {
+        let storage = DashMapStorageBuilder::<&str, i64>::new().build();
+        let changes = storage.watch("config").unwrap();
+        let dropped = storage.watch("config").unwrap();
+        drop(dropped);
+
+        storage.set("other", 1).unwrap();
+        storage.set("config", 1).unwrap();
+        storage.incr("config", 1).unwrap();
+        storage.del("config").unwrap();
+        storage
+            .set_ex("config", 3, Duration::from_millis(10))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(storage.get("config").unwrap(), None);
+
+        assert_eq!(
+            changes.try_iter().collect::<Vec<_>>(),
+            [
+                ChangeEvent::Set,
+                ChangeEvent::Set,
+                ChangeEvent::Del,
+                ChangeEvent::Set,
+                ChangeEvent::Expired
+            ]
+        );
+        assert_eq!(storage.watchers.get("config").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_wait_for() {
+        let storage = Arc::new(DashMapStorageBuilder::<&str, u32>::new().build());
+        assert_eq!(
+            storage.wait_for("job", Duration::from_millis(20)).unwrap(),
+            None
+        );
+
+        let writer = storage.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            writer.set("other", 1).unwrap();
+            writer.set("job", 2).unwrap();
+        });
+        let start = Instant::now();
+        assert_eq!(
+            storage.wait_for("job", Duration::from_secs(5)).unwrap(),
+            Some(2)
+        );
+        // Woken by the write, not by the re-read a second later.
+        assert!(start.elapsed() < Duration::from_millis(500));
+        handle.join().unwrap();
+        // A value already there comes back right away.
+        assert_eq!(storage.wait_for("job", Duration::ZERO).unwrap(), Some(2));
+    }
+
+    #[test]
+    fn test_lock() {
+        let storage = Arc::new(DashMapStorageBuilder::<&str, u32>::new().build());
+        let guard = storage.lock("job", Duration::from_secs(5)).unwrap();
+        assert!(storage
+            .try_lock("job", Duration::from_secs(5))
+            .unwrap()
+            .is_none());
+
+        // Waits for the holder to let go.
+        let waiter = {
+            let storage = storage.clone();
+            std::thread::spawn(move || {
+                storage
+                    .lock("job", Duration::from_secs(5))
+                    .unwrap()
+                    .token()
+                    .to_string()
+            })
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        let token = guard.token().to_string();
+        drop(guard);
+        assert_ne!(waiter.join().unwrap(), token);
+
+        // An expired lock is taken over, its old holder can't renew or release it.
+        let opts = crate::options::OpOptions::new().timeout(Duration::from_millis(100));
+        let stale = storage
+            .try_lock("job", Duration::from_millis(10))
+            .unwrap()
+            .unwrap();
+        let _held = storage
+            .lock_with_opts("job", Duration::from_secs(5), &opts)
+            .unwrap();
+        assert!(!stale.renew(Duration::from_secs(1)).unwrap());
+        assert!(!stale.release().unwrap());
+
+        let e = storage
+            .lock_with_opts("job", Duration::from_secs(5), &opts)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(e.downcast_ref(), Some(&crate::error::StorageError::Timeout));
+    }
+
+    #[test]
+    fn test_with_hasher() {
+        type Hasher = std::hash::BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+        let storage = DashMapStorageBuilder::<u64, u32, Hasher>::with_hasher(Hasher::default())
+            .shard_amount(8)
+            .build();
+        storage.set(1, 10).unwrap();
+        storage.set_ex(2, 20, Duration::from_secs(60)).unwrap();
+        assert_eq!(storage.get(1).unwrap(), Some(10));
+        assert!(storage.ttl(2).unwrap().is_some());
+        assert_eq!(storage.del(1).unwrap(), Some(1));
+
+        for shard_amount in [0, 1, 12] {
+            assert!(DashMapStorageBuilder::<u64, u32>::new()
+                .shard_amount(shard_amount)
+                .try_build()
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_get_ref() {
+        let storage = DashMapStorageBuilder::<&str, Vec<u8>>::new().build();
+        storage.set("blob", vec![7; 1024]).unwrap();
+        storage
+            .set_ex("gone", vec![1], Duration::from_millis(1))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+
+        let blob = storage.get_ref(&"blob").unwrap().unwrap();
+        assert_eq!(blob.len(), 1024);
+        assert_eq!(*blob.key(), "blob");
+        drop(blob);
+        assert!(storage.get_ref(&"gone").unwrap().is_none());
+        assert!(storage.get_ref(&"missing").unwrap().is_none());
+        assert!(!storage.shared().contains_key("gone"));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let storage = DashMapStorageBuilder::new().build();
+        storage.set("a", 1).unwrap();
+        storage.set_ex("b", 2, Duration::from_millis(1)).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(storage.snapshot().unwrap(), [("a", 1)]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_export_restore() {
+        let storage = DashMapStorageBuilder::<String, Vec<u32>>::new().build();
+        storage.set("plain".into(), vec![1, 2]).unwrap();
+        storage
+            .set_ex("session".into(), vec![3], Duration::from_secs(60))
+            .unwrap();
+        let mut file = Vec::new();
+        assert_eq!(storage.export_to_writer(&mut file).unwrap(), 2);
+
+        let restored = DashMapStorageBuilder::<String, Vec<u32>>::new().build();
+        assert_eq!(restored.restore_from_reader(file.as_slice()).unwrap(), 2);
+        assert_eq!(restored.get("plain".into()).unwrap(), Some(vec![1, 2]));
+        let ttl = restored.ttl("session".into()).unwrap().unwrap();
+        assert!(ttl > Duration::from_secs(59));
+
+        let expired = br#"{"key":"old","value":[1],"expires_at":1}"#;
+        assert_eq!(restored.restore_from_reader(&expired[..]).unwrap(), 0);
+        let e = restored.restore_from_reader(&b"{}\n"[..]).unwrap_err();
+        assert!(e.to_string().starts_with("snapshot line 1"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_persist_to() {
+        let dir = std::env::temp_dir().join(format!("dashmap-persist-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cache.jsonl");
+        let _ = std::fs::remove_file(&path);
+
+        let build = || {
+            DashMapStorageBuilder::<String, u32>::new()
+                .persist_to(&path)
+                .flush_interval(Duration::from_millis(20))
+                .build()
+        };
+        let storage = build();
+        storage.set("a".into(), 1).unwrap();
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .contains(r#""key":"a""#));
+
+        // Dropping writes the last changes.
+        storage.set("b".into(), 2).unwrap();
+        drop(storage);
+        let storage = build();
+        assert_eq!(storage.get("b".into()).unwrap(), Some(2));
+        storage.del("a".into()).unwrap();
+        storage.close().unwrap();
+
+        let storage = build();
+        assert_eq!(storage.snapshot().unwrap(), [("b".to_string(), 2)]);
+        assert_eq!(storage.persist_now().unwrap(), 1);
+        drop(storage);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(DashMapStorageBuilder::<String, u32>::new()
+            .persist_to(&path)
+            .try_build()
+            .is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stats() {
+        let storage = DashMapStorageBuilder::<String, u32>::new()
+            .shard_amount(4)
+            .build();
+        storage.set("a".into(), 1).unwrap();
+        storage
+            .set_ex("b".into(), 2, Duration::from_secs(60))
+            .unwrap();
+        let stats = storage.stats().unwrap();
+        assert_eq!(stats.keys, 2);
+        assert_eq!(stats.memory_bytes, None);
+        assert_eq!(stats.details["shards"], "4");
+        assert_eq!(stats.details["expiring"], "1");
+        let sharded: u64 = (0..4)
+            .map(|i| {
+                stats.details[&format!("shard.{}.len", i)]
+                    .parse::<u64>()
+                    .unwrap()
+            })
+            .sum();
+        assert_eq!(sharded, 2);
+    }
+
+    #[test]
+    fn test_collections() {
+        let storage = DashMapStorageBuilder::<&str, Collection<u32>>::new().build();
+        assert_eq!(storage.push("queue", 1).unwrap(), 1);
+        assert_eq!(storage.push("queue", 2).unwrap(), 2);
+        assert_eq!(storage.push_front("queue", 0).unwrap(), 3);
+        assert_eq!(storage.list("queue").unwrap(), [0, 1, 2]);
+        assert_eq!(storage.pop("queue").unwrap(), Some(0));
+        assert_eq!(storage.pop_back("queue").unwrap(), Some(2));
+        assert_eq!(storage.list_len("queue").unwrap(), 1);
+        assert_eq!(storage.pop("queue").unwrap(), Some(1));
+        // The last element takes the key with it.
+        assert!(!storage.contains("queue").unwrap());
+        assert_eq!(storage.pop("queue").unwrap(), None);
+
+        assert!(storage.add_member("tags", 7).unwrap());
+        assert!(!storage.add_member("tags", 7).unwrap());
+        assert!(storage.add_member("tags", 8).unwrap());
+        let mut members = storage.members("tags").unwrap();
+        members.sort();
+        assert_eq!(members, [7, 8]);
+        assert!(storage.is_member("tags", 8).unwrap());
+        assert!(storage.remove_member("tags", 8).unwrap());
+        assert!(!storage.remove_member("tags", 8).unwrap());
+        assert!(storage.push("tags", 1).is_err());
+        assert!(storage.list("tags").is_err());
+        assert_eq!(storage.members("tags").unwrap(), [7]);
+    }
+
+    #[test]
+    fn test_collection_ttl() {
+        let storage = DashMapStorageBuilder::<&str, Collection<u32>>::new().build();
+        storage.push("jobs", 1).unwrap();
+        storage.expire("jobs", Duration::from_millis(50)).unwrap();
+        storage.push("jobs", 2).unwrap();
+        assert!(storage.ttl("jobs").unwrap().is_some());
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(storage.list("jobs").unwrap(), Vec::<u32>::new());
+        // An expired list doesn't count toward the next one.
+        assert_eq!(storage.push("jobs", 3).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_streams() {
+        let storage = DashMapStorageBuilder::<&str, Stream>::new().build();
+        let fields = |n: u32| vec![("n".to_string(), n.to_string())];
+        assert!(storage.read_group("jobs", "workers", "a", 1).is_err());
+        assert!(storage.create_group("jobs", "workers", "0").unwrap());
+        let first = storage.append("jobs", fields(1)).unwrap();
+        storage.append("jobs", fields(2)).unwrap();
+        assert_eq!(storage.stream_len("jobs").unwrap(), 2);
+        assert_eq!(
+            storage.read_from("jobs", &first, 10).unwrap()[0].fields,
+            fields(2)
+        );
+
+        let read = storage.read_group("jobs", "workers", "a", 10).unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(storage.ack("jobs", "workers", vec![first]).unwrap(), 1);
+        assert_eq!(
+            storage.read_pending("jobs", "workers", "a", 10).unwrap(),
+            &read[1..]
+        );
+
+        assert_eq!(storage.trim("jobs", 0).unwrap(), 2);
+        assert!(storage
+            .read_pending("jobs", "workers", "a", 10)
+            .unwrap()
+            .is_empty());
+        // The group outlives the entries.
+        assert!(!storage.create_group("jobs", "workers", "$").unwrap());
+    }
 }
@@ -0,0 +1,122 @@
+use std::time::{Duration, Instant};
+
+use crate::error::BuilderError;
+
+// When file-based backends fsync what they wrote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Durability {
+    // Leave it to the OS, a crash may lose recent writes.
+    #[default]
+    Never,
+    // Sync on the first write after this much time passed since the last sync. Nothing syncs
+    // without writes, so the last writes before a quiet period stay unsynced until the next
+    // one, except on sled, whose flusher thread runs on this period.
+    Interval(#[cfg_attr(feature = "serde", serde(with = "crate::config::secs"))] Duration),
+    // Sync once every `n` writes.
+    Batch(usize),
+    // Sync before every write returns.
+    EveryWrite,
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "jsonl",
+        feature = "toml",
+        feature = "yaml",
+        feature = "fs",
+        feature = "sled"
+    )),
+    allow(dead_code)
+)]
+impl Durability {
+    pub(crate) fn validate(&self) -> Result<(), BuilderError> {
+        match self {
+            Durability::Batch(0) => Err(BuilderError::invalid(
+                "durability",
+                "batch size must be at least 1",
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Tracks writes since the last fsync for a `Durability` policy.
+#[cfg_attr(
+    not(any(
+        feature = "jsonl",
+        feature = "toml",
+        feature = "yaml",
+        feature = "fs",
+        feature = "sled"
+    )),
+    allow(dead_code)
+)]
+#[derive(Debug)]
+pub(crate) struct SyncState {
+    durability: Durability,
+    unsynced: usize,
+    last_sync: Instant,
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "jsonl",
+        feature = "toml",
+        feature = "yaml",
+        feature = "fs",
+        feature = "sled"
+    )),
+    allow(dead_code)
+)]
+impl SyncState {
+    pub(crate) fn new(durability: Durability) -> Self {
+        SyncState {
+            durability,
+            unsynced: 0,
+            last_sync: Instant::now(),
+        }
+    }
+
+    // Records a write and returns whether the caller should fsync now.
+    pub(crate) fn record_write(&mut self) -> bool {
+        self.unsynced += 1;
+        let sync = match self.durability {
+            Durability::Never => false,
+            Durability::Interval(interval) => self.last_sync.elapsed() >= interval,
+            Durability::Batch(size) => self.unsynced >= size,
+            Durability::EveryWrite => true,
+        };
+        if sync {
+            self.synced();
+        }
+        sync
+    }
+
+    pub(crate) fn synced(&mut self) {
+        self.unsynced = 0;
+        self.last_sync = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_write() {
+        let mut state = SyncState::new(Durability::Batch(3));
+        assert_eq!(
+            (0..6).map(|_| state.record_write()).collect::<Vec<_>>(),
+            [false, false, true, false, false, true]
+        );
+
+        let mut state = SyncState::new(Durability::Never);
+        assert!(!state.record_write());
+        let mut state = SyncState::new(Durability::EveryWrite);
+        assert!(state.record_write());
+        let mut state = SyncState::new(Durability::Interval(Duration::ZERO));
+        assert!(state.record_write());
+    }
+}
@@ -0,0 +1,41 @@
+use std::env::{self, VarError};
+
+use crate::error::BuilderError;
+
+// Empty variables count as unset, that's how most deployment tools "remove" them.
+pub(crate) fn var(name: &'static str) -> Result<Option<String>, BuilderError> {
+    match env::var(name) {
+        Ok(value) if value.is_empty() => Ok(None),
+        Ok(value) => Ok(Some(value)),
+        Err(VarError::NotPresent) => Ok(None),
+        Err(VarError::NotUnicode(_)) => Err(BuilderError::invalid(name, "is not valid unicode")),
+    }
+}
+
+#[cfg(any(
+    feature = "nats",
+    feature = "vault",
+    feature = "cloudflare",
+    feature = "postgres",
+    feature = "mongodb",
+    feature = "etcd",
+    feature = "scylla"
+))]
+pub(crate) fn required(name: &'static str) -> Result<String, BuilderError> {
+    var(name)?.ok_or(BuilderError::MissingEnv(name))
+}
+
+#[cfg(any(feature = "redis", feature = "nats"))]
+pub(crate) fn parse<T>(name: &'static str) -> Result<Option<T>, BuilderError>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match var(name)? {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|e| BuilderError::invalid(name, format!("{:?}: {}", value, e))),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt::Display;
+
+use crate::storage::Err;
+
+#[derive(Debug)]
+pub enum BuilderError {
+    MissingField(&'static str),
+    MissingEnv(&'static str),
+    InvalidConfig(ConfigError),
+    // The connection check at build time failed, e.g. the server is down or refused the
+    // credentials.
+    Unreachable { source: Err },
+    Backend(Err),
+    Storage(String, Box<BuilderError>),
+}
+
+impl BuilderError {
+    pub(crate) fn invalid<R: Into<String>>(field: &'static str, reason: R) -> Self {
+        BuilderError::InvalidConfig(ConfigError::new(field, reason))
+    }
+
+    pub(crate) fn backend<E: Into<Err>>(e: E) -> Self {
+        BuilderError::Backend(e.into())
+    }
+
+    #[cfg_attr(
+        not(any(
+            feature = "redis",
+            feature = "postgres",
+            feature = "mongodb",
+            feature = "etcd",
+            feature = "scylla"
+        )),
+        allow(dead_code)
+    )]
+    pub(crate) fn unreachable<E: Into<Err>>(source: E) -> Self {
+        BuilderError::Unreachable {
+            source: source.into(),
+        }
+    }
+}
+
+impl Display for BuilderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuilderError::MissingField(field) => write!(
+                f,
+                "missing `{}`, set it on the builder before building storage",
+                field
+            ),
+            BuilderError::MissingEnv(name) => {
+                write!(f, "environment variable `{}` is not set", name)
+            }
+            BuilderError::InvalidConfig(e) => write!(f, "{}", e),
+            BuilderError::Unreachable { source } => write!(f, "server is unreachable: {}", source),
+            BuilderError::Backend(e) => write!(f, "backend error: {}", e),
+            BuilderError::Storage(name, e) => write!(f, "storage `{}`: {}", name, e),
+        }
+    }
+}
+
+impl std::error::Error for BuilderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BuilderError::InvalidConfig(e) => Some(e),
+            BuilderError::Unreachable { source } => Some(source.as_ref()),
+            BuilderError::Backend(e) => Some(e.as_ref()),
+            BuilderError::Storage(_, e) => Some(e.as_ref()),
+            BuilderError::MissingField(_) | BuilderError::MissingEnv(_) => None,
+        }
+    }
+}
+
+impl From<ConfigError> for BuilderError {
+    fn from(e: ConfigError) -> Self {
+        BuilderError::InvalidConfig(e)
+    }
+}
+
+// Errors raised by storage operations themselves, boxed into `Err`; use
+// `err.downcast_ref::<StorageError>()` to tell them apart from backend errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageError {
+    Closed,
+    Timeout,
+    // A circuit breaker is failing calls fast until its cooldown ends.
+    CircuitOpen,
+    // A tenant used up its quota, see `TenantQuota`.
+    QuotaExceeded,
+    // Others kept writing the key, an optimistic update gave up after its retries.
+    Contended,
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::Closed => write!(f, "storage is closed"),
+            StorageError::Timeout => write!(f, "operation timed out"),
+            StorageError::CircuitOpen => write!(f, "circuit breaker is open"),
+            StorageError::QuotaExceeded => write!(f, "tenant quota exceeded"),
+            StorageError::Contended => write!(f, "key kept changing, gave up retrying"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub field: &'static str,
+    pub reason: String,
+}
+
+impl ConfigError {
+    pub fn new<R: Into<String>>(field: &'static str, reason: R) -> Self {
+        Self {
+            field,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid `{}`: {}", self.field, self.reason)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            BuilderError::MissingField("addr").to_string(),
+            "missing `addr`, set it on the builder before building storage"
+        );
+        assert_eq!(
+            BuilderError::MissingEnv("REDIS_URL").to_string(),
+            "environment variable `REDIS_URL` is not set"
+        );
+        assert_eq!(
+            BuilderError::invalid("port", "must not be 0").to_string(),
+            "invalid `port`: must not be 0"
+        );
+        assert_eq!(
+            BuilderError::unreachable("connection refused").to_string(),
+            "server is unreachable: connection refused"
+        );
+    }
+
+    #[test]
+    fn test_into_err() {
+        let e: Err = BuilderError::MissingField("path").into();
+        assert!(e.downcast_ref::<BuilderError>().is_some());
+    }
+}
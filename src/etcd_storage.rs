@@ -0,0 +1,839 @@
+use std::collections::{HashMap, VecDeque};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+
+use etcd_client::{
+    Client, Compare, CompareOp, ConnectOptions, DeleteOptions, EventType, GetOptions, KeyValue,
+    PutOptions, Txn, TxnOp,
+};
+use tokio::runtime::Runtime;
+
+use crate::admin::AdminStorage;
+use crate::atomic::AtomicStorage;
+use crate::env;
+use crate::error::BuilderError;
+use crate::expiry::ExpiryStorage;
+use crate::export::{EntryIter, ExportEntry, ExportIter, ExportStorage};
+use crate::flush::{FlushConfirmation, FlushNamespace};
+use crate::get_or_insert::GetOrInsertStorage;
+use crate::hooks::{Hooks, StorageHook};
+use crate::scan::{KeyIter, ScanStorage};
+use crate::storage::{CloseFlag, Err, Storage};
+use crate::versioned::{Version, VersionedStorage};
+use crate::watch::{ChangeEvent, Changes, WatchableStorage};
+
+// Keys read per range request by exports and scans.
+const PAGE: i64 = 500;
+
+// How often a watch task checks whether the storage was closed or the `Changes` dropped.
+const WATCH_POLL: Duration = Duration::from_secs(1);
+
+// Keys and values under a key prefix of an etcd cluster. `set_ex` attaches the key to a lease
+// granted for the TTL, so the server deletes it; leases count in whole seconds, and servers
+// raise TTLs below their minimum, about 2 seconds by default. The atomic operations are
+// transactions that compare the key's mod revision, which is also its `Version`.
+#[derive(Clone)]
+pub struct EtcdStorage<K, V>
+where
+    V: Into<String>,
+{
+    runtime: Arc<Runtime>,
+    client: Client,
+    prefix: Arc<str>,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> EtcdStorage<K, V>
+where
+    V: Into<String>,
+{
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    fn path(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    fn lookup(&self, key: &str) -> Result<Option<KeyValue>, Err> {
+        self.closed.check()?;
+        let mut kv = self.client.kv_client();
+        let mut resp = self.runtime.block_on(kv.get(self.path(key), None))?;
+        Ok(resp.take_kvs().into_iter().next())
+    }
+
+    fn txn(&self, txn: Txn) -> Result<bool, Err> {
+        self.closed.check()?;
+        let mut kv = self.client.kv_client();
+        Ok(self.runtime.block_on(kv.txn(txn))?.succeeded())
+    }
+
+    // A lease that ends `expire` from now, rounded up to whole seconds.
+    fn grant(&self, expire: Duration) -> Result<i64, Err> {
+        let secs = expire.as_secs() + u64::from(expire.subsec_nanos() > 0);
+        let mut lease = self.client.lease_client();
+        let resp = self
+            .runtime
+            .block_on(lease.grant(secs.clamp(1, i64::MAX as u64) as i64, None))?;
+        Ok(resp.id())
+    }
+
+    // Best effort, an unused lease expires on its own.
+    fn revoke(&self, lease: i64) {
+        let mut client = self.client.lease_client();
+        let _ = self.runtime.block_on(client.revoke(lease));
+    }
+
+    // The remaining TTL of a lease, `None` for keys without one.
+    fn lease_ttl(&self, lease: i64) -> Result<Option<Duration>, Err> {
+        if lease == 0 {
+            return Ok(None);
+        }
+        let mut client = self.client.lease_client();
+        let resp = self.runtime.block_on(client.time_to_live(lease, None))?;
+        Ok(Some(Duration::from_secs(resp.ttl().max(0) as u64)))
+    }
+
+    // Keys and values under `prefix` in key order, fetched a page at a time.
+    fn range(
+        &self,
+        prefix: &str,
+        keys_only: bool,
+    ) -> impl Iterator<Item = Result<KeyValue, Err>> + '_ {
+        let end = range_end(self.path(prefix).as_bytes());
+        let mut start = self.path(prefix).into_bytes();
+        let mut page = VecDeque::new();
+        let mut done = false;
+        std::iter::from_fn(move || {
+            if page.is_empty() && !done {
+                let mut options = GetOptions::new().with_range(end.clone()).with_limit(PAGE);
+                if keys_only {
+                    options = options.with_keys_only();
+                }
+                let mut kv = self.client.kv_client();
+                match self.runtime.block_on(kv.get(start.clone(), Some(options))) {
+                    Ok(mut resp) => {
+                        done = !resp.more();
+                        let kvs = resp.take_kvs();
+                        if let Some(last) = kvs.last() {
+                            // The smallest key after the last one.
+                            start = [last.key(), &[0]].concat();
+                        }
+                        page.extend(kvs);
+                    }
+                    Err(e) => {
+                        done = true;
+                        return Some(Err(e.into()));
+                    }
+                }
+            }
+            page.pop_front().map(Ok)
+        })
+    }
+
+    // The key with the storage's prefix removed.
+    fn key_of(&self, kv: &KeyValue) -> Result<String, Err> {
+        let key = kv.key_str()?;
+        Ok(key.strip_prefix(&*self.prefix).unwrap_or(key).to_string())
+    }
+}
+
+impl<K, V> Storage<K, V> for EtcdStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    // Also detaches the key from a lease an earlier `set_ex` attached.
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        let mut kv = self.client.kv_client();
+        self.runtime
+            .block_on(kv.put(self.path(key.as_ref()), value.as_str(), None))?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        let lease = self.grant(expire)?;
+        let mut kv = self.client.kv_client();
+        let options = PutOptions::new().with_lease(lease);
+        self.runtime
+            .block_on(kv.put(self.path(key.as_ref()), value.as_str(), Some(options)))?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        let value = match self.lookup(key.as_ref())? {
+            Some(kv) => Some(V::from(kv.value_str()?.to_string())),
+            None => None,
+        };
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let mut kv = self.client.kv_client();
+        let resp = self
+            .runtime
+            .block_on(kv.delete(self.path(key.as_ref()), None))?;
+        self.hooks.del(&key);
+        Ok((resp.deleted() > 0).then_some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        let mut kv = self.client.kv_client();
+        let options = GetOptions::new().with_count_only();
+        let resp = self
+            .runtime
+            .block_on(kv.get(self.path(key.as_ref()), Some(options)))?;
+        Ok(resp.count() > 0)
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+}
+
+impl<K, V> ExportStorage<K, V> for EtcdStorage<K, V>
+where
+    K: AsRef<str> + From<String>,
+    V: Into<String> + From<String>,
+{
+    fn export_iter(&self) -> Result<ExportIter<'_, K, V>, Err> {
+        Ok(Box::new(
+            self.export_entries()?
+                .map(|entry| entry.map(|entry| (entry.key, entry.value))),
+        ))
+    }
+
+    // In key order. TTLs are looked up once per lease.
+    fn export_entries<'a>(&'a self) -> Result<EntryIter<'a, K, V>, Err>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.closed.check()?;
+        let mut ttls = HashMap::new();
+        Ok(Box::new(self.range("", false).map(move |kv| {
+            let kv = kv?;
+            let ttl = match ttls.get(&kv.lease()) {
+                Some(ttl) => *ttl,
+                None => *ttls
+                    .entry(kv.lease())
+                    .or_insert(self.lease_ttl(kv.lease())?),
+            };
+            Ok(ExportEntry {
+                key: K::from(self.key_of(&kv)?),
+                value: V::from(kv.value_str()?.to_string()),
+                ttl,
+            })
+        })))
+    }
+}
+
+// `compare_and_swap` reads the key and writes only if its mod revision is unchanged, and
+// reads again if it changed. `swap` and `take` return the previous value from their put or
+// delete.
+impl<K, V> AtomicStorage<K, V> for EtcdStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String> + PartialEq,
+{
+    fn compare_and_swap(&self, key: K, expected: Option<V>, new: Option<V>) -> Result<bool, Err> {
+        let path = self.path(key.as_ref());
+        let expected: Option<String> = expected.map(Into::into);
+        let new: Option<String> = new.map(Into::into);
+        loop {
+            let current = self.lookup(key.as_ref())?;
+            let value = match &current {
+                Some(kv) => Some(kv.value_str()?),
+                None => None,
+            };
+            if value != expected.as_deref() {
+                return Ok(false);
+            }
+            let revision = current.as_ref().map_or(0, KeyValue::mod_revision);
+            let op = match &new {
+                Some(new) => TxnOp::put(path.as_str(), new.as_str(), None),
+                None => TxnOp::delete(path.as_str(), None),
+            };
+            let txn = Txn::new()
+                .when([Compare::mod_revision(
+                    path.as_str(),
+                    CompareOp::Equal,
+                    revision,
+                )])
+                .and_then([op]);
+            if self.txn(txn)? {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn swap(&self, key: K, value: V) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        let mut kv = self.client.kv_client();
+        let options = PutOptions::new().with_prev_key();
+        let mut resp = self.runtime.block_on(kv.put(
+            self.path(key.as_ref()),
+            value.as_str(),
+            Some(options),
+        ))?;
+        match resp.take_prev_key() {
+            Some(prev) => Ok(Some(V::from(prev.value_str()?.to_string()))),
+            None => Ok(None),
+        }
+    }
+
+    // A single delete, so of concurrent takes only one sees the value.
+    fn take(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let mut kv = self.client.kv_client();
+        let options = DeleteOptions::new().with_prev_key();
+        let mut resp = self
+            .runtime
+            .block_on(kv.delete(self.path(key.as_ref()), Some(options)))?;
+        match resp.take_prev_kvs().into_iter().next() {
+            Some(prev) => Ok(Some(V::from(prev.value_str()?.to_string()))),
+            None => Ok(None),
+        }
+    }
+}
+
+// The mod revision, the cluster-wide revision of the key's last write.
+impl<K, V> VersionedStorage<K, V> for EtcdStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn get_versioned(&self, key: K) -> Result<Option<(V, Version)>, Err> {
+        match self.lookup(key.as_ref())? {
+            Some(kv) => {
+                let version = Version::from_u64(kv.mod_revision() as u64);
+                Ok(Some((V::from(kv.value_str()?.to_string()), version)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn set_if_version(&self, key: K, value: V, expected: Option<Version>) -> Result<bool, Err> {
+        let path = self.path(key.as_ref());
+        let revision = expected.map_or(0, |version| version.as_u64() as i64);
+        let value: String = value.into();
+        let txn = Txn::new()
+            .when([Compare::mod_revision(
+                path.as_str(),
+                CompareOp::Equal,
+                revision,
+            )])
+            .and_then([TxnOp::put(path, value, None)]);
+        self.txn(txn)
+    }
+}
+
+// A transaction that only puts if the key has no create revision, i.e. doesn't exist.
+impl<K, V> GetOrInsertStorage<K, V> for EtcdStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set_nx(&self, key: K, value: V, expire: Option<Duration>) -> Result<bool, Err> {
+        let path = self.path(key.as_ref());
+        let value: String = value.into();
+        let lease = expire.map(|expire| self.grant(expire)).transpose()?;
+        let options = lease.map(|lease| PutOptions::new().with_lease(lease));
+        let txn = Txn::new()
+            .when([Compare::create_revision(path.as_str(), CompareOp::Equal, 0)])
+            .and_then([TxnOp::put(path, value, options)]);
+        let inserted = self.txn(txn)?;
+        if let (false, Some(lease)) = (inserted, lease) {
+            self.revoke(lease);
+        }
+        Ok(inserted)
+    }
+}
+
+// With an empty prefix these cover every key in the cluster.
+impl<K, V> AdminStorage for EtcdStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn clear(&self) -> Result<(), Err> {
+        self.closed.check()?;
+        let mut kv = self.client.kv_client();
+        let options = DeleteOptions::new().with_range(range_end(self.prefix.as_bytes()));
+        self.runtime
+            .block_on(kv.delete(self.prefix.as_bytes(), Some(options)))?;
+        Ok(())
+    }
+
+    fn len(&self) -> Result<usize, Err> {
+        self.closed.check()?;
+        let mut kv = self.client.kv_client();
+        let options = GetOptions::new()
+            .with_range(range_end(self.prefix.as_bytes()))
+            .with_count_only();
+        let resp = self
+            .runtime
+            .block_on(kv.get(self.prefix.as_bytes(), Some(options)))?;
+        Ok(resp.count() as usize)
+    }
+}
+
+impl<K, V> ExpiryStorage<K> for EtcdStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String>,
+{
+    fn ttl(&self, key: K) -> Result<Option<Duration>, Err> {
+        match self.lookup(key.as_ref())? {
+            Some(kv) => self.lease_ttl(kv.lease()),
+            None => Ok(None),
+        }
+    }
+
+    // Moves the key to a new lease, without rewriting its value.
+    fn expire(&self, key: K, expire: Duration) -> Result<bool, Err> {
+        let path = self.path(key.as_ref());
+        let lease = self.grant(expire)?;
+        let options = PutOptions::new().with_lease(lease).with_ignore_value();
+        let txn = Txn::new()
+            .when([Compare::create_revision(
+                path.as_str(),
+                CompareOp::Greater,
+                0,
+            )])
+            .and_then([TxnOp::put(path, "", Some(options))]);
+        let moved = self.txn(txn)?;
+        if !moved {
+            self.revoke(lease);
+        }
+        Ok(moved)
+    }
+
+    // Detaches the key from its lease, if it has one.
+    fn persist(&self, key: K) -> Result<bool, Err> {
+        let path = self.path(key.as_ref());
+        let options = PutOptions::new().with_ignore_value();
+        let txn = Txn::new()
+            .when([
+                Compare::create_revision(path.as_str(), CompareOp::Greater, 0),
+                Compare::lease(path.as_str(), CompareOp::NotEqual, 0),
+            ])
+            .and_then([TxnOp::put(path, "", Some(options))]);
+        self.txn(txn)
+    }
+}
+
+// Each watch runs a task on the storage's runtime with its own etcd watch. Expired leases
+// delete their keys, so expiry is reported as `Del`. The task ends when the watch fails or
+// is canceled, when the storage is closed, and at most `WATCH_POLL` after the `Changes` was
+// dropped.
+impl<K, V> WatchableStorage<K> for EtcdStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String>,
+{
+    fn watch(&self, key: K) -> Result<Changes, Err> {
+        self.closed.check()?;
+        let mut client = self.client.watch_client();
+        let (mut watcher, mut stream) = self
+            .runtime
+            .block_on(client.watch(self.path(key.as_ref()), None))?;
+
+        let closed = self.closed.clone();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let changes = Changes::new(rx);
+        let alive = changes.alive();
+        self.runtime.spawn(async move {
+            while !closed.is_closed() && alive.strong_count() > 0 {
+                let resp = match tokio::time::timeout(WATCH_POLL, stream.message()).await {
+                    Ok(Ok(Some(resp))) if !resp.canceled() => resp,
+                    Ok(_) => break,
+                    Err(_) => continue,
+                };
+                for event in resp.events() {
+                    let change = match event.event_type() {
+                        EventType::Put => ChangeEvent::Set,
+                        EventType::Delete => ChangeEvent::Del,
+                    };
+                    if tx.send(change).is_err() {
+                        break;
+                    }
+                }
+            }
+            let _ = watcher.cancel().await;
+        });
+        Ok(changes)
+    }
+}
+
+impl<K, V> ScanStorage<K> for EtcdStorage<K, V>
+where
+    K: From<String>,
+    V: Into<String>,
+{
+    // A keys-only range in key order, a page at a time.
+    fn scan_prefix(&self, prefix: &str) -> Result<KeyIter<'_, K>, Err> {
+        self.closed.check()?;
+        Ok(Box::new(
+            self.range(prefix, true)
+                .map(|kv| Ok(K::from(self.key_of(&kv?)?))),
+        ))
+    }
+}
+
+impl<K, V> FlushNamespace for EtcdStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn flush_namespace(&self, prefix: &str, confirm: &FlushConfirmation) -> Result<usize, Err> {
+        self.closed.check()?;
+        confirm.check(prefix)?;
+        let path = self.path(prefix);
+        let mut kv = self.client.kv_client();
+        let options = DeleteOptions::new().with_range(range_end(path.as_bytes()));
+        let resp = self.runtime.block_on(kv.delete(path, Some(options)))?;
+        Ok(resp.deleted() as usize)
+    }
+}
+
+// The end of the range of keys starting with `prefix`, as etcd computes it: the prefix with
+// its last byte below 0xff incremented. `\0` means no end, for prefixes without one.
+fn range_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    vec![0]
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct EtcdStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    endpoints: Vec<String>,
+    prefix: String,
+    user: Option<(String, String)>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::config::secs::option"))]
+    connect_timeout: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::config::secs::option"))]
+    timeout: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> EtcdStorageBuilder<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    pub fn new() -> Self {
+        EtcdStorageBuilder::default()
+    }
+
+    // Reads `ETCD_ENDPOINTS`, comma separated, plus an optional `ETCD_PREFIX`.
+    pub fn from_env() -> Result<Self, BuilderError> {
+        let mut builder = EtcdStorageBuilder::new();
+        for endpoint in env::required("ETCD_ENDPOINTS")?.split(',') {
+            builder = builder.endpoint(endpoint.trim());
+        }
+        if let Some(prefix) = env::var("ETCD_PREFIX")? {
+            builder = builder.prefix(&prefix);
+        }
+        Ok(builder)
+    }
+
+    // Adds a cluster member, `http://localhost:2379` or `localhost:2379`. Requests are
+    // balanced over all of them.
+    pub fn endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoints.push(endpoint.to_string());
+        self
+    }
+
+    // Put in front of every key, e.g. `config/`. Defaults to none, so `clear` and `len`
+    // cover the whole cluster.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    // Authenticates as this user on connect.
+    pub fn user(mut self, name: &str, password: &str) -> Self {
+        self.user = Some((name.to_string(), password.to_string()));
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    // Applied to every request except watches.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their TTL and batch variants.
+    // Clones of the storage share them, writes from other clients don't fire them.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if no endpoint is given or the cluster cannot be reached. Use `try_build` to get
+    // the error instead.
+    pub fn build(self) -> EtcdStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build etcd storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<EtcdStorage<K, V>, BuilderError> {
+        if self.endpoints.is_empty() {
+            return Err(BuilderError::MissingField("endpoints"));
+        }
+        if self.endpoints.iter().any(String::is_empty) {
+            return Err(BuilderError::invalid("endpoints", "must not be empty"));
+        }
+        let mut options = ConnectOptions::new();
+        if let Some((name, password)) = &self.user {
+            options = options.with_user(name.as_str(), password.as_str());
+        }
+        if let Some(timeout) = self.connect_timeout {
+            options = options.with_connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            options = options.with_timeout(timeout);
+        }
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(BuilderError::backend)?;
+        let client = runtime
+            .block_on(async {
+                let client = Client::connect(&self.endpoints, Some(options)).await?;
+                // Connecting is lazy, a request checks the cluster is there.
+                let count = GetOptions::new().with_count_only();
+                client
+                    .kv_client()
+                    .get(self.prefix.as_str(), Some(count))
+                    .await?;
+                Ok::<_, etcd_client::Error>(client)
+            })
+            .map_err(BuilderError::unreachable)?;
+
+        Ok(EtcdStorage {
+            runtime: Arc::new(runtime),
+            client,
+            prefix: self.prefix.into(),
+            closed: CloseFlag::default(),
+            hooks: self.hooks,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<K, V> Default for EtcdStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    fn default() -> Self {
+        Self {
+            endpoints: Vec::new(),
+            prefix: String::new(),
+            user: None,
+            connect_timeout: None,
+            timeout: None,
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+// No conformance tests, their 200ms TTLs are below what etcd leases resolve.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get() {
+        let storage = build_localhost::<&str, String>("get");
+        let (key, value) = ("name", "Ferris".to_string());
+        storage.set(key, value.clone()).unwrap();
+        assert_eq!(storage.get(key).unwrap(), Some(value));
+        assert!(storage.contains(key).unwrap());
+        assert_eq!(storage.del(key).unwrap(), Some(key));
+        assert_eq!(storage.get(key).unwrap(), None);
+        assert_eq!(storage.del(key).unwrap(), None);
+    }
+
+    #[test]
+    fn test_set_ex() {
+        let storage = build_localhost::<&str, String>("set_ex");
+        storage
+            .set_ex("a", "ok!".to_string(), Duration::from_secs(2))
+            .unwrap();
+        assert_eq!(storage.get("a").unwrap(), Some("ok!".to_string()));
+        assert!(storage.ttl("a").unwrap().unwrap() <= Duration::from_secs(2));
+        std::thread::sleep(Duration::from_secs(4));
+        assert!(!storage.contains("a").unwrap());
+
+        storage.set("c", "ok!".to_string()).unwrap();
+        assert!(!storage.persist("c").unwrap());
+        assert!(storage.expire("c", Duration::from_secs(60)).unwrap());
+        assert!(storage.ttl("c").unwrap().unwrap() > Duration::from_secs(58));
+        assert_eq!(storage.get("c").unwrap(), Some("ok!".to_string()));
+        assert!(storage.persist("c").unwrap());
+        assert_eq!(storage.ttl("c").unwrap(), None);
+        assert!(!storage.expire("missing", Duration::from_secs(60)).unwrap());
+    }
+
+    #[test]
+    fn test_scan_pages() {
+        let storage = build_localhost::<String, String>("scan_pages");
+        for n in 0..PAGE + 10 {
+            storage
+                .set(format!("tenant:1:{:04}", n), n.to_string())
+                .unwrap();
+        }
+        storage.set("tenant:2:a".into(), "x".into()).unwrap();
+        storage.set("tenant:1".into(), "x".into()).unwrap();
+
+        let keys: Vec<String> = storage
+            .scan_prefix("tenant:1:")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(keys.len(), PAGE as usize + 10);
+        assert_eq!(keys[0], "tenant:1:0000");
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(storage.export_iter().unwrap().count(), PAGE as usize + 12);
+
+        let confirm = FlushConfirmation::for_prefix("tenant:1:");
+        assert_eq!(
+            storage.flush_namespace("tenant:1:", &confirm).unwrap(),
+            PAGE as usize + 10
+        );
+        assert_eq!(storage.len().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let storage = build_localhost::<&str, String>("compare_and_swap");
+        assert!(storage.compare_and_swap("cas", None, None).unwrap());
+        assert!(storage
+            .compare_and_swap("cas", None, Some("1".to_string()))
+            .unwrap());
+        assert!(!storage
+            .compare_and_swap("cas", None, Some("2".to_string()))
+            .unwrap());
+        let seen = storage
+            .fetch_update("cas", |current| {
+                current.map(|n| (n.parse::<u32>().unwrap() + 1).to_string())
+            })
+            .unwrap();
+        assert_eq!(seen, Some("1".to_string()));
+        assert!(storage
+            .compare_and_swap("cas", Some("2".to_string()), None)
+            .unwrap());
+        assert!(!storage.contains("cas").unwrap());
+
+        assert_eq!(storage.swap("a", "1".to_string()).unwrap(), None);
+        assert_eq!(
+            storage.swap("a", "2".to_string()).unwrap(),
+            Some("1".into())
+        );
+        assert_eq!(storage.take("a").unwrap(), Some("2".to_string()));
+        assert_eq!(storage.take("a").unwrap(), None);
+
+        assert!(storage.set_nx("b", "1".to_string(), None).unwrap());
+        assert!(!storage.set_nx("b", "2".to_string(), None).unwrap());
+
+        assert!(storage.set_if_version("c", "1".to_string(), None).unwrap());
+        let (_, version) = storage.get_versioned("c").unwrap().unwrap();
+        assert!(storage
+            .set_if_version("c", "2".to_string(), Some(version))
+            .unwrap());
+        assert!(!storage
+            .set_if_version("c", "3".to_string(), Some(version))
+            .unwrap());
+    }
+
+    #[test]
+    fn test_watch() {
+        let storage = build_localhost::<&str, String>("watch");
+        let changes = storage.watch("a").unwrap();
+        storage.set("a", "1".to_string()).unwrap();
+        storage.set("b", "1".to_string()).unwrap();
+        storage.del("a").unwrap();
+        let timeout = Duration::from_secs(5);
+        assert_eq!(changes.recv_timeout(timeout), Ok(ChangeEvent::Set));
+        assert_eq!(changes.recv_timeout(timeout), Ok(ChangeEvent::Del));
+
+        storage.close().unwrap();
+        assert!(changes.recv_timeout(timeout).is_err());
+    }
+
+    #[test]
+    fn test_range_end() {
+        assert_eq!(range_end(b""), b"\0");
+        assert_eq!(range_end(b"a:"), b"a;");
+        assert_eq!(range_end(b"a\xff"), b"b");
+        assert_eq!(range_end(b"\xff\xff"), b"\0");
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = EtcdStorageBuilder::<&str, String>::new().try_build();
+        assert!(matches!(resp, Err(BuilderError::MissingField("endpoints"))));
+        let resp = EtcdStorageBuilder::<&str, String>::new()
+            .endpoint("")
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(e)) if e.field == "endpoints"));
+    }
+
+    // A prefix per test, emptied first, so tests can run in parallel.
+    fn build_localhost<K: AsRef<str>, V: Into<String> + From<String>>(
+        prefix: &str,
+    ) -> EtcdStorage<K, V> {
+        let storage = EtcdStorageBuilder::<K, V>::new()
+            .endpoint("http://127.0.0.1:2379")
+            .prefix(&format!("storage_trait/{}/", prefix))
+            .connect_timeout(Duration::from_secs(2))
+            .build();
+        storage.clear().unwrap();
+        storage
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::storage::Err;
+
+// Inspects and adjusts a key's expiry after it was written.
+pub trait ExpiryStorage<K> {
+    // The time left to live, `None` when the key doesn't expire or doesn't exist.
+    fn ttl(&self, key: K) -> Result<Option<Duration>, Err>;
+
+    // Sets a new TTL, returns `false` if the key doesn't exist.
+    fn expire(&self, key: K, expire: Duration) -> Result<bool, Err>;
+
+    // Removes the TTL, returns `false` if the key doesn't exist or had none.
+    fn persist(&self, key: K) -> Result<bool, Err>;
+}
+
+macro_rules! forward_expiry {
+    ($($ty:ty),*) => {
+        $(
+            impl<K, S: ExpiryStorage<K> + ?Sized> ExpiryStorage<K> for $ty {
+                fn ttl(&self, key: K) -> Result<Option<Duration>, Err> {
+                    (**self).ttl(key)
+                }
+
+                fn expire(&self, key: K, expire: Duration) -> Result<bool, Err> {
+                    (**self).expire(key, expire)
+                }
+
+                fn persist(&self, key: K) -> Result<bool, Err> {
+                    (**self).persist(key)
+                }
+            }
+        )*
+    };
+}
+
+forward_expiry!(&S, Box<S>, Arc<S>);
@@ -0,0 +1,76 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::storage::{Err, Storage};
+
+pub type ExportIter<'a, K, V> = Box<dyn Iterator<Item = Result<(K, V), Err>> + 'a>;
+pub type EntryIter<'a, K, V> = Box<dyn Iterator<Item = Result<ExportEntry<K, V>, Err>> + 'a>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportEntry<K, V> {
+    pub key: K,
+    pub value: V,
+    // Remaining time to live, `None` for keys that do not expire.
+    pub ttl: Option<Duration>,
+}
+
+// Backends that can walk their whole keyspace lazily, a page at a time, without loading it
+// into memory. Entries written during the export may or may not be yielded.
+pub trait ExportStorage<K, V>: Storage<K, V> {
+    fn export_iter(&self) -> Result<ExportIter<'_, K, V>, Err>;
+
+    // Like `export_iter` with the remaining TTLs, backends without expiry keep the default.
+    fn export_entries<'a>(&'a self) -> Result<EntryIter<'a, K, V>, Err>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        Ok(Box::new(self.export_iter()?.map(|entry| {
+            entry.map(|(key, value)| ExportEntry {
+                key,
+                value,
+                ttl: None,
+            })
+        })))
+    }
+
+    // Copies every entry with its remaining TTL into `target`, one `set`/`set_ex` per key.
+    // Stops at the first error and returns how many entries were copied.
+    fn snapshot_to<T: Storage<K, V> + ?Sized>(&self, target: &T) -> Result<usize, Err>
+    where
+        Self: Sized,
+    {
+        let mut copied = 0;
+        for entry in self.export_entries()? {
+            let entry = entry?;
+            match entry.ttl {
+                Some(ttl) => target.set_ex(entry.key, entry.value, ttl)?,
+                None => target.set(entry.key, entry.value)?,
+            }
+            copied += 1;
+        }
+        Ok(copied)
+    }
+}
+
+macro_rules! forward_export {
+    ($($ty:ty),*) => {
+        $(
+            impl<K, V, S: ExportStorage<K, V> + ?Sized> ExportStorage<K, V> for $ty {
+                fn export_iter(&self) -> Result<ExportIter<'_, K, V>, Err> {
+                    (**self).export_iter()
+                }
+
+                fn export_entries<'a>(&'a self) -> Result<EntryIter<'a, K, V>, Err>
+                where
+                    K: 'a,
+                    V: 'a,
+                {
+                    (**self).export_entries()
+                }
+            }
+        )*
+    };
+}
+
+forward_export!(&S, Box<S>, Arc<S>);
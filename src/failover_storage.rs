@@ -0,0 +1,479 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::error::{BuilderError, StorageError};
+use crate::health::HealthStatus;
+use crate::options::OpOptions;
+use crate::storage::{CloseFlag, Err, Storage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailoverEvent {
+    // The primary stopped answering and the standby was promoted.
+    Failover,
+    // The primary recovered and serves requests again.
+    Failback,
+}
+
+type Probe<P> = Box<dyn Fn(&P) -> bool + Send + Sync>;
+type Hook = Box<dyn Fn(FailoverEvent) + Send + Sync>;
+type FailOn = Box<dyn Fn(&Err) -> bool + Send + Sync>;
+
+// Errors that mean the backend could not be reached: redis io errors, timeouts, refused or
+// dropped connections, `std::io::Error`s and `StorageError::Timeout`.
+pub fn is_connection_error(e: &Err) -> bool {
+    #[cfg(feature = "redis")]
+    if let Some(e) = e.downcast_ref::<redis::RedisError>() {
+        return e.is_io_error()
+            || e.is_timeout()
+            || e.is_connection_refusal()
+            || e.is_connection_dropped();
+    }
+    e.is::<std::io::Error>() || e.downcast_ref::<StorageError>() == Some(&StorageError::Timeout)
+}
+
+struct Shared<P, S> {
+    primary: P,
+    standby: S,
+    on_standby: AtomicBool,
+    failures: AtomicUsize,
+    recoveries: AtomicUsize,
+    failure_threshold: usize,
+    recovery_threshold: usize,
+    fail_on: Option<FailOn>,
+    hooks: Vec<Hook>,
+}
+
+impl<P, S> Shared<P, S> {
+    // Errors `fail_on` rejects still prove the primary answered.
+    fn record_call<T>(&self, resp: &Result<T, Err>) {
+        let failed = match (resp, &self.fail_on) {
+            (Ok(_), _) => false,
+            (Err(e), Some(fail_on)) => fail_on(e),
+            (Err(_), None) => true,
+        };
+        self.record(!failed);
+    }
+
+    fn record(&self, healthy: bool) {
+        if healthy {
+            self.failures.store(0, Ordering::SeqCst);
+            if self.on_standby.load(Ordering::SeqCst)
+                && self.recoveries.fetch_add(1, Ordering::SeqCst) + 1 >= self.recovery_threshold
+            {
+                self.switch(false, FailoverEvent::Failback);
+            }
+        } else {
+            self.recoveries.store(0, Ordering::SeqCst);
+            if !self.on_standby.load(Ordering::SeqCst)
+                && self.failures.fetch_add(1, Ordering::SeqCst) + 1 >= self.failure_threshold
+            {
+                self.switch(true, FailoverEvent::Failover);
+            }
+        }
+    }
+
+    fn switch(&self, on_standby: bool, event: FailoverEvent) {
+        if self.on_standby.swap(on_standby, Ordering::SeqCst) != on_standby {
+            self.failures.store(0, Ordering::SeqCst);
+            self.recoveries.store(0, Ordering::SeqCst);
+            for hook in &self.hooks {
+                hook(event);
+            }
+        }
+    }
+}
+
+// Serves everything from the primary and promotes the warm standby once the primary fails
+// `failure_threshold` times in a row (failed calls and failed probes both count, `fail_on`
+// narrows which call errors do). While on the standby, only probes can bring the primary back.
+pub struct FailoverStorage<P, S> {
+    shared: Arc<Shared<P, S>>,
+    prober: Mutex<Option<(Sender<()>, JoinHandle<()>)>>,
+    closed: CloseFlag,
+}
+
+impl<P, S> FailoverStorage<P, S> {
+    pub fn is_on_standby(&self) -> bool {
+        self.shared.on_standby.load(Ordering::SeqCst)
+    }
+
+    pub fn primary(&self) -> &P {
+        &self.shared.primary
+    }
+
+    pub fn standby(&self) -> &S {
+        &self.shared.standby
+    }
+
+    fn stop_prober(&self) {
+        if let Some((stop, handle)) = self.prober.lock().unwrap().take() {
+            drop(stop);
+            let _ = handle.join();
+        }
+    }
+}
+
+macro_rules! route {
+    ($self:ident, $method:ident($($arg:ident),*)) => {{
+        $self.closed.check()?;
+        if $self.is_on_standby() {
+            return $self.shared.standby.$method($($arg),*);
+        }
+
+        let resp = $self.shared.primary.$method($($arg),*);
+        $self.shared.record_call(&resp);
+        resp
+    }};
+}
+
+impl<K, V, P, S> Storage<K, V> for FailoverStorage<P, S>
+where
+    P: Storage<K, V>,
+    S: Storage<K, V>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        route!(self, set(key, value))
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        route!(self, set_ex(key, value, expire))
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        route!(self, get(key))
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        route!(self, del(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        route!(self, contains(key))
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        if !self.closed.close() {
+            return Ok(());
+        }
+        self.stop_prober();
+        let primary = self.shared.primary.close();
+        self.shared.standby.close()?;
+        primary
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+
+    // Healthy while either side is, the one that failed degrades it. Doesn't switch sides,
+    // that is left to the calls and the prober.
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.closed.check()?;
+        let primary = self.shared.primary.health_check();
+        let standby = self.shared.standby.health_check();
+        let ((serving, serving_name), (other, other_name)) = match self.is_on_standby() {
+            true => ((standby, "standby"), (primary, "primary")),
+            false => ((primary, "primary"), (standby, "standby")),
+        };
+        match (serving, other) {
+            (Ok(mut status), other) => {
+                status.part(other_name, other);
+                Ok(status)
+            }
+            (Err(e), Ok(mut status)) => {
+                status.part(serving_name, Err(e));
+                Ok(status)
+            }
+            (Err(e), Err(_)) => Err(e),
+        }
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        route!(self, get_many(keys))
+    }
+
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        route!(self, set_many(entries))
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        route!(self, del_many(keys))
+    }
+
+    fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+        route!(self, set_with_opts(key, value, opts))
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        route!(self, get_with_opts(key, opts))
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        route!(self, del_with_opts(key, opts))
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        route!(self, contains_with_opts(key, opts))
+    }
+}
+
+impl<P, S> Drop for FailoverStorage<P, S> {
+    fn drop(&mut self) {
+        self.stop_prober();
+    }
+}
+
+pub struct FailoverStorageBuilder<P, S> {
+    primary: P,
+    standby: S,
+    probe: Option<(Duration, Probe<P>)>,
+    failure_threshold: usize,
+    recovery_threshold: usize,
+    fail_on: Option<FailOn>,
+    hooks: Vec<Hook>,
+}
+
+impl<P, S> FailoverStorageBuilder<P, S>
+where
+    P: Send + Sync + 'static,
+    S: Send + Sync + 'static,
+{
+    pub fn new(primary: P, standby: S) -> Self {
+        FailoverStorageBuilder {
+            primary,
+            standby,
+            probe: None,
+            failure_threshold: 3,
+            recovery_threshold: 3,
+            fail_on: None,
+            hooks: Vec::new(),
+        }
+    }
+
+    // Checks the primary every `interval` on a background thread, `probe` returns whether it's healthy.
+    pub fn probe<F>(mut self, interval: Duration, probe: F) -> Self
+    where
+        F: Fn(&P) -> bool + Send + Sync + 'static,
+    {
+        self.probe = Some((interval, Box::new(probe)));
+        self
+    }
+
+    pub fn failure_threshold(mut self, failures: usize) -> Self {
+        self.failure_threshold = failures;
+        self
+    }
+
+    pub fn recovery_threshold(mut self, probes: usize) -> Self {
+        self.recovery_threshold = probes;
+        self
+    }
+
+    // Only errors matching `fail_on` count towards a failover, e.g. `is_connection_error`, so
+    // a rejected value doesn't take the primary out. By default every error counts.
+    pub fn fail_on<F>(mut self, fail_on: F) -> Self
+    where
+        F: Fn(&Err) -> bool + Send + Sync + 'static,
+    {
+        self.fail_on = Some(Box::new(fail_on));
+        self
+    }
+
+    pub fn on_transition<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(FailoverEvent) + Send + Sync + 'static,
+    {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    // # Panics
+    // Panics if a threshold is zero or the probe interval is zero.
+    pub fn build(self) -> FailoverStorage<P, S> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build failover storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<FailoverStorage<P, S>, BuilderError> {
+        if self.failure_threshold == 0 {
+            return Err(BuilderError::invalid(
+                "failure_threshold",
+                "must be at least 1",
+            ));
+        }
+        if self.recovery_threshold == 0 {
+            return Err(BuilderError::invalid(
+                "recovery_threshold",
+                "must be at least 1",
+            ));
+        }
+        if matches!(&self.probe, Some((interval, _)) if interval.is_zero()) {
+            return Err(BuilderError::invalid("probe", "interval must not be zero"));
+        }
+
+        let shared = Arc::new(Shared {
+            primary: self.primary,
+            standby: self.standby,
+            on_standby: AtomicBool::new(false),
+            failures: AtomicUsize::new(0),
+            recoveries: AtomicUsize::new(0),
+            failure_threshold: self.failure_threshold,
+            recovery_threshold: self.recovery_threshold,
+            fail_on: self.fail_on,
+            hooks: self.hooks,
+        });
+
+        let prober = match self.probe {
+            Some((interval, probe)) => {
+                let (stop, stopped) = mpsc::channel::<()>();
+                let shared = shared.clone();
+                let handle = thread::Builder::new()
+                    .name("storage-failover-probe".to_string())
+                    .spawn(move || {
+                        while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                            shared.record(probe(&shared.primary));
+                        }
+                    })
+                    .map_err(BuilderError::backend)?;
+                Some((stop, handle))
+            }
+            None => None,
+        };
+
+        Ok(FailoverStorage {
+            shared,
+            prober: Mutex::new(prober),
+            closed: CloseFlag::default(),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::{DashMapStorage, DashMapStorageBuilder};
+
+    // Fails every call while `down` is set.
+    struct Flaky {
+        inner: DashMapStorage<&'static str, u32>,
+        down: Arc<AtomicBool>,
+    }
+
+    impl Storage<&'static str, u32> for Flaky {
+        fn set(&self, key: &'static str, value: u32) -> Result<(), Err> {
+            self.check()?;
+            self.inner.set(key, value)
+        }
+
+        fn set_ex(&self, key: &'static str, value: u32, expire: Duration) -> Result<(), Err> {
+            self.check()?;
+            self.inner.set_ex(key, value, expire)
+        }
+
+        fn get(&self, key: &'static str) -> Result<Option<u32>, Err> {
+            self.check()?;
+            self.inner.get(key)
+        }
+
+        fn del(&self, key: &'static str) -> Result<Option<&'static str>, Err> {
+            self.check()?;
+            self.inner.del(key)
+        }
+
+        fn contains(&self, key: &'static str) -> Result<bool, Err> {
+            self.check()?;
+            self.inner.contains(key)
+        }
+    }
+
+    impl Flaky {
+        fn check(&self) -> Result<(), Err> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err("primary is down".into());
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_failover_and_failback() {
+        let down = Arc::new(AtomicBool::new(false));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let primary = Flaky {
+            inner: DashMapStorageBuilder::new().build(),
+            down: down.clone(),
+        };
+        let storage = FailoverStorageBuilder::new(primary, DashMapStorageBuilder::new().build())
+            .failure_threshold(2)
+            .recovery_threshold(1)
+            .probe(Duration::from_millis(10), |primary| {
+                primary.get("health").is_ok()
+            })
+            .on_transition(move |event| recorded.lock().unwrap().push(event))
+            .build();
+
+        storage.set("key", 1).unwrap();
+        assert_eq!(storage.primary().inner.get("key").unwrap(), Some(1));
+
+        down.store(true, Ordering::SeqCst);
+        assert!(storage.set("key", 2).is_err());
+        let _ = storage.set("key", 2);
+        assert!(storage.is_on_standby());
+        storage.set("key", 3).unwrap();
+        assert_eq!(storage.standby().get("key").unwrap(), Some(3));
+
+        down.store(false, Ordering::SeqCst);
+        for _ in 0..100 {
+            if !storage.is_on_standby() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!storage.is_on_standby());
+        assert_eq!(
+            *events.lock().unwrap(),
+            [FailoverEvent::Failover, FailoverEvent::Failback]
+        );
+
+        storage.close().unwrap();
+        assert!(storage.get("key").is_err());
+    }
+
+    #[test]
+    fn test_fail_on() {
+        let down = Arc::new(AtomicBool::new(true));
+        let primary = Flaky {
+            inner: DashMapStorageBuilder::new().build(),
+            down: down.clone(),
+        };
+        let storage = FailoverStorageBuilder::new(primary, DashMapStorageBuilder::new().build())
+            .failure_threshold(1)
+            .fail_on(is_connection_error)
+            .build();
+
+        assert!(storage.set("key", 1).is_err());
+        assert!(!storage.is_on_standby());
+
+        let refused: Err = std::io::Error::from(std::io::ErrorKind::ConnectionRefused).into();
+        assert!(is_connection_error(&refused));
+        assert!(is_connection_error(&StorageError::Timeout.into()));
+        assert!(!is_connection_error(&StorageError::Closed.into()));
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = FailoverStorageBuilder::new(
+            DashMapStorageBuilder::<&str, u32>::new().build(),
+            DashMapStorageBuilder::<&str, u32>::new().build(),
+        )
+        .failure_threshold(0)
+        .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(_))));
+    }
+}
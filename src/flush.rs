@@ -0,0 +1,42 @@
+use std::sync::Arc;
+
+use crate::storage::Err;
+
+// Proof that the caller meant to wipe exactly this prefix, a token for another prefix is
+// rejected. Build it right where the flush happens, never from configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlushConfirmation(String);
+
+impl FlushConfirmation {
+    pub fn for_prefix(prefix: &str) -> Self {
+        FlushConfirmation(prefix.to_string())
+    }
+
+    pub(crate) fn check(&self, prefix: &str) -> Result<(), Err> {
+        if self.0 != prefix {
+            return Err(format!(
+                "flush of `{}` refused, the confirmation was issued for `{}`",
+                prefix, self.0
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+pub trait FlushNamespace {
+    // Deletes every key starting with `prefix` and returns how many were removed.
+    fn flush_namespace(&self, prefix: &str, confirm: &FlushConfirmation) -> Result<usize, Err>;
+}
+
+impl<S: FlushNamespace + ?Sized> FlushNamespace for &S {
+    fn flush_namespace(&self, prefix: &str, confirm: &FlushConfirmation) -> Result<usize, Err> {
+        (**self).flush_namespace(prefix, confirm)
+    }
+}
+
+impl<S: FlushNamespace + ?Sized> FlushNamespace for Arc<S> {
+    fn flush_namespace(&self, prefix: &str, confirm: &FlushConfirmation) -> Result<usize, Err> {
+        (**self).flush_namespace(prefix, confirm)
+    }
+}
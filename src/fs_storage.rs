@@ -0,0 +1,607 @@
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::blob::{BlobReader, BlobStorage};
+use crate::durability::{Durability, SyncState};
+use crate::error::BuilderError;
+use crate::export::{EntryIter, ExportEntry, ExportIter, ExportStorage};
+use crate::gc::GarbageCollect;
+use crate::hooks::{Hooks, StorageHook};
+use crate::storage::{CloseFlag, Err, Storage};
+
+const DATA: &str = "data";
+const TTL: &str = "ttl";
+const TMP: &str = "tmp";
+
+// One file per key under `data/`, written to `tmp/` first and renamed into place. Keys with
+// a TTL get a sidecar under `ttl/` holding the expiry in unix millis.
+pub struct FsStorage<K, V>
+where
+    V: Into<String>,
+{
+    root: PathBuf,
+    // Held across each write so a value and its sidecar change together.
+    sync: Mutex<SyncState>,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> FsStorage<K, V>
+where
+    V: Into<String>,
+{
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn path(&self, dir: &str, name: &str) -> PathBuf {
+        self.root.join(dir).join(name)
+    }
+
+    fn write(&self, key: &str, value: &str, expires_at: Option<u64>) -> Result<(), Err> {
+        let name = file_name(key)?;
+        let mut sync = self.sync.lock().unwrap();
+        let fsync = sync.record_write();
+        match expires_at {
+            Some(expires_at) => {
+                self.replace(TTL, &name, expires_at.to_string().as_bytes(), fsync)?
+            }
+            None => {
+                remove(&self.path(TTL, &name))?;
+            }
+        }
+        self.replace(DATA, &name, value.as_bytes(), fsync)
+    }
+
+    fn replace(&self, dir: &str, name: &str, content: &[u8], fsync: bool) -> Result<(), Err> {
+        let tmp = self.path(TMP, name);
+        let mut file = File::create(&tmp)?;
+        file.write_all(content)?;
+        if fsync {
+            file.sync_all()?;
+        }
+        drop(file);
+        fs::rename(&tmp, self.path(dir, name))?;
+        if fsync {
+            sync_dir(&self.root.join(dir))?;
+        }
+        Ok(())
+    }
+
+    // The remaining TTL, or `None` for keys without one. Expired keys report `Some(ZERO)`.
+    fn ttl(&self, name: &str) -> Result<Option<Duration>, Err> {
+        match fs::read_to_string(self.path(TTL, name)) {
+            Ok(expires_at) => {
+                let expires_at: u64 = expires_at.trim().parse()?;
+                Ok(Some(Duration::from_millis(
+                    expires_at.saturating_sub(now_millis()),
+                )))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn read(&self, name: &str) -> Result<Option<(String, Option<Duration>)>, Err> {
+        let ttl = self.ttl(name)?;
+        if ttl.is_some_and(|ttl| ttl.is_zero()) {
+            self.purge(name)?;
+            return Ok(None);
+        }
+        match fs::read_to_string(self.path(DATA, name)) {
+            Ok(value) => Ok(Some((value, ttl))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Re-checks the sidecar under the write lock, so a concurrent `set` survives.
+    fn purge(&self, name: &str) -> Result<bool, Err> {
+        let _sync = self.sync.lock().unwrap();
+        if !self.ttl(name)?.is_some_and(|ttl| ttl.is_zero()) {
+            return Ok(false);
+        }
+        remove(&self.path(DATA, name))?;
+        remove(&self.path(TTL, name))?;
+        Ok(true)
+    }
+}
+
+impl<K, V> Storage<K, V> for FsStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        self.write(key.as_ref(), &value, None)?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        let expires_at = now_millis() + expire.as_millis() as u64;
+        self.write(key.as_ref(), &value, Some(expires_at))?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let name = file_name(key.as_ref())?;
+        let value = self.read(&name)?.map(|(value, _)| V::from(value));
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let name = file_name(key.as_ref())?;
+        let _sync = self.sync.lock().unwrap();
+        let expired = self.ttl(&name)?.is_some_and(|ttl| ttl.is_zero());
+        let removed = remove(&self.path(DATA, &name))?;
+        remove(&self.path(TTL, &name))?;
+        self.hooks.del(&key);
+        Ok((removed && !expired).then_some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        let name = file_name(key.as_ref())?;
+        Ok(self.read(&name)?.is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+}
+
+impl<K, V> ExportStorage<K, V> for FsStorage<K, V>
+where
+    K: AsRef<str> + From<String>,
+    V: Into<String> + From<String>,
+{
+    fn export_iter(&self) -> Result<ExportIter<'_, K, V>, Err> {
+        Ok(Box::new(
+            self.export_entries()?
+                .map(|entry| entry.map(|entry| (entry.key, entry.value))),
+        ))
+    }
+
+    // Walks `data/` in directory order, expired and concurrently removed keys are skipped.
+    fn export_entries<'a>(&'a self) -> Result<EntryIter<'a, K, V>, Err>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.closed.check()?;
+        let entries = fs::read_dir(self.root.join(DATA))?;
+        Ok(Box::new(entries.filter_map(move |entry| {
+            let entry = entry.map_err(Err::from).and_then(|entry| {
+                let name = entry.file_name().into_string().map_err(|name| {
+                    Err::from(format!("Unexpected file in FsStorage: {:?}", name))
+                })?;
+                let key = key_name(&name)?;
+                Ok(self.read(&name)?.map(|(value, ttl)| ExportEntry {
+                    key: K::from(key),
+                    value: V::from(value),
+                    ttl,
+                }))
+            });
+            entry.transpose()
+        })))
+    }
+}
+
+// Streams into its own file under `tmp/` without holding the write lock, so a large blob
+// doesn't block other writes, and renames it into place like `set`. Readers keep reading the
+// file they opened after it is replaced. Blobs aren't UTF-8 checked, a `get` of one that
+// isn't valid UTF-8 fails.
+impl<K, V> BlobStorage<K> for FsStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String>,
+{
+    fn get_reader(&self, key: K) -> Result<Option<BlobReader<'_>>, Err> {
+        self.closed.check()?;
+        let name = file_name(key.as_ref())?;
+        if self.ttl(&name)?.is_some_and(|ttl| ttl.is_zero()) {
+            self.purge(&name)?;
+            return Ok(None);
+        }
+        match File::open(self.path(DATA, &name)) {
+            Ok(file) => Ok(Some(BlobReader::new(file.metadata()?.len(), file))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set_from_reader(&self, key: K, reader: &mut dyn Read, len: u64) -> Result<(), Err> {
+        static BLOBS: AtomicU64 = AtomicU64::new(0);
+
+        self.closed.check()?;
+        let name = file_name(key.as_ref())?;
+        // File names never contain `.`, so this can't collide with `set`'s tmp files.
+        let tmp = self.path(
+            TMP,
+            &format!("blob.{}.{}", BLOBS.fetch_add(1, Ordering::SeqCst), name),
+        );
+        let written = File::create(&tmp).and_then(|mut file| {
+            let copied = io::copy(&mut reader.take(len), &mut file)?;
+            if copied < len {
+                return Err(io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "blob reader ended before its length",
+                ));
+            }
+            Ok(file)
+        });
+        let file = match written {
+            Ok(file) => file,
+            Err(e) => {
+                remove(&tmp)?;
+                return Err(e.into());
+            }
+        };
+
+        let mut sync = self.sync.lock().unwrap();
+        let fsync = sync.record_write();
+        if fsync {
+            file.sync_all()?;
+        }
+        drop(file);
+        remove(&self.path(TTL, &name))?;
+        fs::rename(&tmp, self.path(DATA, &name))?;
+        if fsync {
+            sync_dir(&self.root.join(DATA))?;
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> GarbageCollect for FsStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn gc_now(&self, batch_size: usize) -> Result<usize, Err> {
+        self.closed.check()?;
+        let mut removed = 0;
+        for entry in fs::read_dir(self.root.join(TTL))? {
+            if removed >= batch_size {
+                break;
+            }
+            let name = entry?.file_name();
+            if let Some(name) = name.to_str() {
+                if self.purge(name)? {
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+}
+
+// Keys become file names: ASCII letters, digits, `-` and `_` are kept, every other byte is
+// written as `%XX`, so no key can name a path outside `data/`.
+fn file_name(key: &str) -> Result<String, Err> {
+    if key.is_empty() {
+        return Err("Invalid key for FsStorage: empty key".into());
+    }
+    let mut name = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    Ok(name)
+}
+
+fn key_name(name: &str) -> Result<String, Err> {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut rest = name.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' && tail.len() >= 2 {
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&tail[..2])?, 16)?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+// Returns whether the file existed.
+fn remove(path: &Path) -> Result<bool, Err> {
+    match fs::remove_file(path) {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Persists the rename itself, directories cannot be opened for syncing on windows.
+fn sync_dir(dir: &Path) -> Result<(), Err> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct FsStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    path: Option<PathBuf>,
+    durability: Durability,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> FsStorageBuilder<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    pub fn new() -> Self {
+        FsStorageBuilder::default()
+    }
+
+    // The directory is created if it does not exist.
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    // Synced files are fsynced before the rename, and their directory after it.
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their TTL variants, in the
+    // order they were added.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if the path is missing, the durability is invalid, or the directory cannot be
+    // created.
+    pub fn build(self) -> FsStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build fs storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<FsStorage<K, V>, BuilderError> {
+        let root = self
+            .path
+            .clone()
+            .ok_or(BuilderError::MissingField("path"))?;
+        self.durability.validate()?;
+
+        for dir in [DATA, TTL, TMP] {
+            fs::create_dir_all(root.join(dir)).map_err(BuilderError::backend)?;
+        }
+        // Leftovers of writes interrupted before their rename.
+        for entry in fs::read_dir(root.join(TMP)).map_err(BuilderError::backend)? {
+            let entry = entry.map_err(BuilderError::backend)?;
+            fs::remove_file(entry.path()).map_err(BuilderError::backend)?;
+        }
+
+        Ok(FsStorage {
+            root,
+            sync: Mutex::new(SyncState::new(self.durability)),
+            closed: CloseFlag::default(),
+            hooks: self.hooks,
+            _marker: self._marker,
+        })
+    }
+}
+
+impl<K, V> Default for FsStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    fn default() -> Self {
+        Self {
+            path: None,
+            durability: Durability::Never,
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FsStorageBuilder::new().path(dir.path()).build();
+
+        let (key, value) = ("users/name", "Ferris".to_string());
+        storage.set(key, value.clone()).unwrap();
+        assert_eq!(storage.get(key).unwrap(), Some(value));
+        assert!(dir.path().join("data/users%2Fname").exists());
+
+        assert_eq!(storage.del(key).unwrap(), Some(key));
+        assert_eq!(storage.get(key).unwrap(), None);
+        assert_eq!(storage.del(key).unwrap(), None);
+    }
+
+    #[test]
+    fn test_set_ex() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FsStorageBuilder::new()
+            .path(dir.path())
+            .durability(Durability::EveryWrite)
+            .build();
+
+        let (key, value) = ("set_ex_test", "ok!".to_string());
+        storage
+            .set_ex(key, value.clone(), Duration::from_millis(100))
+            .unwrap();
+        storage
+            .set_ex("renewed", value.clone(), Duration::from_millis(100))
+            .unwrap();
+        storage.set("renewed", value.clone()).unwrap();
+        assert_eq!(storage.get(key).unwrap(), Some(value));
+
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(!storage.contains(key).unwrap());
+        assert!(storage.contains("renewed").unwrap());
+        assert!(!dir.path().join("data/set_ex_test").exists());
+    }
+
+    #[test]
+    fn test_blob() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FsStorageBuilder::<&str, String>::new()
+            .path(dir.path())
+            .build();
+        let blob: Vec<u8> = (0..100_000).map(|i| i as u8).collect();
+        storage
+            .set_from_reader("report", &mut &blob[..], blob.len() as u64)
+            .unwrap();
+        // Opened readers keep their file when the blob is replaced.
+        let mut reader = storage.get_reader("report").unwrap().unwrap();
+        storage
+            .set_from_reader("report", &mut &b"small"[..], 5)
+            .unwrap();
+        assert_eq!(reader.len(), blob.len() as u64);
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert!(read == blob);
+
+        assert!(storage
+            .set_from_reader("report", &mut &b"abc"[..], 4)
+            .is_err());
+        assert_eq!(storage.get("report").unwrap(), Some("small".to_string()));
+        assert_eq!(fs::read_dir(dir.path().join(TMP)).unwrap().count(), 0);
+        storage.del("report").unwrap();
+        assert!(storage.get_reader("report").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_gc_now() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FsStorageBuilder::<&str, String>::new()
+            .path(dir.path())
+            .build();
+        for key in ["a", "b", "c"] {
+            storage
+                .set_ex(key, "ok!".to_string(), Duration::from_millis(10))
+                .unwrap();
+        }
+        storage
+            .set_ex("d", "ok!".to_string(), Duration::from_secs(60))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(storage.gc_now(10).unwrap(), 3);
+        assert_eq!(fs::read_dir(dir.path().join(DATA)).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_export_iter() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FsStorageBuilder::<String, String>::new()
+            .path(dir.path())
+            .build();
+        storage
+            .set("config/a.b c".to_string(), "1".to_string())
+            .unwrap();
+        storage
+            .set_ex("ttl".to_string(), "2".to_string(), Duration::from_secs(60))
+            .unwrap();
+
+        let mut entries: Vec<_> = storage
+            .export_entries()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(entries[0].key, "config/a.b c");
+        assert_eq!(entries[0].ttl, None);
+        assert!(entries[1].ttl.is_some());
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("../etc/passwd").unwrap(), "%2E%2E%2Fetc%2Fpasswd");
+        assert_eq!(key_name("%2E%2E%2Fetc%2Fpasswd").unwrap(), "../etc/passwd");
+        assert_eq!(key_name(&file_name("naïve").unwrap()).unwrap(), "naïve");
+        assert!(file_name("").is_err());
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = FsStorageBuilder::<&str, String>::new().try_build();
+        assert!(matches!(resp, Err(BuilderError::MissingField("path"))));
+
+        let dir = tempfile::tempdir().unwrap();
+        let resp = FsStorageBuilder::<&str, String>::new()
+            .path(dir.path())
+            .durability(Durability::Batch(0))
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_hooks() {
+        struct Writes(Mutex<std::sync::mpsc::Sender<String>>);
+
+        impl StorageHook<&'static str, String> for Writes {
+            fn on_set(&self, key: &&'static str, value: &String) {
+                let write = format!("{}={}", key, value);
+                self.0.lock().unwrap().send(write).unwrap();
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let storage = FsStorageBuilder::new()
+            .path(dir.path())
+            .hook(Writes(Mutex::new(tx)))
+            .build();
+        storage.set("a", "1".to_string()).unwrap();
+        storage
+            .set_ex("b", "2".to_string(), Duration::from_secs(60))
+            .unwrap();
+        assert!(storage.set("", "3".to_string()).is_err());
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), ["a=1", "b=2"]);
+    }
+}
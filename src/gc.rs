@@ -0,0 +1,212 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::error::{BuilderError, StorageError};
+use crate::storage::Err;
+
+// Backends that emulate TTLs keep expired entries around until something removes them.
+pub trait GarbageCollect {
+    // Removes up to `batch_size` expired entries and returns how many were removed.
+    fn gc_now(&self, batch_size: usize) -> Result<usize, Err>;
+}
+
+impl<S: GarbageCollect + ?Sized> GarbageCollect for &S {
+    fn gc_now(&self, batch_size: usize) -> Result<usize, Err> {
+        (**self).gc_now(batch_size)
+    }
+}
+
+impl<S: GarbageCollect + ?Sized> GarbageCollect for Arc<S> {
+    fn gc_now(&self, batch_size: usize) -> Result<usize, Err> {
+        (**self).gc_now(batch_size)
+    }
+}
+
+type ErrorHook = Box<dyn Fn(&Err) + Send>;
+
+// Runs `gc_now` every interval on a background thread until stopped, dropped or the storage
+// is closed. Each run collects batch after batch until one comes back short.
+pub struct GcTask {
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+    collected: Arc<AtomicU64>,
+}
+
+impl GcTask {
+    // Entries removed since the task started.
+    pub fn collected(&self) -> u64 {
+        self.collected.load(Ordering::SeqCst)
+    }
+
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        drop(self.stop.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for GcTask {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+pub struct GcTaskBuilder<S> {
+    storage: S,
+    interval: Duration,
+    batch_size: usize,
+    on_error: Option<ErrorHook>,
+}
+
+impl<S: GarbageCollect + Send + 'static> GcTaskBuilder<S> {
+    pub fn new(storage: S) -> Self {
+        GcTaskBuilder {
+            storage,
+            interval: Duration::from_secs(60),
+            batch_size: 1000,
+            on_error: None,
+        }
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    // Called with errors from `gc_now`, the task keeps running and retries next interval. A
+    // closed storage ends the task instead.
+    pub fn on_error<F: Fn(&Err) + Send + 'static>(mut self, hook: F) -> Self {
+        self.on_error = Some(Box::new(hook));
+        self
+    }
+
+    // # Panics
+    // Panics if the interval or the batch size is zero.
+    pub fn build(self) -> GcTask {
+        match self.try_build() {
+            Ok(task) => task,
+            Err(e) => panic!("Failed to start gc task: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<GcTask, BuilderError> {
+        if self.interval.is_zero() {
+            return Err(BuilderError::invalid("interval", "must not be zero"));
+        }
+        if self.batch_size == 0 {
+            return Err(BuilderError::invalid("batch_size", "must be at least 1"));
+        }
+
+        let (stop, stopped) = mpsc::channel::<()>();
+        let collected = Arc::new(AtomicU64::new(0));
+        let counter = collected.clone();
+        let GcTaskBuilder {
+            storage,
+            interval,
+            batch_size,
+            on_error,
+        } = self;
+        let handle = thread::Builder::new()
+            .name("storage-gc".to_string())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    loop {
+                        match storage.gc_now(batch_size) {
+                            Ok(removed) => {
+                                counter.fetch_add(removed as u64, Ordering::SeqCst);
+                                if removed < batch_size {
+                                    break;
+                                }
+                            }
+                            Err(e) if is_closed(&e) => return,
+                            Err(e) => {
+                                if let Some(hook) = &on_error {
+                                    hook(&e);
+                                }
+                                break;
+                            }
+                        }
+                    }
+                }
+            })
+            .map_err(BuilderError::backend)?;
+
+        Ok(GcTask {
+            stop: Some(stop),
+            handle: Some(handle),
+            collected,
+        })
+    }
+}
+
+fn is_closed(e: &Err) -> bool {
+    e.downcast_ref::<StorageError>() == Some(&StorageError::Closed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    // Fails every run with `result`, counting the runs.
+    struct Failing {
+        runs: Arc<AtomicUsize>,
+        result: fn() -> Err,
+    }
+
+    impl GarbageCollect for Failing {
+        fn gc_now(&self, _batch_size: usize) -> Result<usize, Err> {
+            self.runs.fetch_add(1, Ordering::SeqCst);
+            Err((self.result)())
+        }
+    }
+
+    fn start(result: fn() -> Err) -> (GcTask, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let errors = Arc::new(AtomicUsize::new(0));
+        let counter = errors.clone();
+        let storage = Failing {
+            runs: runs.clone(),
+            result,
+        };
+        let task = GcTaskBuilder::new(storage)
+            .interval(Duration::from_millis(5))
+            .on_error(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .build();
+        (task, runs, errors)
+    }
+
+    #[test]
+    fn test_closed_storage_ends_task() {
+        let (task, runs, errors) = start(|| StorageError::Closed.into());
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(errors.load(Ordering::SeqCst), 0);
+        task.stop();
+    }
+
+    #[test]
+    fn test_errors_are_retried() {
+        let (task, runs, errors) = start(|| "disk full".into());
+        thread::sleep(Duration::from_millis(100));
+        task.stop();
+        assert!(runs.load(Ordering::SeqCst) > 1);
+        assert_eq!(errors.load(Ordering::SeqCst), runs.load(Ordering::SeqCst));
+    }
+}
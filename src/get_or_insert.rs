@@ -0,0 +1,87 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::storage::{Err, Storage};
+
+// Cache-aside without the race: when two callers miss at once, both end up with the value
+// that was stored first.
+pub trait GetOrInsertStorage<K, V>: Storage<K, V> {
+    // Writes the value only if the key is missing, returns whether it did.
+    fn set_nx(&self, key: K, value: V, expire: Option<Duration>) -> Result<bool, Err>;
+
+    fn get_or_insert_with<F>(&self, key: K, f: F) -> Result<V, Err>
+    where
+        K: Clone,
+        V: Clone,
+        F: FnOnce() -> V,
+    {
+        get_or_insert(self, key, None, f)
+    }
+
+    // Like `get_or_insert_with`, a value computed here expires after `expire`.
+    fn get_or_insert_with_ex<F>(&self, key: K, expire: Duration, f: F) -> Result<V, Err>
+    where
+        K: Clone,
+        V: Clone,
+        F: FnOnce() -> V,
+    {
+        get_or_insert(self, key, Some(expire), f)
+    }
+}
+
+// `f` runs at most once, a lost race re-reads the winner's value.
+fn get_or_insert<K, V, S, F>(storage: &S, key: K, expire: Option<Duration>, f: F) -> Result<V, Err>
+where
+    K: Clone,
+    V: Clone,
+    S: GetOrInsertStorage<K, V> + ?Sized,
+    F: FnOnce() -> V,
+{
+    let mut f = Some(f);
+    let mut computed = None;
+    loop {
+        if let Some(value) = storage.get(key.clone())? {
+            return Ok(value);
+        }
+        let value = match computed.take() {
+            Some(value) => value,
+            None => (f.take().expect("computed once"))(),
+        };
+        if storage.set_nx(key.clone(), value.clone(), expire)? {
+            return Ok(value);
+        }
+        computed = Some(value);
+    }
+}
+
+macro_rules! forward_get_or_insert {
+    ($($ty:ty),*) => {
+        $(
+            impl<K, V, S: GetOrInsertStorage<K, V> + ?Sized> GetOrInsertStorage<K, V> for $ty {
+                fn set_nx(&self, key: K, value: V, expire: Option<Duration>) -> Result<bool, Err> {
+                    (**self).set_nx(key, value, expire)
+                }
+
+                fn get_or_insert_with<F>(&self, key: K, f: F) -> Result<V, Err>
+                where
+                    K: Clone,
+                    V: Clone,
+                    F: FnOnce() -> V,
+                {
+                    (**self).get_or_insert_with(key, f)
+                }
+
+                fn get_or_insert_with_ex<F>(&self, key: K, expire: Duration, f: F) -> Result<V, Err>
+                where
+                    K: Clone,
+                    V: Clone,
+                    F: FnOnce() -> V,
+                {
+                    (**self).get_or_insert_with_ex(key, expire, f)
+                }
+            }
+        )*
+    };
+}
+
+forward_get_or_insert!(&S, Box<S>, Arc<S>);
@@ -0,0 +1,346 @@
+use std::fs;
+use std::marker::PhantomData;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use git2::{ErrorCode, Index, Repository, Signature};
+
+use crate::error::BuilderError;
+use crate::hooks::{Hooks, StorageHook};
+use crate::storage::{CloseFlag, Err, Storage};
+
+pub struct GitStorage<K, V>
+where
+    V: Into<String>,
+{
+    repo: Mutex<Repository>,
+    workdir: PathBuf,
+    reference: Option<String>,
+    author: (String, String),
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> GitStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    pub fn get_at(&self, key: K, reference: &str) -> Result<Option<V>, Err> {
+        self.read_at(key.as_ref(), reference)
+    }
+
+    fn read_at(&self, key: &str, reference: &str) -> Result<Option<V>, Err> {
+        let path = relative_path(key)?;
+        let repo = self.repo.lock().unwrap();
+
+        let tree = repo.revparse_single(reference)?.peel_to_tree()?;
+        let entry = match tree.get_path(&path) {
+            Ok(entry) => entry,
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let blob = entry.to_object(&repo)?.peel_to_blob()?;
+        Ok(Some(String::from_utf8(blob.content().to_vec())?.into()))
+    }
+
+    // The value at `reference` when there is one, otherwise in the work tree.
+    fn read(&self, key: &str) -> Result<Option<V>, Err> {
+        if let Some(reference) = &self.reference {
+            return self.read_at(key, reference);
+        }
+
+        let path = relative_path(key)?;
+        match fs::read_to_string(self.workdir.join(path)) {
+            Ok(content) => Ok(Some(content.into())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn commit(&self, repo: &Repository, index: &mut Index, message: &str) -> Result<(), Err> {
+        index.write()?;
+        let tree = repo.find_tree(index.write_tree()?)?;
+
+        let signature = Signature::now(&self.author.0, &self.author.1)?;
+        let parent = match repo.head() {
+            Ok(head) => Some(head.peel_to_commit()?),
+            Err(e) if e.code() == ErrorCode::UnbornBranch || e.code() == ErrorCode::NotFound => {
+                None
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let parents = parent.iter().collect::<Vec<_>>();
+
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )?;
+        Ok(())
+    }
+}
+
+impl<K, V> Storage<K, V> for GitStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        let path = relative_path(key.as_ref())?;
+        let repo = self.repo.lock().unwrap();
+
+        let file = self.workdir.join(&path);
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let value: String = value.into();
+        fs::write(&file, &value)?;
+
+        let mut index = repo.index()?;
+        index.add_path(&path)?;
+        self.commit(&repo, &mut index, &format!("set {}", key.as_ref()))?;
+        drop(repo);
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn set_ex(&self, _key: K, _value: V, _expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        Err("GitStorage does not support expiration".into())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let value = self.read(key.as_ref())?;
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let path = relative_path(key.as_ref())?;
+        let repo = self.repo.lock().unwrap();
+
+        let removed = match fs::remove_file(self.workdir.join(&path)) {
+            Ok(_) => {
+                let mut index = repo.index()?;
+                index.remove_path(&path)?;
+                self.commit(&repo, &mut index, &format!("del {}", key.as_ref()))?;
+                true
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+            Err(e) => return Err(e.into()),
+        };
+        drop(repo);
+        self.hooks.del(&key);
+        Ok(removed.then_some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        Ok(self.read(key.as_ref())?.is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+}
+
+// Keys are paths inside the work tree, anything that could escape it is rejected.
+fn relative_path(key: &str) -> Result<PathBuf, Err> {
+    let path = Path::new(key);
+    if key.is_empty()
+        || path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return Err(format!("Invalid key for GitStorage: {:?}", key).into());
+    }
+    Ok(path.to_path_buf())
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct GitStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    path: Option<PathBuf>,
+    reference: Option<String>,
+    author: (String, String),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> GitStorageBuilder<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    pub fn new() -> Self {
+        GitStorageBuilder::default()
+    }
+
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn reference(mut self, reference: &str) -> Self {
+        self.reference = Some(reference.to_string());
+        self
+    }
+
+    pub fn author(mut self, name: &str, email: &str) -> Self {
+        self.author = (name.to_string(), email.to_string());
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del`, in the order they were added.
+    // `get_at` doesn't fire them.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if the path is missing, the repository cannot be opened or created, or it is bare.
+    pub fn build(self) -> GitStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build git storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<GitStorage<K, V>, BuilderError> {
+        let path = self
+            .path
+            .clone()
+            .ok_or(BuilderError::MissingField("path"))?;
+        if self.author.0.is_empty() || self.author.1.is_empty() {
+            return Err(BuilderError::invalid(
+                "author",
+                "name and email must not be empty",
+            ));
+        }
+
+        let repo = match Repository::open(&path) {
+            Ok(repo) => repo,
+            Err(e) if e.code() == ErrorCode::NotFound => {
+                Repository::init(&path).map_err(BuilderError::backend)?
+            }
+            Err(e) => return Err(BuilderError::backend(e)),
+        };
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| BuilderError::invalid("path", "bare repositories are not supported"))?
+            .to_path_buf();
+
+        Ok(GitStorage {
+            repo: Mutex::new(repo),
+            workdir,
+            reference: self.reference,
+            author: self.author,
+            closed: CloseFlag::default(),
+            hooks: self.hooks,
+            _marker: self._marker,
+        })
+    }
+}
+
+impl<K, V> Default for GitStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    fn default() -> Self {
+        Self {
+            path: None,
+            reference: None,
+            author: (
+                "storage-trait".to_string(),
+                "storage-trait@localhost".to_string(),
+            ),
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorageBuilder::new().path(dir.path()).build();
+
+        let (key, value) = ("config/name", "Ferris".to_string());
+        let _ = storage.set(key, value.clone());
+        let resp = storage.get(key).unwrap();
+        assert_eq!(resp, Some(value));
+
+        let _ = storage.del(key).unwrap();
+        let resp = storage.get(key).unwrap();
+        assert_eq!(resp, None);
+    }
+
+    #[test]
+    fn test_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorageBuilder::new().path(dir.path()).build();
+
+        storage.set("name", "Ferris".to_string()).unwrap();
+        storage.set("name", "Corro".to_string()).unwrap();
+        assert_eq!(
+            storage.get_at("name", "HEAD~1").unwrap(),
+            Some("Ferris".to_string())
+        );
+        assert_eq!(
+            storage.get_at("name", "HEAD").unwrap(),
+            Some("Corro".to_string())
+        );
+
+        let reader = GitStorageBuilder::<&str, String>::new()
+            .path(dir.path())
+            .reference("HEAD~1")
+            .build();
+        assert_eq!(reader.get("name").unwrap(), Some("Ferris".to_string()));
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = GitStorageBuilder::<&str, String>::new().try_build();
+        assert!(matches!(resp, Err(BuilderError::MissingField("path"))));
+
+        let dir = tempfile::tempdir().unwrap();
+        let resp = GitStorageBuilder::<&str, String>::new()
+            .path(dir.path())
+            .author("", "")
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = GitStorageBuilder::new().path(dir.path()).build();
+
+        assert!(storage.set("../escape", "Ferris".to_string()).is_err());
+        assert!(storage.set("/etc/passwd", "Ferris".to_string()).is_err());
+    }
+}
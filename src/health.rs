@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+// What `Storage::health_check` found. A storage that can't serve requests returns an error
+// instead, so readiness probes only need `is_ok()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HealthStatus {
+    // Round trip of the probe, zero for in-process backends.
+    pub latency: Duration,
+    // Parts that failed without taking the storage down, e.g. a cache tier or a replica.
+    pub degraded: Vec<String>,
+}
+
+impl HealthStatus {
+    pub fn new(latency: Duration) -> Self {
+        HealthStatus {
+            latency,
+            degraded: Vec::new(),
+        }
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        !self.degraded.is_empty()
+    }
+
+    // Folds in the status of a part, its failure recorded under `name`.
+    pub(crate) fn part<E: std::fmt::Display>(&mut self, name: &str, part: Result<HealthStatus, E>) {
+        match part {
+            Ok(part) => self.absorb(name, part),
+            Err(e) => self.degraded.push(format!("{}: {}", name, e)),
+        }
+    }
+
+    // Takes the slower latency and the part's degradations, prefixed with `name`.
+    pub(crate) fn absorb(&mut self, name: &str, part: HealthStatus) {
+        self.latency = self.latency.max(part.latency);
+        self.degraded.extend(
+            part.degraded
+                .into_iter()
+                .map(|degraded| format!("{}: {}", name, degraded)),
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::health::HealthStatus;
+use crate::options::OpOptions;
+use crate::storage::{Err, Storage};
+
+// Observes successful operations, e.g. for audit logs or broadcasting invalidations. Hooks
+// run on the calling thread after the backend answered, so they should be quick; the
+// methods they don't override do nothing. Only `Storage` methods fire them, batches and
+// `*_with_opts` included. Writes through the capability traits (`AtomicStorage`,
+// `CounterStorage`, `UpdateStorage`, `MergeStorage`, ...), scripts and transactions don't.
+pub trait StorageHook<K, V>: Send + Sync {
+    fn on_set(&self, _key: &K, _value: &V) {}
+
+    fn on_get(&self, _key: &K, _value: Option<&V>) {}
+
+    fn on_del(&self, _key: &K) {}
+}
+
+// The hooks registered with `hook` on a builder. Clones share the list.
+pub struct Hooks<K, V>(Arc<Vec<Arc<dyn StorageHook<K, V>>>>);
+
+impl<K, V> Hooks<K, V> {
+    pub(crate) fn push<H: StorageHook<K, V> + 'static>(&mut self, hook: H) {
+        let mut hooks = self.0.as_ref().clone();
+        hooks.push(Arc::new(hook));
+        self.0 = Arc::new(hooks);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn set(&self, key: &K, value: &V) {
+        self.0.iter().for_each(|hook| hook.on_set(key, value));
+    }
+
+    // For backends that gave the value away, `value` is only called when there are hooks.
+    #[cfg_attr(
+        not(any(
+            feature = "nats",
+            feature = "sled",
+            feature = "fs",
+            feature = "persy",
+            feature = "git",
+            feature = "cloudflare"
+        )),
+        allow(dead_code)
+    )]
+    pub(crate) fn set_with<F: FnOnce() -> V>(&self, key: &K, value: F) {
+        if !self.is_empty() {
+            self.set(key, &value());
+        }
+    }
+
+    pub(crate) fn get(&self, key: &K, value: Option<&V>) {
+        self.0.iter().for_each(|hook| hook.on_get(key, value));
+    }
+
+    pub(crate) fn del(&self, key: &K) {
+        self.0.iter().for_each(|hook| hook.on_del(key));
+    }
+}
+
+impl<K, V> Default for Hooks<K, V> {
+    fn default() -> Self {
+        Hooks(Arc::new(Vec::new()))
+    }
+}
+
+impl<K, V> Clone for Hooks<K, V> {
+    fn clone(&self) -> Self {
+        Hooks(self.0.clone())
+    }
+}
+
+impl<K, V> Debug for Hooks<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Hooks").field(&self.0.len()).finish()
+    }
+}
+
+// Fires hooks around any backend, for the ones whose builder takes none.
+pub struct HookedStorage<S, K, V> {
+    inner: S,
+    hooks: Hooks<K, V>,
+}
+
+impl<S, K, V> HookedStorage<S, K, V> {
+    pub fn new(inner: S) -> Self {
+        HookedStorage {
+            inner,
+            hooks: Hooks::default(),
+        }
+    }
+
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<K, V, S> Storage<K, V> for HookedStorage<S, K, V>
+where
+    K: Clone,
+    V: Clone,
+    S: Storage<K, V>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.inner.set(key.clone(), value.clone())?;
+        self.hooks.set(&key, &value);
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.inner.set_ex(key.clone(), value.clone(), expire)?;
+        self.hooks.set(&key, &value);
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        let value = self.inner.get(key.clone())?;
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        let removed = self.inner.del(key.clone())?;
+        self.hooks.del(&key);
+        Ok(removed)
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.inner.contains(key)
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.inner.close()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.inner.health_check()
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        let values = self.inner.get_many(keys.clone())?;
+        for (key, value) in keys.iter().zip(&values) {
+            self.hooks.get(key, value.as_ref());
+        }
+        Ok(values)
+    }
+
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.inner.set_many(entries.clone())?;
+        for (key, value) in &entries {
+            self.hooks.set(key, value);
+        }
+        Ok(())
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        let removed = self.inner.del_many(keys.clone())?;
+        keys.iter().for_each(|key| self.hooks.del(key));
+        Ok(removed)
+    }
+
+    fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+        self.inner.set_with_opts(key.clone(), value.clone(), opts)?;
+        self.hooks.set(&key, &value);
+        Ok(())
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        let value = self.inner.get_with_opts(key.clone(), opts)?;
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        let removed = self.inner.del_with_opts(key.clone(), opts)?;
+        self.hooks.del(&key);
+        Ok(removed)
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        self.inner.contains_with_opts(key, opts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_storage::MockStorage;
+    use std::sync::Mutex;
+
+    // Records every hook call as a line.
+    #[derive(Clone, Default)]
+    pub(crate) struct Audit(pub(crate) Arc<Mutex<Vec<String>>>);
+
+    impl StorageHook<&'static str, u32> for Audit {
+        fn on_set(&self, key: &&'static str, value: &u32) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("set {}={}", key, value));
+        }
+
+        fn on_get(&self, key: &&'static str, value: Option<&u32>) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("get {}={:?}", key, value));
+        }
+
+        fn on_del(&self, key: &&'static str) {
+            self.0.lock().unwrap().push(format!("del {}", key));
+        }
+    }
+
+    #[test]
+    fn test_hooked() {
+        let audit = Audit::default();
+        let mock = MockStorage::new();
+        mock.fail_nth(3, "down");
+        let storage = HookedStorage::new(mock).hook(audit.clone());
+
+        storage.set("a", 1).unwrap();
+        storage.get("a").unwrap();
+        storage.get_many(vec!["b"]).unwrap();
+        // Failed calls don't fire.
+        assert!(storage.del("a").is_err());
+        storage.del("a").unwrap();
+
+        assert_eq!(
+            *audit.0.lock().unwrap(),
+            ["set a=1", "get a=Some(1)", "get b=None", "del a"]
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use crate::health::HealthStatus;
+use crate::options::OpOptions;
+use crate::storage::{Err, Storage};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Jitter {
+    // TTLs move by up to this amount in either direction.
+    Fixed(#[cfg_attr(feature = "serde", serde(with = "crate::config::secs"))] Duration),
+    // TTLs move by up to this fraction of themselves, clamped to `0.0..=1.0`.
+    Ratio(f64),
+}
+
+impl Jitter {
+    // Never shorter than a millisecond, so a jittered key still gets written.
+    pub fn apply(self, expire: Duration) -> Duration {
+        let spread = match self {
+            Jitter::Fixed(spread) => spread.as_secs_f64(),
+            Jitter::Ratio(ratio) => expire.as_secs_f64() * ratio.clamp(0.0, 1.0),
+        };
+        let offset = (random_unit() * 2.0 - 1.0) * spread;
+        let secs = (expire.as_secs_f64() + offset).max(0.001);
+        Duration::from_secs_f64(secs)
+    }
+}
+
+// TTL settings of a backend's builder, so call sites don't pass TTLs or jitter themselves.
+// `default_ttl` applies to writes without a TTL of their own, `jitter` to every TTL.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TtlPolicy {
+    pub default_ttl: Option<Duration>,
+    pub jitter: Option<Jitter>,
+}
+
+impl TtlPolicy {
+    // The TTL to write with, given the call's own.
+    pub fn apply(&self, ttl: Option<Duration>) -> Option<Duration> {
+        let ttl = ttl.or(self.default_ttl)?;
+        Some(self.jitter.map_or(ttl, |jitter| jitter.apply(ttl)))
+    }
+}
+
+// Spreads the TTL of every `set_ex` so keys written together don't expire together.
+pub struct JitterStorage<S> {
+    inner: S,
+    jitter: Jitter,
+}
+
+impl<S> JitterStorage<S> {
+    pub fn new(inner: S, jitter: Jitter) -> Self {
+        let jitter = match jitter {
+            Jitter::Ratio(ratio) => Jitter::Ratio(ratio.clamp(0.0, 1.0)),
+            jitter => jitter,
+        };
+        JitterStorage { inner, jitter }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn jitter(&self) -> Jitter {
+        self.jitter
+    }
+
+    pub fn jittered(&self, expire: Duration) -> Duration {
+        self.jitter.apply(expire)
+    }
+}
+
+impl<K, V, S> Storage<K, V> for JitterStorage<S>
+where
+    S: Storage<K, V>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.inner.set(key, value)
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.inner.set_ex(key, value, self.jittered(expire))
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.inner.get(key)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.inner.del(key)
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.inner.contains(key)
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.inner.close()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.inner.health_check()
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        self.inner.get_many(keys)
+    }
+
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.inner.set_many(entries)
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        self.inner.del_many(keys)
+    }
+
+    // A TTL override is jittered like `set_ex`.
+    fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+        match opts.ttl {
+            Some(ttl) => {
+                let opts = OpOptions {
+                    ttl: Some(self.jittered(ttl)),
+                    ..opts.clone()
+                };
+                self.inner.set_with_opts(key, value, &opts)
+            }
+            None => self.inner.set_with_opts(key, value, opts),
+        }
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        self.inner.get_with_opts(key, opts)
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        self.inner.del_with_opts(key, opts)
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        self.inner.contains_with_opts(key, opts)
+    }
+}
+
+// A fresh `RandomState` is randomly keyed, good enough to spread TTLs without a rand dependency.
+pub(crate) fn random_unit() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder {
+        ttls: Mutex<Vec<Duration>>,
+    }
+
+    impl Storage<&'static str, u32> for Recorder {
+        fn set(&self, _key: &'static str, _value: u32) -> Result<(), Err> {
+            Ok(())
+        }
+
+        fn set_ex(&self, _key: &'static str, _value: u32, expire: Duration) -> Result<(), Err> {
+            self.ttls.lock().unwrap().push(expire);
+            Ok(())
+        }
+
+        fn get(&self, _key: &'static str) -> Result<Option<u32>, Err> {
+            Ok(None)
+        }
+
+        fn del(&self, _key: &'static str) -> Result<Option<&'static str>, Err> {
+            Ok(None)
+        }
+
+        fn contains(&self, _key: &'static str) -> Result<bool, Err> {
+            Ok(false)
+        }
+    }
+
+    #[test]
+    fn test_ratio() {
+        let storage = JitterStorage::new(Recorder::default(), Jitter::Ratio(0.1));
+        for i in 0..100 {
+            storage.set_ex("key", i, Duration::from_secs(100)).unwrap();
+        }
+
+        let ttls = storage.inner().ttls.lock().unwrap();
+        assert!(ttls
+            .iter()
+            .all(|ttl| *ttl >= Duration::from_secs(90) && *ttl <= Duration::from_secs(110)));
+        assert!(ttls.iter().any(|ttl| *ttl != ttls[0]));
+    }
+
+    #[test]
+    fn test_ttl_policy() {
+        assert_eq!(TtlPolicy::default().apply(None), None);
+        let policy = TtlPolicy {
+            default_ttl: Some(Duration::from_secs(60)),
+            jitter: None,
+        };
+        assert_eq!(policy.apply(None), Some(Duration::from_secs(60)));
+        assert_eq!(
+            policy.apply(Some(Duration::from_secs(5))),
+            Some(Duration::from_secs(5))
+        );
+
+        let policy = TtlPolicy {
+            jitter: Some(Jitter::Ratio(0.5)),
+            ..policy
+        };
+        let ttl = policy.apply(None).unwrap();
+        assert!(ttl >= Duration::from_secs(30) && ttl <= Duration::from_secs(90));
+    }
+
+    #[test]
+    fn test_fixed() {
+        let storage = JitterStorage::new(
+            Mutex::new(HashMap::<&str, u32>::new()),
+            Jitter::Fixed(Duration::from_secs(5)),
+        );
+        for _ in 0..100 {
+            let ttl = storage.jittered(Duration::from_secs(2));
+            assert!(ttl > Duration::ZERO && ttl <= Duration::from_secs(7));
+        }
+        storage.set("key", 1).unwrap();
+        assert_eq!(storage.get("key").unwrap(), Some(1));
+    }
+}
@@ -0,0 +1,48 @@
+use crate::typed_storage::{Json, TypedStorage};
+
+// Stores `T` as JSON in any backend holding `String`s (or `Vec<u8>` with `R = Vec<u8>`).
+pub type JsonStorage<T, S, R = String> = TypedStorage<T, S, Json, R>;
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::DashMapStorageBuilder;
+    use crate::storage::Storage;
+    use serde::Deserialize;
+    use serde::Serialize;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Session {
+        user_id: u64,
+        roles: Vec<String>,
+    }
+
+    #[test]
+    fn test_get() {
+        let storage: JsonStorage<Session, _> =
+            JsonStorage::new(DashMapStorageBuilder::<&str, String>::new().build());
+
+        let session = Session {
+            user_id: 7,
+            roles: vec!["admin".to_string()],
+        };
+        storage.set("session", session.clone()).unwrap();
+        assert_eq!(storage.get("session").unwrap(), Some(session));
+        assert_eq!(
+            storage.inner().get("session").unwrap(),
+            Some(r#"{"user_id":7,"roles":["admin"]}"#.to_string())
+        );
+
+        storage.inner().set("broken", "{".to_string()).unwrap();
+        assert!(storage.get("broken").is_err());
+    }
+
+    #[test]
+    fn test_bytes() {
+        let storage: JsonStorage<Vec<u32>, _, Vec<u8>> =
+            JsonStorage::new(DashMapStorageBuilder::<&str, Vec<u8>>::new().build());
+
+        storage.set("numbers", vec![1, 2, 3]).unwrap();
+        assert_eq!(storage.get("numbers").unwrap(), Some(vec![1, 2, 3]));
+    }
+}
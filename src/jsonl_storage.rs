@@ -0,0 +1,490 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::durability::{Durability, SyncState};
+use crate::error::BuilderError;
+use crate::gc::GarbageCollect;
+use crate::hooks::{Hooks, StorageHook};
+use crate::storage::{CloseFlag, Err, Storage};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Record {
+    Set {
+        key: String,
+        value: Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expires_at: Option<u64>,
+    },
+    Del {
+        key: String,
+    },
+}
+
+struct Entry {
+    value: Value,
+    expires_at: Option<u64>,
+}
+
+impl Entry {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+struct Inner {
+    file: File,
+    entries: HashMap<String, Entry>,
+    records: usize,
+    sync: SyncState,
+}
+
+pub struct JsonlStorage<K, V> {
+    path: PathBuf,
+    compact_ratio: usize,
+    compact_min_records: usize,
+    inner: Mutex<Inner>,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> JsonlStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Serialize + DeserializeOwned,
+{
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Fsyncs the log regardless of the durability policy.
+    pub fn sync(&self) -> Result<(), Err> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.file.sync_data()?;
+        inner.sync.synced();
+        Ok(())
+    }
+
+    pub fn compact(&self) -> Result<(), Err> {
+        let mut inner = self.inner.lock().unwrap();
+        self.compact_locked(&mut inner)
+    }
+
+    fn compact_locked(&self, inner: &mut Inner) -> Result<(), Err> {
+        let now = now_millis();
+        inner.entries.retain(|_, entry| !entry.is_expired(now));
+
+        let tmp = self.path.with_extension("jsonl.tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        for (key, entry) in inner.entries.iter() {
+            let record = Record::Set {
+                key: key.clone(),
+                value: entry.value.clone(),
+                expires_at: entry.expires_at,
+            };
+            serde_json::to_writer(&mut writer, &record)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+
+        inner.file = OpenOptions::new().append(true).open(&self.path)?;
+        inner.records = inner.entries.len();
+        inner.sync.synced();
+        Ok(())
+    }
+
+    fn append(&self, record: Record) -> Result<(), Err> {
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.file.write_all(&line)?;
+        inner.records += 1;
+        if inner.sync.record_write() {
+            inner.file.sync_data()?;
+        }
+        match record {
+            Record::Set {
+                key,
+                value,
+                expires_at,
+            } => {
+                inner.entries.insert(key, Entry { value, expires_at });
+            }
+            Record::Del { key } => {
+                inner.entries.remove(&key);
+            }
+        }
+
+        if inner.records >= self.compact_min_records
+            && inner.records > inner.entries.len() * self.compact_ratio
+        {
+            self.compact_locked(&mut inner)?;
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, key: &str) -> Option<Value> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .entries
+            .get(key)
+            .filter(|entry| !entry.is_expired(now_millis()))
+            .map(|entry| entry.value.clone())
+    }
+}
+
+// Drops expired entries from memory, the log itself shrinks at the next compaction.
+impl<K, V> GarbageCollect for JsonlStorage<K, V> {
+    fn gc_now(&self, batch_size: usize) -> Result<usize, Err> {
+        self.closed.check()?;
+        let now = now_millis();
+        let mut inner = self.inner.lock().unwrap();
+        let expired: Vec<String> = inner
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.is_expired(now))
+            .map(|(key, _)| key.clone())
+            .take(batch_size)
+            .collect();
+        for key in &expired {
+            inner.entries.remove(key);
+        }
+        Ok(expired.len())
+    }
+}
+
+impl<K, V> Storage<K, V> for JsonlStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Serialize + DeserializeOwned,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        self.append(Record::Set {
+            key: key.as_ref().to_string(),
+            value: serde_json::to_value(&value)?,
+            expires_at: None,
+        })?;
+        self.hooks.set(&key, &value);
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        self.append(Record::Set {
+            key: key.as_ref().to_string(),
+            value: serde_json::to_value(&value)?,
+            expires_at: Some(now_millis() + expire.as_millis() as u64),
+        })?;
+        self.hooks.set(&key, &value);
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let value = match self.lookup(key.as_ref()) {
+            Some(value) => Some(serde_json::from_value(value)?),
+            None => None,
+        };
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let found = self.lookup(key.as_ref()).is_some();
+        if found {
+            self.append(Record::Del {
+                key: key.as_ref().to_string(),
+            })?;
+        }
+        self.hooks.del(&key);
+        Ok(found.then_some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        Ok(self.lookup(key.as_ref()).is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        if !self.closed.close() {
+            return Ok(());
+        }
+        self.inner.lock().unwrap().file.sync_all()?;
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct JsonlStorageBuilder<K, V> {
+    path: Option<PathBuf>,
+    compact_ratio: usize,
+    compact_min_records: usize,
+    durability: Durability,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> JsonlStorageBuilder<K, V>
+where
+    K: AsRef<str>,
+    V: Serialize + DeserializeOwned,
+{
+    pub fn new() -> Self {
+        JsonlStorageBuilder::default()
+    }
+
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    // Compact once the log holds `ratio` times more records than live entries.
+    pub fn compact_ratio(mut self, ratio: usize) -> Self {
+        self.compact_ratio = ratio;
+        self
+    }
+
+    pub fn compact_min_records(mut self, records: usize) -> Self {
+        self.compact_min_records = records;
+        self
+    }
+
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their TTL variants, in the
+    // order they were added.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if the path is missing, the compaction ratio or durability batch size is zero, or
+    // the file cannot be opened or replayed.
+    pub fn build(self) -> JsonlStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build jsonl storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<JsonlStorage<K, V>, BuilderError> {
+        let path = self
+            .path
+            .clone()
+            .ok_or(BuilderError::MissingField("path"))?;
+        if self.compact_ratio == 0 {
+            return Err(BuilderError::invalid("compact_ratio", "must be at least 1"));
+        }
+        self.durability.validate()?;
+
+        let (file, entries, records) = open(&path).map_err(BuilderError::Backend)?;
+        Ok(JsonlStorage {
+            path,
+            compact_ratio: self.compact_ratio,
+            compact_min_records: self.compact_min_records,
+            inner: Mutex::new(Inner {
+                file,
+                entries,
+                records,
+                sync: SyncState::new(self.durability),
+            }),
+            closed: CloseFlag::default(),
+            hooks: self.hooks,
+            _marker: self._marker,
+        })
+    }
+}
+
+fn open(path: &Path) -> Result<(File, HashMap<String, Entry>, usize), Err> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+
+    let file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)?;
+
+    let mut entries = HashMap::new();
+    let mut records = 0;
+    for line in BufReader::new(&file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line)? {
+            Record::Set {
+                key,
+                value,
+                expires_at,
+            } => {
+                entries.insert(key, Entry { value, expires_at });
+            }
+            Record::Del { key } => {
+                entries.remove(&key);
+            }
+        }
+        records += 1;
+    }
+    Ok((file, entries, records))
+}
+
+impl<K, V> Default for JsonlStorageBuilder<K, V> {
+    fn default() -> Self {
+        Self {
+            path: None,
+            compact_ratio: 2,
+            compact_min_records: 1024,
+            durability: Durability::Never,
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = JsonlStorageBuilder::new()
+            .path(dir.path().join("data.jsonl"))
+            .build();
+
+        let (key, value) = ("name", "Ferris".to_string());
+        let _ = storage.set(key, value.clone());
+        let resp = storage.get(key).unwrap();
+        assert_eq!(resp, Some(value));
+
+        let _ = storage.del(key).unwrap();
+        let resp = storage.get(key).unwrap();
+        assert_eq!(resp, None);
+    }
+
+    #[test]
+    fn test_gc_now() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = JsonlStorageBuilder::<&str, u32>::new()
+            .path(dir.path().join("data.jsonl"))
+            .build();
+        for key in ["a", "b", "c"] {
+            storage.set_ex(key, 1, Duration::from_millis(10)).unwrap();
+        }
+        storage.set("d", 1).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(storage.gc_now(2).unwrap(), 2);
+        assert_eq!(storage.gc_now(2).unwrap(), 1);
+        assert_eq!(storage.gc_now(2).unwrap(), 0);
+        assert!(storage.contains("d").unwrap());
+    }
+
+    #[test]
+    fn test_set_ex() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = JsonlStorageBuilder::new()
+            .path(dir.path().join("data.jsonl"))
+            .build();
+
+        let (key, value) = ("set_ex_test", "ok!".to_string());
+        storage
+            .set_ex(key, value.clone(), Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(storage.get(key).unwrap(), Some(value));
+        std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(storage.get(key).unwrap(), None);
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = JsonlStorageBuilder::<&str, String>::new().try_build();
+        assert!(matches!(resp, Err(BuilderError::MissingField("path"))));
+
+        let dir = tempfile::tempdir().unwrap();
+        let resp = JsonlStorageBuilder::<&str, String>::new()
+            .path(dir.path().join("data.jsonl"))
+            .compact_ratio(0)
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(_))));
+
+        let resp = JsonlStorageBuilder::<&str, String>::new()
+            .path(dir.path().join("data.jsonl"))
+            .durability(Durability::Batch(0))
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_close() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.jsonl");
+        let storage = JsonlStorageBuilder::<&str, u32>::new()
+            .path(&path)
+            .durability(Durability::EveryWrite)
+            .build();
+
+        storage.set("counter", 1).unwrap();
+        storage.close().unwrap();
+        assert!(storage.set("counter", 2).is_err());
+
+        let storage = JsonlStorageBuilder::<&str, u32>::new().path(&path).build();
+        assert_eq!(storage.get("counter").unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_reopen_and_compact() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.jsonl");
+        let storage = JsonlStorageBuilder::<&str, u32>::new()
+            .path(&path)
+            .compact_min_records(4)
+            .build();
+
+        for i in 0..10 {
+            storage.set("counter", i).unwrap();
+        }
+        storage.set("other", 42).unwrap();
+        drop(storage);
+
+        let lines = fs::read_to_string(&path).unwrap().lines().count();
+        assert!(lines < 11);
+
+        let storage = JsonlStorageBuilder::<&str, u32>::new().path(&path).build();
+        assert_eq!(storage.get("counter").unwrap(), Some(9));
+        assert_eq!(storage.get("other").unwrap(), Some(42));
+    }
+}
@@ -0,0 +1,158 @@
+use std::time::Duration;
+
+use crate::byte_codec::KeyCodec;
+use crate::health::HealthStatus;
+use crate::options::OpOptions;
+use crate::storage::{Err, Storage};
+
+// Exposes a string-keyed backend through structured keys, encoded by any `KeyCodec` that
+// writes UTF-8, e.g. `JoinedKeys` or a closure. Keys that encode to other bytes fail the call.
+pub struct KeyEncodedStorage<S, E> {
+    inner: S,
+    encoder: E,
+}
+
+impl<S, E> KeyEncodedStorage<S, E> {
+    pub fn new(inner: S, encoder: E) -> Self {
+        KeyEncodedStorage { inner, encoder }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn encoder(&self) -> &E {
+        &self.encoder
+    }
+
+    fn encode<K>(&self, key: &K) -> Result<String, Err>
+    where
+        E: KeyCodec<K>,
+    {
+        Ok(String::from_utf8(self.encoder.encode_key(key)?)?)
+    }
+
+    fn encode_all<K>(&self, keys: &[K]) -> Result<Vec<String>, Err>
+    where
+        E: KeyCodec<K>,
+    {
+        keys.iter().map(|key| self.encode(key)).collect()
+    }
+}
+
+impl<K, V, S, E> Storage<K, V> for KeyEncodedStorage<S, E>
+where
+    S: Storage<String, V>,
+    E: KeyCodec<K>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.inner.set(self.encode(&key)?, value)
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.inner.set_ex(self.encode(&key)?, value, expire)
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.inner.get(self.encode(&key)?)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        Ok(self.inner.del(self.encode(&key)?)?.map(|_| key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.inner.contains(self.encode(&key)?)
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.inner.close()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.inner.health_check()
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        self.inner.get_many(self.encode_all(&keys)?)
+    }
+
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        let entries = entries
+            .into_iter()
+            .map(|(key, value)| Ok((self.encode(&key)?, value)))
+            .collect::<Result<_, Err>>()?;
+        self.inner.set_many(entries)
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        let removed = self.inner.del_many(self.encode_all(&keys)?)?;
+        Ok(keys
+            .into_iter()
+            .zip(removed)
+            .map(|(key, removed)| removed.map(|_| key))
+            .collect())
+    }
+
+    fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+        self.inner.set_with_opts(self.encode(&key)?, value, opts)
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        self.inner.get_with_opts(self.encode(&key)?, opts)
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        Ok(self
+            .inner
+            .del_with_opts(self.encode(&key)?, opts)?
+            .map(|_| key))
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        self.inner.contains_with_opts(self.encode(&key)?, opts)
+    }
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::byte_codec::JoinedKeys;
+    use crate::dashmap_storage::DashMapStorageBuilder;
+
+    #[test]
+    fn test_get() {
+        let storage = KeyEncodedStorage::new(
+            DashMapStorageBuilder::<String, String>::new().build(),
+            JoinedKeys::new(":").labels(["t", "u"]),
+        );
+
+        storage.set((42, 7), "Ferris".to_string()).unwrap();
+        assert_eq!(storage.get((42, 7)).unwrap(), Some("Ferris".to_string()));
+        assert_eq!(
+            storage.inner().get("t:42:u:7".to_string()).unwrap(),
+            Some("Ferris".to_string())
+        );
+        assert_eq!(storage.del((42, 7)).unwrap(), Some((42, 7)));
+        assert!(!storage.contains((42, 7)).unwrap());
+    }
+
+    #[test]
+    fn test_closure_encoder() {
+        struct UserKey {
+            tenant: u32,
+            user: u32,
+        }
+        let storage = KeyEncodedStorage::new(
+            DashMapStorageBuilder::<String, u32>::new().build(),
+            |key: &UserKey| format!("{}/{}", key.tenant, key.user),
+        );
+
+        storage.set(UserKey { tenant: 1, user: 2 }, 3).unwrap();
+        assert_eq!(storage.inner().get("1/2".to_string()).unwrap(), Some(3));
+    }
+}
@@ -1,11 +1,309 @@
+pub mod admin;
+pub mod append;
+pub mod atomic;
+pub mod blob;
+pub mod bulk;
+pub mod byte_codec;
+pub mod circuit_breaker_storage;
+pub mod collection;
+#[cfg(feature = "serde")]
+pub mod config;
+pub mod context;
+pub mod counter;
+#[cfg(any(
+    feature = "jsonl",
+    feature = "toml",
+    feature = "yaml",
+    feature = "persy",
+    feature = "fs",
+    feature = "sled"
+))]
+pub mod durability;
+#[cfg(any(
+    feature = "redis",
+    feature = "nats",
+    feature = "vault",
+    feature = "cloudflare",
+    feature = "postgres",
+    feature = "mongodb",
+    feature = "etcd",
+    feature = "scylla"
+))]
+mod env;
+pub mod error;
+pub mod expiry;
+pub mod export;
+pub mod failover_storage;
+pub mod flush;
+pub mod gc;
+pub mod get_or_insert;
+pub mod health;
+pub mod hooks;
+pub mod jitter_storage;
+pub mod key_encoder;
+pub mod lock;
+pub mod lookup;
+pub mod merge;
+pub mod metrics_storage;
+pub mod migrate;
+pub mod namespaced_storage;
+pub mod options;
+pub mod outbox;
+pub mod rate_limit;
+pub mod read_only_storage;
+pub mod registry;
+pub mod replicated_storage;
+pub mod retry_storage;
+pub mod scan;
+pub mod secret;
+pub mod sharded_storage;
+pub mod stats;
 pub mod storage;
+pub mod stream;
+pub mod tenant_storage;
+pub mod tiered_storage;
+pub mod timeout_storage;
+#[cfg(any(feature = "dashmap", feature = "redis"))]
+mod trace;
+#[cfg(feature = "codec")]
+pub mod typed_storage;
+pub mod update;
+pub mod versioned;
+pub mod wait;
+pub mod watch;
+pub mod write_behind_storage;
+pub mod write_through_storage;
+pub use admin::*;
+pub use append::*;
+pub use atomic::*;
+pub use blob::*;
+pub use bulk::*;
+pub use byte_codec::*;
+pub use circuit_breaker_storage::*;
+pub use collection::*;
+#[cfg(feature = "serde")]
+pub use config::*;
+pub use context::*;
+pub use counter::*;
+#[cfg(any(
+    feature = "jsonl",
+    feature = "toml",
+    feature = "yaml",
+    feature = "persy",
+    feature = "fs"
+))]
+pub use durability::*;
+pub use error::*;
+pub use expiry::*;
+pub use export::*;
+pub use failover_storage::*;
+pub use flush::*;
+pub use gc::*;
+pub use get_or_insert::*;
+pub use health::*;
+pub use hooks::*;
+pub use jitter_storage::*;
+pub use key_encoder::*;
+pub use lock::*;
+pub use lookup::*;
+pub use merge::*;
+pub use metrics_storage::*;
+pub use migrate::*;
+pub use namespaced_storage::*;
+pub use options::*;
+pub use outbox::*;
+pub use rate_limit::*;
+pub use read_only_storage::*;
+pub use registry::*;
+pub use replicated_storage::*;
+pub use retry_storage::*;
+pub use scan::*;
+pub use secret::*;
+pub use sharded_storage::*;
+pub use stats::*;
 pub use storage::*;
+pub use stream::*;
+pub use tenant_storage::*;
+pub use tiered_storage::*;
+pub use timeout_storage::*;
+#[cfg(all(feature = "tracing", any(feature = "dashmap", feature = "redis")))]
+pub use trace::{set_trace_keys, trace_keys, TraceKeys};
+#[cfg(feature = "codec")]
+pub use typed_storage::*;
+pub use update::*;
+pub use versioned::*;
+pub use wait::*;
+pub use watch::*;
+pub use write_behind_storage::*;
+pub use write_through_storage::*;
 
-pub mod dashmap_storage;
-pub mod redis_storage;
+pub mod std_storage;
 
+#[cfg(feature = "dashmap")]
+pub mod dashmap_storage;
+#[cfg(feature = "dashmap")]
 pub use dashmap_storage::*;
+
+#[cfg(feature = "redis")]
+pub mod redis_blob;
+#[cfg(feature = "redis")]
+pub mod redis_hash_storage;
+#[cfg(feature = "redis")]
+pub mod redis_keyspace;
+#[cfg(feature = "redis")]
+pub mod redis_pipeline;
+#[cfg(feature = "redis")]
+pub mod redis_pool;
+#[cfg(feature = "redis")]
+pub mod redis_sentinel;
+#[cfg(feature = "redis")]
+pub mod redis_storage;
+#[cfg(feature = "redis")]
+pub mod redis_transaction;
+#[cfg(feature = "redis")]
+pub use redis_blob::*;
+#[cfg(feature = "redis")]
+pub use redis_hash_storage::*;
+#[cfg(feature = "redis")]
+pub use redis_keyspace::*;
+#[cfg(feature = "redis")]
+pub use redis_pipeline::*;
+#[cfg(feature = "redis")]
+pub use redis_pool::*;
+#[cfg(feature = "redis")]
+pub use redis_sentinel::*;
+#[cfg(feature = "redis")]
 pub use redis_storage::*;
+#[cfg(feature = "redis")]
+pub use redis_transaction::*;
+
+#[cfg(feature = "async")]
+pub mod async_redis_storage;
+#[cfg(feature = "async")]
+pub use async_redis_storage::*;
+
+#[cfg(feature = "nats")]
+pub mod nats_storage;
+#[cfg(feature = "nats")]
+pub use nats_storage::*;
+
+#[cfg(feature = "vault")]
+pub mod vault_storage;
+#[cfg(feature = "vault")]
+pub use vault_storage::*;
+
+#[cfg(feature = "git")]
+pub mod git_storage;
+#[cfg(feature = "git")]
+pub use git_storage::*;
+
+#[cfg(feature = "json")]
+pub mod json_storage;
+#[cfg(feature = "json")]
+pub use json_storage::*;
+
+#[cfg(feature = "jsonl")]
+pub mod jsonl_storage;
+#[cfg(feature = "jsonl")]
+pub use jsonl_storage::*;
+
+#[cfg(any(feature = "toml", feature = "yaml"))]
+pub mod config_file_storage;
+#[cfg(any(feature = "toml", feature = "yaml"))]
+pub use config_file_storage::*;
+
+#[cfg(feature = "persy")]
+pub mod persy_storage;
+#[cfg(feature = "persy")]
+pub use persy_storage::*;
+
+#[cfg(feature = "fs")]
+pub mod fs_storage;
+#[cfg(feature = "fs")]
+pub use fs_storage::*;
+
+#[cfg(feature = "sled")]
+pub mod sled_storage;
+#[cfg(feature = "sled")]
+pub use sled_storage::*;
+
+#[cfg(feature = "rocksdb")]
+pub mod rocksdb_storage;
+#[cfg(feature = "rocksdb")]
+pub use rocksdb_storage::*;
+
+#[cfg(feature = "lmdb")]
+pub mod lmdb_storage;
+#[cfg(feature = "lmdb")]
+pub use lmdb_storage::*;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_storage;
+#[cfg(feature = "sqlite")]
+pub use sqlite_storage::*;
+#[cfg(feature = "postgres")]
+pub mod postgres_storage;
+#[cfg(feature = "postgres")]
+pub use postgres_storage::*;
+
+#[cfg(feature = "mongodb")]
+pub mod mongodb_storage;
+#[cfg(feature = "mongodb")]
+pub use mongodb_storage::*;
+
+#[cfg(feature = "etcd")]
+pub mod etcd_storage;
+#[cfg(feature = "etcd")]
+pub use etcd_storage::*;
+
+#[cfg(feature = "scylla")]
+pub mod scylla_storage;
+#[cfg(feature = "scylla")]
+pub use scylla_storage::*;
+
+#[cfg(feature = "cloudflare")]
+pub mod cloudflare_storage;
+#[cfg(feature = "cloudflare")]
+pub use cloudflare_storage::*;
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub mod web_storage;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub use web_storage::*;
+
+#[cfg(feature = "compression")]
+pub mod compressed_storage;
+#[cfg(feature = "compression")]
+pub use compressed_storage::*;
+
+#[cfg(feature = "lru")]
+pub mod lru_storage;
+#[cfg(feature = "lru")]
+pub use lru_storage::*;
+
+#[cfg(feature = "moka")]
+pub mod moka_storage;
+#[cfg(feature = "moka")]
+pub use moka_storage::*;
+
+#[cfg(feature = "object_store")]
+pub mod object_store_storage;
+#[cfg(feature = "object_store")]
+pub use object_store_storage::*;
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod chaos_storage;
+#[cfg(any(test, feature = "test-util"))]
+pub use chaos_storage::*;
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock_storage;
+#[cfg(any(test, feature = "test-util"))]
+pub use mock_storage::*;
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod testkit;
+
+pub mod workload;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,853 @@
+use std::marker::PhantomData;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use heed::types::{Bytes, Str};
+use heed::{Database, Env, EnvOpenOptions, RoTxn, RwTxn};
+
+use crate::admin::AdminStorage;
+use crate::atomic::AtomicStorage;
+use crate::error::BuilderError;
+use crate::expiry::ExpiryStorage;
+use crate::export::{EntryIter, ExportEntry, ExportIter, ExportStorage};
+use crate::flush::{FlushConfirmation, FlushNamespace};
+use crate::gc::GarbageCollect;
+use crate::get_or_insert::GetOrInsertStorage;
+use crate::hooks::{Hooks, StorageHook};
+use crate::scan::{KeyIter, ScanStorage};
+use crate::storage::{CloseFlag, Err, Storage};
+use crate::versioned::{Version, VersionedStorage};
+
+// Records read per transaction by exports and scans, so they don't pin old pages.
+const PAGE: usize = 500;
+
+// The default map size, the most the database can grow to. LMDB only reserves address
+// space for it, the file grows as data is written.
+const MAP_SIZE: usize = 1 << 30;
+
+type Db = Database<Str, Bytes>;
+
+// Keys and records in an LMDB environment's unnamed database. Records use the layout of
+// `SledStorage`. Writes are serialized by LMDB, so every write and atomic operation is one
+// write transaction, and reads never wait for them. `read_txn` hands out a snapshot.
+#[derive(Clone)]
+pub struct LmdbStorage<K, V>
+where
+    V: Into<String>,
+{
+    env: Env,
+    db: Db,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    _marker: PhantomData<(K, V)>,
+}
+
+// A read transaction: every read sees the database as it was when the snapshot was taken,
+// and keys don't expire while it lives. Values can be borrowed from the memory map with
+// `get_str`. Keep snapshots short, LMDB can't reuse the pages they still see.
+pub struct LmdbSnapshot<'a, K, V> {
+    txn: RoTxn<'a>,
+    db: Db,
+    now: u64,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> LmdbSnapshot<'_, K, V>
+where
+    K: AsRef<str>,
+    V: From<String>,
+{
+    pub fn get(&self, key: K) -> Result<Option<V>, Err> {
+        Ok(self
+            .get_str(key.as_ref())?
+            .map(|value| V::from(value.to_string())))
+    }
+
+    // Results are in the order of `keys`, all read from the same snapshot.
+    pub fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        keys.into_iter().map(|key| self.get(key)).collect()
+    }
+
+    pub fn contains(&self, key: K) -> Result<bool, Err> {
+        Ok(self.get_str(key.as_ref())?.is_some())
+    }
+
+    // The value as it lies in the memory map, without a copy.
+    pub fn get_str(&self, key: &str) -> Result<Option<&str>, Err> {
+        match self.db.get(&self.txn, key)? {
+            Some(record) => Ok(decode(record, self.now)?.map(|(value, _)| value)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<K, V> LmdbStorage<K, V>
+where
+    V: Into<String>,
+{
+    // Reuses an environment and database the application already opened.
+    pub fn from_database(env: Env, db: Database<Str, Bytes>) -> Self {
+        LmdbStorage {
+            env,
+            db,
+            closed: CloseFlag::default(),
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    pub fn read_txn(&self) -> Result<LmdbSnapshot<'_, K, V>, Err> {
+        self.closed.check()?;
+        Ok(LmdbSnapshot {
+            txn: self.env.read_txn()?,
+            db: self.db,
+            now: now_millis(),
+            _marker: PhantomData,
+        })
+    }
+
+    fn lookup(&self, key: &str) -> Result<Option<String>, Err> {
+        let txn = self.env.read_txn()?;
+        match self.db.get(&txn, key)? {
+            Some(record) => Ok(decode(record, now_millis())?.map(|(value, _)| value.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    // Runs `f` in a write transaction and commits it, unless `f` fails.
+    fn write<T, F>(&self, f: F) -> Result<T, Err>
+    where
+        F: FnOnce(&mut RwTxn, Db) -> Result<T, Err>,
+    {
+        self.closed.check()?;
+        let mut txn = self.env.write_txn()?;
+        let out = f(&mut txn, self.db)?;
+        txn.commit()?;
+        Ok(out)
+    }
+
+    // Up to `PAGE` live records after `after` whose key starts with `prefix`, in key order.
+    fn page(&self, after: Option<&str>, prefix: &str) -> Result<Vec<Row>, Err> {
+        let txn = self.env.read_txn()?;
+        let now = now_millis();
+        let start = match after {
+            Some(after) => Bound::Excluded(after),
+            // LMDB rejects empty keys, even as a bound.
+            None if prefix.is_empty() => Bound::Unbounded,
+            None => Bound::Included(prefix),
+        };
+        let mut rows = Vec::new();
+        for entry in self.db.range(&txn, &(start, Bound::Unbounded))? {
+            let (key, record) = entry?;
+            if !key.starts_with(prefix) || rows.len() == PAGE {
+                break;
+            }
+            if let Some((value, ttl)) = decode(record, now)? {
+                rows.push((key.to_string(), value.to_string(), ttl));
+            }
+        }
+        Ok(rows)
+    }
+
+    // Live records whose key starts with `prefix`, a transaction per page.
+    fn rows<'a>(&'a self, prefix: String) -> impl Iterator<Item = Result<Row, Err>> + 'a {
+        let mut after: Option<String> = None;
+        let mut page = std::collections::VecDeque::new();
+        let mut done = false;
+        std::iter::from_fn(move || {
+            if page.is_empty() && !done {
+                match self.page(after.as_deref(), &prefix) {
+                    Ok(rows) => {
+                        done = rows.len() < PAGE;
+                        after = rows.last().map(|(key, _, _)| key.clone());
+                        page.extend(rows);
+                    }
+                    Err(e) => {
+                        done = true;
+                        return Some(Err(e));
+                    }
+                }
+            }
+            page.pop_front().map(Ok)
+        })
+    }
+}
+
+type Row = (String, String, Option<Duration>);
+
+// The live value under `key` in a write transaction.
+fn current(txn: &RwTxn, db: Db, key: &str) -> Result<Option<String>, Err> {
+    match db.get(txn, key)? {
+        Some(record) => Ok(decode(record, now_millis())?.map(|(value, _)| value.to_string())),
+        None => Ok(None),
+    }
+}
+
+impl<K, V> Storage<K, V> for LmdbStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        let value: String = value.into();
+        self.write(|txn, db| Ok(db.put(txn, key.as_ref(), &encode(&value, 0))?))?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        let value: String = value.into();
+        let expires_at = now_millis() + expire.as_millis() as u64;
+        self.write(|txn, db| Ok(db.put(txn, key.as_ref(), &encode(&value, expires_at))?))?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let value = self.lookup(key.as_ref())?.map(V::from);
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        let found = self.write(|txn, db| {
+            let found = current(txn, db, key.as_ref())?.is_some();
+            db.delete(txn, key.as_ref())?;
+            Ok(found)
+        })?;
+        self.hooks.del(&key);
+        Ok(found.then_some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        Ok(self.lookup(key.as_ref())?.is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+
+    // One read transaction, so the values are a consistent snapshot.
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        let snapshot = self.read_txn()?;
+        let values: Vec<Option<V>> = keys
+            .iter()
+            .map(|key| {
+                let value = snapshot.get_str(key.as_ref())?;
+                Ok(value.map(|value| V::from(value.to_string())))
+            })
+            .collect::<Result<_, Err>>()?;
+        for (key, value) in keys.iter().zip(&values) {
+            self.hooks.get(key, value.as_ref());
+        }
+        Ok(values)
+    }
+
+    // One write transaction, so either every entry is written or none.
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        let entries: Vec<(K, String)> = entries
+            .into_iter()
+            .map(|(key, value)| (key, value.into()))
+            .collect();
+        self.write(|txn, db| {
+            for (key, value) in &entries {
+                db.put(txn, key.as_ref(), &encode(value, 0))?;
+            }
+            Ok(())
+        })?;
+        for (key, value) in entries {
+            self.hooks.set_with(&key, || V::from(value));
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> ExportStorage<K, V> for LmdbStorage<K, V>
+where
+    K: AsRef<str> + From<String>,
+    V: Into<String> + From<String>,
+{
+    fn export_iter(&self) -> Result<ExportIter<'_, K, V>, Err> {
+        Ok(Box::new(
+            self.export_entries()?
+                .map(|entry| entry.map(|entry| (entry.key, entry.value))),
+        ))
+    }
+
+    // In key order, expired entries are skipped. Take a `read_txn` for a consistent view.
+    fn export_entries<'a>(&'a self) -> Result<EntryIter<'a, K, V>, Err>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.closed.check()?;
+        Ok(Box::new(self.rows(String::new()).map(|row| {
+            row.map(|(key, value, ttl)| ExportEntry {
+                key: K::from(key),
+                value: V::from(value),
+                ttl,
+            })
+        })))
+    }
+}
+
+impl<K, V> GarbageCollect for LmdbStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn gc_now(&self, batch_size: usize) -> Result<usize, Err> {
+        self.write(|txn, db| {
+            let now = now_millis();
+            let mut expired = Vec::new();
+            for entry in db.iter(txn)? {
+                if expired.len() >= batch_size {
+                    break;
+                }
+                let (key, record) = entry?;
+                if decode(record, now)?.is_none() {
+                    expired.push(key.to_string());
+                }
+            }
+            for key in &expired {
+                db.delete(txn, key)?;
+            }
+            Ok(expired.len())
+        })
+    }
+}
+
+// Each operation reads and writes in one write transaction, which LMDB runs one at a time.
+impl<K, V> AtomicStorage<K, V> for LmdbStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String> + PartialEq,
+{
+    fn compare_and_swap(&self, key: K, expected: Option<V>, new: Option<V>) -> Result<bool, Err> {
+        let expected: Option<String> = expected.map(Into::into);
+        let new: Option<String> = new.map(Into::into);
+        self.write(|txn, db| {
+            if current(txn, db, key.as_ref())? != expected {
+                return Ok(false);
+            }
+            match new {
+                Some(new) => db.put(txn, key.as_ref(), &encode(&new, 0))?,
+                None => {
+                    db.delete(txn, key.as_ref())?;
+                }
+            }
+            Ok(true)
+        })
+    }
+
+    fn swap(&self, key: K, value: V) -> Result<Option<V>, Err> {
+        let value: String = value.into();
+        let old = self.write(|txn, db| {
+            let old = current(txn, db, key.as_ref())?;
+            db.put(txn, key.as_ref(), &encode(&value, 0))?;
+            Ok(old)
+        })?;
+        Ok(old.map(V::from))
+    }
+
+    fn take(&self, key: K) -> Result<Option<V>, Err> {
+        let old = self.write(|txn, db| {
+            let old = current(txn, db, key.as_ref())?;
+            db.delete(txn, key.as_ref())?;
+            Ok(old)
+        })?;
+        Ok(old.map(V::from))
+    }
+}
+
+// Versions hash the stored string, checked and written in one write transaction.
+impl<K, V> VersionedStorage<K, V> for LmdbStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn get_versioned(&self, key: K) -> Result<Option<(V, Version)>, Err> {
+        self.closed.check()?;
+        Ok(self.lookup(key.as_ref())?.map(|value| {
+            let version = Version::of_bytes(value.as_bytes());
+            (V::from(value), version)
+        }))
+    }
+
+    fn set_if_version(&self, key: K, value: V, expected: Option<Version>) -> Result<bool, Err> {
+        let value: String = value.into();
+        self.write(|txn, db| {
+            let current = current(txn, db, key.as_ref())?;
+            if current.map(|value| Version::of_bytes(value.as_bytes())) != expected {
+                return Ok(false);
+            }
+            db.put(txn, key.as_ref(), &encode(&value, 0))?;
+            Ok(true)
+        })
+    }
+}
+
+impl<K, V> GetOrInsertStorage<K, V> for LmdbStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set_nx(&self, key: K, value: V, expire: Option<Duration>) -> Result<bool, Err> {
+        let value: String = value.into();
+        let expires_at = expire.map_or(0, |expire| now_millis() + expire.as_millis() as u64);
+        self.write(|txn, db| {
+            if current(txn, db, key.as_ref())?.is_some() {
+                return Ok(false);
+            }
+            db.put(txn, key.as_ref(), &encode(&value, expires_at))?;
+            Ok(true)
+        })
+    }
+}
+
+// `len` walks the database to skip expired records.
+impl<K, V> AdminStorage for LmdbStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn clear(&self) -> Result<(), Err> {
+        self.write(|txn, db| Ok(db.clear(txn)?))
+    }
+
+    fn len(&self) -> Result<usize, Err> {
+        self.closed.check()?;
+        let txn = self.env.read_txn()?;
+        let now = now_millis();
+        let mut len = 0;
+        for entry in self.db.iter(&txn)? {
+            let (_, record) = entry?;
+            if decode(record, now)?.is_some() {
+                len += 1;
+            }
+        }
+        Ok(len)
+    }
+}
+
+impl<K, V> ExpiryStorage<K> for LmdbStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String>,
+{
+    fn ttl(&self, key: K) -> Result<Option<Duration>, Err> {
+        self.closed.check()?;
+        let txn = self.env.read_txn()?;
+        match self.db.get(&txn, key.as_ref())? {
+            Some(record) => Ok(decode(record, now_millis())?.and_then(|(_, ttl)| ttl)),
+            None => Ok(None),
+        }
+    }
+
+    fn expire(&self, key: K, expire: Duration) -> Result<bool, Err> {
+        let expires_at = now_millis() + expire.as_millis() as u64;
+        self.retime(key.as_ref(), |_| Some(expires_at))
+    }
+
+    fn persist(&self, key: K) -> Result<bool, Err> {
+        self.retime(key.as_ref(), |ttl| ttl.map(|_| 0))
+    }
+}
+
+impl<K, V> LmdbStorage<K, V>
+where
+    V: Into<String>,
+{
+    // `expires_at` maps the live record's TTL to its new expiry, `None` leaves it alone.
+    fn retime<F>(&self, key: &str, expires_at: F) -> Result<bool, Err>
+    where
+        F: FnOnce(Option<Duration>) -> Option<u64>,
+    {
+        self.write(|txn, db| {
+            let Some(record) = db.get(txn, key)? else {
+                return Ok(false);
+            };
+            let Some((value, ttl)) = decode(record, now_millis())? else {
+                return Ok(false);
+            };
+            let Some(expires_at) = expires_at(ttl) else {
+                return Ok(false);
+            };
+            let retimed = encode(value, expires_at);
+            db.put(txn, key, &retimed)?;
+            Ok(true)
+        })
+    }
+}
+
+impl<K, V> ScanStorage<K> for LmdbStorage<K, V>
+where
+    K: From<String>,
+    V: Into<String>,
+{
+    // A prefix range in key order, a read transaction per page like the export.
+    fn scan_prefix(&self, prefix: &str) -> Result<KeyIter<'_, K>, Err> {
+        self.closed.check()?;
+        Ok(Box::new(
+            self.rows(prefix.to_string())
+                .map(|row| row.map(|(key, _, _)| K::from(key))),
+        ))
+    }
+}
+
+impl<K, V> FlushNamespace for LmdbStorage<K, V>
+where
+    V: Into<String>,
+{
+    // Collected and removed in one write transaction.
+    fn flush_namespace(&self, prefix: &str, confirm: &FlushConfirmation) -> Result<usize, Err> {
+        confirm.check(prefix)?;
+        self.write(|txn, db| {
+            // LMDB rejects empty keys, even as a prefix.
+            if prefix.is_empty() {
+                let removed = db.len(txn)?;
+                db.clear(txn)?;
+                return Ok(removed as usize);
+            }
+            let mut keys = Vec::new();
+            for entry in db.prefix_iter(txn, prefix)? {
+                let (key, _) = entry?;
+                keys.push(key.to_string());
+            }
+            for key in &keys {
+                db.delete(txn, key)?;
+            }
+            Ok(keys.len())
+        })
+    }
+}
+
+// Records are the expiry in unix millis (0 for none) followed by the value, as in
+// `SledStorage`.
+fn encode(value: &str, expires_at: u64) -> Vec<u8> {
+    let mut record = expires_at.to_be_bytes().to_vec();
+    record.extend_from_slice(value.as_bytes());
+    record
+}
+
+// Returns the value and its remaining TTL at `now`, `None` once expired.
+fn decode(record: &[u8], now: u64) -> Result<Option<(&str, Option<Duration>)>, Err> {
+    if record.len() < 8 {
+        return Err("LmdbStorage record is too short, was it written by another program?".into());
+    }
+    let (expires_at, value) = record.split_at(8);
+    let expires_at = u64::from_be_bytes(expires_at.try_into()?);
+    let ttl = match expires_at {
+        0 => None,
+        expires_at => match expires_at.checked_sub(now) {
+            Some(left) if left > 0 => Some(Duration::from_millis(left)),
+            _ => return Ok(None),
+        },
+    };
+    Ok(Some((std::str::from_utf8(value)?, ttl)))
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct LmdbStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    path: Option<PathBuf>,
+    map_size: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> LmdbStorageBuilder<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    pub fn new() -> Self {
+        LmdbStorageBuilder::default()
+    }
+
+    // The environment's directory, created if it doesn't exist.
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    // The most the database can grow to in bytes, a multiple of the page size. Defaults to
+    // 1 GiB, writes fail with `MDB_MAP_FULL` beyond it.
+    pub fn map_size(mut self, map_size: usize) -> Self {
+        self.map_size = Some(map_size);
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their TTL and batch variants.
+    // Clones of the storage share them.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if the path is missing, the map size is zero, or the environment cannot be
+    // opened.
+    pub fn build(self) -> LmdbStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build lmdb storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<LmdbStorage<K, V>, BuilderError> {
+        let path = self
+            .path
+            .clone()
+            .ok_or(BuilderError::MissingField("path"))?;
+        let map_size = self.map_size.unwrap_or(MAP_SIZE);
+        if map_size == 0 {
+            return Err(BuilderError::invalid("map_size", "must not be 0"));
+        }
+
+        std::fs::create_dir_all(&path).map_err(BuilderError::backend)?;
+        // Safety: LMDB maps the file into memory, which is undefined behavior if something
+        // other than LMDB truncates or rewrites it while it is open. heed hands out the same
+        // environment when a path is opened twice in this process.
+        let env = unsafe { EnvOpenOptions::new().map_size(map_size).open(&path) }
+            .map_err(BuilderError::backend)?;
+        let mut txn = env.write_txn().map_err(BuilderError::backend)?;
+        let db = env
+            .create_database(&mut txn, None)
+            .map_err(BuilderError::backend)?;
+        txn.commit().map_err(BuilderError::backend)?;
+
+        Ok(LmdbStorage {
+            hooks: self.hooks,
+            ..LmdbStorage::from_database(env, db)
+        })
+    }
+}
+
+impl<K, V> Default for LmdbStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    fn default() -> Self {
+        Self {
+            path: None,
+            map_size: None,
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::storage_conformance_tests!(
+        conformance,
+        LmdbStorageBuilder::<String, String>::new()
+            .path(tempfile::tempdir().unwrap().keep())
+            .build()
+    );
+
+    fn build<K: AsRef<str>>(dir: &tempfile::TempDir) -> LmdbStorage<K, String> {
+        LmdbStorageBuilder::new().path(dir.path()).build()
+    }
+
+    #[test]
+    fn test_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = build::<&str>(&dir);
+
+        let (key, value) = ("name", "Ferris".to_string());
+        storage.set(key, value.clone()).unwrap();
+        assert_eq!(storage.get(key).unwrap(), Some(value));
+        assert_eq!(storage.del(key).unwrap(), Some(key));
+        assert_eq!(storage.get(key).unwrap(), None);
+        assert_eq!(storage.del(key).unwrap(), None);
+    }
+
+    #[test]
+    fn test_set_ex() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = build::<&str>(&dir);
+
+        let (key, value) = ("set_ex_test", "ok!".to_string());
+        storage
+            .set_ex(key, value.clone(), Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(storage.get(key).unwrap(), Some(value));
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(!storage.contains(key).unwrap());
+        assert!(storage.is_empty().unwrap());
+        assert_eq!(storage.gc_now(10).unwrap(), 1);
+        assert_eq!(storage.gc_now(10).unwrap(), 0);
+
+        storage.set("a", "ok!".to_string()).unwrap();
+        assert!(!storage.persist("a").unwrap());
+        assert!(storage.expire("a", Duration::from_secs(60)).unwrap());
+        assert!(storage.ttl("a").unwrap().unwrap() > Duration::from_secs(59));
+        assert!(storage.persist("a").unwrap());
+        assert_eq!(storage.ttl("a").unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_txn() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = build::<&str>(&dir);
+        storage
+            .set_many(vec![("a", "1".to_string()), ("b", "2".to_string())])
+            .unwrap();
+
+        let snapshot = storage.read_txn().unwrap();
+        storage.set("a", "3".to_string()).unwrap();
+        storage.del("b").unwrap();
+        // The snapshot still sees the values from before the writes.
+        assert_eq!(
+            snapshot.get_many(vec!["a", "b", "c"]).unwrap(),
+            [Some("1".to_string()), Some("2".to_string()), None]
+        );
+        assert_eq!(snapshot.get_str("a").unwrap(), Some("1"));
+        drop(snapshot);
+
+        assert_eq!(
+            storage.get_many(vec!["a", "b"]).unwrap(),
+            [Some("3".to_string()), None]
+        );
+    }
+
+    #[test]
+    fn test_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = build::<&str>(&dir);
+        storage
+            .set_many(vec![("a", "1".to_string()), ("b", "2".to_string())])
+            .unwrap();
+        storage.close().unwrap();
+        assert!(storage.get("a").is_err());
+        drop(storage);
+
+        let storage = build::<String>(&dir);
+        let entries: Vec<(String, String)> =
+            storage.export_iter().unwrap().map(Result::unwrap).collect();
+        assert_eq!(
+            entries,
+            [
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "2".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scan_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = build::<String>(&dir);
+        let entries = (0..PAGE + 10)
+            .map(|n| (format!("tenant:1:{:04}", n), n.to_string()))
+            .collect();
+        storage.set_many(entries).unwrap();
+        storage.set("tenant:2:a".into(), "x".into()).unwrap();
+        storage.set("tenant:1".into(), "x".into()).unwrap();
+
+        let keys: Vec<String> = storage
+            .scan_prefix("tenant:1:")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(keys.len(), PAGE + 10);
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(storage.export_iter().unwrap().count(), PAGE + 12);
+
+        let confirm = FlushConfirmation::for_prefix("tenant:1:");
+        assert_eq!(
+            storage.flush_namespace("tenant:1:", &confirm).unwrap(),
+            PAGE + 10
+        );
+        assert_eq!(storage.len().unwrap(), 2);
+        let confirm = FlushConfirmation::for_prefix("");
+        assert_eq!(storage.flush_namespace("", &confirm).unwrap(), 2);
+        assert!(storage.is_empty().unwrap());
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = build::<&str>(&dir);
+
+        assert!(storage
+            .compare_and_swap("cas", None, Some("1".to_string()))
+            .unwrap());
+        assert!(!storage
+            .compare_and_swap("cas", None, Some("2".to_string()))
+            .unwrap());
+        let seen = storage
+            .fetch_update("cas", |current| {
+                current.map(|n| (n.parse::<u32>().unwrap() + 1).to_string())
+            })
+            .unwrap();
+        assert_eq!(seen, Some("1".to_string()));
+        assert_eq!(storage.get("cas").unwrap(), Some("2".to_string()));
+
+        storage
+            .set_ex("a", "1".to_string(), Duration::from_millis(10))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        // An expired value was never there.
+        assert_eq!(storage.swap("a", "2".to_string()).unwrap(), None);
+        assert_eq!(storage.take("a").unwrap(), Some("2".to_string()));
+        assert_eq!(storage.take("a").unwrap(), None);
+
+        assert!(storage.set_nx("b", "1".to_string(), None).unwrap());
+        assert!(!storage.set_nx("b", "2".to_string(), None).unwrap());
+    }
+
+    #[test]
+    fn test_versioned() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = build::<&str>(&dir);
+
+        assert!(storage.set_if_version("a", "1".to_string(), None).unwrap());
+        let (value, version) = storage.get_versioned("a").unwrap().unwrap();
+        assert_eq!(value, "1");
+        assert!(!storage.set_if_version("a", "2".to_string(), None).unwrap());
+        assert!(storage
+            .set_if_version("a", "2".to_string(), Some(version))
+            .unwrap());
+        assert!(!storage
+            .set_if_version("a", "3".to_string(), Some(version))
+            .unwrap());
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = LmdbStorageBuilder::<&str, String>::new().try_build();
+        assert!(matches!(resp, Err(BuilderError::MissingField("path"))));
+
+        let resp = LmdbStorageBuilder::<&str, String>::new()
+            .path("data.lmdb")
+            .map_size(0)
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(_))));
+    }
+}
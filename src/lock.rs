@@ -0,0 +1,168 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::options::OpOptions;
+use crate::storage::Err;
+
+// How long `lock` waits between attempts to take a held lock.
+pub const LOCK_RETRY: Duration = Duration::from_millis(50);
+
+// Mutual exclusion across processes sharing a backend. A lock is held under a random token
+// until it is released or its TTL runs out, so a crashed holder can't block others forever;
+// only the token's holder can renew or release it. Holders that may outlive the TTL must
+// `renew` in time, a lock that expired may already be someone else's.
+pub trait LockStorage<K> {
+    // Takes the lock for `token` if nobody holds it.
+    fn try_acquire(&self, key: K, token: &str, ttl: Duration) -> Result<bool, Err>;
+
+    // Resets the TTL, `false` if `token` no longer holds the lock.
+    fn renew(&self, key: K, token: &str, ttl: Duration) -> Result<bool, Err>;
+
+    // Frees the lock, `false` if `token` no longer held it.
+    fn release(&self, key: K, token: &str) -> Result<bool, Err>;
+
+    fn try_lock(&self, key: K, ttl: Duration) -> Result<Option<LockGuard<'_, Self, K>>, Err>
+    where
+        Self: Sized,
+        K: Clone,
+    {
+        let token = new_token();
+        if !self.try_acquire(key.clone(), &token, ttl)? {
+            return Ok(None);
+        }
+        Ok(Some(LockGuard {
+            storage: self,
+            key,
+            token,
+            released: false,
+        }))
+    }
+
+    // Waits until the lock is free, retrying every `LOCK_RETRY`.
+    fn lock(&self, key: K, ttl: Duration) -> Result<LockGuard<'_, Self, K>, Err>
+    where
+        Self: Sized,
+        K: Clone,
+    {
+        self.lock_with_opts(key, ttl, &OpOptions::default())
+    }
+
+    // Like `lock`, failing with `StorageError::Timeout` once the deadline passes.
+    fn lock_with_opts(
+        &self,
+        key: K,
+        ttl: Duration,
+        opts: &OpOptions,
+    ) -> Result<LockGuard<'_, Self, K>, Err>
+    where
+        Self: Sized,
+        K: Clone,
+    {
+        loop {
+            let left = opts.remaining()?;
+            if let Some(guard) = self.try_lock(key.clone(), ttl)? {
+                return Ok(guard);
+            }
+            thread::sleep(left.map_or(LOCK_RETRY, |left| left.min(LOCK_RETRY)));
+        }
+    }
+}
+
+impl<K, S: LockStorage<K> + ?Sized> LockStorage<K> for &S {
+    fn try_acquire(&self, key: K, token: &str, ttl: Duration) -> Result<bool, Err> {
+        (**self).try_acquire(key, token, ttl)
+    }
+
+    fn renew(&self, key: K, token: &str, ttl: Duration) -> Result<bool, Err> {
+        (**self).renew(key, token, ttl)
+    }
+
+    fn release(&self, key: K, token: &str) -> Result<bool, Err> {
+        (**self).release(key, token)
+    }
+}
+
+impl<K, S: LockStorage<K> + ?Sized> LockStorage<K> for Arc<S> {
+    fn try_acquire(&self, key: K, token: &str, ttl: Duration) -> Result<bool, Err> {
+        (**self).try_acquire(key, token, ttl)
+    }
+
+    fn renew(&self, key: K, token: &str, ttl: Duration) -> Result<bool, Err> {
+        (**self).renew(key, token, ttl)
+    }
+
+    fn release(&self, key: K, token: &str) -> Result<bool, Err> {
+        (**self).release(key, token)
+    }
+}
+
+// Releases the lock when dropped, errors are ignored then; call `release` to see them.
+pub struct LockGuard<'a, S: LockStorage<K>, K: Clone> {
+    storage: &'a S,
+    key: K,
+    token: String,
+    released: bool,
+}
+
+impl<S: LockStorage<K>, K: Clone> LockGuard<'_, S, K> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    // `false` means the lock expired and was lost, stop the work it protects.
+    pub fn renew(&self, ttl: Duration) -> Result<bool, Err> {
+        self.storage.renew(self.key.clone(), &self.token, ttl)
+    }
+
+    pub fn release(mut self) -> Result<bool, Err> {
+        self.released = true;
+        self.storage.release(self.key.clone(), &self.token)
+    }
+}
+
+impl<S: LockStorage<K>, K: Clone> Drop for LockGuard<'_, S, K> {
+    fn drop(&mut self) {
+        if !self.released {
+            let _ = self.storage.release(self.key.clone(), &self.token);
+        }
+    }
+}
+
+// 128 random bits, unique per process through the counter and across processes through
+// randomly keyed hashers and the clock.
+pub(crate) fn new_token() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    let mut halves = [0u64; 2];
+    for half in &mut halves {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(count);
+        hasher.write_u64(nanos);
+        hasher.write_u32(std::process::id());
+        *half = hasher.finish();
+    }
+    format!("{:016x}{:016x}", halves[0], halves[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token() {
+        let (a, b) = (new_token(), new_token());
+        assert_eq!(a.len(), 32);
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use crate::storage::{Err, Storage};
+
+// Reads and deletes by a borrowed form of the key, like `HashMap::get` takes a `&str` for a
+// `String` key, so hot paths don't allocate a key per call. `Q` is whatever `K` borrows as,
+// and must hash, compare and encode like the owned key. Hooks still see an owned key, which
+// is only made when there are hooks.
+pub trait LookupStorage<K, V, Q: ?Sized = K>: Storage<K, V> {
+    fn get_borrowed(&self, key: &Q) -> Result<Option<V>, Err>;
+
+    fn contains_borrowed(&self, key: &Q) -> Result<bool, Err>;
+
+    // Whether there was a value to delete.
+    fn del_borrowed(&self, key: &Q) -> Result<bool, Err>;
+}
+
+macro_rules! forward_lookup {
+    ($($ty:ty),*) => {
+        $(
+            impl<K, V, Q: ?Sized, S: LookupStorage<K, V, Q> + ?Sized> LookupStorage<K, V, Q>
+                for $ty
+            {
+                fn get_borrowed(&self, key: &Q) -> Result<Option<V>, Err> {
+                    (**self).get_borrowed(key)
+                }
+
+                fn contains_borrowed(&self, key: &Q) -> Result<bool, Err> {
+                    (**self).contains_borrowed(key)
+                }
+
+                fn del_borrowed(&self, key: &Q) -> Result<bool, Err> {
+                    (**self).del_borrowed(key)
+                }
+            }
+        )*
+    };
+}
+
+forward_lookup!(&S, Box<S>, Arc<S>);
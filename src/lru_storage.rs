@@ -0,0 +1,616 @@
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use linked_hash_map::LinkedHashMap;
+
+use crate::admin::AdminStorage;
+use crate::error::BuilderError;
+use crate::gc::GarbageCollect;
+use crate::hooks::{Hooks, StorageHook};
+use crate::stats::{StorageInfo, StorageStats};
+use crate::storage::{CloseFlag, Err, Storage};
+
+// Why an entry left the storage without being deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionCause {
+    // The least recently used entry made room for a new one, or a new entry outweighed
+    // `max_weight` on its own and was never stored.
+    Capacity,
+    // Its TTL ran out and it was purged on access, by `gc_now` or instead of a live entry.
+    Expired,
+}
+
+type EvictionHook<K, V> = Box<dyn Fn(&K, &V, EvictionCause) + Send + Sync>;
+
+type Weigher<K, V> = Box<dyn Fn(&K, &V) -> usize + Send + Sync>;
+
+struct Entry<V> {
+    value: V,
+    deadline: Option<Instant>,
+    weight: usize,
+}
+
+impl<V> Entry<V> {
+    fn expired(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|deadline| deadline <= now)
+    }
+}
+
+// The recency list and the total weight of what is on it.
+struct Entries<K, V> {
+    map: LinkedHashMap<K, Entry<V>>,
+    weight: usize,
+}
+
+impl<K: Hash + Eq, V> Entries<K, V> {
+    fn insert(&mut self, key: K, entry: Entry<V>) {
+        self.weight += entry.weight;
+        if let Some(old) = self.map.insert(key, entry) {
+            self.weight -= old.weight;
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Entry<V>> {
+        let entry = self.map.remove(key)?;
+        self.weight -= entry.weight;
+        Some(entry)
+    }
+
+    fn pop_front(&mut self) -> Option<(K, Entry<V>)> {
+        let (key, entry) = self.map.pop_front()?;
+        self.weight -= entry.weight;
+        Some((key, entry))
+    }
+}
+
+// An in-memory store bounded by a number of keys, a total weight, or both. Reads and writes
+// move a key to the back of the recency list, writes beyond a bound evict from the front.
+// One mutex guards the list, callbacks run after it is released so they may use the storage.
+pub struct LruStorage<K: Hash + Eq, V> {
+    entries: Mutex<Entries<K, V>>,
+    max_entries: Option<usize>,
+    max_weight: Option<usize>,
+    weigher: Option<Weigher<K, V>>,
+    on_evict: Option<EvictionHook<K, V>>,
+    hooks: Hooks<K, V>,
+    closed: CloseFlag,
+}
+
+impl<K: Hash + Eq, V> LruStorage<K, V> {
+    pub fn max_entries(&self) -> Option<usize> {
+        self.max_entries
+    }
+
+    pub fn max_weight(&self) -> Option<usize> {
+        self.max_weight
+    }
+
+    // Total weight of the stored entries, expired ones included until they are purged.
+    pub fn weight(&self) -> usize {
+        self.lock().weight
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries<K, V>> {
+        // The list is consistent after every call, a panicking callback can't poison it.
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn insert(&self, batch: Vec<(K, V, Option<Duration>)>) {
+        let now = Instant::now();
+        let mut evicted = Vec::new();
+        {
+            let mut entries = self.lock();
+            for (key, value, expire) in batch {
+                let weight = self
+                    .weigher
+                    .as_ref()
+                    .map_or(0, |weigher| weigher(&key, &value));
+                if self
+                    .max_weight
+                    .is_some_and(|max_weight| weight > max_weight)
+                {
+                    // It would push out everything else and still not fit. The value it
+                    // overwrites is gone all the same.
+                    entries.remove(&key);
+                    evicted.push((key, value, EvictionCause::Capacity));
+                    continue;
+                }
+                let deadline = expire.map(|expire| now + expire);
+                entries.insert(
+                    key,
+                    Entry {
+                        value,
+                        deadline,
+                        weight,
+                    },
+                );
+            }
+            while self.over(&entries) {
+                let Some((key, entry)) = entries.pop_front() else {
+                    break;
+                };
+                let cause = if entry.expired(now) {
+                    EvictionCause::Expired
+                } else {
+                    EvictionCause::Capacity
+                };
+                evicted.push((key, entry.value, cause));
+            }
+        }
+        self.evicted(evicted);
+    }
+
+    // Keys and values are only cloned for the hooks when there are any.
+    fn insert_hooked(&self, batch: Vec<(K, V, Option<Duration>)>)
+    where
+        K: Clone,
+        V: Clone,
+    {
+        if self.hooks.is_empty() {
+            return self.insert(batch);
+        }
+        let written: Vec<(K, V)> = batch
+            .iter()
+            .map(|(key, value, _)| (key.clone(), value.clone()))
+            .collect();
+        self.insert(batch);
+        for (key, value) in &written {
+            self.hooks.set(key, value);
+        }
+    }
+
+    fn over(&self, entries: &Entries<K, V>) -> bool {
+        self.max_entries
+            .is_some_and(|max_entries| entries.map.len() > max_entries)
+            || self
+                .max_weight
+                .is_some_and(|max_weight| entries.weight > max_weight)
+    }
+
+    // Removes `key` if it expired and returns what was removed.
+    fn purge(&self, entries: &mut Entries<K, V>, key: &K) -> Option<(K, V)>
+    where
+        K: Clone,
+    {
+        if !entries.map.get(key)?.expired(Instant::now()) {
+            return None;
+        }
+        entries.remove(key).map(|entry| (key.clone(), entry.value))
+    }
+
+    fn evicted(&self, evicted: Vec<(K, V, EvictionCause)>) {
+        if let Some(on_evict) = &self.on_evict {
+            for (key, value, cause) in &evicted {
+                on_evict(key, value, *cause);
+            }
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Storage<K, V> for LruStorage<K, V> {
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        self.insert_hooked(vec![(key, value, None)]);
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        self.insert_hooked(vec![(key, value, Some(expire))]);
+        Ok(())
+    }
+
+    // Marks the key as the most recently used.
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let mut entries = self.lock();
+        let value = match self.purge(&mut entries, &key) {
+            Some((expired, value)) => {
+                drop(entries);
+                self.evicted(vec![(expired, value, EvictionCause::Expired)]);
+                None
+            }
+            None => {
+                let value = entries
+                    .map
+                    .get_refresh(&key)
+                    .map(|entry| entry.value.clone());
+                drop(entries);
+                value
+            }
+        };
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let entry = self.lock().remove(&key);
+        self.hooks.del(&key);
+        Ok(entry
+            .filter(|entry| !entry.expired(Instant::now()))
+            .map(|_| key))
+    }
+
+    // Leaves the key's recency alone.
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        let entries = self.lock();
+        Ok(entries
+            .map
+            .get(&key)
+            .is_some_and(|entry| !entry.expired(Instant::now())))
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+
+    // One lock for the whole batch, evictions happen once it is in.
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.closed.check()?;
+        self.insert_hooked(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key, value, None))
+                .collect(),
+        );
+        Ok(())
+    }
+}
+
+// Weights are whatever the weigher counts, they show up as details next to the bounds.
+impl<K: Hash + Eq, V> StorageStats for LruStorage<K, V> {
+    fn stats(&self) -> Result<StorageInfo, Err> {
+        self.closed.check()?;
+        let (keys, weight) = {
+            let entries = self.lock();
+            (entries.map.len(), entries.weight)
+        };
+        let mut info = StorageInfo {
+            keys: keys as u64,
+            ..StorageInfo::default()
+        };
+        if let Some(max_entries) = self.max_entries {
+            info.detail("max_entries", max_entries);
+        }
+        if let Some(max_weight) = self.max_weight {
+            info.detail("weight", weight);
+            info.detail("max_weight", max_weight);
+        }
+        Ok(info)
+    }
+}
+
+// `len` counts expired entries nobody has touched since.
+impl<K: Hash + Eq, V> AdminStorage for LruStorage<K, V> {
+    fn clear(&self) -> Result<(), Err> {
+        self.closed.check()?;
+        let mut entries = self.lock();
+        entries.map.clear();
+        entries.weight = 0;
+        Ok(())
+    }
+
+    fn len(&self) -> Result<usize, Err> {
+        self.closed.check()?;
+        Ok(self.lock().map.len())
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> GarbageCollect for LruStorage<K, V> {
+    // Walks the list from the least recently used end.
+    fn gc_now(&self, batch_size: usize) -> Result<usize, Err> {
+        self.closed.check()?;
+        let now = Instant::now();
+        let mut evicted = Vec::new();
+        {
+            let mut entries = self.lock();
+            let expired: Vec<K> = entries
+                .map
+                .iter()
+                .filter(|(_, entry)| entry.expired(now))
+                .map(|(key, _)| key.clone())
+                .take(batch_size)
+                .collect();
+            for key in expired {
+                if let Some(entry) = entries.remove(&key) {
+                    evicted.push((key, entry.value, EvictionCause::Expired));
+                }
+            }
+        }
+        let removed = evicted.len();
+        self.evicted(evicted);
+        Ok(removed)
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct LruStorageBuilder<K, V> {
+    max_entries: Option<usize>,
+    max_weight: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    weigher: Option<Weigher<K, V>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    on_evict: Option<EvictionHook<K, V>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+}
+
+impl<K: Hash + Eq, V> LruStorageBuilder<K, V> {
+    pub fn new() -> Self {
+        LruStorageBuilder::default()
+    }
+
+    // Keys kept before the least recently used one is evicted.
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    // Total weight kept before the least recently used entries are evicted, needs a weigher.
+    pub fn max_weight(mut self, max_weight: usize) -> Self {
+        self.max_weight = Some(max_weight);
+        self
+    }
+
+    // Weighs an entry once when it is written, e.g. by its size in bytes. Weights should
+    // stay the same for as long as the entry is stored.
+    pub fn weigher<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&K, &V) -> usize + Send + Sync + 'static,
+    {
+        self.weigher = Some(Box::new(f));
+        self
+    }
+
+    // Called with every entry evicted for capacity or expiry, not for `del` or overwrites.
+    pub fn on_evict<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&K, &V, EvictionCause) + Send + Sync + 'static,
+    {
+        self.on_evict = Some(Box::new(f));
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their TTL and batch variants,
+    // outside the list's lock like `on_evict`.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if neither `max_entries` nor `max_weight` is set, either is zero, or
+    // `max_weight` is set without a weigher.
+    pub fn build(self) -> LruStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build lru storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<LruStorage<K, V>, BuilderError> {
+        if self.max_entries.is_none() && self.max_weight.is_none() {
+            return Err(BuilderError::MissingField("max_entries"));
+        }
+        if self.max_entries == Some(0) {
+            return Err(BuilderError::invalid("max_entries", "must not be 0"));
+        }
+        if self.max_weight == Some(0) {
+            return Err(BuilderError::invalid("max_weight", "must not be 0"));
+        }
+        if self.max_weight.is_some() && self.weigher.is_none() {
+            return Err(BuilderError::MissingField("weigher"));
+        }
+        Ok(LruStorage {
+            entries: Mutex::new(Entries {
+                map: LinkedHashMap::new(),
+                weight: 0,
+            }),
+            max_entries: self.max_entries,
+            max_weight: self.max_weight,
+            weigher: self.weigher,
+            on_evict: self.on_evict,
+            hooks: self.hooks,
+            closed: CloseFlag::default(),
+        })
+    }
+}
+
+impl<K, V> Default for LruStorageBuilder<K, V> {
+    fn default() -> Self {
+        LruStorageBuilder {
+            max_entries: None,
+            max_weight: None,
+            weigher: None,
+            on_evict: None,
+            hooks: Hooks::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    crate::storage_conformance_tests!(
+        conformance,
+        LruStorageBuilder::<String, String>::new()
+            .max_entries(10_000)
+            .build()
+    );
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let seen = evicted.clone();
+        let storage = LruStorageBuilder::<&str, u32>::new()
+            .max_entries(2)
+            .on_evict(move |key, value, cause| seen.lock().unwrap().push((*key, *value, cause)))
+            .build();
+
+        storage.set("a", 1).unwrap();
+        storage.set("b", 2).unwrap();
+        // Reading `a` makes `b` the least recently used.
+        assert_eq!(storage.get("a").unwrap(), Some(1));
+        storage.set("c", 3).unwrap();
+        assert!(!storage.contains("b").unwrap());
+        assert_eq!(storage.len().unwrap(), 2);
+
+        // `contains` doesn't count as a use, `a` goes next.
+        assert!(storage.contains("a").unwrap());
+        storage.set_many(vec![("d", 4), ("c", 30)]).unwrap();
+        assert_eq!(storage.get("a").unwrap(), None);
+        assert_eq!(storage.get("c").unwrap(), Some(30));
+
+        storage.del("d").unwrap();
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            [
+                ("b", 2, EvictionCause::Capacity),
+                ("a", 1, EvictionCause::Capacity)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expired() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let seen = evicted.clone();
+        let storage = LruStorageBuilder::<&str, u32>::new()
+            .max_entries(10)
+            .on_evict(move |key, _, cause| seen.lock().unwrap().push((*key, cause)))
+            .build();
+
+        storage.set_ex("a", 1, Duration::from_millis(20)).unwrap();
+        storage.set_ex("b", 2, Duration::from_millis(20)).unwrap();
+        storage.set("c", 3).unwrap();
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(storage.get("a").unwrap(), None);
+        assert_eq!(storage.len().unwrap(), 2);
+        assert_eq!(storage.gc_now(10).unwrap(), 1);
+        assert_eq!(storage.get("c").unwrap(), Some(3));
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            [("a", EvictionCause::Expired), ("b", EvictionCause::Expired)]
+        );
+    }
+
+    #[test]
+    fn test_try_build() {
+        assert!(LruStorageBuilder::<String, String>::new()
+            .try_build()
+            .is_err());
+        assert!(LruStorageBuilder::<String, String>::new()
+            .max_entries(0)
+            .try_build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_max_weight() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let seen = evicted.clone();
+        let storage = LruStorageBuilder::<&str, String>::new()
+            .max_weight(10)
+            .weigher(|_, value| value.len())
+            .on_evict(move |key, _, cause| seen.lock().unwrap().push((*key, cause)))
+            .build();
+
+        storage.set("a", "1234".to_string()).unwrap();
+        storage.set("b", "1234".to_string()).unwrap();
+        assert_eq!(storage.weight(), 8);
+        // Overwrites are weighed again.
+        storage.set("a", "12".to_string()).unwrap();
+        assert_eq!(storage.weight(), 6);
+        storage.set("c", "123456".to_string()).unwrap();
+        assert_eq!(storage.weight(), 8);
+        assert!(!storage.contains("b").unwrap());
+
+        // Too heavy on its own, it isn't stored and the rest stays.
+        storage.set("a", "12345678901".to_string()).unwrap();
+        assert_eq!(storage.get("a").unwrap(), None);
+        assert_eq!(storage.weight(), 6);
+        storage.del("c").unwrap();
+        assert_eq!(storage.weight(), 0);
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            [
+                ("b", EvictionCause::Capacity),
+                ("a", EvictionCause::Capacity)
+            ]
+        );
+
+        assert!(LruStorageBuilder::<&str, String>::new()
+            .max_weight(10)
+            .try_build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_stats() {
+        let storage = LruStorageBuilder::<&str, String>::new()
+            .max_weight(100)
+            .weigher(|_, value| value.len())
+            .build();
+        storage.set("a", "abc".to_string()).unwrap();
+        let stats = storage.stats().unwrap();
+        assert_eq!(stats.keys, 1);
+        assert_eq!(stats.details["weight"], "3");
+        assert_eq!(stats.details["max_weight"], "100");
+        assert!(!stats.details.contains_key("max_entries"));
+    }
+
+    #[test]
+    fn test_hooks() {
+        struct Audit(Arc<Mutex<Vec<String>>>);
+
+        impl StorageHook<&'static str, u32> for Audit {
+            fn on_set(&self, key: &&'static str, value: &u32) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("set {}={}", key, value));
+            }
+
+            fn on_get(&self, key: &&'static str, value: Option<&u32>) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("get {}={:?}", key, value));
+            }
+
+            fn on_del(&self, key: &&'static str) {
+                self.0.lock().unwrap().push(format!("del {}", key));
+            }
+        }
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let storage = LruStorageBuilder::new()
+            .max_entries(1)
+            .hook(Audit(calls.clone()))
+            .build();
+        storage.set("a", 1).unwrap();
+        // Evicting `a` isn't a delete.
+        storage.set_many(vec![("b", 2)]).unwrap();
+        storage.get("a").unwrap();
+        storage.del("b").unwrap();
+        assert!(!storage.contains("b").unwrap());
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            ["set a=1", "set b=2", "get a=None", "del b"]
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::storage::{Err, Storage};
+
+// A state-based CRDT: merging must be commutative, associative and idempotent, so replicas
+// that saw the same writes in any order end up equal.
+pub trait Merge {
+    fn merge(&mut self, other: Self);
+}
+
+// Values written with `merge` are combined with what is stored instead of replacing it.
+pub trait MergeableStorage<K, V: Merge>: Storage<K, V> {
+    // Returns the merged value. The default reads, merges and writes back, which is only as
+    // atomic as the backend: a concurrent `merge` between the read and the write is lost
+    // until a later merge carries it again.
+    fn merge(&self, key: K, value: V) -> Result<V, Err>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let merged = match self.get(key.clone())? {
+            Some(mut current) => {
+                current.merge(value);
+                current
+            }
+            None => value,
+        };
+        self.set(key, merged.clone())?;
+        Ok(merged)
+    }
+}
+
+// Grow-only set, merging is the union.
+impl<T: Ord> Merge for BTreeSet<T> {
+    fn merge(&mut self, other: Self) {
+        self.extend(other);
+    }
+}
+
+// Grow-only counter with a slot per replica.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GCounter {
+    counts: BTreeMap<String, u64>,
+}
+
+impl GCounter {
+    pub fn new() -> Self {
+        GCounter::default()
+    }
+
+    pub fn increment(&mut self, replica: &str, by: u64) {
+        *self.counts.entry(replica.to_string()).or_default() += by;
+    }
+
+    pub fn value(&self) -> u64 {
+        self.counts.values().sum()
+    }
+}
+
+impl Merge for GCounter {
+    fn merge(&mut self, other: Self) {
+        for (replica, count) in other.counts {
+            let slot = self.counts.entry(replica).or_default();
+            *slot = (*slot).max(count);
+        }
+    }
+}
+
+// Counter that also decrements, a pair of grow-only counters.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PNCounter {
+    increments: GCounter,
+    decrements: GCounter,
+}
+
+impl PNCounter {
+    pub fn new() -> Self {
+        PNCounter::default()
+    }
+
+    pub fn increment(&mut self, replica: &str, by: u64) {
+        self.increments.increment(replica, by);
+    }
+
+    pub fn decrement(&mut self, replica: &str, by: u64) {
+        self.decrements.increment(replica, by);
+    }
+
+    pub fn value(&self) -> i64 {
+        self.increments.value() as i64 - self.decrements.value() as i64
+    }
+}
+
+impl Merge for PNCounter {
+    fn merge(&mut self, other: Self) {
+        self.increments.merge(other.increments);
+        self.decrements.merge(other.decrements);
+    }
+}
+
+// Observed-remove set: a remove only cancels the adds it has seen, so an add concurrent
+// with a remove survives the merge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OrSet<T: Ord> {
+    entries: BTreeMap<T, BTreeSet<(String, u64)>>,
+    removed: BTreeSet<(String, u64)>,
+    clock: BTreeMap<String, u64>,
+}
+
+impl<T: Ord> Default for OrSet<T> {
+    fn default() -> Self {
+        OrSet {
+            entries: BTreeMap::new(),
+            removed: BTreeSet::new(),
+            clock: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: Ord> OrSet<T> {
+    pub fn new() -> Self {
+        OrSet::default()
+    }
+
+    pub fn insert(&mut self, replica: &str, value: T) {
+        let clock = self.clock.entry(replica.to_string()).or_default();
+        *clock += 1;
+        let tag = (replica.to_string(), *clock);
+        self.entries.entry(value).or_default().insert(tag);
+    }
+
+    pub fn remove(&mut self, value: &T) -> bool {
+        match self.entries.remove(value) {
+            Some(tags) => {
+                self.removed.extend(tags);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.entries.contains_key(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.entries.keys()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<T: Ord> Merge for OrSet<T> {
+    fn merge(&mut self, other: Self) {
+        self.removed.extend(other.removed);
+        for (value, tags) in other.entries {
+            self.entries.entry(value).or_default().extend(tags);
+        }
+        let removed = &self.removed;
+        self.entries.retain(|_, tags| {
+            tags.retain(|tag| !removed.contains(tag));
+            !tags.is_empty()
+        });
+        for (replica, clock) in other.clock {
+            let slot = self.clock.entry(replica).or_default();
+            *slot = (*slot).max(clock);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::DashMapStorageBuilder;
+
+    #[test]
+    fn test_counters() {
+        let mut eu = PNCounter::new();
+        let mut us = PNCounter::new();
+        eu.increment("eu", 3);
+        us.increment("us", 2);
+        us.decrement("us", 1);
+
+        let mut merged = eu.clone();
+        merged.merge(us.clone());
+        merged.merge(us.clone());
+        us.merge(eu);
+        assert_eq!(merged, us);
+        assert_eq!(merged.value(), 4);
+    }
+
+    #[test]
+    fn test_or_set() {
+        let mut a = OrSet::new();
+        a.insert("a", "ferris");
+        let mut b = a.clone();
+
+        // `a` removes ferris while `b` re-adds it concurrently, the re-add wins.
+        a.remove(&"ferris");
+        b.insert("b", "ferris");
+        b.insert("b", "corro");
+        a.merge(b.clone());
+        b.merge(a.clone());
+        assert_eq!(a, b);
+        assert_eq!(a.iter().copied().collect::<Vec<_>>(), ["corro", "ferris"]);
+
+        b.remove(&"ferris");
+        a.merge(b);
+        assert!(!a.contains(&"ferris"));
+    }
+
+    #[test]
+    fn test_merge_storage() {
+        let storage = DashMapStorageBuilder::<&str, GCounter>::new().build();
+        let mut eu = GCounter::new();
+        eu.increment("eu", 2);
+        let mut us = GCounter::new();
+        us.increment("us", 5);
+
+        storage.merge("visits", eu.clone()).unwrap();
+        storage.merge("visits", us).unwrap();
+        storage.merge("visits", eu).unwrap();
+        assert_eq!(storage.get("visits").unwrap().unwrap().value(), 7);
+    }
+}
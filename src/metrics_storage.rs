@@ -0,0 +1,313 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::health::HealthStatus;
+use crate::options::OpOptions;
+use crate::storage::{Err, Storage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Operation {
+    // `set`, `set_ex` and `set_with_opts`.
+    Set,
+    Get,
+    Del,
+    Contains,
+    GetMany,
+    SetMany,
+    DelMany,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 7] = [
+        Operation::Set,
+        Operation::Get,
+        Operation::Del,
+        Operation::Contains,
+        Operation::GetMany,
+        Operation::SetMany,
+        Operation::DelMany,
+    ];
+}
+
+// Upper bounds of the latency buckets, slower calls land in a last overflow bucket.
+pub const LATENCY_BUCKETS: [Duration; 10] = [
+    Duration::from_micros(100),
+    Duration::from_micros(500),
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpStats {
+    pub calls: u64,
+    pub errors: u64,
+    pub total_latency: Duration,
+    // Calls per bucket of `LATENCY_BUCKETS`, plus one for the slower ones.
+    pub latency: [u64; LATENCY_BUCKETS.len() + 1],
+}
+
+impl OpStats {
+    pub fn mean_latency(&self) -> Option<Duration> {
+        let calls = u32::try_from(self.calls).ok().filter(|calls| *calls > 0)?;
+        Some(self.total_latency / calls)
+    }
+
+    // The bucket bound at least a `q` fraction of calls finished within, `None` without calls
+    // or when that is the overflow bucket.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let target = (self.calls as f64 * q.clamp(0.0, 1.0)).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (n, count) in self.latency.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return LATENCY_BUCKETS.get(n).copied();
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    // Indexed like `Operation::ALL`, see `op`.
+    pub ops: [OpStats; 7],
+    // Keys `get` and `get_many` found, and didn't.
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl MetricsSnapshot {
+    pub fn op(&self, op: Operation) -> &OpStats {
+        &self.ops[op as usize]
+    }
+
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let lookups = self.hits + self.misses;
+        (lookups > 0).then(|| self.hits as f64 / lookups as f64)
+    }
+}
+
+#[derive(Default)]
+struct OpCounters {
+    calls: AtomicU64,
+    errors: AtomicU64,
+    total_nanos: AtomicU64,
+    latency: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+}
+
+// Counts calls, errors, lookup hits and misses and latencies of everything that goes through
+// it. Counters are relaxed atomics, a `snapshot` taken under load may be off by a few calls.
+pub struct MetricsStorage<S> {
+    inner: S,
+    ops: [OpCounters; 7],
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<S> MetricsStorage<S> {
+    pub fn new(inner: S) -> Self {
+        MetricsStorage {
+            inner,
+            ops: Default::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let mut snapshot = MetricsSnapshot {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            ..MetricsSnapshot::default()
+        };
+        for (stats, counters) in snapshot.ops.iter_mut().zip(&self.ops) {
+            stats.calls = counters.calls.load(Ordering::Relaxed);
+            stats.errors = counters.errors.load(Ordering::Relaxed);
+            stats.total_latency =
+                Duration::from_nanos(counters.total_nanos.load(Ordering::Relaxed));
+            for (count, bucket) in stats.latency.iter_mut().zip(&counters.latency) {
+                *count = bucket.load(Ordering::Relaxed);
+            }
+        }
+        snapshot
+    }
+
+    pub fn reset(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+        for counters in &self.ops {
+            counters.calls.store(0, Ordering::Relaxed);
+            counters.errors.store(0, Ordering::Relaxed);
+            counters.total_nanos.store(0, Ordering::Relaxed);
+            for bucket in &counters.latency {
+                bucket.store(0, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn measure<T, F>(&self, op: Operation, f: F) -> Result<T, Err>
+    where
+        F: FnOnce() -> Result<T, Err>,
+    {
+        let start = Instant::now();
+        let resp = f();
+        let elapsed = start.elapsed();
+
+        let counters = &self.ops[op as usize];
+        counters.calls.fetch_add(1, Ordering::Relaxed);
+        if resp.is_err() {
+            counters.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        counters.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| elapsed <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        counters.latency[bucket].fetch_add(1, Ordering::Relaxed);
+        resp
+    }
+
+    fn lookup<V>(&self, found: &Option<V>) {
+        match found {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+}
+
+impl<K, V, S> Storage<K, V> for MetricsStorage<S>
+where
+    S: Storage<K, V>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.measure(Operation::Set, || self.inner.set(key, value))
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.measure(Operation::Set, || self.inner.set_ex(key, value, expire))
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        let value = self.measure(Operation::Get, || self.inner.get(key))?;
+        self.lookup(&value);
+        Ok(value)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.measure(Operation::Del, || self.inner.del(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.measure(Operation::Contains, || self.inner.contains(key))
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.inner.close()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.inner.health_check()
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        let values = self.measure(Operation::GetMany, || self.inner.get_many(keys))?;
+        values.iter().for_each(|value| self.lookup(value));
+        Ok(values)
+    }
+
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.measure(Operation::SetMany, || self.inner.set_many(entries))
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        self.measure(Operation::DelMany, || self.inner.del_many(keys))
+    }
+
+    fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+        self.measure(Operation::Set, || {
+            self.inner.set_with_opts(key, value, opts)
+        })
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        let value = self.measure(Operation::Get, || self.inner.get_with_opts(key, opts))?;
+        self.lookup(&value);
+        Ok(value)
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        self.measure(Operation::Del, || self.inner.del_with_opts(key, opts))
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        self.measure(Operation::Contains, || {
+            self.inner.contains_with_opts(key, opts)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::StorageError;
+    use crate::mock_storage::MockStorage;
+
+    #[test]
+    fn test_counts() {
+        let storage = MetricsStorage::new(MockStorage::new().with_entry("a", 1));
+        storage.set("b", 2).unwrap();
+        storage.get("a").unwrap();
+        storage.get("missing").unwrap();
+        storage.get_many(vec!["a", "b", "c"]).unwrap();
+        // `get_many` on the mock is three `get`s.
+        storage.inner().fail_nth(6, StorageError::Timeout);
+        assert!(storage.del("a").is_err());
+
+        let snapshot = storage.snapshot();
+        assert_eq!(snapshot.op(Operation::Set).calls, 1);
+        assert_eq!(snapshot.op(Operation::Get).calls, 2);
+        assert_eq!(snapshot.op(Operation::GetMany).calls, 1);
+        assert_eq!(snapshot.op(Operation::Del).errors, 1);
+        assert_eq!((snapshot.hits, snapshot.misses), (3, 2));
+        assert_eq!(snapshot.hit_ratio(), Some(0.6));
+
+        storage.reset();
+        assert_eq!(storage.snapshot(), MetricsSnapshot::default());
+        assert_eq!(storage.snapshot().hit_ratio(), None);
+    }
+
+    #[test]
+    fn test_latency() {
+        let mut stats = OpStats {
+            calls: 4,
+            ..OpStats::default()
+        };
+        stats.latency[0] = 3;
+        stats.latency[LATENCY_BUCKETS.len()] = 1;
+        stats.total_latency = Duration::from_millis(8);
+        assert_eq!(stats.mean_latency(), Some(Duration::from_millis(2)));
+        assert_eq!(stats.quantile(0.5), Some(LATENCY_BUCKETS[0]));
+        assert_eq!(stats.quantile(0.99), None);
+        assert_eq!(OpStats::default().mean_latency(), None);
+
+        let storage = MetricsStorage::new(MockStorage::<&str, u32>::new());
+        storage.contains("a").unwrap();
+        let stats = storage.snapshot().op(Operation::Contains).clone();
+        assert_eq!(stats.latency.iter().sum::<u64>(), 1);
+    }
+}
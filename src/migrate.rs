@@ -0,0 +1,144 @@
+use crate::scan::ScanStorage;
+use crate::storage::{Err, Storage};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MigrateReport {
+    pub batches: usize,
+    // Keys the scan listed.
+    pub scanned: usize,
+    pub copied: usize,
+    // Keys that were gone by the time their batch was read, e.g. expired.
+    pub missing: usize,
+}
+
+type ProgressHook = Box<dyn Fn(&MigrateReport)>;
+
+pub struct MigrateOptions {
+    prefix: String,
+    batch_size: usize,
+    on_progress: Option<ProgressHook>,
+}
+
+impl MigrateOptions {
+    pub fn new() -> Self {
+        MigrateOptions::default()
+    }
+
+    // Only copies keys starting with `prefix`, all of them by default.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    // Keys read with one `get_many` and written with one `set_many`, 500 by default.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    // Called with the totals so far after every batch.
+    pub fn on_progress<F: Fn(&MigrateReport) + 'static>(mut self, f: F) -> Self {
+        self.on_progress = Some(Box::new(f));
+        self
+    }
+}
+
+impl Default for MigrateOptions {
+    fn default() -> Self {
+        MigrateOptions {
+            prefix: String::new(),
+            batch_size: 500,
+            on_progress: None,
+        }
+    }
+}
+
+// Copies the keys `from` lists under the options' prefix into `to`, overwriting what is
+// there. TTLs are not carried over, `ExportStorage::snapshot_to` copies them for backends
+// that can export. Keys written to `from` during the copy may or may not be copied. Stops at
+// the first error; batches before it stay written, so running it again resumes the copy.
+pub fn migrate<K, V, F, T>(from: &F, to: &T, options: &MigrateOptions) -> Result<MigrateReport, Err>
+where
+    K: Clone,
+    F: ScanStorage<K> + Storage<K, V> + ?Sized,
+    T: Storage<K, V> + ?Sized,
+{
+    let mut report = MigrateReport::default();
+    let mut keys = from.scan_prefix(&options.prefix)?.peekable();
+    while keys.peek().is_some() {
+        let batch = keys
+            .by_ref()
+            .take(options.batch_size)
+            .collect::<Result<Vec<K>, Err>>()?;
+        let scanned = batch.len();
+        report.scanned += scanned;
+
+        let values = from.get_many(batch.clone())?;
+        let entries: Vec<(K, V)> = batch
+            .into_iter()
+            .zip(values)
+            .filter_map(|(key, value)| value.map(|value| (key, value)))
+            .collect();
+        report.missing += scanned - entries.len();
+        report.copied += entries.len();
+        if !entries.is_empty() {
+            to.set_many(entries)?;
+        }
+
+        report.batches += 1;
+        if let Some(on_progress) = &options.on_progress {
+            on_progress(&report);
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::DashMapStorageBuilder;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_migrate() {
+        let from = DashMapStorageBuilder::<String, u32>::new().build();
+        for i in 0..25 {
+            from.set(format!("user:{}", i), i).unwrap();
+        }
+        from.set("session:1".into(), 1).unwrap();
+        let to = DashMapStorageBuilder::<String, u32>::new().build();
+
+        let progress = Rc::new(RefCell::new(Vec::new()));
+        let seen = progress.clone();
+        let options = MigrateOptions::new()
+            .prefix("user:")
+            .batch_size(10)
+            .on_progress(move |report| seen.borrow_mut().push(report.copied));
+        let report = migrate(&from, &to, &options).unwrap();
+
+        assert_eq!(
+            report,
+            MigrateReport {
+                batches: 3,
+                scanned: 25,
+                copied: 25,
+                missing: 0,
+            }
+        );
+        assert_eq!(*progress.borrow(), [10, 20, 25]);
+        assert_eq!(to.get("user:7".into()).unwrap(), Some(7));
+        assert_eq!(to.get("session:1".into()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_migrate_empty() {
+        let from = DashMapStorageBuilder::<String, u32>::new().build();
+        let to = DashMapStorageBuilder::<String, u32>::new().build();
+        from.set_ex("a".into(), 1, Duration::from_secs(60)).unwrap();
+        let report = migrate(&from, &to, &MigrateOptions::new().prefix("b")).unwrap();
+        assert_eq!(report, MigrateReport::default());
+        assert!(!to.contains("a".into()).unwrap());
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::storage::{CloseFlag, Err, Storage};
+
+// One operation seen by a `MockStorage`, in the order it arrived.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Call<K, V> {
+    Set(K, V),
+    SetEx(K, V, Duration),
+    Get(K),
+    Del(K),
+    Contains(K),
+    Close,
+}
+
+// An in-memory test double that records every call. Answers come from its own map unless a
+// response was scripted with `push_get`/`push_contains`, and `fail_nth` makes a chosen call
+// fail instead. Failed calls are recorded too.
+pub struct MockStorage<K, V> {
+    state: Mutex<MockState<K, V>>,
+    closed: CloseFlag,
+}
+
+struct MockState<K, V> {
+    entries: HashMap<K, (V, Option<Instant>)>,
+    calls: Vec<Call<K, V>>,
+    gets: VecDeque<Option<V>>,
+    contains: VecDeque<bool>,
+    // Kept `Send + Sync` so the mock can be shared between threads.
+    failures: HashMap<usize, Box<dyn Error + Send + Sync>>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> MockStorage<K, V> {
+    pub fn new() -> Self {
+        MockStorage {
+            state: Mutex::new(MockState {
+                entries: HashMap::new(),
+                calls: Vec::new(),
+                gets: VecDeque::new(),
+                contains: VecDeque::new(),
+                failures: HashMap::new(),
+            }),
+            closed: CloseFlag::default(),
+        }
+    }
+
+    // Seeds an entry without recording a call.
+    pub fn with_entry(self, key: K, value: V) -> Self {
+        self.state().entries.insert(key, (value, None));
+        self
+    }
+
+    // The next `get` returns `value` whatever the map holds. Responses queue up in order.
+    pub fn push_get(&self, value: Option<V>) {
+        self.state().gets.push_back(value);
+    }
+
+    pub fn push_contains(&self, found: bool) {
+        self.state().contains.push_back(found);
+    }
+
+    // Fails the call that will sit at index `n` of `calls()` with `e`. `clear_calls` restarts
+    // the count.
+    pub fn fail_nth<E: Into<Box<dyn Error + Send + Sync>>>(&self, n: usize, e: E) {
+        self.state().failures.insert(n, e.into());
+    }
+
+    pub fn calls(&self) -> Vec<Call<K, V>> {
+        self.state().calls.clone()
+    }
+
+    pub fn call_count(&self) -> usize {
+        self.state().calls.len()
+    }
+
+    pub fn clear_calls(&self) {
+        self.state().calls.clear();
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState<K, V>> {
+        // A panicking test shouldn't hide what the mock saw from the others.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Records `call` and returns the state to answer it with, or the scripted failure.
+    // Closing stays idempotent, everything else fails once closed.
+    fn record(&self, call: Call<K, V>) -> Result<MutexGuard<'_, MockState<K, V>>, Err> {
+        let closing = matches!(call, Call::Close);
+        let mut state = self.state();
+        let n = state.calls.len();
+        state.calls.push(call);
+        if let Some(e) = state.failures.remove(&n) {
+            return Err(e);
+        }
+        if !closing {
+            self.closed.check()?;
+        }
+        Ok(state)
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Default for MockStorage<K, V> {
+    fn default() -> Self {
+        MockStorage::new()
+    }
+}
+
+impl<K, V> MockState<K, V>
+where
+    K: Hash + Eq,
+{
+    fn live(&mut self, key: &K) -> Option<&V> {
+        let expired = matches!(
+            self.entries.get(key),
+            Some((_, Some(deadline))) if *deadline <= Instant::now()
+        );
+        if expired {
+            self.entries.remove(key);
+        }
+        self.entries.get(key).map(|(value, _)| value)
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Storage<K, V> for MockStorage<K, V> {
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        let mut state = self.record(Call::Set(key.clone(), value.clone()))?;
+        state.entries.insert(key, (value, None));
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        let mut state = self.record(Call::SetEx(key.clone(), value.clone(), expire))?;
+        state
+            .entries
+            .insert(key, (value, Some(Instant::now() + expire)));
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        let mut state = self.record(Call::Get(key.clone()))?;
+        if let Some(value) = state.gets.pop_front() {
+            return Ok(value);
+        }
+        Ok(state.live(&key).cloned())
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        let mut state = self.record(Call::Del(key.clone()))?;
+        let found = state.live(&key).is_some();
+        state.entries.remove(&key);
+        Ok(found.then_some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        let mut state = self.record(Call::Contains(key.clone()))?;
+        if let Some(found) = state.contains.pop_front() {
+            return Ok(found);
+        }
+        Ok(state.live(&key).is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        let _state = self.record(Call::Close)?;
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::StorageError;
+
+    crate::storage_conformance_tests!(conformance, MockStorage::<String, String>::new());
+
+    #[test]
+    fn test_records_calls() {
+        let mock = MockStorage::new().with_entry("seeded", 1);
+        mock.set("a", 2).unwrap();
+        assert_eq!(mock.get("seeded").unwrap(), Some(1));
+        assert_eq!(mock.del("missing").unwrap(), None);
+        assert_eq!(
+            mock.calls(),
+            [Call::Set("a", 2), Call::Get("seeded"), Call::Del("missing")]
+        );
+
+        mock.clear_calls();
+        assert_eq!(mock.call_count(), 0);
+    }
+
+    #[test]
+    fn test_scripted_responses() {
+        let mock = MockStorage::<&str, i32>::new();
+        mock.push_get(Some(7));
+        mock.push_contains(true);
+        assert_eq!(mock.get("a").unwrap(), Some(7));
+        assert_eq!(mock.get("a").unwrap(), None);
+        assert!(mock.contains("a").unwrap());
+        assert!(!mock.contains("a").unwrap());
+    }
+
+    #[test]
+    fn test_fail_nth() {
+        let mock = MockStorage::<&str, i32>::new();
+        mock.fail_nth(1, StorageError::Timeout);
+        mock.set("a", 1).unwrap();
+        let e = mock.get("a").unwrap_err();
+        assert_eq!(e.downcast_ref(), Some(&StorageError::Timeout));
+        assert_eq!(mock.get("a").unwrap(), Some(1));
+        assert_eq!(mock.call_count(), 3);
+    }
+
+    #[test]
+    fn test_close_and_expiry() {
+        let mock = MockStorage::<&str, i32>::new();
+        mock.set_ex("a", 1, Duration::ZERO).unwrap();
+        assert!(!mock.contains("a").unwrap());
+
+        mock.close().unwrap();
+        mock.close().unwrap();
+        assert!(mock.is_closed());
+        let e = mock.get("a").unwrap_err();
+        assert_eq!(e.downcast_ref(), Some(&StorageError::Closed));
+    }
+}
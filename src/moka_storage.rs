@@ -0,0 +1,548 @@
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use moka::ops::compute::{CompResult, Op};
+use moka::sync::Cache;
+use moka::Expiry;
+
+use crate::admin::AdminStorage;
+use crate::atomic::AtomicStorage;
+use crate::error::BuilderError;
+use crate::gc::GarbageCollect;
+use crate::get_or_insert::GetOrInsertStorage;
+use crate::hooks::{Hooks, StorageHook};
+use crate::stats::{StorageInfo, StorageStats};
+use crate::storage::{CloseFlag, Err, Storage};
+
+type Weigher<K, V> = Box<dyn Fn(&K, &V) -> u32 + Send + Sync>;
+
+#[derive(Clone)]
+struct Entry<V> {
+    value: V,
+    ttl: Option<Duration>,
+}
+
+// Expires an entry after the TTL it was written with, an overwrite starts over. The cache's
+// own `time_to_live` and `time_to_idle` still apply, whichever comes first wins.
+struct EntryExpiry;
+
+impl<K, V> Expiry<K, Entry<V>> for EntryExpiry {
+    fn expire_after_create(&self, _: &K, entry: &Entry<V>, _: Instant) -> Option<Duration> {
+        entry.ttl
+    }
+
+    fn expire_after_update(
+        &self,
+        _: &K,
+        entry: &Entry<V>,
+        _: Instant,
+        _: Option<Duration>,
+    ) -> Option<Duration> {
+        entry.ttl
+    }
+}
+
+// An in-memory cache on moka, bounded by `max_capacity` entries, or by their total weight
+// with a weigher. Eviction keeps the entries most likely to be read again rather than the
+// most recent ones. Every write goes through moka's per-key compute, so the atomic
+// operations can't lose a concurrent `set`. Clones share the cache.
+#[derive(Clone)]
+pub struct MokaStorage<K, V>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    cache: Cache<K, Entry<V>>,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+}
+
+impl<K, V> MokaStorage<K, V>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    pub fn max_capacity(&self) -> Option<u64> {
+        self.cache.policy().max_capacity()
+    }
+
+    pub fn time_to_live(&self) -> Option<Duration> {
+        self.cache.policy().time_to_live()
+    }
+
+    pub fn time_to_idle(&self) -> Option<Duration> {
+        self.cache.policy().time_to_idle()
+    }
+
+    fn put(&self, key: K, value: V, ttl: Option<Duration>)
+    where
+        K: Clone,
+    {
+        if self.hooks.is_empty() {
+            self.cache
+                .entry(key)
+                .and_upsert_with(|_| Entry { value, ttl });
+            return;
+        }
+        let entry = Entry {
+            value: value.clone(),
+            ttl,
+        };
+        self.cache.entry(key.clone()).and_upsert_with(|_| entry);
+        self.hooks.set(&key, &value);
+    }
+
+    // Replaces the live value with `new`, `None` removing it, if `f` of the live value says
+    // so. Returns whether it did and the value it saw.
+    fn compute<F>(&self, key: K, new: Option<V>, f: F) -> (bool, Option<V>)
+    where
+        F: FnOnce(Option<&V>) -> bool,
+    {
+        let mut seen = None;
+        let mut done = false;
+        self.cache.entry(key).and_compute_with(|current| {
+            seen = current.map(|entry| entry.into_value().value);
+            done = f(seen.as_ref());
+            match (done, new) {
+                (false, _) => Op::Nop,
+                (true, Some(value)) => Op::Put(Entry { value, ttl: None }),
+                (true, None) => Op::Remove,
+            }
+        });
+        (done, seen)
+    }
+}
+
+impl<K, V> Storage<K, V> for MokaStorage<K, V>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        self.put(key, value, None);
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        self.put(key, value, Some(expire));
+        Ok(())
+    }
+
+    // Counts as a read for `time_to_idle` and for eviction.
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let value = self.cache.get(&key).map(|entry| entry.value);
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    // Goes through the key's compute like the writes. A plain `remove` would also return
+    // entries that expired but weren't evicted yet.
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let result = self
+            .cache
+            .entry_by_ref(&key)
+            .and_compute_with(|current| match current {
+                Some(_) => Op::Remove,
+                None => Op::Nop,
+            });
+        self.hooks.del(&key);
+        Ok(matches!(result, CompResult::Removed(_)).then_some(key))
+    }
+
+    // Leaves the key's idle time and eviction rank alone.
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        Ok(self.cache.contains_key(&key))
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+}
+
+impl<K, V> AtomicStorage<K, V> for MokaStorage<K, V>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    V: Clone + PartialEq + Send + Sync + 'static,
+{
+    fn compare_and_swap(&self, key: K, expected: Option<V>, new: Option<V>) -> Result<bool, Err> {
+        self.closed.check()?;
+        let (swapped, _) = self.compute(key, new, |current| current == expected.as_ref());
+        Ok(swapped)
+    }
+
+    fn swap(&self, key: K, value: V) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let (_, old) = self.compute(key, Some(value), |_| true);
+        Ok(old)
+    }
+
+    fn take(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let (_, old) = self.compute(key, None, |_| true);
+        Ok(old)
+    }
+}
+
+// moka runs `f` once however many callers miss the key at the same time, the others wait
+// for its value.
+impl<K, V> GetOrInsertStorage<K, V> for MokaStorage<K, V>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    fn set_nx(&self, key: K, value: V, expire: Option<Duration>) -> Result<bool, Err> {
+        self.closed.check()?;
+        let entry = self
+            .cache
+            .entry(key)
+            .or_insert_with(|| Entry { value, ttl: expire });
+        Ok(entry.is_fresh())
+    }
+
+    fn get_or_insert_with<F>(&self, key: K, f: F) -> Result<V, Err>
+    where
+        K: Clone,
+        V: Clone,
+        F: FnOnce() -> V,
+    {
+        self.closed.check()?;
+        let entry = self.cache.entry(key).or_insert_with(|| Entry {
+            value: f(),
+            ttl: None,
+        });
+        Ok(entry.into_value().value)
+    }
+
+    fn get_or_insert_with_ex<F>(&self, key: K, expire: Duration, f: F) -> Result<V, Err>
+    where
+        K: Clone,
+        V: Clone,
+        F: FnOnce() -> V,
+    {
+        self.closed.check()?;
+        let entry = self.cache.entry(key).or_insert_with(|| Entry {
+            value: f(),
+            ttl: Some(expire),
+        });
+        Ok(entry.into_value().value)
+    }
+}
+
+// Counts and weights lag behind writes until moka's pending maintenance runs, `len` and
+// `stats` run it first.
+impl<K, V> AdminStorage for MokaStorage<K, V>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    fn clear(&self) -> Result<(), Err> {
+        self.closed.check()?;
+        self.cache.invalidate_all();
+        self.cache.run_pending_tasks();
+        Ok(())
+    }
+
+    fn len(&self) -> Result<usize, Err> {
+        self.closed.check()?;
+        self.cache.run_pending_tasks();
+        Ok(self.cache.entry_count() as usize)
+    }
+}
+
+impl<K, V> StorageStats for MokaStorage<K, V>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    fn stats(&self) -> Result<StorageInfo, Err> {
+        self.closed.check()?;
+        self.cache.run_pending_tasks();
+        let mut info = StorageInfo {
+            keys: self.cache.entry_count(),
+            ..StorageInfo::default()
+        };
+        info.detail("weighted_size", self.cache.weighted_size());
+        if let Some(max_capacity) = self.max_capacity() {
+            info.detail("max_capacity", max_capacity);
+        }
+        Ok(info)
+    }
+}
+
+// moka evicts expired entries during its own maintenance, which this runs right away. It
+// removes every expired entry it finds, `batch_size` doesn't limit it, and the count is the
+// drop in `entry_count`, so writes still pending before the run make it come out low.
+impl<K, V> GarbageCollect for MokaStorage<K, V>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    fn gc_now(&self, _batch_size: usize) -> Result<usize, Err> {
+        self.closed.check()?;
+        let before = self.cache.entry_count();
+        self.cache.run_pending_tasks();
+        Ok(before.saturating_sub(self.cache.entry_count()) as usize)
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct MokaStorageBuilder<K, V> {
+    max_capacity: Option<u64>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::config::secs::option"))]
+    time_to_live: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::config::secs::option"))]
+    time_to_idle: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    weigher: Option<Weigher<K, V>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+}
+
+impl<K, V> MokaStorageBuilder<K, V>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        MokaStorageBuilder::default()
+    }
+
+    // Entries kept before eviction starts, or their total weight with a weigher.
+    pub fn max_capacity(mut self, max_capacity: u64) -> Self {
+        self.max_capacity = Some(max_capacity);
+        self
+    }
+
+    // Every entry expires this long after it was written, unless `set_ex` gave it a
+    // shorter TTL.
+    pub fn time_to_live(mut self, ttl: Duration) -> Self {
+        self.time_to_live = Some(ttl);
+        self
+    }
+
+    // Entries expire when they weren't read or written for this long.
+    pub fn time_to_idle(mut self, tti: Duration) -> Self {
+        self.time_to_idle = Some(tti);
+        self
+    }
+
+    // Weighs an entry when it is written, e.g. by its size in bytes, `max_capacity` then
+    // bounds the total weight.
+    pub fn weigher<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&K, &V) -> u32 + Send + Sync + 'static,
+    {
+        self.weigher = Some(Box::new(f));
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their TTL and batch variants.
+    // Clones of the storage share them.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if `max_capacity` is missing or zero, or a TTL or TTI is zero or longer than
+    // 1000 years. Use `try_build` to get the error instead.
+    pub fn build(self) -> MokaStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build moka storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<MokaStorage<K, V>, BuilderError> {
+        let max_capacity = self
+            .max_capacity
+            .ok_or(BuilderError::MissingField("max_capacity"))?;
+        if max_capacity == 0 {
+            return Err(BuilderError::invalid("max_capacity", "must not be 0"));
+        }
+        // moka panics on durations it can't add to an `Instant`.
+        let max = Duration::from_secs(1000 * 365 * 24 * 3600);
+        for (field, duration) in [
+            ("time_to_live", self.time_to_live),
+            ("time_to_idle", self.time_to_idle),
+        ] {
+            match duration {
+                Some(duration) if duration.is_zero() => {
+                    return Err(BuilderError::invalid(field, "must not be 0"))
+                }
+                Some(duration) if duration > max => {
+                    return Err(BuilderError::invalid(field, "must be at most 1000 years"))
+                }
+                _ => {}
+            }
+        }
+
+        let mut builder = Cache::builder()
+            .max_capacity(max_capacity)
+            .expire_after(EntryExpiry);
+        if let Some(ttl) = self.time_to_live {
+            builder = builder.time_to_live(ttl);
+        }
+        if let Some(tti) = self.time_to_idle {
+            builder = builder.time_to_idle(tti);
+        }
+        if let Some(weigher) = self.weigher {
+            builder = builder.weigher(move |key, entry: &Entry<V>| weigher(key, &entry.value));
+        }
+        Ok(MokaStorage {
+            cache: builder.build(),
+            closed: CloseFlag::default(),
+            hooks: self.hooks,
+        })
+    }
+}
+
+impl<K, V> Default for MokaStorageBuilder<K, V> {
+    fn default() -> Self {
+        MokaStorageBuilder {
+            max_capacity: None,
+            time_to_live: None,
+            time_to_idle: None,
+            weigher: None,
+            hooks: Hooks::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::storage_conformance_tests!(
+        conformance,
+        MokaStorageBuilder::<String, String>::new()
+            .max_capacity(10_000)
+            .build()
+    );
+
+    #[test]
+    fn test_max_capacity() {
+        let storage = MokaStorageBuilder::<u32, u32>::new()
+            .max_capacity(100)
+            .build();
+        for n in 0..1_000 {
+            storage.set(n, n).unwrap();
+        }
+        assert!(storage.len().unwrap() <= 100);
+
+        let storage = MokaStorageBuilder::<&str, String>::new()
+            .max_capacity(10)
+            .weigher(|_, value| value.len() as u32)
+            .build();
+        storage.set("a", "123456".to_string()).unwrap();
+        storage.set("b", "123456".to_string()).unwrap();
+        let stats = storage.stats().unwrap();
+        assert_eq!(stats.keys, 1);
+        assert_eq!(stats.details["weighted_size"], "6");
+        assert_eq!(stats.details["max_capacity"], "10");
+    }
+
+    #[test]
+    fn test_expiry() {
+        let storage = MokaStorageBuilder::<&str, u32>::new()
+            .max_capacity(100)
+            .time_to_live(Duration::from_millis(200))
+            .build();
+        storage
+            .set_ex("short", 1, Duration::from_millis(20))
+            .unwrap();
+        storage.set("long", 2).unwrap();
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(storage.get("short").unwrap(), None);
+        assert_eq!(storage.del("short").unwrap(), None);
+        assert_eq!(storage.get("long").unwrap(), Some(2));
+
+        // A plain overwrite drops the entry's own TTL.
+        storage.set_ex("a", 1, Duration::from_millis(20)).unwrap();
+        storage.set("a", 2).unwrap();
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(storage.get("a").unwrap(), Some(2));
+
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!storage.contains("long").unwrap());
+        storage.gc_now(10).unwrap();
+        assert_eq!(storage.len().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_time_to_idle() {
+        let storage = MokaStorageBuilder::<&str, u32>::new()
+            .max_capacity(100)
+            .time_to_idle(Duration::from_millis(100))
+            .build();
+        storage.set("read", 1).unwrap();
+        storage.set("idle", 2).unwrap();
+        for _ in 0..4 {
+            std::thread::sleep(Duration::from_millis(40));
+            assert_eq!(storage.get("read").unwrap(), Some(1));
+        }
+        assert_eq!(storage.get("idle").unwrap(), None);
+    }
+
+    #[test]
+    fn test_atomic() {
+        let storage = MokaStorageBuilder::<&str, u32>::new()
+            .max_capacity(100)
+            .build();
+        assert!(storage.compare_and_swap("a", None, None).unwrap());
+        assert!(storage.compare_and_swap("a", None, Some(1)).unwrap());
+        assert!(!storage.compare_and_swap("a", None, Some(2)).unwrap());
+        assert!(!storage.compare_and_swap("b", Some(1), None).unwrap());
+        assert_eq!(
+            storage.fetch_update("a", |n| n.map(|n| n + 1)).unwrap(),
+            Some(1)
+        );
+        assert_eq!(storage.swap("a", 5).unwrap(), Some(2));
+        assert_eq!(storage.take("a").unwrap(), Some(5));
+        assert_eq!(storage.take("a").unwrap(), None);
+
+        assert!(storage.set_nx("b", 1, None).unwrap());
+        assert!(!storage.set_nx("b", 2, None).unwrap());
+        let calls = std::sync::atomic::AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    let value = storage
+                        .get_or_insert_with("c", || {
+                            calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                            std::thread::sleep(Duration::from_millis(20));
+                            3
+                        })
+                        .unwrap();
+                    assert_eq!(value, 3);
+                });
+            }
+        });
+        assert_eq!(calls.into_inner(), 1);
+    }
+
+    #[test]
+    fn test_try_build() {
+        let resp = MokaStorageBuilder::<String, String>::new().try_build();
+        assert!(matches!(
+            resp,
+            Err(BuilderError::MissingField("max_capacity"))
+        ));
+        let resp = MokaStorageBuilder::<String, String>::new()
+            .max_capacity(10)
+            .time_to_idle(Duration::ZERO)
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(e)) if e.field == "time_to_idle"));
+    }
+}
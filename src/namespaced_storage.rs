@@ -0,0 +1,403 @@
+use std::time::Duration;
+
+use crate::admin::AdminStorage;
+use crate::append::{Append, AppendStorage};
+use crate::atomic::AtomicStorage;
+use crate::counter::CounterStorage;
+use crate::expiry::ExpiryStorage;
+use crate::export::{EntryIter, ExportEntry, ExportIter, ExportStorage};
+use crate::flush::{FlushConfirmation, FlushNamespace};
+use crate::get_or_insert::GetOrInsertStorage;
+use crate::health::HealthStatus;
+use crate::options::OpOptions;
+use crate::scan::{KeyIter, ScanStorage};
+use crate::storage::{Err, Storage};
+use crate::versioned::{Version, VersionedStorage};
+
+// Prefixes every key with `prefix` on its way to a string-keyed backend, so services sharing
+// one backend can't see each other's keys. Namespaces nest: wrapping a `NamespacedStorage`
+// appends to its prefix.
+pub struct NamespacedStorage<S> {
+    inner: S,
+    prefix: String,
+}
+
+impl<S> NamespacedStorage<S> {
+    pub fn new(inner: S, prefix: &str) -> Self {
+        NamespacedStorage {
+            inner,
+            prefix: prefix.to_string(),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    fn key<K: AsRef<str>>(&self, key: &K) -> String {
+        format!("{}{}", self.prefix, key.as_ref())
+    }
+}
+
+impl<K, V, S> Storage<K, V> for NamespacedStorage<S>
+where
+    K: AsRef<str>,
+    S: Storage<String, V>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.inner.set(self.key(&key), value)
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.inner.set_ex(self.key(&key), value, expire)
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.inner.get(self.key(&key))
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        Ok(self.inner.del(self.key(&key))?.map(|_| key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.inner.contains(self.key(&key))
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.inner.close()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.inner.health_check()
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        self.inner
+            .get_many(keys.iter().map(|key| self.key(key)).collect())
+    }
+
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.inner.set_many(
+            entries
+                .into_iter()
+                .map(|(key, value)| (self.key(&key), value))
+                .collect(),
+        )
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        let prefixed = keys.iter().map(|key| self.key(key)).collect();
+        let removed = self.inner.del_many(prefixed)?;
+        Ok(keys
+            .into_iter()
+            .zip(removed)
+            .map(|(key, removed)| removed.map(|_| key))
+            .collect())
+    }
+
+    fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+        self.inner.set_with_opts(self.key(&key), value, opts)
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        self.inner.get_with_opts(self.key(&key), opts)
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        Ok(self.inner.del_with_opts(self.key(&key), opts)?.map(|_| key))
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        self.inner.contains_with_opts(self.key(&key), opts)
+    }
+}
+
+impl<K, V, S> AtomicStorage<K, V> for NamespacedStorage<S>
+where
+    K: AsRef<str>,
+    V: PartialEq,
+    S: AtomicStorage<String, V>,
+{
+    fn compare_and_swap(&self, key: K, expected: Option<V>, new: Option<V>) -> Result<bool, Err> {
+        self.inner.compare_and_swap(self.key(&key), expected, new)
+    }
+
+    fn fetch_update<F>(&self, key: K, f: F) -> Result<Option<V>, Err>
+    where
+        K: Clone,
+        V: Clone,
+        F: FnMut(Option<&V>) -> Option<V>,
+    {
+        self.inner.fetch_update(self.key(&key), f)
+    }
+
+    fn swap(&self, key: K, value: V) -> Result<Option<V>, Err>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.inner.swap(self.key(&key), value)
+    }
+
+    fn take(&self, key: K) -> Result<Option<V>, Err>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.inner.take(self.key(&key))
+    }
+}
+
+impl<K, V, S> VersionedStorage<K, V> for NamespacedStorage<S>
+where
+    K: AsRef<str>,
+    S: VersionedStorage<String, V>,
+{
+    fn get_versioned(&self, key: K) -> Result<Option<(V, Version)>, Err> {
+        self.inner.get_versioned(self.key(&key))
+    }
+
+    fn set_if_version(&self, key: K, value: V, expected: Option<Version>) -> Result<bool, Err> {
+        self.inner.set_if_version(self.key(&key), value, expected)
+    }
+}
+
+impl<K: AsRef<str>, V: Append, S: AppendStorage<String, V>> AppendStorage<K, V>
+    for NamespacedStorage<S>
+{
+    fn append_to(&self, key: K, value: V) -> Result<usize, Err> {
+        self.inner.append_to(self.key(&key), value)
+    }
+}
+
+impl<K: AsRef<str>, S: CounterStorage<String>> CounterStorage<K> for NamespacedStorage<S> {
+    fn incr(&self, key: K, delta: i64) -> Result<i64, Err> {
+        self.inner.incr(self.key(&key), delta)
+    }
+
+    fn decr(&self, key: K, delta: i64) -> Result<i64, Err> {
+        self.inner.decr(self.key(&key), delta)
+    }
+
+    fn incr_within(
+        &self,
+        key: K,
+        delta: i64,
+        max: i64,
+        expire: Duration,
+    ) -> Result<(i64, bool), Err> {
+        self.inner.incr_within(self.key(&key), delta, max, expire)
+    }
+}
+
+impl<K: AsRef<str>, S: ExpiryStorage<String>> ExpiryStorage<K> for NamespacedStorage<S> {
+    fn ttl(&self, key: K) -> Result<Option<Duration>, Err> {
+        self.inner.ttl(self.key(&key))
+    }
+
+    fn expire(&self, key: K, expire: Duration) -> Result<bool, Err> {
+        self.inner.expire(self.key(&key), expire)
+    }
+
+    fn persist(&self, key: K) -> Result<bool, Err> {
+        self.inner.persist(self.key(&key))
+    }
+}
+
+impl<K, V, S> GetOrInsertStorage<K, V> for NamespacedStorage<S>
+where
+    K: AsRef<str>,
+    S: GetOrInsertStorage<String, V>,
+{
+    fn set_nx(&self, key: K, value: V, expire: Option<Duration>) -> Result<bool, Err> {
+        self.inner.set_nx(self.key(&key), value, expire)
+    }
+
+    fn get_or_insert_with<F>(&self, key: K, f: F) -> Result<V, Err>
+    where
+        K: Clone,
+        V: Clone,
+        F: FnOnce() -> V,
+    {
+        self.inner.get_or_insert_with(self.key(&key), f)
+    }
+
+    fn get_or_insert_with_ex<F>(&self, key: K, expire: Duration, f: F) -> Result<V, Err>
+    where
+        K: Clone,
+        V: Clone,
+        F: FnOnce() -> V,
+    {
+        self.inner.get_or_insert_with_ex(self.key(&key), expire, f)
+    }
+}
+
+// Walks the whole inner export and keeps the keys in this namespace, prefix stripped.
+impl<K, V, S> ExportStorage<K, V> for NamespacedStorage<S>
+where
+    K: AsRef<str> + From<String> + 'static,
+    V: 'static,
+    S: ExportStorage<String, V>,
+{
+    fn export_iter(&self) -> Result<ExportIter<'_, K, V>, Err> {
+        Ok(Box::new(
+            self.export_entries()?
+                .map(|entry| entry.map(|entry| (entry.key, entry.value))),
+        ))
+    }
+
+    fn export_entries<'a>(&'a self) -> Result<EntryIter<'a, K, V>, Err>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        let entries = self.inner.export_entries()?;
+        Ok(Box::new(entries.filter_map(|entry| match entry {
+            Ok(entry) => entry.key.strip_prefix(&self.prefix).map(|key| {
+                Ok(ExportEntry {
+                    key: K::from(key.to_string()),
+                    value: entry.value,
+                    ttl: entry.ttl,
+                })
+            }),
+            Err(e) => Some(Err(e)),
+        })))
+    }
+}
+
+// Scans below this namespace and strips the prefix from the keys.
+impl<K, S> ScanStorage<K> for NamespacedStorage<S>
+where
+    K: From<String>,
+    S: ScanStorage<String>,
+{
+    fn scan_prefix(&self, prefix: &str) -> Result<KeyIter<'_, K>, Err> {
+        let keys = self
+            .inner
+            .scan_prefix(&format!("{}{}", self.prefix, prefix))?;
+        Ok(Box::new(keys.map(|key| {
+            key.map(|key| K::from(key[self.prefix.len()..].to_string()))
+        })))
+    }
+}
+
+// Clears and counts only the keys in this namespace.
+impl<S> AdminStorage for NamespacedStorage<S>
+where
+    S: FlushNamespace + ScanStorage<String>,
+{
+    fn clear(&self) -> Result<(), Err> {
+        let confirm = FlushConfirmation::for_prefix(&self.prefix);
+        self.inner.flush_namespace(&self.prefix, &confirm)?;
+        Ok(())
+    }
+
+    fn len(&self) -> Result<usize, Err> {
+        let mut len = 0;
+        for key in self.inner.scan_prefix(&self.prefix)? {
+            key?;
+            len += 1;
+        }
+        Ok(len)
+    }
+}
+
+// Flushes below this namespace, `prefix` and the confirmation are relative to it.
+impl<S: FlushNamespace> FlushNamespace for NamespacedStorage<S> {
+    fn flush_namespace(&self, prefix: &str, confirm: &FlushConfirmation) -> Result<usize, Err> {
+        confirm.check(prefix)?;
+        let prefix = format!("{}{}", self.prefix, prefix);
+        self.inner
+            .flush_namespace(&prefix, &FlushConfirmation::for_prefix(&prefix))
+    }
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::{DashMapStorage, DashMapStorageBuilder};
+    use std::sync::Arc;
+
+    fn shared() -> Arc<DashMapStorage<String, String>> {
+        Arc::new(DashMapStorageBuilder::new().build())
+    }
+
+    #[test]
+    fn test_get() {
+        let backend = shared();
+        let sessions = NamespacedStorage::new(backend.clone(), "sessions:");
+        let users = NamespacedStorage::new(backend.clone(), "users:");
+
+        sessions.set("42", "Ferris".to_string()).unwrap();
+        assert_eq!(
+            backend.get("sessions:42".to_string()).unwrap(),
+            Some("Ferris".to_string())
+        );
+        assert_eq!(sessions.get("42").unwrap(), Some("Ferris".to_string()));
+        assert!(!users.contains("42").unwrap());
+        assert_eq!(sessions.del("42").unwrap(), Some("42"));
+        assert!(backend.shared().is_empty());
+    }
+
+    #[test]
+    fn test_clear() {
+        let backend = shared();
+        let sessions = NamespacedStorage::new(backend.clone(), "sessions:");
+        sessions.set("a", "1".to_string()).unwrap();
+        backend.set("users:a".to_string(), "2".to_string()).unwrap();
+        assert_eq!(sessions.len().unwrap(), 1);
+
+        sessions.clear().unwrap();
+        assert!(sessions.is_empty().unwrap());
+        assert_eq!(backend.len().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_nested() {
+        let backend = shared();
+        let tenant = NamespacedStorage::new(backend.clone(), "acme:");
+        let sessions = NamespacedStorage::new(&tenant, "sessions:");
+
+        sessions.set("42", "Ferris".to_string()).unwrap();
+        assert!(backend.contains("acme:sessions:42".to_string()).unwrap());
+        assert_eq!(
+            tenant.get("sessions:42").unwrap(),
+            Some("Ferris".to_string())
+        );
+    }
+
+    #[test]
+    fn test_export_and_flush() {
+        let backend = shared();
+        let sessions = NamespacedStorage::new(backend.clone(), "sessions:");
+        sessions.set("a", "1".to_string()).unwrap();
+        backend.set("users:a".to_string(), "2".to_string()).unwrap();
+
+        let entries: Vec<(String, String)> = sessions
+            .export_iter()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(entries, [("a".to_string(), "1".to_string())]);
+
+        let keys: Vec<String> = sessions
+            .scan_prefix("")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(keys, ["a"]);
+
+        let confirm = FlushConfirmation::for_prefix("");
+        assert_eq!(sessions.flush_namespace("", &confirm).unwrap(), 1);
+        assert!(backend.contains("users:a".to_string()).unwrap());
+    }
+}
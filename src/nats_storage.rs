@@ -0,0 +1,377 @@
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_nats::header::{HeaderMap, HeaderValue, NATS_MESSAGE_TTL};
+use async_nats::jetstream::kv::{Config, Entry, Store, Watch};
+use async_nats::jetstream::Context;
+use futures::StreamExt;
+use tokio::runtime::Runtime;
+
+use crate::env;
+use crate::error::BuilderError;
+use crate::hooks::{Hooks, StorageHook};
+use crate::storage::{CloseFlag, Err, Storage};
+
+#[derive(Debug, Clone)]
+pub struct NatsStorage<K, V>
+where
+    V: Into<String>,
+{
+    runtime: Arc<Runtime>,
+    context: Context,
+    store: Store,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> NatsStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String>,
+{
+    pub fn bucket(&self) -> &str {
+        &self.store.name
+    }
+
+    pub fn watch(&self, key: K) -> Result<NatsWatch, Err> {
+        let watch = self.runtime.block_on(self.store.watch(key))?;
+        Ok(NatsWatch {
+            runtime: self.runtime.clone(),
+            watch,
+        })
+    }
+
+    fn subject(&self, key: &str) -> String {
+        let mut subject = String::new();
+        if self.store.use_jetstream_prefix {
+            subject.push_str("$JS.API.");
+        }
+        subject.push_str(self.store.put_prefix.as_ref().unwrap_or(&self.store.prefix));
+        subject.push_str(key);
+        subject
+    }
+}
+
+impl<K, V> Storage<K, V> for NatsStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        self.runtime.block_on(
+            self.store
+                .put(key.as_ref(), value.as_bytes().to_vec().into()),
+        )?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    // Per-message TTLs need a bucket created with `limit_markers` on a 2.11+ server.
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        let mut headers = HeaderMap::new();
+        headers.insert(NATS_MESSAGE_TTL, HeaderValue::from(expire.as_secs().max(1)));
+
+        self.runtime.block_on(async {
+            self.context
+                .publish_with_headers(
+                    self.subject(key.as_ref()),
+                    headers,
+                    value.as_bytes().to_vec().into(),
+                )
+                .await?
+                .await?;
+            Ok::<_, Err>(())
+        })?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let value = match self.runtime.block_on(self.store.get(key.as_ref()))? {
+            Some(bytes) => Some(String::from_utf8(bytes.to_vec())?.into()),
+            None => None,
+        };
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let found = self.runtime.block_on(async {
+            if self.store.get(key.as_ref()).await?.is_none() {
+                return Ok::<_, Err>(false);
+            }
+
+            self.store.delete(key.as_ref()).await?;
+            Ok(true)
+        })?;
+        self.hooks.del(&key);
+        Ok(found.then_some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        Ok(self
+            .runtime
+            .block_on(self.store.get(key.as_ref()))?
+            .is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        if !self.closed.close() {
+            return Ok(());
+        }
+        self.runtime.block_on(self.context.client().drain())?;
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+}
+
+pub struct NatsWatch {
+    runtime: Arc<Runtime>,
+    watch: Watch,
+}
+
+impl Iterator for NatsWatch {
+    type Item = Result<Entry, Err>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.runtime
+            .block_on(self.watch.next())
+            .map(|entry| entry.map_err(|e| e.into()))
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct NatsStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    addr: Option<String>,
+    bucket: Option<String>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::config::secs::option"))]
+    max_age: Option<Duration>,
+    history: Option<i64>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::config::secs::option"))]
+    limit_markers: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> NatsStorageBuilder<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String>,
+{
+    pub fn new() -> Self {
+        NatsStorageBuilder::default()
+    }
+
+    // Reads `NATS_URL` and `NATS_BUCKET`, plus optional `NATS_MAX_AGE` (seconds) and `NATS_HISTORY`.
+    pub fn from_env() -> Result<Self, BuilderError> {
+        let mut builder = NatsStorageBuilder::new()
+            .addr(&env::required("NATS_URL")?)
+            .bucket(&env::required("NATS_BUCKET")?);
+        if let Some(max_age) = env::parse("NATS_MAX_AGE")? {
+            builder = builder.max_age(Duration::from_secs(max_age));
+        }
+        if let Some(history) = env::parse("NATS_HISTORY")? {
+            builder = builder.history(history);
+        }
+        Ok(builder)
+    }
+
+    pub fn addr(mut self, addr: &str) -> Self {
+        self.addr = Some(addr.to_string());
+        self
+    }
+
+    pub fn bucket(mut self, bucket: &str) -> Self {
+        self.bucket = Some(bucket.to_string());
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn history(mut self, history: i64) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    pub fn limit_markers(mut self, limit_markers: Duration) -> Self {
+        self.limit_markers = Some(limit_markers);
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their TTL and batch variants.
+    // Clones of the storage share them, writes from other clients don't fire them.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if the address or bucket is missing or invalid, or the server cannot be reached.
+    pub fn build(self) -> NatsStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build nats storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<NatsStorage<K, V>, BuilderError> {
+        let addr = self
+            .addr
+            .clone()
+            .ok_or(BuilderError::MissingField("addr"))?;
+        let bucket = self
+            .bucket
+            .clone()
+            .ok_or(BuilderError::MissingField("bucket"))?;
+        if bucket.is_empty()
+            || !bucket
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(BuilderError::invalid(
+                "bucket",
+                "may only contain letters, digits, `-` and `_`",
+            ));
+        }
+        if let Some(history) = self.history {
+            if !(1..=64).contains(&history) {
+                return Err(BuilderError::invalid("history", "must be between 1 and 64"));
+            }
+        }
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(BuilderError::backend)?;
+
+        let (context, store) = runtime
+            .block_on(async {
+                let client = async_nats::connect(addr).await?;
+                let context = async_nats::jetstream::new(client);
+                let store = match context.get_key_value(bucket.as_str()).await {
+                    Ok(store) => store,
+                    Err(_) => {
+                        context
+                            .create_key_value(Config {
+                                bucket,
+                                history: self.history.unwrap_or(1),
+                                max_age: self.max_age.unwrap_or_default(),
+                                limit_markers: self.limit_markers,
+                                ..Default::default()
+                            })
+                            .await?
+                    }
+                };
+                Ok::<_, Err>((context, store))
+            })
+            .map_err(BuilderError::Backend)?;
+
+        Ok(NatsStorage {
+            runtime: Arc::new(runtime),
+            context,
+            store,
+            closed: CloseFlag::default(),
+            hooks: self.hooks,
+            _marker: self._marker,
+        })
+    }
+}
+
+impl<K, V> Default for NatsStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    fn default() -> Self {
+        Self {
+            addr: None,
+            bucket: None,
+            max_age: None,
+            history: None,
+            limit_markers: None,
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains() {
+        let storage = build_localhost::<String, String>();
+
+        storage
+            .set("name".to_string(), "Ferris".to_string())
+            .unwrap();
+        assert!(storage.contains("name".into()).unwrap());
+    }
+
+    #[test]
+    fn test_get() {
+        let storage = build_localhost();
+
+        let (key, value) = ("name", "Ferris".to_string());
+        let _ = storage.set(key, value.clone());
+        let resp = storage.get(key).unwrap();
+        assert_eq!(resp, Some(value));
+
+        let _ = storage.del(key).unwrap();
+        let resp = storage.get(key).unwrap();
+        assert_eq!(resp, None);
+    }
+
+    #[test]
+    fn test_watch() {
+        let storage = build_localhost::<&str, String>();
+
+        let mut watch = storage.watch("watch_test").unwrap();
+        storage.set("watch_test", "ok!".to_string()).unwrap();
+        let entry = watch.next().unwrap().unwrap();
+        assert_eq!(entry.key, "watch_test");
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = NatsStorageBuilder::<String, String>::new()
+            .addr("nats://127.0.0.1:4222")
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::MissingField("bucket"))));
+
+        let resp = NatsStorageBuilder::<String, String>::new()
+            .addr("nats://127.0.0.1:4222")
+            .bucket("not a bucket")
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(_))));
+    }
+
+    fn build_localhost<K: AsRef<str>, V: Into<String>>() -> NatsStorage<K, V> {
+        NatsStorageBuilder::<K, V>::new()
+            .addr("nats://127.0.0.1:4222")
+            .bucket("storage_trait_test")
+            .build()
+    }
+}
@@ -0,0 +1,706 @@
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use object_store::path::{Path, PathPart};
+use object_store::{ObjectStore, PutMode, PutOptions, PutPayload, UpdateVersion};
+use tokio::runtime::Runtime;
+
+use crate::admin::AdminStorage;
+use crate::error::BuilderError;
+use crate::export::{EntryIter, ExportEntry, ExportIter, ExportStorage};
+use crate::gc::GarbageCollect;
+use crate::get_or_insert::GetOrInsertStorage;
+use crate::hooks::{Hooks, StorageHook};
+use crate::storage::{CloseFlag, Err, Storage};
+
+// Bytes in front of every value: the expiry in unix millis, big-endian, 0 for none.
+const HEADER: usize = 8;
+
+// One object per key under a prefix of any `object_store` store: S3, GCS, Azure, a local
+// directory or memory. The expiry is written in front of the value rather than in object
+// metadata, so TTLs work on stores without metadata too, local files included. Expired
+// objects are never returned and stay in the store until overwritten, deleted or collected
+// by `gc_now`, or by a lifecycle rule on the bucket. Clones share the store and runtime.
+#[derive(Clone)]
+pub struct ObjectStoreStorage<K, V>
+where
+    V: Into<String>,
+{
+    runtime: Arc<Runtime>,
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    _marker: PhantomData<(K, V)>,
+}
+
+// An object as read, with the version to update it in place.
+struct Object {
+    value: Bytes,
+    expires_at: Option<u64>,
+    version: UpdateVersion,
+}
+
+impl<K, V> ObjectStoreStorage<K, V>
+where
+    V: Into<String>,
+{
+    pub fn prefix(&self) -> &Path {
+        &self.prefix
+    }
+
+    pub fn store(&self) -> &Arc<dyn ObjectStore> {
+        &self.store
+    }
+
+    fn location(&self, key: &str) -> Result<Path, Err> {
+        Ok(self.prefix.child(PathPart::parse(&object_name(key)?)?))
+    }
+
+    fn put(&self, key: &str, value: String, expires_at: Option<u64>) -> Result<(), Err> {
+        let location = self.location(key)?;
+        self.runtime
+            .block_on(self.store.put(&location, frame(value, expires_at)))?;
+        Ok(())
+    }
+
+    // The object, expired or not, `None` if there is none.
+    fn read(&self, location: &Path) -> Result<Option<Object>, Err> {
+        self.runtime.block_on(async {
+            let result = match self.store.get(location).await {
+                Ok(result) => result,
+                Err(object_store::Error::NotFound { .. }) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            let version = UpdateVersion {
+                e_tag: result.meta.e_tag.clone(),
+                version: result.meta.version.clone(),
+            };
+            let (expires_at, value) = unframe(result.bytes().await?)?;
+            Ok(Some(Object {
+                value,
+                expires_at,
+                version,
+            }))
+        })
+    }
+
+    fn lookup(&self, key: &str) -> Result<Option<String>, Err> {
+        match self.read(&self.location(key)?)? {
+            Some(object) if is_live(object.expires_at) => {
+                Ok(Some(String::from_utf8(object.value.to_vec())?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // Returns whether an object was removed. Stores report deleting a missing object as
+    // success, only some as `NotFound`.
+    fn remove(&self, location: &Path) -> Result<bool, Err> {
+        match self.runtime.block_on(self.store.delete(location)) {
+            Ok(()) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Every object under the prefix, listed up front, the values are read one at a time.
+    fn locations(&self) -> Result<Vec<Path>, Err> {
+        let metas = self
+            .runtime
+            .block_on(self.store.list(Some(&self.prefix)).try_collect::<Vec<_>>())?;
+        Ok(metas.into_iter().map(|meta| meta.location).collect())
+    }
+}
+
+impl<K, V> Storage<K, V> for ObjectStoreStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        self.put(key.as_ref(), value.clone(), None)?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        self.put(key.as_ref(), value.clone(), Some(expires_at(expire)))?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let value = self.lookup(key.as_ref())?.map(V::from);
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    // Reads the object first to tell whether the key was live, then deletes it. A write by
+    // another client in between is deleted with it.
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let location = self.location(key.as_ref())?;
+        let found = match self.read(&location)? {
+            Some(object) => self.remove(&location)? && is_live(object.expires_at),
+            None => false,
+        };
+        self.hooks.del(&key);
+        Ok(found.then_some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        Ok(self.lookup(key.as_ref())?.is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+
+    // Puts run concurrently, a failed one leaves the others written.
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.closed.check()?;
+        let entries: Vec<(K, String)> = entries
+            .into_iter()
+            .map(|(key, value)| (key, value.into()))
+            .collect();
+        let puts = entries
+            .iter()
+            .map(|(key, value)| {
+                let location = self.location(key.as_ref())?;
+                Ok((location, frame(value.clone(), None)))
+            })
+            .collect::<Result<Vec<_>, Err>>()?;
+        self.runtime.block_on(
+            futures::stream::iter(puts)
+                .map(|(location, payload)| async move {
+                    self.store.put(&location, payload).await.map(drop)
+                })
+                .buffer_unordered(16)
+                .try_collect::<Vec<()>>(),
+        )?;
+        for (key, value) in entries {
+            self.hooks.set_with(&key, || V::from(value));
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> ExportStorage<K, V> for ObjectStoreStorage<K, V>
+where
+    K: AsRef<str> + From<String>,
+    V: Into<String> + From<String>,
+{
+    fn export_iter(&self) -> Result<ExportIter<'_, K, V>, Err> {
+        Ok(Box::new(
+            self.export_entries()?
+                .map(|entry| entry.map(|entry| (entry.key, entry.value))),
+        ))
+    }
+
+    // Lists the prefix first and reads each object when it is reached. Expired objects and
+    // ones deleted since the listing are skipped.
+    fn export_entries<'a>(&'a self) -> Result<EntryIter<'a, K, V>, Err>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.closed.check()?;
+        let locations = self.locations()?;
+        Ok(Box::new(locations.into_iter().filter_map(
+            move |location| {
+                let entry = (|| {
+                    let Some(name) = location.filename() else {
+                        return Ok(None);
+                    };
+                    let key = key_name(name)?;
+                    let Some(object) = self.read(&location)? else {
+                        return Ok(None);
+                    };
+                    if !is_live(object.expires_at) {
+                        return Ok(None);
+                    }
+                    let now = now_millis();
+                    Ok(Some(ExportEntry {
+                        key: K::from(key),
+                        value: V::from(String::from_utf8(object.value.to_vec())?),
+                        ttl: object.expires_at.map(|expires_at| {
+                            Duration::from_millis(expires_at.saturating_sub(now))
+                        }),
+                    }))
+                })();
+                entry.transpose()
+            },
+        )))
+    }
+}
+
+// Reads every object under the prefix for its header, so a run costs a listing plus one
+// read per object until `batch_size` expired ones were deleted.
+impl<K, V> GarbageCollect for ObjectStoreStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn gc_now(&self, batch_size: usize) -> Result<usize, Err> {
+        self.closed.check()?;
+        let mut removed = 0;
+        for location in self.locations()? {
+            if removed >= batch_size {
+                break;
+            }
+            let Some(object) = self.read(&location)? else {
+                continue;
+            };
+            if is_live(object.expires_at) {
+                continue;
+            }
+            // Only deletes the version that was read, where the store can tell, so a
+            // concurrent `set` survives.
+            if self.expire_version(&location, object.version)? {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+impl<K, V> ObjectStoreStorage<K, V>
+where
+    V: Into<String>,
+{
+    // Stores can't delete conditionally, so the expired object is first overwritten with an
+    // empty expired one on its version, which fails if it changed, and then deleted. Stores
+    // without conditional updates, like local files, fall back to a plain delete.
+    fn expire_version(&self, location: &Path, version: UpdateVersion) -> Result<bool, Err> {
+        let opts = PutOptions {
+            mode: PutMode::Update(version),
+            ..PutOptions::default()
+        };
+        let tombstone = frame(String::new(), Some(1));
+        match self
+            .runtime
+            .block_on(self.store.put_opts(location, tombstone, opts))
+        {
+            Ok(_) | Err(object_store::Error::NotImplemented) => self.remove(location),
+            Err(object_store::Error::Precondition { .. })
+            | Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+// A create that fails if the object exists. An expired object is replaced on its version
+// instead, so of concurrent callers only one wins either way. Stores without conditional
+// creates or updates, e.g. S3 without a conditional put configured, or local files for the
+// update, fail with their `NotImplemented` error.
+impl<K, V> GetOrInsertStorage<K, V> for ObjectStoreStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set_nx(&self, key: K, value: V, expire: Option<Duration>) -> Result<bool, Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        let location = self.location(key.as_ref())?;
+        let payload = frame(value, expire.map(expires_at));
+        let create = PutOptions {
+            mode: PutMode::Create,
+            ..PutOptions::default()
+        };
+        match self
+            .runtime
+            .block_on(self.store.put_opts(&location, payload.clone(), create))
+        {
+            Ok(_) => return Ok(true),
+            Err(object_store::Error::AlreadyExists { .. }) => {}
+            Err(e) => return Err(e.into()),
+        }
+        let version = match self.read(&location)? {
+            Some(object) if !is_live(object.expires_at) => object.version,
+            // Live, or deleted since the create failed, which counts as losing the race.
+            _ => return Ok(false),
+        };
+        let update = PutOptions {
+            mode: PutMode::Update(version),
+            ..PutOptions::default()
+        };
+        match self
+            .runtime
+            .block_on(self.store.put_opts(&location, payload, update))
+        {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::Precondition { .. })
+            | Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+// `len` reads every object to skip expired ones, `clear` deletes everything under the prefix.
+impl<K, V> AdminStorage for ObjectStoreStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn clear(&self) -> Result<(), Err> {
+        self.closed.check()?;
+        self.runtime.block_on(async {
+            let locations = self
+                .store
+                .list(Some(&self.prefix))
+                .map_ok(|meta| meta.location)
+                .boxed();
+            self.store
+                .delete_stream(locations)
+                .try_collect::<Vec<_>>()
+                .await
+        })?;
+        Ok(())
+    }
+
+    fn len(&self) -> Result<usize, Err> {
+        self.closed.check()?;
+        let mut len = 0;
+        for location in self.locations()? {
+            if self
+                .read(&location)?
+                .is_some_and(|object| is_live(object.expires_at))
+            {
+                len += 1;
+            }
+        }
+        Ok(len)
+    }
+}
+
+fn frame(value: String, expires_at: Option<u64>) -> PutPayload {
+    let mut bytes = Vec::with_capacity(HEADER + value.len());
+    bytes.extend_from_slice(&expires_at.unwrap_or(0).to_be_bytes());
+    bytes.extend_from_slice(value.as_bytes());
+    PutPayload::from(bytes)
+}
+
+fn unframe(mut bytes: Bytes) -> Result<(Option<u64>, Bytes), Err> {
+    if bytes.len() < HEADER {
+        return Err("Object too short for ObjectStoreStorage, it wasn't written by it".into());
+    }
+    let value = bytes.split_off(HEADER);
+    let expires_at = u64::from_be_bytes(bytes[..].try_into()?);
+    Ok(((expires_at > 0).then_some(expires_at), value))
+}
+
+// Keys become object names like `FsStorage` file names: ASCII letters, digits, `-` and `_`
+// are kept, every other byte is written as `%XX`.
+fn object_name(key: &str) -> Result<String, Err> {
+    if key.is_empty() {
+        return Err("Invalid key for ObjectStoreStorage: empty key".into());
+    }
+    let mut name = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    Ok(name)
+}
+
+fn key_name(name: &str) -> Result<String, Err> {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut rest = name.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' && tail.len() >= 2 {
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&tail[..2])?, 16)?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+fn expires_at(expire: Duration) -> u64 {
+    now_millis() + expire.as_millis() as u64
+}
+
+fn is_live(expires_at: Option<u64>) -> bool {
+    expires_at.is_none_or(|expires_at| expires_at > now_millis())
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ObjectStoreStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    url: Option<String>,
+    options: BTreeMap<String, String>,
+    prefix: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    store: Option<Arc<dyn ObjectStore>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> ObjectStoreStorageBuilder<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    pub fn new() -> Self {
+        ObjectStoreStorageBuilder::default()
+    }
+
+    // A store URL like `s3://bucket/path`, `file:///var/cache` or `memory:///`, its path
+    // becomes the prefix. Cloud schemes need the matching `object_store` feature, e.g.
+    // `aws`, enabled in your own manifest.
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_string());
+        self
+    }
+
+    // A store option for the URL, e.g. `("aws_region", "eu-west-1")`. Options the store
+    // doesn't know are ignored.
+    pub fn option(mut self, key: &str, value: &str) -> Self {
+        self.options.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    // A store built by hand, used instead of `url`.
+    pub fn store(mut self, store: Arc<dyn ObjectStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    // The path the objects go under, after the URL's own path.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.to_string());
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their TTL and batch variants.
+    // Clones of the storage share them, writes from other clients don't fire them.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if neither a store nor a URL is set, or the URL or prefix is invalid. Use
+    // `try_build` to get the error instead.
+    pub fn build(self) -> ObjectStoreStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build object store storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<ObjectStoreStorage<K, V>, BuilderError> {
+        let (store, mut prefix) = match (self.store, &self.url) {
+            (Some(store), _) => (store, Path::default()),
+            (None, Some(url)) => {
+                let url = url::Url::parse(url)
+                    .map_err(|e| BuilderError::invalid("url", e.to_string()))?;
+                let (store, path) = object_store::parse_url_opts(&url, self.options)
+                    .map_err(|e| BuilderError::invalid("url", e.to_string()))?;
+                (Arc::from(store), path)
+            }
+            (None, None) => return Err(BuilderError::MissingField("url")),
+        };
+        if let Some(extra) = &self.prefix {
+            let extra =
+                Path::parse(extra).map_err(|e| BuilderError::invalid("prefix", e.to_string()))?;
+            prefix = prefix.parts().chain(extra.parts()).collect();
+        }
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(BuilderError::backend)?;
+
+        Ok(ObjectStoreStorage {
+            runtime: Arc::new(runtime),
+            store,
+            prefix,
+            closed: CloseFlag::default(),
+            hooks: self.hooks,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<K, V> Default for ObjectStoreStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    fn default() -> Self {
+        Self {
+            url: None,
+            options: BTreeMap::new(),
+            prefix: None,
+            store: None,
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    crate::storage_conformance_tests!(
+        conformance,
+        ObjectStoreStorageBuilder::<String, String>::new()
+            .url("memory:///")
+            .prefix("cache")
+            .build()
+    );
+
+    fn memory() -> ObjectStoreStorage<&'static str, String> {
+        ObjectStoreStorageBuilder::new()
+            .store(Arc::new(InMemory::new()))
+            .build()
+    }
+
+    #[test]
+    fn test_local_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = ObjectStoreStorageBuilder::<String, String>::new()
+            .url(&format!("file://{}", dir.path().display()))
+            .prefix("cache/v1")
+            .build();
+
+        storage
+            .set("user/1 name".to_string(), "Ferris".to_string())
+            .unwrap();
+        assert!(dir.path().join("cache/v1/user%2F1%20name").is_file());
+        assert_eq!(
+            storage.get("user/1 name".to_string()).unwrap(),
+            Some("Ferris".to_string())
+        );
+        let keys: Vec<String> = storage
+            .export_iter()
+            .unwrap()
+            .map(|entry| entry.unwrap().0)
+            .collect();
+        assert_eq!(keys, ["user/1 name"]);
+
+        // Local files have no conditional updates, `gc_now` deletes without one.
+        storage
+            .set_ex("a".into(), "1".into(), Duration::from_millis(10))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(storage.gc_now(10).unwrap(), 1);
+        assert!(storage.set_nx("a".into(), "2".into(), None).unwrap());
+        assert!(!storage.set_nx("a".into(), "3".into(), None).unwrap());
+    }
+
+    #[test]
+    fn test_expiry() {
+        let storage = ObjectStoreStorageBuilder::<String, String>::new()
+            .url("memory:///")
+            .build();
+        storage
+            .set_ex("a".into(), "1".into(), Duration::from_millis(20))
+            .unwrap();
+        storage
+            .set_ex("b".into(), "2".into(), Duration::from_secs(60))
+            .unwrap();
+        storage.set("c".into(), "3".into()).unwrap();
+        std::thread::sleep(Duration::from_millis(30));
+
+        assert_eq!(storage.get("a".into()).unwrap(), None);
+        assert_eq!(storage.del("a".into()).unwrap(), None);
+        storage
+            .set_ex("a".into(), "1".into(), Duration::from_millis(1))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(storage.len().unwrap(), 2);
+        let entries: Vec<ExportEntry<String, String>> = storage
+            .export_entries()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].key, "b");
+        assert!(entries[0].ttl.unwrap() > Duration::from_secs(59));
+        assert_eq!((entries[1].key.as_str(), entries[1].ttl), ("c", None));
+
+        assert_eq!(storage.gc_now(10).unwrap(), 1);
+        assert_eq!(storage.gc_now(10).unwrap(), 0);
+        storage.clear().unwrap();
+        assert!(storage.is_empty().unwrap());
+    }
+
+    #[test]
+    fn test_set_nx() {
+        let storage = memory();
+        assert!(storage
+            .set_nx("a", "1".to_string(), Some(Duration::from_millis(10)))
+            .unwrap());
+        assert!(!storage.set_nx("a", "2".to_string(), None).unwrap());
+        std::thread::sleep(Duration::from_millis(20));
+        // Replaces the expired object on its version.
+        assert!(storage.set_nx("a", "3".to_string(), None).unwrap());
+        assert_eq!(storage.get("a").unwrap(), Some("3".to_string()));
+        let value = storage.get_or_insert_with("b", || "4".to_string()).unwrap();
+        assert_eq!(value, "4");
+    }
+
+    #[test]
+    fn test_foreign_objects() {
+        let store = Arc::new(InMemory::new());
+        let storage = ObjectStoreStorageBuilder::<&str, String>::new()
+            .store(store.clone())
+            .build();
+        let runtime = Runtime::new().unwrap();
+        runtime
+            .block_on(store.put(&Path::from("short"), PutPayload::from_static(b"abc")))
+            .unwrap();
+        assert!(storage.get("short").is_err());
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = ObjectStoreStorageBuilder::<&str, String>::new().try_build();
+        assert!(matches!(resp, Err(BuilderError::MissingField("url"))));
+        for url in ["not a url", "unknown://bucket"] {
+            let resp = ObjectStoreStorageBuilder::<&str, String>::new()
+                .url(url)
+                .try_build();
+            assert!(matches!(resp, Err(BuilderError::InvalidConfig(e)) if e.field == "url"));
+        }
+        let resp = ObjectStoreStorageBuilder::<&str, String>::new()
+            .url("memory:///")
+            .prefix("a//b")
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(e)) if e.field == "prefix"));
+    }
+}
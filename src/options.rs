@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant};
+
+use crate::context::Context;
+use crate::error::StorageError;
+use crate::storage::Err;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Consistency {
+    // Whatever the storage does without options.
+    #[default]
+    Default,
+    // Prefer the authoritative copy, e.g. compare every replica.
+    Strong,
+    // Any copy will do, e.g. the first replica that answers.
+    Eventual,
+}
+
+// Per-call overrides of the builder defaults, see the `*_with_opts` methods on `Storage`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpOptions {
+    pub deadline: Option<Instant>,
+    pub consistency: Consistency,
+    // Makes `set_with_opts` expire the key, like `set_ex`.
+    pub ttl: Option<Duration>,
+    pub context: Option<Context>,
+}
+
+impl OpOptions {
+    pub fn new() -> Self {
+        OpOptions::default()
+    }
+
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.deadline(Instant::now() + timeout)
+    }
+
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn consistency(mut self, consistency: Consistency) -> Self {
+        self.consistency = consistency;
+        self
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn context(mut self, context: Context) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    // Time left before the deadline, `StorageError::Timeout` once it has passed.
+    pub fn remaining(&self) -> Result<Option<Duration>, Err> {
+        match self.deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(left) if !left.is_zero() => Ok(Some(left)),
+                _ => Err(StorageError::Timeout.into()),
+            },
+            None => Ok(None),
+        }
+    }
+}
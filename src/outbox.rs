@@ -0,0 +1,78 @@
+use crate::storage::{Err, Storage};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutboxEvent {
+    pub id: u64,
+    pub payload: String,
+}
+
+// Backends that can commit an event in the same transaction as the value it describes.
+pub trait OutboxStorage<K, V>: Storage<K, V> + OutboxLog {
+    fn set_with_event(&self, key: K, value: V, event: &str) -> Result<u64, Err>;
+    // The event is only recorded if the key existed.
+    fn del_with_event(&self, key: K, event: &str) -> Result<Option<K>, Err>;
+}
+
+// The relay side of an outbox: events stay pending, oldest first, until acknowledged.
+pub trait OutboxLog {
+    fn pending_events(&self, limit: usize) -> Result<Vec<OutboxEvent>, Err>;
+    fn ack_events(&self, ids: &[u64]) -> Result<(), Err>;
+}
+
+impl<L: OutboxLog + ?Sized> OutboxLog for &L {
+    fn pending_events(&self, limit: usize) -> Result<Vec<OutboxEvent>, Err> {
+        (**self).pending_events(limit)
+    }
+
+    fn ack_events(&self, ids: &[u64]) -> Result<(), Err> {
+        (**self).ack_events(ids)
+    }
+}
+
+// Polls an `OutboxLog` and hands events to a publisher, acknowledging the ones it accepted.
+pub struct Outbox<L> {
+    log: L,
+    batch_size: usize,
+}
+
+impl<L: OutboxLog> Outbox<L> {
+    pub fn new(log: L) -> Self {
+        Outbox {
+            log,
+            batch_size: 100,
+        }
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    // Publishes one batch in order and returns how many events were delivered. Stops at the
+    // first publish error, events before it are acknowledged, the rest stay pending.
+    pub fn relay<F>(&self, mut publish: F) -> Result<usize, Err>
+    where
+        F: FnMut(&OutboxEvent) -> Result<(), Err>,
+    {
+        let events = self.log.pending_events(self.batch_size)?;
+        let mut delivered = Vec::with_capacity(events.len());
+        let mut failed = None;
+        for event in &events {
+            match publish(event) {
+                Ok(()) => delivered.push(event.id),
+                Err(e) => {
+                    failed = Some(e);
+                    break;
+                }
+            }
+        }
+
+        if !delivered.is_empty() {
+            self.log.ack_events(&delivered)?;
+        }
+        match failed {
+            Some(e) => Err(e),
+            None => Ok(delivered.len()),
+        }
+    }
+}
@@ -0,0 +1,664 @@
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use persy::{ByteVec, Config, Persy, TransactionConfig, ValueMode};
+
+use crate::durability::Durability;
+use crate::error::BuilderError;
+use crate::export::{EntryIter, ExportEntry, ExportIter, ExportStorage};
+use crate::flush::{FlushConfirmation, FlushNamespace};
+use crate::gc::GarbageCollect;
+use crate::hooks::{Hooks, StorageHook};
+use crate::outbox::{OutboxEvent, OutboxLog, OutboxStorage};
+use crate::storage::{CloseFlag, Err, Storage};
+
+const INDEX: &str = "storage_trait";
+const OUTBOX: &str = "storage_trait_outbox";
+
+#[derive(Clone)]
+pub struct PersyStorage<K, V>
+where
+    V: Into<String>,
+{
+    persy: Persy,
+    background_sync: bool,
+    next_event: Arc<AtomicU64>,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> PersyStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn put(
+        &self,
+        key: &str,
+        value: &str,
+        expires_at: u64,
+        event: Option<&str>,
+    ) -> Result<u64, Err> {
+        let mut record = expires_at.to_be_bytes().to_vec();
+        record.extend_from_slice(value.as_bytes());
+
+        let mut tx = self.begin()?;
+        tx.put::<String, ByteVec>(INDEX, key.to_string(), record.into())?;
+        let id = self.queue_event(&mut tx, event)?;
+        tx.prepare()?.commit()?;
+        Ok(id)
+    }
+
+    // Whether there was a value to remove.
+    fn remove(&self, key: &str, event: Option<&str>) -> Result<bool, Err> {
+        if self.lookup(key)?.is_none() {
+            return Ok(false);
+        }
+
+        let mut tx = self.begin()?;
+        tx.remove::<String, ByteVec>(INDEX, key.to_string(), None)?;
+        self.queue_event(&mut tx, event)?;
+        tx.prepare()?.commit()?;
+        Ok(true)
+    }
+
+    fn queue_event(&self, tx: &mut persy::Transaction, event: Option<&str>) -> Result<u64, Err> {
+        match event {
+            Some(event) => {
+                let id = self.next_event.fetch_add(1, Ordering::SeqCst);
+                tx.put::<u64, ByteVec>(OUTBOX, id, event.as_bytes().to_vec().into())?;
+                Ok(id)
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn lookup(&self, key: &str) -> Result<Option<String>, Err> {
+        match self.persy.one::<String, ByteVec>(INDEX, &key.to_string())? {
+            Some(record) => Ok(decode(&record)?.map(|(value, _)| value)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<K, V> PersyStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn begin(&self) -> Result<persy::Transaction, Err> {
+        Ok(self
+            .persy
+            .begin_with(TransactionConfig::new().set_background_sync(self.background_sync))?)
+    }
+}
+
+impl<K, V> Storage<K, V> for PersyStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        self.put(key.as_ref(), &value, 0, None)?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        self.put(
+            key.as_ref(),
+            &value,
+            now_millis() + expire.as_millis() as u64,
+            None,
+        )?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let value = self.lookup(key.as_ref())?.map(V::from);
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let removed = self.remove(key.as_ref(), None)?;
+        self.hooks.del(&key);
+        Ok(removed.then_some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        Ok(self.lookup(key.as_ref())?.is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+}
+
+impl<K, V> OutboxStorage<K, V> for PersyStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set_with_event(&self, key: K, value: V, event: &str) -> Result<u64, Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        self.put(key.as_ref(), &value, 0, Some(event))
+    }
+
+    fn del_with_event(&self, key: K, event: &str) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        Ok(self.remove(key.as_ref(), Some(event))?.then_some(key))
+    }
+}
+
+impl<K, V> OutboxLog for PersyStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn pending_events(&self, limit: usize) -> Result<Vec<OutboxEvent>, Err> {
+        self.closed.check()?;
+        let mut events = Vec::new();
+        for (id, mut payload) in self.persy.range::<u64, ByteVec, _>(OUTBOX, ..)?.take(limit) {
+            if let Some(payload) = payload.next() {
+                events.push(OutboxEvent {
+                    id,
+                    payload: String::from_utf8(payload.to_vec())?,
+                });
+            }
+        }
+        Ok(events)
+    }
+
+    fn ack_events(&self, ids: &[u64]) -> Result<(), Err> {
+        self.closed.check()?;
+        let mut tx = self.begin()?;
+        for id in ids {
+            tx.remove::<u64, ByteVec>(OUTBOX, *id, None)?;
+        }
+        tx.prepare()?.commit()?;
+        Ok(())
+    }
+}
+
+impl<K, V> ExportStorage<K, V> for PersyStorage<K, V>
+where
+    K: AsRef<str> + From<String>,
+    V: Into<String> + From<String>,
+{
+    fn export_iter(&self) -> Result<ExportIter<'_, K, V>, Err> {
+        Ok(Box::new(
+            self.export_entries()?
+                .map(|entry| entry.map(|entry| (entry.key, entry.value))),
+        ))
+    }
+
+    // Follows the index in key order, expired entries are skipped.
+    fn export_entries<'a>(&'a self) -> Result<EntryIter<'a, K, V>, Err>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.closed.check()?;
+        let entries = self.persy.range::<String, ByteVec, _>(INDEX, ..)?;
+        Ok(Box::new(entries.filter_map(
+            |(key, mut records)| match decode(&records.next()?) {
+                Ok(value) => value.map(|(value, ttl)| {
+                    Ok(ExportEntry {
+                        key: K::from(key),
+                        value: V::from(value),
+                        ttl,
+                    })
+                }),
+                Err(e) => Some(Err(e)),
+            },
+        )))
+    }
+}
+
+impl<K, V> GarbageCollect for PersyStorage<K, V>
+where
+    V: Into<String>,
+{
+    // Expired keys are re-checked inside the transaction, so a concurrent `set` survives.
+    fn gc_now(&self, batch_size: usize) -> Result<usize, Err> {
+        self.closed.check()?;
+        let mut expired = Vec::new();
+        for (key, mut records) in self.persy.range::<String, ByteVec, _>(INDEX, ..)? {
+            if expired.len() >= batch_size {
+                break;
+            }
+            if let Some(record) = records.next() {
+                if decode(&record)?.is_none() {
+                    expired.push(key);
+                }
+            }
+        }
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        let mut tx = self.begin()?;
+        let mut removed = 0;
+        for key in expired {
+            if let Some(record) = tx.one::<String, ByteVec>(INDEX, &key)? {
+                if decode(&record)?.is_none() {
+                    tx.remove::<String, ByteVec>(INDEX, key, None)?;
+                    removed += 1;
+                }
+            }
+        }
+        tx.prepare()?.commit()?;
+        Ok(removed)
+    }
+}
+
+impl<K, V> FlushNamespace for PersyStorage<K, V>
+where
+    V: Into<String>,
+{
+    // A range delete over the index, committed in one transaction.
+    fn flush_namespace(&self, prefix: &str, confirm: &FlushConfirmation) -> Result<usize, Err> {
+        self.closed.check()?;
+        confirm.check(prefix)?;
+        let keys: Vec<String> = self
+            .persy
+            .range::<String, ByteVec, _>(INDEX, prefix.to_string()..)?
+            .map(|(key, _)| key)
+            .take_while(|key| key.starts_with(prefix))
+            .collect();
+        if keys.is_empty() {
+            return Ok(0);
+        }
+
+        let mut tx = self.begin()?;
+        for key in &keys {
+            tx.remove::<String, ByteVec>(INDEX, key.clone(), None)?;
+        }
+        tx.prepare()?.commit()?;
+        Ok(keys.len())
+    }
+}
+
+// Records are the expiry in unix millis (0 for none) followed by the value. Returns the
+// value and its remaining TTL, `None` once expired.
+fn decode(record: &[u8]) -> Result<Option<(String, Option<Duration>)>, Err> {
+    let (expires_at, value) = record.split_at(8);
+    let expires_at = u64::from_be_bytes(expires_at.try_into()?);
+    let ttl = match expires_at {
+        0 => None,
+        expires_at => match expires_at.checked_sub(now_millis()) {
+            Some(left) if left > 0 => Some(Duration::from_millis(left)),
+            _ => return Ok(None),
+        },
+    };
+    Ok(Some((String::from_utf8(value.to_vec())?, ttl)))
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct PersyStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    path: Option<PathBuf>,
+    cache_size: Option<u64>,
+    background_sync: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> PersyStorageBuilder<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    pub fn new() -> Self {
+        PersyStorageBuilder::default()
+    }
+
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn cache_size(mut self, cache_size: u64) -> Self {
+        self.cache_size = Some(cache_size);
+        self
+    }
+
+    // Commit without waiting for the fsync, trading durability of the last writes for latency.
+    pub fn background_sync(mut self, background_sync: bool) -> Self {
+        self.background_sync = background_sync;
+        self
+    }
+
+    // Persy syncs on every commit unless background sync is on, so anything short of
+    // `EveryWrite` maps to `background_sync(true)`.
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.background_sync = durability != Durability::EveryWrite;
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their TTL variants. Clones of
+    // the storage share them, the outbox writes don't fire them.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if the path is missing, the cache size is zero, or the database cannot be opened.
+    pub fn build(self) -> PersyStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build persy storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<PersyStorage<K, V>, BuilderError> {
+        let path = self
+            .path
+            .clone()
+            .ok_or(BuilderError::MissingField("path"))?;
+
+        let mut config = Config::new();
+        if let Some(cache_size) = self.cache_size {
+            if cache_size == 0 {
+                return Err(BuilderError::invalid("cache_size", "must not be 0"));
+            }
+            config.change_cache_size(cache_size);
+        }
+
+        let persy = Persy::open_or_create_with(path, config, |persy| {
+            let mut tx = persy.begin()?;
+            tx.create_index::<String, ByteVec>(INDEX, ValueMode::Replace)?;
+            tx.prepare()?.commit()?;
+            Ok(())
+        })
+        .map_err(BuilderError::backend)?;
+        let next_event = last_event(&persy).map_err(BuilderError::Backend)? + 1;
+
+        Ok(PersyStorage {
+            persy,
+            background_sync: self.background_sync,
+            next_event: Arc::new(AtomicU64::new(next_event)),
+            closed: CloseFlag::default(),
+            hooks: self.hooks,
+            _marker: self._marker,
+        })
+    }
+}
+
+// Creates the outbox index for files written before it existed.
+fn last_event(persy: &Persy) -> Result<u64, Err> {
+    if !persy.exists_index(OUTBOX)? {
+        let mut tx = persy.begin()?;
+        tx.create_index::<u64, ByteVec>(OUTBOX, ValueMode::Replace)?;
+        tx.prepare()?.commit()?;
+        return Ok(0);
+    }
+    Ok(persy
+        .range::<u64, ByteVec, _>(OUTBOX, ..)?
+        .next_back()
+        .map_or(0, |(id, _)| id))
+}
+
+impl<K, V> Default for PersyStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    fn default() -> Self {
+        Self {
+            path: None,
+            cache_size: None,
+            background_sync: false,
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outbox::Outbox;
+
+    #[test]
+    fn test_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = PersyStorageBuilder::new()
+            .path(dir.path().join("data.persy"))
+            .build();
+
+        let (key, value) = ("name", "Ferris".to_string());
+        let _ = storage.set(key, value.clone());
+        let resp = storage.get(key).unwrap();
+        assert_eq!(resp, Some(value));
+
+        let _ = storage.del(key).unwrap();
+        let resp = storage.get(key).unwrap();
+        assert_eq!(resp, None);
+    }
+
+    #[test]
+    fn test_set_ex() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = PersyStorageBuilder::new()
+            .path(dir.path().join("data.persy"))
+            .build();
+
+        let (key, value) = ("set_ex_test", "ok!".to_string());
+        storage
+            .set_ex(key, value.clone(), Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(storage.get(key).unwrap(), Some(value));
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(!storage.contains(key).unwrap());
+    }
+
+    #[test]
+    fn test_gc_task() {
+        use crate::gc::GcTaskBuilder;
+
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(
+            PersyStorageBuilder::<&str, String>::new()
+                .path(dir.path().join("data.persy"))
+                .build(),
+        );
+        for key in ["a", "b", "c"] {
+            storage
+                .set_ex(key, "ok!".to_string(), Duration::from_millis(10))
+                .unwrap();
+        }
+        storage.set("d", "ok!".to_string()).unwrap();
+
+        let task = GcTaskBuilder::new(storage.clone())
+            .interval(Duration::from_millis(20))
+            .batch_size(2)
+            .build();
+        for _ in 0..100 {
+            if task.collected() == 3 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        task.stop();
+        assert_eq!(
+            storage
+                .persy
+                .range::<String, ByteVec, _>(INDEX, ..)
+                .unwrap()
+                .count(),
+            1
+        );
+        assert_eq!(storage.gc_now(10).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_flush_namespace() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = PersyStorageBuilder::<&str, String>::new()
+            .path(dir.path().join("data.persy"))
+            .build();
+        for key in ["tenant:1:a", "tenant:1:b", "tenant:2:a"] {
+            storage.set(key, "ok!".to_string()).unwrap();
+        }
+
+        let confirm = FlushConfirmation::for_prefix("tenant:1:");
+        assert!(storage.flush_namespace("tenant:", &confirm).is_err());
+        assert_eq!(storage.flush_namespace("tenant:1:", &confirm).unwrap(), 2);
+        assert!(!storage.contains("tenant:1:a").unwrap());
+        assert!(storage.contains("tenant:2:a").unwrap());
+    }
+
+    #[test]
+    fn test_outbox() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.persy");
+        let storage = PersyStorageBuilder::<&str, String>::new()
+            .path(&path)
+            .build();
+
+        storage
+            .set_with_event("user/7", "Ferris".to_string(), "user 7 created")
+            .unwrap();
+        storage.del_with_event("user/7", "user 7 deleted").unwrap();
+        assert_eq!(storage.del_with_event("user/7", "ignored").unwrap(), None);
+
+        let outbox = Outbox::new(&storage).batch_size(1);
+        let mut published = Vec::new();
+        assert_eq!(
+            outbox
+                .relay(|event| {
+                    published.push(event.payload.clone());
+                    Ok(())
+                })
+                .unwrap(),
+            1
+        );
+        assert!(outbox.relay(|_| Err("broker down".into())).is_err());
+        drop(storage);
+
+        let storage = PersyStorageBuilder::<&str, String>::new()
+            .path(&path)
+            .build();
+        let pending = storage.pending_events(10).unwrap();
+        assert_eq!(published, ["user 7 created"]);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].payload, "user 7 deleted");
+        storage.ack_events(&[pending[0].id]).unwrap();
+        assert!(storage.pending_events(10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_export_iter() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = PersyStorageBuilder::<String, String>::new()
+            .path(dir.path().join("data.persy"))
+            .build();
+        storage.set("a".to_string(), "1".to_string()).unwrap();
+        storage.set("b".to_string(), "2".to_string()).unwrap();
+        storage
+            .set_ex("c".to_string(), "3".to_string(), Duration::from_millis(10))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+
+        let entries: Vec<_> = storage.export_iter().unwrap().map(Result::unwrap).collect();
+        assert_eq!(
+            entries,
+            [
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "2".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_snapshot_to() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = PersyStorageBuilder::<String, String>::new()
+            .path(dir.path().join("data.persy"))
+            .build();
+        storage.set("a".to_string(), "1".to_string()).unwrap();
+        storage
+            .set_ex("b".to_string(), "2".to_string(), Duration::from_secs(60))
+            .unwrap();
+
+        let entries: Vec<_> = storage
+            .export_entries()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(entries[0].ttl, None);
+        assert!(entries[1].ttl.unwrap() <= Duration::from_secs(60));
+
+        let copy = PersyStorageBuilder::<String, String>::new()
+            .path(dir.path().join("copy.persy"))
+            .build();
+        assert_eq!(storage.snapshot_to(&copy).unwrap(), 2);
+        assert!(copy
+            .export_entries()
+            .unwrap()
+            .nth(1)
+            .unwrap()
+            .unwrap()
+            .ttl
+            .is_some());
+
+        let restored = PersyStorageBuilder::<String, String>::new()
+            .path(dir.path().join("restored.persy"))
+            .build();
+        assert_eq!(restored.restore_from(&copy).unwrap(), 2);
+        assert_eq!(
+            restored.get("a".to_string()).unwrap(),
+            Some("1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = PersyStorageBuilder::<&str, String>::new().try_build();
+        assert!(matches!(resp, Err(BuilderError::MissingField("path"))));
+    }
+
+    #[test]
+    fn test_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.persy");
+
+        let storage = PersyStorageBuilder::new().path(&path).build();
+        storage.set("name", "Ferris".to_string()).unwrap();
+        drop(storage);
+
+        let storage = PersyStorageBuilder::<&str, String>::new()
+            .path(&path)
+            .build();
+        assert_eq!(storage.get("name").unwrap(), Some("Ferris".to_string()));
+    }
+}
@@ -0,0 +1,275 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::atomic::AtomicStorage;
+use crate::counter::CounterStorage;
+use crate::expiry::ExpiryStorage;
+use crate::storage::Err;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitStrategy {
+    // At most `limit` per window, windows start at multiples of `window` since the epoch.
+    // Bursts of up to twice the limit are possible around a window's end.
+    FixedWindow { limit: u64, window: Duration },
+    // Like the fixed window, but the previous window's count is weighted by how much of it
+    // still overlaps the last `window`, which smooths the edges.
+    SlidingWindow { limit: u64, window: Duration },
+    // Bursts of up to `capacity`, refilled by one every `refill`.
+    TokenBucket { capacity: u64, refill: Duration },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub allowed: bool,
+    // What could still be spent right now.
+    pub remaining: u64,
+    // When a denied call may succeed, `None` when allowed.
+    pub retry_after: Option<Duration>,
+}
+
+// Limits calls per key with state kept in any backend with counters, expiry and atomic
+// updates, so processes sharing a redis share limits and tests can run against a DashMap.
+// Windows are counters that `incr_within` only raises up to the limit, so a denied call never
+// touches them, and buckets are one compare-and-swapped timestamp. Clocks of processes
+// sharing a limit should roughly agree.
+pub struct RateLimiter<S> {
+    storage: S,
+    strategy: RateLimitStrategy,
+    prefix: String,
+}
+
+impl<S> RateLimiter<S> {
+    pub fn new(storage: S, strategy: RateLimitStrategy) -> Self {
+        RateLimiter {
+            storage,
+            strategy,
+            prefix: "ratelimit:".to_string(),
+        }
+    }
+
+    // Prepended to every key the limiter writes, `ratelimit:` by default.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn strategy(&self) -> RateLimitStrategy {
+        self.strategy
+    }
+}
+
+impl<S> RateLimiter<S>
+where
+    S: AtomicStorage<String, i64> + CounterStorage<String> + ExpiryStorage<String>,
+{
+    pub fn check(&self, key: &str) -> Result<RateLimit, Err> {
+        self.check_n(key, 1)
+    }
+
+    // Spends `cost` at once, all of it or nothing.
+    pub fn check_n(&self, key: &str, cost: u64) -> Result<RateLimit, Err> {
+        let now = now_millis();
+        match self.strategy {
+            RateLimitStrategy::FixedWindow { limit, window } => {
+                let window = millis(window);
+                let left = window - now % window;
+                let (count, allowed) = self.count(key, cost, limit as f64, window, now, 1)?;
+                self.decide(allowed, cost, count as f64, limit, |_| left)
+            }
+            RateLimitStrategy::SlidingWindow { limit, window } => {
+                let window = millis(window);
+                let elapsed = now % window;
+                let previous = self
+                    .storage
+                    .get(self.window_key(key, now / window - 1))?
+                    .unwrap_or(0)
+                    .max(0) as f64;
+                let weight = 1.0 - elapsed as f64 / window as f64;
+                let max = limit as f64 - previous * weight;
+                let (count, allowed) = self.count(key, cost, max, window, now, 2)?;
+                let estimate = previous * weight + count as f64;
+                self.decide(allowed, cost, estimate, limit, |excess| {
+                    // The previous window's share fades at `previous / window` per millisecond.
+                    let fade = if previous > 0.0 {
+                        (excess * window as f64 / previous).ceil() as i64
+                    } else {
+                        i64::MAX
+                    };
+                    fade.min(window - elapsed)
+                })
+            }
+            RateLimitStrategy::TokenBucket { capacity, refill } => {
+                self.take(key, cost, capacity, millis(refill), now)
+            }
+        }
+    }
+
+    fn window_key(&self, key: &str, window: i64) -> String {
+        format!("{}{}:{}", self.prefix, key, window)
+    }
+
+    // Adds `cost` to the current window's counter unless that takes it above `max`. A new
+    // counter lives for `windows` windows. Returns the count and whether `cost` was added.
+    fn count(
+        &self,
+        key: &str,
+        cost: u64,
+        max: f64,
+        window: i64,
+        now: i64,
+        windows: u32,
+    ) -> Result<(i64, bool), Err> {
+        let ttl = Duration::from_millis((window * windows as i64) as u64);
+        self.storage.incr_within(
+            self.window_key(key, now / window),
+            cost as i64,
+            max.floor() as i64,
+            ttl,
+        )
+    }
+
+    // `used` is what the window holds after the call, without `cost` when it was denied.
+    fn decide<F: FnOnce(f64) -> i64>(
+        &self,
+        allowed: bool,
+        cost: u64,
+        used: f64,
+        limit: u64,
+        retry: F,
+    ) -> Result<RateLimit, Err> {
+        let remaining = (limit as f64 - used).max(0.0).floor() as u64;
+        if allowed {
+            return Ok(RateLimit {
+                allowed,
+                remaining,
+                retry_after: None,
+            });
+        }
+        let retry_after = retry(used + cost as f64 - limit as f64).max(1);
+        Ok(RateLimit {
+            allowed,
+            remaining,
+            retry_after: Some(Duration::from_millis(retry_after as u64)),
+        })
+    }
+
+    // GCRA: the bucket is the time it will be full again, each token pushes it `refill`
+    // further. A call fits if that stays within `capacity` tokens from now.
+    fn take(
+        &self,
+        key: &str,
+        cost: u64,
+        capacity: u64,
+        refill: i64,
+        now: i64,
+    ) -> Result<RateLimit, Err> {
+        let key = format!("{}{}", self.prefix, key);
+        let tolerance = refill * capacity as i64;
+        let mut full_at = now;
+        let mut allowed = false;
+        self.storage.fetch_update(key.clone(), |current| {
+            let start = current.copied().unwrap_or(now).max(now);
+            full_at = start + refill * cost as i64;
+            allowed = full_at - now <= tolerance;
+            if allowed {
+                Some(full_at)
+            } else {
+                full_at = start;
+                current.copied()
+            }
+        })?;
+        let pending = full_at - now;
+        if allowed {
+            // Compare-and-swap drops the TTL, an idle bucket is full and needn't be kept.
+            self.storage
+                .expire(key, Duration::from_millis(pending.max(1) as u64))?;
+        }
+        let needed = pending + refill * cost as i64 - tolerance;
+        Ok(RateLimit {
+            allowed,
+            remaining: ((tolerance - pending) / refill.max(1)).max(0) as u64,
+            retry_after: (!allowed).then(|| Duration::from_millis(needed.max(1) as u64)),
+        })
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64)
+}
+
+fn millis(duration: Duration) -> i64 {
+    (duration.as_millis() as i64).max(1)
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::{DashMapStorage, DashMapStorageBuilder};
+    use crate::storage::Storage;
+
+    fn limiter(strategy: RateLimitStrategy) -> RateLimiter<DashMapStorage<String, i64>> {
+        RateLimiter::new(DashMapStorageBuilder::new().build(), strategy)
+    }
+
+    #[test]
+    fn test_fixed_window() {
+        let limiter = limiter(RateLimitStrategy::FixedWindow {
+            limit: 3,
+            window: Duration::from_secs(60),
+        });
+        for remaining in [2, 1, 0] {
+            let limit = limiter.check("user:1").unwrap();
+            assert!(limit.allowed);
+            assert_eq!(limit.remaining, remaining);
+        }
+        let denied = limiter.check("user:1").unwrap();
+        assert!(!denied.allowed);
+        assert!(denied.retry_after.unwrap() <= Duration::from_secs(60));
+        assert!(limiter.check("user:2").unwrap().allowed);
+        assert!(!limiter.check_n("user:2", 3).unwrap().allowed);
+        assert!(limiter.check_n("user:2", 2).unwrap().allowed);
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let limiter = limiter(RateLimitStrategy::SlidingWindow {
+            limit: 10,
+            window: Duration::from_secs(60),
+        });
+        let now = now_millis();
+        // A full previous window still counts for most of the current one.
+        let previous = limiter.window_key("user", now / 60_000 - 1);
+        limiter.inner().set(previous, 10).unwrap();
+
+        let limit = limiter.check("user").unwrap();
+        let weight = 1.0 - (now % 60_000) as f64 / 60_000.0;
+        assert_eq!(limit.allowed, 10.0 * weight + 1.0 <= 10.0);
+        limiter
+            .inner()
+            .set(limiter.window_key("user", now / 60_000 - 1), 0)
+            .unwrap();
+        assert!(limiter.check("user").unwrap().allowed);
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = limiter(RateLimitStrategy::TokenBucket {
+            capacity: 2,
+            refill: Duration::from_millis(50),
+        });
+        assert_eq!(limiter.check("user").unwrap().remaining, 1);
+        assert!(limiter.check("user").unwrap().allowed);
+        let denied = limiter.check("user").unwrap();
+        assert!(!denied.allowed);
+        assert!(denied.retry_after.unwrap() <= Duration::from_millis(50));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(limiter.check("user").unwrap().allowed);
+        assert!(!limiter.check_n("other", 3).unwrap().allowed);
+    }
+}
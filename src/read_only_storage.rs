@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use crate::health::HealthStatus;
+use crate::options::OpOptions;
+use crate::scan::{KeyIter, ScanStorage};
+use crate::storage::{Err, Storage};
+
+// A read-only handle chosen at runtime, like `DynStorage` for writers.
+pub type DynReadStorage<K = String, V = Vec<u8>> = Arc<dyn ReadStorage<K, V> + Send + Sync>;
+
+// The reading half of `Storage`, for code that must not write. Object safe, so read-only
+// handles can be passed around as `DynReadStorage`.
+pub trait ReadStorage<K, V> {
+    fn get(&self, key: K) -> Result<Option<V>, Err>;
+    fn contains(&self, key: K) -> Result<bool, Err>;
+
+    fn is_closed(&self) -> bool;
+    fn health_check(&self) -> Result<HealthStatus, Err>;
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        keys.into_iter().map(|key| self.get(key)).collect()
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        opts.remaining()?;
+        self.get(key)
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        opts.remaining()?;
+        self.contains(key)
+    }
+}
+
+// Lets code read a storage without being able to change it. It doesn't implement `Storage`,
+// so writes don't compile, and it can't close the storage or hand it back: the owner keeps
+// its own handle, e.g. behind an `Arc`. Scans pass through for storages with `ScanStorage`.
+pub struct ReadOnlyStorage<S> {
+    inner: S,
+}
+
+impl<S> ReadOnlyStorage<S> {
+    pub fn new(inner: S) -> Self {
+        ReadOnlyStorage { inner }
+    }
+}
+
+impl<S: Clone> Clone for ReadOnlyStorage<S> {
+    fn clone(&self) -> Self {
+        ReadOnlyStorage::new(self.inner.clone())
+    }
+}
+
+impl<K, V, S: Storage<K, V>> ReadStorage<K, V> for ReadOnlyStorage<S> {
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.inner.get(key)
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.inner.contains(key)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.inner.health_check()
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        self.inner.get_many(keys)
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        self.inner.get_with_opts(key, opts)
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        self.inner.contains_with_opts(key, opts)
+    }
+}
+
+impl<K, S: ScanStorage<K>> ScanStorage<K> for ReadOnlyStorage<S> {
+    fn scan_prefix(&self, prefix: &str) -> Result<KeyIter<'_, K>, Err> {
+        self.inner.scan_prefix(prefix)
+    }
+}
+
+macro_rules! forward_read {
+    ($($ty:ty),*) => {
+        $(
+            impl<K, V, S: ReadStorage<K, V> + ?Sized> ReadStorage<K, V> for $ty {
+                fn get(&self, key: K) -> Result<Option<V>, Err> {
+                    (**self).get(key)
+                }
+
+                fn contains(&self, key: K) -> Result<bool, Err> {
+                    (**self).contains(key)
+                }
+
+                fn is_closed(&self) -> bool {
+                    (**self).is_closed()
+                }
+
+                fn health_check(&self) -> Result<HealthStatus, Err> {
+                    (**self).health_check()
+                }
+
+                fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+                    (**self).get_many(keys)
+                }
+
+                fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+                    (**self).get_with_opts(key, opts)
+                }
+
+                fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+                    (**self).contains_with_opts(key, opts)
+                }
+            }
+        )*
+    };
+}
+
+forward_read!(&S, Box<S>, Arc<S>);
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::{DashMapStorage, DashMapStorageBuilder};
+
+    #[test]
+    fn test_read_only() {
+        let storage: Arc<DashMapStorage<String, u32>> =
+            Arc::new(DashMapStorageBuilder::new().build());
+        storage.set("a:1".to_string(), 1).unwrap();
+        storage.set("b:1".to_string(), 2).unwrap();
+
+        let view: DynReadStorage<String, u32> = Arc::new(ReadOnlyStorage::new(storage.clone()));
+        assert_eq!(view.get("a:1".to_string()).unwrap(), Some(1));
+        assert!(!view.contains("a:2".to_string()).unwrap());
+        assert_eq!(
+            view.get_many(vec!["b:1".to_string(), "b:2".to_string()])
+                .unwrap(),
+            [Some(2), None]
+        );
+
+        // The owner's writes show through the view.
+        storage.del("a:1".to_string()).unwrap();
+        assert_eq!(view.get("a:1".to_string()).unwrap(), None);
+
+        let scan = ReadOnlyStorage::new(storage.clone());
+        let keys: Vec<String> = scan
+            .scan_prefix("b:")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(keys, ["b:1"]);
+    }
+}
@@ -0,0 +1,324 @@
+use std::io::{self, Read};
+use std::time::Duration;
+
+use redis::Commands;
+
+use crate::blob::{read_chunk, BlobReader, BlobStorage};
+use crate::byte_codec::KeyCodec;
+use crate::jitter_storage::random_unit;
+use crate::options::OpOptions;
+use crate::redis_pool::PooledConnection;
+use crate::redis_storage::RedisStorage;
+use crate::storage::Err;
+
+// Bytes per chunk written by `set_from_reader`, and the most a reader holds at once.
+pub const BLOB_CHUNK: usize = 1 << 20;
+
+const LEN: &str = "len";
+const ID: &str = "id";
+// How long an upload that never finished lingers before redis drops it.
+const UPLOAD_TTL: Duration = Duration::from_secs(3600);
+
+// A fresh upload's id and the temporary key it is written to.
+fn upload(key: &[u8]) -> (String, Vec<u8>) {
+    let id = format!("{:x}", (random_unit() * (1u64 << 53) as f64) as u64);
+    let tmp = [key, b"\0upload:", id.as_bytes()].concat();
+    (id, tmp)
+}
+
+fn changed() -> io::Error {
+    io::Error::other("blob was replaced or removed while reading")
+}
+
+// A blob is a hash at its key: `len` and `id` fields, and its chunks in fields `0`, `1`, ...
+// Uploads go to a temporary key with a TTL, renamed over the key once complete, so readers
+// never see half a blob, and a reader whose blob is replaced or removed meanwhile fails
+// instead of mixing two blobs. A `get` of a blob key fails with WRONGTYPE.
+impl<K, V, C: KeyCodec<K>> BlobStorage<K> for RedisStorage<K, V, C> {
+    // Holds a pooled connection until the reader is dropped.
+    fn get_reader(&self, key: K) -> Result<Option<BlobReader<'_>>, Err> {
+        self.closed.check()?;
+        let key = self.codec().encode_key(&key)?;
+        let mut conn = self.connection(&OpOptions::default())?;
+        let (len, id): (Option<u64>, Option<String>) = redis::cmd("HMGET")
+            .arg(&key)
+            .arg(LEN)
+            .arg(ID)
+            .query(&mut conn)?;
+        let (Some(len), Some(id)) = (len, id) else {
+            return Ok(None);
+        };
+        let reader = RedisBlobReader {
+            conn,
+            key,
+            id,
+            left: len,
+            next: 0,
+            chunk: Vec::new(),
+            pos: 0,
+        };
+        Ok(Some(BlobReader::new(len, reader)))
+    }
+
+    fn set_from_reader(&self, key: K, reader: &mut dyn Read, len: u64) -> Result<(), Err> {
+        self.closed.check()?;
+        let key = self.codec().encode_key(&key)?;
+        let (id, tmp) = upload(&key);
+        let mut conn = self.connection(&OpOptions::default())?;
+        redis::pipe()
+            .hset_multiple(&tmp, &[(LEN, len.to_string()), (ID, id)])
+            .ignore()
+            .pexpire(&tmp, UPLOAD_TTL.as_millis() as i64)
+            .ignore()
+            .query::<()>(&mut conn)?;
+
+        if let Err(e) = send_chunks(&mut conn, &tmp, reader, len) {
+            let _ = conn.del::<_, ()>(&tmp);
+            return Err(e);
+        }
+        redis::pipe()
+            .atomic()
+            .rename(&tmp, &key)
+            .ignore()
+            .persist(&key)
+            .ignore()
+            .query::<()>(&mut conn)?;
+        Ok(())
+    }
+}
+
+fn send_chunks(
+    conn: &mut PooledConnection,
+    tmp: &[u8],
+    reader: &mut dyn Read,
+    len: u64,
+) -> Result<(), Err> {
+    let mut buf = Vec::with_capacity(BLOB_CHUNK.min(len as usize));
+    let (mut left, mut index) = (len, 0);
+    while left > 0 {
+        left -= read_chunk(reader, &mut buf, left)? as u64;
+        conn.hset::<_, _, _, ()>(tmp, index, &buf)?;
+        index += 1;
+    }
+    Ok(())
+}
+
+struct RedisBlobReader {
+    conn: PooledConnection,
+    key: Vec<u8>,
+    id: String,
+    left: u64,
+    next: usize,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for RedisBlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            if self.left == 0 {
+                return Ok(0);
+            }
+            let (id, chunk): (Option<String>, Option<Vec<u8>>) = redis::cmd("HMGET")
+                .arg(&self.key)
+                .arg(ID)
+                .arg(self.next)
+                .query(&mut self.conn)
+                .map_err(io::Error::other)?;
+            match chunk {
+                Some(chunk) if id.as_deref() == Some(&self.id) && !chunk.is_empty() => {
+                    self.left = self.left.saturating_sub(chunk.len() as u64);
+                    self.chunk = chunk;
+                }
+                _ => return Err(changed()),
+            }
+            self.next += 1;
+            self.pos = 0;
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// Blobs for `AsyncRedisStorage`, chunk by chunk as there is no async `Read` here. Both sides
+// use the layout of `BlobStorage` for `RedisStorage`, so either can read the other's blobs.
+#[cfg(feature = "async")]
+mod nonblocking {
+    use redis::aio::ConnectionManager;
+    use redis::{AsyncCommands, ToRedisArgs};
+
+    use super::{upload, ID, LEN, UPLOAD_TTL};
+    use crate::async_redis_storage::AsyncRedisStorage;
+    use crate::storage::AsyncErr;
+
+    impl<K: ToRedisArgs, V> AsyncRedisStorage<K, V> {
+        // `None` for a missing key.
+        pub async fn get_chunks(&self, key: K) -> Result<Option<AsyncBlobReader>, AsyncErr> {
+            self.closed.check_async()?;
+            let key = key.to_redis_args().concat();
+            let mut conn = self.manager().clone();
+            let (len, id): (Option<u64>, Option<String>) = redis::cmd("HMGET")
+                .arg(&key)
+                .arg(LEN)
+                .arg(ID)
+                .query_async(&mut conn)
+                .await?;
+            let (Some(len), Some(id)) = (len, id) else {
+                return Ok(None);
+            };
+            Ok(Some(AsyncBlobReader {
+                conn,
+                key,
+                id,
+                len,
+                left: len,
+                next: 0,
+            }))
+        }
+
+        // Starts an upload of exactly `len` bytes, stored once `finish` is called.
+        pub async fn blob_writer(&self, key: K, len: u64) -> Result<AsyncBlobWriter, AsyncErr> {
+            self.closed.check_async()?;
+            let key = key.to_redis_args().concat();
+            let (id, tmp) = upload(&key);
+            let mut conn = self.manager().clone();
+            redis::pipe()
+                .hset_multiple(&tmp, &[(LEN, len.to_string()), (ID, id)])
+                .ignore()
+                .pexpire(&tmp, UPLOAD_TTL.as_millis() as i64)
+                .ignore()
+                .query_async::<_, ()>(&mut conn)
+                .await?;
+            Ok(AsyncBlobWriter {
+                conn,
+                key,
+                tmp,
+                left: len,
+                next: 0,
+            })
+        }
+    }
+
+    pub struct AsyncBlobReader {
+        conn: ConnectionManager,
+        key: Vec<u8>,
+        id: String,
+        len: u64,
+        left: u64,
+        next: usize,
+    }
+
+    impl AsyncBlobReader {
+        pub fn len(&self) -> u64 {
+            self.len
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.len == 0
+        }
+
+        // The next chunk, `None` once the blob has been read.
+        pub async fn next_chunk(&mut self) -> Result<Option<Vec<u8>>, AsyncErr> {
+            if self.left == 0 {
+                return Ok(None);
+            }
+            let (id, chunk): (Option<String>, Option<Vec<u8>>) = redis::cmd("HMGET")
+                .arg(&self.key)
+                .arg(ID)
+                .arg(self.next)
+                .query_async(&mut self.conn)
+                .await?;
+            match chunk {
+                Some(chunk) if id.as_deref() == Some(&self.id) && !chunk.is_empty() => {
+                    self.left = self.left.saturating_sub(chunk.len() as u64);
+                    self.next += 1;
+                    Ok(Some(chunk))
+                }
+                _ => Err(super::changed().into()),
+            }
+        }
+    }
+
+    // Dropped without `finish`, the upload is left to expire.
+    pub struct AsyncBlobWriter {
+        conn: ConnectionManager,
+        key: Vec<u8>,
+        tmp: Vec<u8>,
+        left: u64,
+        next: usize,
+    }
+
+    impl AsyncBlobWriter {
+        // Chunks may have any size, each is one round trip.
+        pub async fn write_chunk(&mut self, chunk: &[u8]) -> Result<(), AsyncErr> {
+            if chunk.len() as u64 > self.left {
+                return Err("blob chunk goes past the blob's length".into());
+            }
+            if chunk.is_empty() {
+                return Ok(());
+            }
+            self.conn
+                .hset::<_, _, _, ()>(&self.tmp, self.next, chunk)
+                .await?;
+            self.left -= chunk.len() as u64;
+            self.next += 1;
+            Ok(())
+        }
+
+        pub async fn finish(mut self) -> Result<(), AsyncErr> {
+            if self.left > 0 {
+                return Err(format!("blob is missing {} bytes", self.left).into());
+            }
+            redis::pipe()
+                .atomic()
+                .rename(&self.tmp, &self.key)
+                .ignore()
+                .persist(&self.key)
+                .ignore()
+                .query_async::<_, ()>(&mut self.conn)
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+pub use nonblocking::{AsyncBlobReader, AsyncBlobWriter};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis_storage::RedisStorageBuilder;
+    use crate::storage::Storage;
+
+    #[test]
+    fn test_blob() {
+        let storage: RedisStorage<&str, String> = RedisStorageBuilder::new()
+            .addr("redis://127.0.0.1:6379")
+            .build();
+        let blob: Vec<u8> = (0..BLOB_CHUNK * 2 + 10).map(|i| i as u8).collect();
+        storage
+            .set_from_reader("blob_test", &mut &blob[..], blob.len() as u64)
+            .unwrap();
+
+        let mut reader = storage.get_reader("blob_test").unwrap().unwrap();
+        assert_eq!(reader.len(), blob.len() as u64);
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert!(read == blob);
+
+        // A short reader leaves the stored blob alone.
+        assert!(storage
+            .set_from_reader("blob_test", &mut &blob[..5], 6)
+            .is_err());
+        assert_eq!(
+            storage.get_reader("blob_test").unwrap().unwrap().len(),
+            blob.len() as u64
+        );
+
+        storage.del("blob_test").unwrap();
+        assert!(storage.get_reader("blob_test").unwrap().is_none());
+    }
+}
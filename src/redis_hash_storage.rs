@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use redis::{Commands, FromRedisValue, ToRedisArgs};
+
+use crate::options::OpOptions;
+use crate::redis_storage::RedisStorage;
+use crate::storage::{Err, Storage};
+
+// Keeps every key as a redis hash from fields `F` to values `V`, e.g. one hash per user with
+// a field per attribute. Fields can be read and written one at a time without the rest of
+// the record, other clients see plain HSET/HGET-able hashes.
+//
+// As a `Storage` the value is the whole record: `set` replaces every field at once and `get`
+// is `None` for a missing key. A hash can't be empty, setting an empty record deletes it.
+pub struct RedisHashStorage<K, F, V> {
+    storage: RedisStorage<K, V>,
+    _marker: PhantomData<F>,
+}
+
+impl<K, F, V> Clone for RedisHashStorage<K, F, V> {
+    fn clone(&self) -> Self {
+        RedisHashStorage {
+            storage: self.storage.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<K, V> RedisStorage<K, V> {
+    // Shares the pool and is closed along with this storage.
+    pub fn hashes<F>(&self) -> RedisHashStorage<K, F, V> {
+        RedisHashStorage::new(self.clone())
+    }
+}
+
+impl<K, F, V> RedisHashStorage<K, F, V> {
+    pub fn new(storage: RedisStorage<K, V>) -> Self {
+        RedisHashStorage {
+            storage,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn inner(&self) -> &RedisStorage<K, V> {
+        &self.storage
+    }
+}
+
+impl<K, F, V> RedisHashStorage<K, F, V>
+where
+    K: ToRedisArgs,
+    F: ToRedisArgs + FromRedisValue,
+    V: ToRedisArgs + FromRedisValue,
+{
+    pub fn get_field(&self, key: K, field: F) -> Result<Option<V>, Err> {
+        self.storage.closed.check()?;
+        let mut conn = self.storage.connection(&OpOptions::default())?;
+        Ok(conn.hget(key, field)?)
+    }
+
+    // One HMGET, values come back in the order of `fields`.
+    pub fn get_fields(&self, key: K, fields: Vec<F>) -> Result<Vec<Option<V>>, Err> {
+        self.storage.closed.check()?;
+        if fields.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut conn = self.storage.connection(&OpOptions::default())?;
+        Ok(redis::cmd("HMGET").arg(key).arg(fields).query(&mut conn)?)
+    }
+
+    // `true` if the field is new, `false` if an old value was overwritten. Creates the hash if
+    // needed and keeps any TTL it has.
+    pub fn set_field(&self, key: K, field: F, value: V) -> Result<bool, Err> {
+        self.storage.closed.check()?;
+        let mut conn = self.storage.connection(&OpOptions::default())?;
+        let added: usize = conn.hset(key, field, value)?;
+        Ok(added > 0)
+    }
+
+    // Writes the fields with one HSET, leaving the record's other fields alone.
+    pub fn set_fields(&self, key: K, fields: Vec<(F, V)>) -> Result<(), Err> {
+        self.storage.closed.check()?;
+        if fields.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.storage.connection(&OpOptions::default())?;
+        Ok(conn.hset_multiple(key, &fields)?)
+    }
+
+    // `false` if the field wasn't there. Removing the last field deletes the key.
+    pub fn del_field(&self, key: K, field: F) -> Result<bool, Err> {
+        self.storage.closed.check()?;
+        let mut conn = self.storage.connection(&OpOptions::default())?;
+        let removed: usize = conn.hdel(key, field)?;
+        Ok(removed > 0)
+    }
+
+    pub fn contains_field(&self, key: K, field: F) -> Result<bool, Err> {
+        self.storage.closed.check()?;
+        let mut conn = self.storage.connection(&OpOptions::default())?;
+        Ok(conn.hexists(key, field)?)
+    }
+
+    pub fn field_names(&self, key: K) -> Result<Vec<F>, Err> {
+        self.storage.closed.check()?;
+        let mut conn = self.storage.connection(&OpOptions::default())?;
+        Ok(conn.hkeys(key)?)
+    }
+
+    pub fn field_count(&self, key: K) -> Result<usize, Err> {
+        self.storage.closed.check()?;
+        let mut conn = self.storage.connection(&OpOptions::default())?;
+        Ok(conn.hlen(key)?)
+    }
+}
+
+impl<K: ToRedisArgs, F, V> RedisHashStorage<K, F, V> {
+    // DEL and HSET, plus PEXPIRE for a TTL, in one MULTI so readers never see half a record.
+    fn replace<A, B>(&self, key: K, fields: &[(A, B)], ttl: Option<Duration>) -> Result<(), Err>
+    where
+        A: ToRedisArgs,
+        B: ToRedisArgs,
+    {
+        self.storage.closed.check()?;
+        let mut conn = self.storage.connection(&OpOptions::default())?;
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(&key).ignore();
+        if !fields.is_empty() {
+            pipe.hset_multiple(&key, fields).ignore();
+            if let Some(ttl) = ttl {
+                pipe.pexpire(&key, ttl.as_millis().max(1) as i64).ignore();
+            }
+        }
+        Ok(pipe.query(&mut conn)?)
+    }
+}
+
+// Records are read with HGETALL, so a `get` sees every field at one point in time.
+impl<K, F, V> Storage<K, HashMap<F, V>> for RedisHashStorage<K, F, V>
+where
+    K: ToRedisArgs,
+    F: ToRedisArgs + FromRedisValue + Eq + Hash,
+    V: ToRedisArgs + FromRedisValue,
+{
+    fn set(&self, key: K, value: HashMap<F, V>) -> Result<(), Err> {
+        self.replace(key, &value.into_iter().collect::<Vec<_>>(), None)
+    }
+
+    fn set_ex(&self, key: K, value: HashMap<F, V>, expire: Duration) -> Result<(), Err> {
+        self.replace(key, &value.into_iter().collect::<Vec<_>>(), Some(expire))
+    }
+
+    fn get(&self, key: K) -> Result<Option<HashMap<F, V>>, Err> {
+        self.storage.closed.check()?;
+        let mut conn = self.storage.connection(&OpOptions::default())?;
+        let record: HashMap<F, V> = conn.hgetall(key)?;
+        Ok((!record.is_empty()).then_some(record))
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.storage.closed.check()?;
+        let mut conn = self.storage.connection(&OpOptions::default())?;
+        conn.del::<&K, ()>(&key)?;
+        Ok(Some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.storage.closed.check()?;
+        let mut conn = self.storage.connection(&OpOptions::default())?;
+        Ok(conn.exists(key)?)
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.storage.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.storage.closed.is_closed()
+    }
+}
+
+// Structs are stored with a field per struct field. Strings are stored as they are, other
+// values as JSON, e.g. `42`, `true` or `["a","b"]`; `None` fields are left out. Reading
+// decodes each field into the type the struct expects, so a string field holding `42` stays
+// a string.
+#[cfg(feature = "json")]
+impl<K: ToRedisArgs, F, V> RedisHashStorage<K, F, V> {
+    // Replaces the whole record. Fails for values that don't serialize to a JSON object.
+    pub fn set_record<T: serde::Serialize>(&self, key: K, record: &T) -> Result<(), Err> {
+        self.replace(key, &record::encode(record)?, None)
+    }
+
+    pub fn get_record<T: serde::de::DeserializeOwned>(&self, key: K) -> Result<Option<T>, Err> {
+        self.storage.closed.check()?;
+        let mut conn = self.storage.connection(&OpOptions::default())?;
+        let fields: HashMap<String, String> = conn.hgetall(key)?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(record::decode(fields)?))
+    }
+}
+
+#[cfg(feature = "json")]
+mod record {
+    use std::collections::HashMap;
+
+    use serde::de::value::MapDeserializer;
+    use serde::de::{DeserializeOwned, Deserializer, IntoDeserializer, Visitor};
+    use serde::forward_to_deserialize_any;
+    use serde::Serialize;
+    use serde_json::Value;
+
+    use crate::storage::Err;
+
+    pub(super) fn encode<T: Serialize>(record: &T) -> Result<Vec<(String, String)>, Err> {
+        let Value::Object(fields) = serde_json::to_value(record)? else {
+            return Err("records must serialize to a map or struct".into());
+        };
+        Ok(fields
+            .into_iter()
+            .filter_map(|(name, value)| match value {
+                Value::Null => None,
+                Value::String(value) => Some((name, value)),
+                value => Some((name, value.to_string())),
+            })
+            .collect())
+    }
+
+    pub(super) fn decode<T: DeserializeOwned>(fields: HashMap<String, String>) -> Result<T, Err> {
+        let fields = fields.into_iter().map(|(name, raw)| (name, Field(raw)));
+        Ok(T::deserialize(MapDeserializer::new(fields))?)
+    }
+
+    // A field read back: as a string where the struct wants one, as JSON otherwise.
+    struct Field(String);
+
+    impl Field {
+        fn value(self) -> Value {
+            serde_json::from_str(&self.0).unwrap_or(Value::String(self.0))
+        }
+    }
+
+    impl IntoDeserializer<'_, serde_json::Error> for Field {
+        type Deserializer = Field;
+
+        fn into_deserializer(self) -> Field {
+            self
+        }
+    }
+
+    impl<'de> Deserializer<'de> for Field {
+        type Error = serde_json::Error;
+
+        fn deserialize_any<W: Visitor<'de>>(self, visitor: W) -> Result<W::Value, Self::Error> {
+            self.value().deserialize_any(visitor)
+        }
+
+        fn deserialize_str<W: Visitor<'de>>(self, visitor: W) -> Result<W::Value, Self::Error> {
+            visitor.visit_string(self.0)
+        }
+
+        fn deserialize_string<W: Visitor<'de>>(self, visitor: W) -> Result<W::Value, Self::Error> {
+            visitor.visit_string(self.0)
+        }
+
+        fn deserialize_char<W: Visitor<'de>>(self, visitor: W) -> Result<W::Value, Self::Error> {
+            visitor.visit_string(self.0)
+        }
+
+        // Missing fields are `None`, present ones are never null.
+        fn deserialize_option<W: Visitor<'de>>(self, visitor: W) -> Result<W::Value, Self::Error> {
+            visitor.visit_some(self)
+        }
+
+        fn deserialize_newtype_struct<W: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            visitor: W,
+        ) -> Result<W::Value, Self::Error> {
+            visitor.visit_newtype_struct(self)
+        }
+
+        fn deserialize_enum<W: Visitor<'de>>(
+            self,
+            name: &'static str,
+            variants: &'static [&'static str],
+            visitor: W,
+        ) -> Result<W::Value, Self::Error> {
+            self.value().deserialize_enum(name, variants, visitor)
+        }
+
+        forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 bytes byte_buf unit
+            unit_struct seq tuple tuple_struct map struct identifier ignored_any
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis_storage::RedisStorageBuilder;
+
+    fn build_localhost() -> RedisHashStorage<String, String, String> {
+        RedisStorageBuilder::<String, String>::new()
+            .addr("redis://127.0.0.1:6379")
+            .build()
+            .hashes()
+    }
+
+    #[test]
+    fn test_fields() {
+        let storage = build_localhost();
+        let key = "hash:test_fields".to_string();
+        storage.del(key.clone()).unwrap();
+
+        assert!(storage
+            .set_field(key.clone(), "name".into(), "alice".into())
+            .unwrap());
+        assert!(!storage
+            .set_field(key.clone(), "name".into(), "bob".into())
+            .unwrap());
+        storage
+            .set_fields(
+                key.clone(),
+                vec![("email".into(), "bob@example.com".into())],
+            )
+            .unwrap();
+        assert_eq!(
+            storage.get_field(key.clone(), "name".into()).unwrap(),
+            Some("bob".to_string())
+        );
+        assert_eq!(
+            storage
+                .get_fields(key.clone(), vec!["email".into(), "phone".into()])
+                .unwrap(),
+            vec![Some("bob@example.com".to_string()), None]
+        );
+        assert_eq!(storage.field_count(key.clone()).unwrap(), 2);
+        assert!(storage.del_field(key.clone(), "email".into()).unwrap());
+        assert!(!storage.contains_field(key.clone(), "email".into()).unwrap());
+        assert_eq!(storage.field_names(key.clone()).unwrap(), vec!["name"]);
+        storage.del(key).unwrap();
+    }
+
+    #[test]
+    fn test_record() {
+        let storage = build_localhost();
+        let key = "hash:test_record".to_string();
+        let record = HashMap::from([
+            ("name".to_string(), "alice".to_string()),
+            ("plan".to_string(), "pro".to_string()),
+        ]);
+        storage.set(key.clone(), record.clone()).unwrap();
+        assert_eq!(storage.get(key.clone()).unwrap(), Some(record));
+
+        // `set` replaces the record, fields it doesn't have are gone.
+        storage
+            .set(key.clone(), HashMap::from([("name".into(), "bob".into())]))
+            .unwrap();
+        assert_eq!(storage.field_names(key.clone()).unwrap(), vec!["name"]);
+        storage.set(key.clone(), HashMap::new()).unwrap();
+        assert!(!storage.contains(key.clone()).unwrap());
+        assert_eq!(storage.get(key).unwrap(), None);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_struct_record() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct User {
+            name: String,
+            zip: String,
+            age: u32,
+            admin: bool,
+            tags: Vec<String>,
+            nickname: Option<String>,
+        }
+
+        let storage = build_localhost();
+        let key = "hash:test_struct_record".to_string();
+        let user = User {
+            name: "alice".into(),
+            zip: "02134".into(),
+            age: 30,
+            admin: false,
+            tags: vec!["beta".into()],
+            nickname: None,
+        };
+        storage.set_record(key.clone(), &user).unwrap();
+        assert_eq!(
+            storage.get_field(key.clone(), "age".into()).unwrap(),
+            Some("30".to_string())
+        );
+        assert!(!storage
+            .contains_field(key.clone(), "nickname".into())
+            .unwrap());
+        assert_eq!(storage.get_record::<User>(key.clone()).unwrap(), Some(user));
+        storage.del(key).unwrap();
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_decode() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Account {
+            id: String,
+            balance: i64,
+        }
+
+        let fields = HashMap::from([
+            ("id".to_string(), "007".to_string()),
+            ("balance".to_string(), "-5".to_string()),
+        ]);
+        let account: Account = record::decode(fields).unwrap();
+        assert_eq!(
+            account,
+            Account {
+                id: "007".into(),
+                balance: -5
+            }
+        );
+        assert!(record::encode(&1).is_err());
+    }
+}
@@ -0,0 +1,312 @@
+use std::marker::PhantomData;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use redis::{FromRedisValue, Msg, Value};
+
+use crate::byte_codec::{KeyCodec, ValueCodec};
+use crate::redis_storage::{escape_glob, RedisStorage};
+use crate::storage::{Err, Storage};
+use crate::wait::{WaitStorage, WAIT_POLL};
+use crate::watch::{ChangeEvent, Changes, WatchableStorage};
+
+// How often a watch thread checks whether the storage was closed.
+const WATCH_POLL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyEvent {
+    // SET and friends, including SETEX and MSET.
+    Set,
+    Del,
+    Expired,
+    // Removed by the server's `maxmemory` policy.
+    Evicted,
+    // Anything else, by its event name, e.g. `expire` or `incrby`.
+    Other(String),
+}
+
+impl KeyEvent {
+    fn parse(event: &str) -> KeyEvent {
+        match event {
+            "set" => KeyEvent::Set,
+            "del" => KeyEvent::Del,
+            "expired" => KeyEvent::Expired,
+            "evicted" => KeyEvent::Evicted,
+            other => KeyEvent::Other(other.to_string()),
+        }
+    }
+}
+
+impl<K, V> RedisStorage<K, V> {
+    // Follows changes to keys starting with `prefix` through keyspace notifications, made by
+    // any client. The server only sends them once `notify-keyspace-events` enables them, e.g.
+    // `CONFIG SET notify-keyspace-events KA`; this doesn't change the server's config.
+    //
+    // Each subscription holds its own connection outside the pool. Notifications are fire
+    // and forget: events sent while the connection is down are lost, so after an error
+    // subscribe again and treat local copies as stale.
+    pub fn subscribe_changes(&self, prefix: &str) -> Result<KeyEvents<K>, Err> {
+        self.closed.check()?;
+        let channel = self.keyspace_channel();
+        let mut subscribe = redis::cmd("PSUBSCRIBE");
+        subscribe.arg(format!("{}{}*", channel, escape_glob(prefix)));
+        KeyEvents::open(self.dedicated_connection()?, channel, subscribe)
+    }
+}
+
+impl<K, V, C> RedisStorage<K, V, C> {
+    // Prefixes the keys in channels of this database's keyspace notifications.
+    fn keyspace_channel(&self) -> String {
+        format!(
+            "__keyspace@{}__:",
+            self.client().get_connection_info().redis.db
+        )
+    }
+}
+
+// Each watch runs a thread with its own subscription, set up like `subscribe_changes`.
+// `Evicted` keys are reported as `Del`, other commands (`expire`, `incrby`, ...) aren't
+// reported. The thread ends when the storage is closed, when the connection fails, and at
+// most `WATCH_POLL` after the `Changes` was dropped.
+impl<K, V, C: KeyCodec<K>> WatchableStorage<K> for RedisStorage<K, V, C> {
+    fn watch(&self, key: K) -> Result<Changes, Err> {
+        self.closed.check()?;
+        let channel = self.keyspace_channel();
+        let mut subscribe = redis::cmd("SUBSCRIBE");
+        subscribe.arg([channel.as_bytes(), &self.codec().encode_key(&key)?].concat());
+        let events = KeyEvents::<Vec<u8>>::open(self.dedicated_connection()?, channel, subscribe)?;
+        events.set_timeout(Some(WATCH_POLL))?;
+
+        let closed = self.closed.clone();
+        let (tx, rx) = mpsc::channel();
+        let changes = Changes::new(rx);
+        let alive = changes.alive();
+        thread::Builder::new()
+            .name("storage-watch".to_string())
+            .spawn(move || {
+                for event in events {
+                    let change = match event {
+                        Ok((_, KeyEvent::Set)) => ChangeEvent::Set,
+                        Ok((_, KeyEvent::Del | KeyEvent::Evicted)) => ChangeEvent::Del,
+                        Ok((_, KeyEvent::Expired)) => ChangeEvent::Expired,
+                        Ok((_, KeyEvent::Other(_))) => continue,
+                        // Timeouts, other errors end the iterator.
+                        Err(_) if !closed.is_closed() && alive.strong_count() > 0 => continue,
+                        Err(_) => break,
+                    };
+                    if tx.send(change).is_err() {
+                        break;
+                    }
+                }
+            })?;
+        Ok(changes)
+    }
+}
+
+// Subscribes to the key like `watch`, but on the calling thread and for the wait only. Any
+// notification re-reads the key, and so does every second without one, so the wait also ends
+// when the server doesn't send keyspace notifications, just later.
+impl<K: Clone, V, C> WaitStorage<K, V> for RedisStorage<K, V, C>
+where
+    C: KeyCodec<K> + ValueCodec<V>,
+{
+    fn wait_for(&self, key: K, timeout: Duration) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let deadline = Instant::now() + timeout;
+        let channel = self.keyspace_channel();
+        let mut subscribe = redis::cmd("SUBSCRIBE");
+        subscribe.arg([channel.as_bytes(), &self.codec().encode_key(&key)?].concat());
+        let mut events =
+            KeyEvents::<Vec<u8>>::open(self.dedicated_connection()?, channel, subscribe.clone())?;
+        loop {
+            if let Some(value) = self.get(key.clone())? {
+                return Ok(Some(value));
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(None);
+            }
+            events.set_timeout(Some(left.min(WAIT_POLL)))?;
+            // Timeouts and events alike re-read the key, a failed subscription is opened again.
+            if events.next().is_none() {
+                let channel = self.keyspace_channel();
+                events = KeyEvents::open(self.dedicated_connection()?, channel, subscribe.clone())?;
+            }
+        }
+    }
+}
+
+// Blocks in `next` until the server sends an event, see `set_timeout`. Ends after the first
+// error other than a timeout.
+pub struct KeyEvents<K> {
+    conn: redis::Connection,
+    channel: String,
+    failed: bool,
+    _marker: PhantomData<K>,
+}
+
+impl<K> KeyEvents<K> {
+    fn open(
+        mut conn: redis::Connection,
+        channel: String,
+        subscribe: redis::Cmd,
+    ) -> Result<Self, Err> {
+        subscribe.query::<()>(&mut conn)?;
+        Ok(KeyEvents {
+            conn,
+            channel,
+            failed: false,
+            _marker: PhantomData,
+        })
+    }
+
+    // How long `next` waits for an event before returning a timeout error, forever by default.
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<(), Err> {
+        Ok(self.conn.set_read_timeout(timeout)?)
+    }
+
+    // The changed key and event of a keyspace notification, `None` for other messages.
+    fn event(&self, msg: &Msg) -> Option<Result<(K, KeyEvent), Err>>
+    where
+        K: FromRedisValue,
+    {
+        let channel: Vec<u8> = msg.get_channel().ok()?;
+        let key = channel.strip_prefix(self.channel.as_bytes())?;
+        let event = std::str::from_utf8(msg.get_payload_bytes()).ok()?;
+        Some(
+            K::from_redis_value(&Value::Data(key.to_vec()))
+                .map(|key| (key, KeyEvent::parse(event)))
+                .map_err(Err::from),
+        )
+    }
+}
+
+impl<K: FromRedisValue> Iterator for KeyEvents<K> {
+    type Item = Result<(K, KeyEvent), Err>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.failed {
+            let value = match self.conn.recv_response() {
+                Ok(value) => value,
+                Err(e) => {
+                    // After a timeout the connection is still in step, keep reading.
+                    self.failed = !e.is_timeout();
+                    return Some(Err(e.into()));
+                }
+            };
+            // Subscription confirmations aren't messages.
+            if let Some(event) = Msg::from_value(&value).and_then(|msg| self.event(&msg)) {
+                return Some(event);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis_storage::RedisStorageBuilder;
+    use crate::storage::Storage;
+    use redis::Commands;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(KeyEvent::parse("set"), KeyEvent::Set);
+        assert_eq!(KeyEvent::parse("expired"), KeyEvent::Expired);
+        assert_eq!(
+            KeyEvent::parse("incrby"),
+            KeyEvent::Other("incrby".to_string())
+        );
+    }
+
+    #[test]
+    fn test_watch() {
+        let storage = RedisStorageBuilder::<String, String>::new()
+            .addr("redis://127.0.0.1:6379")
+            .build();
+        redis::cmd("CONFIG")
+            .arg("SET")
+            .arg("notify-keyspace-events")
+            .arg("KA")
+            .query::<()>(&mut storage.client().get_connection().unwrap())
+            .unwrap();
+
+        let changes = storage.watch("watch:config".into()).unwrap();
+        storage
+            .set("watch:config:other".into(), "x".into())
+            .unwrap();
+        storage.set("watch:config".into(), "v1".into()).unwrap();
+        storage.del("watch:config".into()).unwrap();
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(changes.recv_timeout(timeout), Ok(ChangeEvent::Set));
+        assert_eq!(changes.recv_timeout(timeout), Ok(ChangeEvent::Del));
+    }
+
+    #[test]
+    fn test_wait_for() {
+        let storage = RedisStorageBuilder::<String, String>::new()
+            .addr("redis://127.0.0.1:6379")
+            .build();
+        storage.del("wait:job".into()).unwrap();
+        assert_eq!(
+            storage
+                .wait_for("wait:job".into(), Duration::from_millis(50))
+                .unwrap(),
+            None
+        );
+
+        let writer = storage.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            writer.set("wait:job".into(), "done".into()).unwrap();
+        });
+        assert_eq!(
+            storage
+                .wait_for("wait:job".into(), Duration::from_secs(5))
+                .unwrap(),
+            Some("done".to_string())
+        );
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_subscribe_changes() {
+        let storage = RedisStorageBuilder::<String, String>::new()
+            .addr("redis://127.0.0.1:6379")
+            .build();
+        let mut conn = storage.client().get_connection().unwrap();
+        redis::cmd("CONFIG")
+            .arg("SET")
+            .arg("notify-keyspace-events")
+            .arg("KA")
+            .query::<()>(&mut conn)
+            .unwrap();
+
+        let mut events = storage.subscribe_changes("keyspace:").unwrap();
+        events.set_timeout(Some(Duration::from_secs(5))).unwrap();
+        storage.set("other".into(), "ignored".into()).unwrap();
+        storage.set("keyspace:a".into(), "1".into()).unwrap();
+        conn.pexpire::<_, ()>("keyspace:a", 10).unwrap();
+        storage.del("keyspace:b".into()).unwrap();
+        storage.set("keyspace:b".into(), "2".into()).unwrap();
+        storage.del("keyspace:b".into()).unwrap();
+
+        let mut seen = Vec::new();
+        while seen.len() < 5 {
+            seen.push(events.next().unwrap().unwrap());
+        }
+        assert_eq!(
+            seen,
+            [
+                ("keyspace:a".to_string(), KeyEvent::Set),
+                ("keyspace:a".to_string(), KeyEvent::Other("expire".into())),
+                ("keyspace:b".to_string(), KeyEvent::Set),
+                ("keyspace:b".to_string(), KeyEvent::Del),
+                ("keyspace:a".to_string(), KeyEvent::Expired),
+            ]
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use redis::{FromRedisValue, Pipeline, ToRedisArgs};
+
+use crate::options::OpOptions;
+use crate::redis_storage::RedisStorage;
+use crate::storage::Err;
+
+// Queues commands and sends them in one round trip. Writes don't answer, `execute` returns
+// the value of every `get` in the order they were queued.
+pub struct RedisPipeline<'a, K, V> {
+    storage: &'a RedisStorage<K, V>,
+    pipe: Pipeline,
+}
+
+impl<K, V> RedisPipeline<'_, K, V>
+where
+    K: ToRedisArgs,
+    V: ToRedisArgs + FromRedisValue,
+{
+    // Wraps the commands in MULTI/EXEC, so no other client's command runs in between.
+    pub fn atomic(mut self) -> Self {
+        self.pipe.atomic();
+        self
+    }
+
+    pub fn get(mut self, key: K) -> Self {
+        self.pipe.get(key);
+        self
+    }
+
+    pub fn set(mut self, key: K, value: V) -> Self {
+        self.pipe.set(key, value).ignore();
+        self
+    }
+
+    pub fn set_ex(mut self, key: K, value: V, expire: Duration) -> Self {
+        self.pipe
+            .pset_ex(key, value, expire.as_millis() as u64)
+            .ignore();
+        self
+    }
+
+    pub fn del(mut self, key: K) -> Self {
+        self.pipe.del(key).ignore();
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.pipe.cmd_iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn execute(self) -> Result<Vec<Option<V>>, Err> {
+        self.execute_with_opts(&OpOptions::default())
+    }
+
+    // The deadline in `opts` bounds the whole round trip.
+    pub fn execute_with_opts(self, opts: &OpOptions) -> Result<Vec<Option<V>>, Err> {
+        self.storage.closed.check()?;
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut conn = self.storage.connection(opts)?;
+        Ok(self.pipe.query(&mut conn)?)
+    }
+}
+
+impl<K, V> RedisStorage<K, V> {
+    pub fn pipeline(&self) -> RedisPipeline<'_, K, V> {
+        RedisPipeline {
+            storage: self,
+            pipe: redis::pipe(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::redis_storage::RedisStorageBuilder;
+    use std::time::Duration;
+
+    #[test]
+    fn test_pipeline() {
+        let storage = RedisStorageBuilder::<&str, String>::new()
+            .addr("redis://127.0.0.1:6379")
+            .build();
+
+        let values = storage
+            .pipeline()
+            .set("pipe_a", "1".to_string())
+            .set_ex("pipe_b", "2".to_string(), Duration::from_secs(60))
+            .del("pipe_c")
+            .get("pipe_a")
+            .get("pipe_c")
+            .atomic()
+            .execute()
+            .unwrap();
+        assert_eq!(values, [Some("1".to_string()), None]);
+        assert!(storage.pipeline().execute().unwrap().is_empty());
+    }
+}
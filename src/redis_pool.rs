@@ -0,0 +1,329 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use redis::{ConnectionLike, RedisResult, Value};
+
+use crate::error::{BuilderError, StorageError};
+use crate::redis_sentinel::SentinelConfig;
+use crate::storage::Err;
+
+// Connections `RedisStorage` keeps open between operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct PoolConfig {
+    // Upper bound on open connections, idle or checked out.
+    pub max_size: usize,
+    // Idle connections older than this are closed instead of reused.
+    #[cfg_attr(feature = "serde", serde(with = "crate::config::secs::option"))]
+    pub idle_timeout: Option<Duration>,
+    // How long an operation waits for a connection once `max_size` are checked out.
+    #[cfg_attr(feature = "serde", serde(with = "crate::config::secs::option"))]
+    pub checkout_timeout: Option<Duration>,
+    // How long opening a connection may take, sentinel lookups included.
+    #[cfg_attr(feature = "serde", serde(with = "crate::config::secs::option"))]
+    pub connect_timeout: Option<Duration>,
+    // How long a command waits for its reply, and for its request to be sent. An operation's
+    // deadline can only shorten them. A command that times out fails with a redis timeout
+    // error and its connection is closed.
+    #[cfg_attr(feature = "serde", serde(with = "crate::config::secs::option"))]
+    pub read_timeout: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::config::secs::option"))]
+    pub write_timeout: Option<Duration>,
+}
+
+impl PoolConfig {
+    pub(crate) fn validate(&self) -> Result<(), BuilderError> {
+        if self.max_size == 0 {
+            return Err(BuilderError::invalid("pool_size", "must be at least 1"));
+        }
+        for (field, timeout) in [
+            ("connect_timeout", self.connect_timeout),
+            ("read_timeout", self.read_timeout),
+            ("write_timeout", self.write_timeout),
+        ] {
+            if timeout.is_some_and(|timeout| timeout.is_zero()) {
+                return Err(BuilderError::invalid(field, "must not be zero"));
+            }
+        }
+        Ok(())
+    }
+}
+
+// The earlier of two optional limits.
+pub(crate) fn shortest(a: Option<Duration>, b: Option<Duration>) -> Option<Duration> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 8,
+            idle_timeout: Some(Duration::from_secs(300)),
+            checkout_timeout: Some(Duration::from_secs(30)),
+            connect_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStatus {
+    pub open: usize,
+    pub idle: usize,
+}
+
+struct PoolState {
+    idle: Vec<(redis::Connection, Instant)>,
+    open: usize,
+    closed: bool,
+}
+
+// Connections are opened lazily, up to `max_size`, and handed back when the guard drops.
+pub(crate) struct Pool {
+    // Replaced by the master the sentinels name on every connect, if there are sentinels.
+    client: Mutex<redis::Client>,
+    sentinel: Option<SentinelConfig>,
+    config: PoolConfig,
+    state: Mutex<PoolState>,
+    returned: Condvar,
+}
+
+impl Pool {
+    pub(crate) fn new(client: redis::Client, config: PoolConfig) -> Self {
+        Pool {
+            client: Mutex::new(client),
+            sentinel: None,
+            config,
+            state: Mutex::new(PoolState {
+                idle: Vec::new(),
+                open: 0,
+                closed: false,
+            }),
+            returned: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn with_sentinel(mut self, sentinel: SentinelConfig) -> Self {
+        self.sentinel = Some(sentinel);
+        self
+    }
+
+    pub(crate) fn client(&self) -> redis::Client {
+        self.client.lock().unwrap().clone()
+    }
+
+    pub(crate) fn config(&self) -> &PoolConfig {
+        &self.config
+    }
+
+    pub(crate) fn status(&self) -> PoolStatus {
+        let state = self.state.lock().unwrap();
+        PoolStatus {
+            open: state.open,
+            idle: state.idle.len(),
+        }
+    }
+
+    // Drops the idle connections and fails every later checkout, waiting ones included.
+    // Connections still checked out are dropped when they come back.
+    pub(crate) fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.open -= state.idle.len();
+        state.idle.clear();
+        self.returned.notify_all();
+    }
+
+    // Waits until the checkout timeout or `deadline`, whichever comes first.
+    pub(crate) fn get(
+        self: &Arc<Self>,
+        deadline: Option<Instant>,
+    ) -> Result<PooledConnection, Err> {
+        let now = Instant::now();
+        let deadline = match (deadline, self.config.checkout_timeout) {
+            (Some(deadline), Some(timeout)) => Some(deadline.min(now + timeout)),
+            (deadline, timeout) => deadline.or(timeout.map(|timeout| now + timeout)),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.closed {
+                return Err(StorageError::Closed.into());
+            }
+            if let Some(idle_timeout) = self.config.idle_timeout {
+                let before = state.idle.len();
+                state
+                    .idle
+                    .retain(|(_, since)| since.elapsed() < idle_timeout);
+                state.open -= before - state.idle.len();
+            }
+            if let Some((conn, _)) = state.idle.pop() {
+                return Ok(self.guard(conn));
+            }
+            if state.open < self.config.max_size {
+                state.open += 1;
+                drop(state);
+                return self.connect(deadline).map(|conn| self.guard(conn));
+            }
+
+            state = match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Err(StorageError::Timeout.into());
+                    }
+                    self.returned.wait_timeout(state, left).unwrap().0
+                }
+                None => self.returned.wait(state).unwrap(),
+            };
+        }
+    }
+
+    fn connect(&self, deadline: Option<Instant>) -> Result<redis::Connection, Err> {
+        let deadline = match (deadline, self.config.connect_timeout) {
+            (Some(deadline), Some(timeout)) => Some(deadline.min(Instant::now() + timeout)),
+            (deadline, timeout) => deadline.or(timeout.map(|timeout| Instant::now() + timeout)),
+        };
+        let resp = self.client_for(deadline).and_then(|client| match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(left) if !left.is_zero() => Ok(client.get_connection_with_timeout(left)?),
+                _ => Err(StorageError::Timeout.into()),
+            },
+            None => Ok(client.get_connection()?),
+        });
+        resp.inspect_err(|_| self.release(None))
+    }
+
+    // A connection outside the pool, e.g. for a subscription. It gets the connect and write
+    // timeouts but no read timeout, as it waits for the server to push.
+    pub(crate) fn dedicated(&self) -> Result<redis::Connection, Err> {
+        let deadline = self
+            .config
+            .connect_timeout
+            .map(|timeout| Instant::now() + timeout);
+        let client = self.client_for(deadline)?;
+        let conn = match self.config.connect_timeout {
+            Some(timeout) => client.get_connection_with_timeout(timeout)?,
+            None => client.get_connection()?,
+        };
+        conn.set_write_timeout(self.config.write_timeout)?;
+        Ok(conn)
+    }
+
+    fn client_for(&self, deadline: Option<Instant>) -> Result<redis::Client, Err> {
+        let Some(sentinel) = &self.sentinel else {
+            return Ok(self.client());
+        };
+        let redis = self.client().get_connection_info().redis.clone();
+        let master = sentinel.master(&redis, deadline)?;
+        *self.client.lock().unwrap() = master.clone();
+        Ok(master)
+    }
+
+    fn guard(self: &Arc<Self>, conn: redis::Connection) -> PooledConnection {
+        PooledConnection {
+            conn: Some(conn),
+            broken: false,
+            discarded: false,
+            pool: self.clone(),
+        }
+    }
+
+    // Broken connections are dropped, freeing their slot for a fresh one. The idle ones go
+    // too, they most likely lead to the same dead or demoted server.
+    fn release(&self, conn: Option<redis::Connection>) {
+        let mut state = self.state.lock().unwrap();
+        match conn.filter(|conn| conn.is_open() && !state.closed) {
+            Some(conn) => state.idle.push((conn, Instant::now())),
+            None => {
+                state.open -= 1 + state.idle.len();
+                state.idle.clear();
+            }
+        }
+        self.returned.notify_all();
+    }
+
+    // Frees the slot of a connection that was closed on purpose.
+    fn forget(&self) {
+        self.state.lock().unwrap().open -= 1;
+        self.returned.notify_all();
+    }
+}
+
+// Commands sent through the guard are watched: after an io error or timeout a reply may
+// still be in flight, so the connection is closed rather than reused. So is one to a
+// master that was demoted in a failover and answers READONLY.
+pub(crate) struct PooledConnection {
+    conn: Option<redis::Connection>,
+    broken: bool,
+    discarded: bool,
+    pool: Arc<Pool>,
+}
+
+impl PooledConnection {
+    // Closes the connection instead of pooling it, e.g. when it may still WATCH a key. Unlike
+    // a broken one, the idle connections are kept.
+    pub(crate) fn discard(&mut self) {
+        self.discarded = true;
+    }
+
+    // Not `Deref`, so every command goes through `ConnectionLike` and is watched.
+    pub(crate) fn get_ref(&self) -> &redis::Connection {
+        self.conn.as_ref().unwrap()
+    }
+
+    fn get_mut(&mut self) -> &mut redis::Connection {
+        self.conn.as_mut().unwrap()
+    }
+
+    fn watch<T>(&mut self, resp: RedisResult<T>) -> RedisResult<T> {
+        if let Err(e) = &resp {
+            self.broken |= e.is_io_error() || e.is_timeout() || e.code() == Some("READONLY");
+        }
+        resp
+    }
+}
+
+impl ConnectionLike for PooledConnection {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+        let resp = self.get_mut().req_packed_command(cmd);
+        self.watch(resp)
+    }
+
+    fn req_packed_commands(
+        &mut self,
+        cmd: &[u8],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        let resp = self.get_mut().req_packed_commands(cmd, offset, count);
+        self.watch(resp)
+    }
+
+    fn get_db(&self) -> i64 {
+        self.get_ref().get_db()
+    }
+
+    fn check_connection(&mut self) -> bool {
+        self.get_mut().check_connection()
+    }
+
+    fn is_open(&self) -> bool {
+        !self.broken && self.get_ref().is_open()
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        let conn = self.conn.take().filter(|_| !self.broken);
+        match conn {
+            Some(_) if self.discarded => self.pool.forget(),
+            conn => self.pool.release(conn),
+        }
+    }
+}
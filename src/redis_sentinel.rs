@@ -0,0 +1,129 @@
+use std::time::{Duration, Instant};
+
+use redis::{ConnectionAddr, ConnectionInfo, RedisConnectionInfo};
+
+use crate::error::{BuilderError, StorageError};
+use crate::storage::Err;
+
+// Sentinels to ask for the current master of `service`. The pool asks again whenever it
+// opens a connection, so connections opened after a failover reach the promoted master.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SentinelConfig {
+    // Sentinel urls, e.g. `redis://10.0.0.1:26379`, tried in order.
+    pub sentinels: Vec<String>,
+    pub service: String,
+}
+
+impl SentinelConfig {
+    pub fn new(sentinels: &[&str], service: &str) -> Self {
+        SentinelConfig {
+            sentinels: sentinels.iter().map(|addr| addr.to_string()).collect(),
+            service: service.to_string(),
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), BuilderError> {
+        if self.sentinels.is_empty() {
+            return Err(BuilderError::invalid("sentinels", "must not be empty"));
+        }
+        if self.service.is_empty() {
+            return Err(BuilderError::invalid("service", "must not be empty"));
+        }
+        for addr in &self.sentinels {
+            redis::Client::open(addr.as_str())
+                .map_err(|e| BuilderError::invalid("sentinels", e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    // A client for the master the first reachable sentinel names, using the database and
+    // credentials in `redis`. A master that reports another role, e.g. one that was just
+    // demoted, is skipped.
+    pub(crate) fn master(
+        &self,
+        redis: &RedisConnectionInfo,
+        deadline: Option<Instant>,
+    ) -> Result<redis::Client, Err> {
+        let mut last: Option<Err> = None;
+        for addr in &self.sentinels {
+            match self.ask(addr, redis, deadline) {
+                Ok(client) => return Ok(client),
+                Err(e) => last = Some(e),
+            }
+        }
+        Err(last.unwrap_or_else(|| "no sentinel configured".into()))
+    }
+
+    fn ask(
+        &self,
+        addr: &str,
+        redis: &RedisConnectionInfo,
+        deadline: Option<Instant>,
+    ) -> Result<redis::Client, Err> {
+        let mut conn = connect(&redis::Client::open(addr)?, deadline)?;
+        let master: Option<(String, u16)> = redis::cmd("SENTINEL")
+            .arg("get-master-addr-by-name")
+            .arg(&self.service)
+            .query(&mut conn)?;
+        let (host, port) = master
+            .ok_or_else(|| format!("sentinel {} doesn't know service `{}`", addr, self.service))?;
+
+        let client = redis::Client::open(ConnectionInfo {
+            addr: ConnectionAddr::Tcp(host.clone(), port),
+            redis: redis.clone(),
+        })?;
+        let role: Vec<redis::Value> = redis::cmd("ROLE").query(&mut connect(&client, deadline)?)?;
+        match role.first() {
+            Some(redis::Value::Data(role)) if role == b"master" => Ok(client),
+            _ => Err(format!(
+                "{}:{} named by sentinel {} is not a master",
+                host, port, addr
+            )
+            .into()),
+        }
+    }
+}
+
+fn connect(client: &redis::Client, deadline: Option<Instant>) -> Result<redis::Connection, Err> {
+    match deadline {
+        Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+            Some(left) if !left.is_zero() => Ok(client.get_connection_with_timeout(left)?),
+            _ => Err(StorageError::Timeout.into()),
+        },
+        None => Ok(client.get_connection_with_timeout(SENTINEL_TIMEOUT)?),
+    }
+}
+
+// Sentinels that hang shouldn't stall a connect without a deadline.
+const SENTINEL_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(
+            SentinelConfig::new(&["redis://127.0.0.1:26379"], "mymaster")
+                .validate()
+                .is_ok()
+        );
+        assert!(SentinelConfig::new(&[], "mymaster").validate().is_err());
+        assert!(SentinelConfig::new(&["redis://127.0.0.1:26379"], "")
+            .validate()
+            .is_err());
+        assert!(SentinelConfig::new(&["not a url"], "mymaster")
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let sentinel = SentinelConfig::new(&["redis://127.0.0.1:1"], "mymaster");
+        let deadline = Instant::now() + Duration::from_millis(200);
+        assert!(sentinel
+            .master(&RedisConnectionInfo::default(), Some(deadline))
+            .is_err());
+    }
+}
@@ -0,0 +1,126 @@
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use redis::{Commands, FromRedisValue, Pipeline, ToRedisArgs};
+
+use crate::options::OpOptions;
+use crate::redis_pool::PooledConnection;
+use crate::redis_storage::RedisStorage;
+use crate::storage::Err;
+
+// Handed to the closure of `RedisStorage::transaction`. Reads run right away and see the
+// watched keys as they are, writes are queued and applied together by EXEC.
+pub struct RedisTransaction<'a, K, V> {
+    conn: &'a mut PooledConnection,
+    pipe: Pipeline,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> RedisTransaction<'_, K, V>
+where
+    K: ToRedisArgs,
+    V: ToRedisArgs + FromRedisValue,
+{
+    pub fn get(&mut self, key: K) -> Result<Option<V>, Err> {
+        Ok(self.conn.get(key)?)
+    }
+
+    pub fn contains(&mut self, key: K) -> Result<bool, Err> {
+        Ok(self.conn.exists(key)?)
+    }
+
+    pub fn set(&mut self, key: K, value: V) -> &mut Self {
+        self.pipe.set(key, value).ignore();
+        self
+    }
+
+    pub fn set_ex(&mut self, key: K, value: V, expire: Duration) -> &mut Self {
+        self.pipe
+            .pset_ex(key, value, expire.as_millis() as u64)
+            .ignore();
+        self
+    }
+
+    pub fn del(&mut self, key: K) -> &mut Self {
+        self.pipe.del(key).ignore();
+        self
+    }
+}
+
+impl<K, V> RedisStorage<K, V>
+where
+    K: ToRedisArgs,
+    V: ToRedisArgs + FromRedisValue,
+{
+    // WATCHes `watch`, runs `f` and applies its writes with one MULTI/EXEC. Redis aborts the
+    // EXEC if a watched key changed in between, then `f` runs again with fresh reads, so it
+    // must not have side effects outside the transaction. An error from `f` discards the
+    // queued writes.
+    pub fn transaction<T, F>(&self, watch: &[K], mut f: F) -> Result<T, Err>
+    where
+        F: FnMut(&mut RedisTransaction<'_, K, V>) -> Result<T, Err>,
+    {
+        self.closed.check()?;
+        let mut conn = self.connection(&OpOptions::default())?;
+        loop {
+            if !watch.is_empty() {
+                redis::cmd("WATCH").arg(watch).query::<()>(&mut conn)?;
+            }
+
+            let mut tx = RedisTransaction {
+                conn: &mut conn,
+                pipe: redis::pipe(),
+                _marker: PhantomData,
+            };
+            tx.pipe.atomic();
+            let value = match f(&mut tx) {
+                Ok(value) => value,
+                Err(e) => {
+                    redis::cmd("UNWATCH").query::<()>(&mut conn)?;
+                    return Err(e);
+                }
+            };
+
+            let pipe = tx.pipe;
+            let exec: Option<()> = pipe.query(&mut conn)?;
+            if exec.is_some() {
+                return Ok(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::redis_storage::RedisStorageBuilder;
+    use crate::storage::Storage;
+
+    #[test]
+    fn test_transaction() {
+        let storage = RedisStorageBuilder::<&str, String>::new()
+            .addr("redis://127.0.0.1:6379")
+            .build();
+        storage.set("tx_from", "10".to_string()).unwrap();
+        storage.set("tx_to", "0".to_string()).unwrap();
+
+        let moved = storage
+            .transaction(&["tx_from", "tx_to"], |tx| {
+                let from: i64 = tx.get("tx_from")?.unwrap_or_default().parse()?;
+                let to: i64 = tx.get("tx_to")?.unwrap_or_default().parse()?;
+                tx.set("tx_from", (from - 3).to_string())
+                    .set("tx_to", (to + 3).to_string());
+                Ok(3)
+            })
+            .unwrap();
+        assert_eq!(moved, 3);
+        assert_eq!(storage.get("tx_from").unwrap(), Some("7".to_string()));
+        assert_eq!(storage.get("tx_to").unwrap(), Some("3".to_string()));
+
+        let resp = storage.transaction(&["tx_from"], |tx| {
+            tx.del("tx_from");
+            Err::<(), _>("abort".into())
+        });
+        assert!(resp.is_err());
+        assert!(storage.contains("tx_from").unwrap());
+    }
+}
@@ -0,0 +1,238 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::error::BuilderError;
+use crate::storage::{DynStorage, Err, Storage};
+
+// `DynStorage` with `String` values, what `StorageConfig` builds.
+pub type SharedStorage<K = String, V = String> = DynStorage<K, V>;
+
+type Handle = Box<dyn Any + Send + Sync>;
+type Closer = Box<dyn Fn() -> Result<(), Err> + Send + Sync>;
+type Factory = Box<dyn Fn() -> Result<(Handle, Closer), BuilderError> + Send + Sync>;
+type Hook = Box<dyn FnOnce() + Send>;
+
+enum Slot {
+    Ready(Handle, Closer),
+    Lazy(Factory),
+}
+
+// Named storages built once and handed out as shared handles, the registry itself is
+// meant to be shared (e.g. in an `Arc`) across the application.
+#[derive(Default)]
+pub struct StorageRegistry {
+    slots: RwLock<HashMap<String, Arc<Mutex<Slot>>>>,
+    hooks: Mutex<Vec<Hook>>,
+}
+
+impl StorageRegistry {
+    pub fn new() -> Self {
+        StorageRegistry::default()
+    }
+
+    pub fn insert<K, V, S>(&self, name: &str, storage: S)
+    where
+        K: 'static,
+        V: 'static,
+        S: Storage<K, V> + Send + Sync + 'static,
+    {
+        self.insert_shared::<K, V>(name, Arc::new(storage))
+    }
+
+    pub fn insert_shared<K: 'static, V: 'static>(&self, name: &str, storage: SharedStorage<K, V>) {
+        let (handle, closer) = ready(storage);
+        self.put(name, Slot::Ready(handle, closer));
+    }
+
+    // The storage is built on first access, a failed build is retried on the next one.
+    pub fn insert_lazy<K, V, S, F>(&self, name: &str, factory: F)
+    where
+        K: 'static,
+        V: 'static,
+        S: Storage<K, V> + Send + Sync + 'static,
+        F: Fn() -> Result<S, BuilderError> + Send + Sync + 'static,
+    {
+        self.put(
+            name,
+            Slot::Lazy(Box::new(move || {
+                let storage: SharedStorage<K, V> = Arc::new(factory()?);
+                Ok(ready(storage))
+            })),
+        );
+    }
+
+    // # Panics
+    // Panics if a lazy storage fails to build or `name` holds a storage of other key/value
+    // types. Use `try_get` to get the error instead.
+    pub fn get<K: 'static, V: 'static>(&self, name: &str) -> Option<SharedStorage<K, V>> {
+        match self.try_get(name) {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to get storage from registry: {}", e),
+        }
+    }
+
+    pub fn try_get<K: 'static, V: 'static>(
+        &self,
+        name: &str,
+    ) -> Result<Option<SharedStorage<K, V>>, BuilderError> {
+        let slot = match self.slots.read().unwrap().get(name) {
+            Some(slot) => slot.clone(),
+            None => return Ok(None),
+        };
+
+        let mut slot = slot.lock().unwrap();
+        if let Slot::Lazy(factory) = &*slot {
+            let (handle, closer) =
+                factory().map_err(|e| BuilderError::Storage(name.to_string(), Box::new(e)))?;
+            *slot = Slot::Ready(handle, closer);
+        }
+        match &*slot {
+            Slot::Ready(handle, _) => match handle.downcast_ref::<SharedStorage<K, V>>() {
+                Some(storage) => Ok(Some(storage.clone())),
+                None => Err(BuilderError::Storage(
+                    name.to_string(),
+                    Box::new(BuilderError::invalid(
+                        "type",
+                        format!(
+                            "not a Storage<{}, {}>",
+                            std::any::type_name::<K>(),
+                            std::any::type_name::<V>()
+                        ),
+                    )),
+                )),
+            },
+            Slot::Lazy(_) => unreachable!(),
+        }
+    }
+
+    pub fn remove(&self, name: &str) -> bool {
+        self.slots.write().unwrap().remove(name).is_some()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.slots.read().unwrap().contains_key(name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.slots.read().unwrap().keys().cloned().collect()
+    }
+
+    pub fn on_shutdown<F: FnOnce() + Send + 'static>(&self, hook: F) {
+        self.hooks.lock().unwrap().push(Box::new(hook));
+    }
+
+    // Closes every storage that was built, drops the registry's handles and runs the shutdown
+    // hooks in reverse order. Everything is attempted, the first error is returned.
+    pub fn shutdown(&self) -> Result<(), Err> {
+        let slots = std::mem::take(&mut *self.slots.write().unwrap());
+        let mut result = Ok(());
+        for slot in slots.into_values() {
+            if let Slot::Ready(_, closer) = &*slot.lock().unwrap() {
+                let closed = closer();
+                if result.is_ok() {
+                    result = closed;
+                }
+            }
+        }
+
+        let hooks = std::mem::take(&mut *self.hooks.lock().unwrap());
+        for hook in hooks.into_iter().rev() {
+            hook();
+        }
+        result
+    }
+
+    fn put(&self, name: &str, slot: Slot) {
+        self.slots
+            .write()
+            .unwrap()
+            .insert(name.to_string(), Arc::new(Mutex::new(slot)));
+    }
+}
+
+fn ready<K: 'static, V: 'static>(storage: SharedStorage<K, V>) -> (Handle, Closer) {
+    let closing = storage.clone();
+    (Box::new(storage), Box::new(move || closing.close()))
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::{DashMapStorage, DashMapStorageBuilder};
+    use crate::error::StorageError;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_get() {
+        let registry = StorageRegistry::new();
+        registry.insert(
+            "cache",
+            DashMapStorageBuilder::<String, String>::new().build(),
+        );
+
+        let cache = registry.get::<String, String>("cache").unwrap();
+        cache.set("name".to_string(), "Ferris".to_string()).unwrap();
+        assert_eq!(
+            registry
+                .get::<String, String>("cache")
+                .unwrap()
+                .get("name".to_string())
+                .unwrap(),
+            Some("Ferris".to_string())
+        );
+        assert!(registry.get::<String, String>("sessions").is_none());
+        assert!(matches!(
+            registry.try_get::<u32, u32>("cache"),
+            Err(BuilderError::Storage(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_lazy() {
+        let registry = StorageRegistry::new();
+        let builds = Arc::new(AtomicUsize::new(0));
+        let counter = builds.clone();
+        registry.insert_lazy("counters", move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(DashMapStorage::<&str, u32>::from_map(Default::default()))
+        });
+        assert_eq!(builds.load(Ordering::SeqCst), 0);
+
+        registry
+            .get::<&str, u32>("counters")
+            .unwrap()
+            .set("a", 1)
+            .unwrap();
+        let resp = registry.get::<&str, u32>("counters").unwrap().get("a");
+        assert_eq!(resp.unwrap(), Some(1));
+        assert_eq!(builds.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let registry = StorageRegistry::new();
+        registry.insert(
+            "cache",
+            DashMapStorageBuilder::<String, String>::new().build(),
+        );
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for i in 0..2 {
+            let order = order.clone();
+            registry.on_shutdown(move || order.lock().unwrap().push(i));
+        }
+
+        let cache = registry.get::<String, String>("cache").unwrap();
+        registry.shutdown().unwrap();
+        assert!(!registry.contains("cache"));
+        assert_eq!(*order.lock().unwrap(), [1, 0]);
+        assert!(cache.is_closed());
+        let e = cache
+            .set("name".to_string(), "Ferris".to_string())
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<StorageError>(),
+            Some(&StorageError::Closed)
+        );
+    }
+}
@@ -0,0 +1,588 @@
+use std::time::Duration;
+
+use crate::error::BuilderError;
+use crate::health::HealthStatus;
+use crate::merge::{Merge, MergeableStorage};
+use crate::options::{Consistency, OpOptions};
+use crate::storage::{CloseFlag, Err, Storage};
+
+type Replica<K, V> = Box<dyn Storage<K, V> + Send + Sync>;
+type Timestamp<V> = Box<dyn Fn(&V) -> u64 + Send + Sync>;
+type Merger<V> = Box<dyn Fn(&[Option<V>]) -> Option<V> + Send + Sync>;
+
+// Picks the value returned by `get` when replicas disagree, a missing key counts as a value.
+pub enum Resolution<V> {
+    // The first replica that answered wins.
+    PreferPrimary,
+    // The value with the highest timestamp wins, missing keys lose and ties go to the
+    // earlier replica.
+    LastWriteWins(Timestamp<V>),
+    // Receives every answer in replica order, replicas that failed are left out.
+    Merge(Merger<V>),
+}
+
+impl<V> Resolution<V> {
+    pub fn last_write_wins<F: Fn(&V) -> u64 + Send + Sync + 'static>(timestamp: F) -> Self {
+        Resolution::LastWriteWins(Box::new(timestamp))
+    }
+
+    pub fn merge<F>(merge: F) -> Self
+    where
+        F: Fn(&[Option<V>]) -> Option<V> + Send + Sync + 'static,
+    {
+        Resolution::Merge(Box::new(merge))
+    }
+
+    // Merges every answer, replicas missing the key are ignored.
+    pub fn crdt() -> Self
+    where
+        V: Merge + Clone,
+    {
+        Resolution::merge(|answers: &[Option<V>]| {
+            answers
+                .iter()
+                .flatten()
+                .cloned()
+                .reduce(|mut merged, value| {
+                    merged.merge(value);
+                    merged
+                })
+        })
+    }
+
+    fn resolve(&self, answers: &[Option<V>]) -> Option<V>
+    where
+        V: Clone,
+    {
+        match self {
+            Resolution::PreferPrimary => answers[0].clone(),
+            Resolution::LastWriteWins(timestamp) => {
+                let mut winner: Option<(u64, &V)> = None;
+                for value in answers.iter().flatten() {
+                    let ts = timestamp(value);
+                    if winner.is_none_or(|(best, _)| ts > best) {
+                        winner = Some((ts, value));
+                    }
+                }
+                winner.map(|(_, value)| value.clone())
+            }
+            Resolution::Merge(merge) => merge(answers),
+        }
+    }
+}
+
+// When a write to several replicas counts as done. Every replica is always tried, the first
+// error is returned when too few succeeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WritePolicy {
+    // Every replica must accept the write.
+    #[default]
+    All,
+    // At least this many replicas must accept the write.
+    Quorum(usize),
+    // One replica accepting the write is enough, e.g. when a local mirror is only a warm copy.
+    BestEffort,
+}
+
+impl WritePolicy {
+    fn required(&self, replicas: usize) -> usize {
+        match self {
+            WritePolicy::All => replicas,
+            WritePolicy::Quorum(n) => *n,
+            WritePolicy::BestEffort => 1,
+        }
+    }
+}
+
+// Writes go to every replica under the `WritePolicy`, reads ask all of them and settle disagreements with the
+// configured `Resolution`. The first replica is the primary.
+pub struct ReplicatedStorage<K, V> {
+    replicas: Vec<Replica<K, V>>,
+    resolution: Resolution<V>,
+    write_policy: WritePolicy,
+    repair_on_read: bool,
+    closed: CloseFlag,
+}
+
+impl<K, V> ReplicatedStorage<K, V> {
+    pub fn replicas(&self) -> &[Replica<K, V>] {
+        &self.replicas
+    }
+
+    // Calls every replica, returns the first error once all of them were tried.
+    fn each<F: FnMut(&Replica<K, V>) -> Result<(), Err>>(&self, mut f: F) -> Result<(), Err> {
+        let mut result = Ok(());
+        for replica in &self.replicas {
+            let resp = f(replica);
+            if result.is_ok() {
+                result = resp;
+            }
+        }
+        result
+    }
+
+    // Calls every replica and applies the write policy to the outcome.
+    fn write<F: FnMut(&Replica<K, V>) -> Result<(), Err>>(&self, mut f: F) -> Result<(), Err> {
+        let mut succeeded = 0;
+        let mut error = None;
+        for replica in &self.replicas {
+            match f(replica) {
+                Ok(()) => succeeded += 1,
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        match error {
+            Some(e) if succeeded < self.write_policy.required(self.replicas.len()) => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<K, V> Storage<K, V> for ReplicatedStorage<K, V>
+where
+    K: Clone,
+    V: Clone + PartialEq,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        self.write(|replica| replica.set(key.clone(), value.clone()))
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        self.write(|replica| replica.set_ex(key.clone(), value.clone(), expire))
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.get_with_opts(key, &OpOptions::default())
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.del_with_opts(key, &OpOptions::default())
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        if !self.closed.close() {
+            return Ok(());
+        }
+        self.each(|replica| replica.close())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+
+    // Healthy while enough replicas are to satisfy the write policy, the others degrade it.
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.closed.check()?;
+        let mut status = HealthStatus::default();
+        let mut healthy = 0;
+        let mut error = None;
+        for (i, replica) in self.replicas.iter().enumerate() {
+            let resp = replica.health_check();
+            match &resp {
+                Ok(_) => healthy += 1,
+                Err(e) if error.is_none() => error = Some(format!("replica {}: {}", i, e)),
+                Err(_) => {}
+            }
+            status.part(&format!("replica {}", i), resp);
+        }
+        match error {
+            Some(e) if healthy < self.write_policy.required(self.replicas.len()) => Err(e.into()),
+            _ => Ok(status),
+        }
+    }
+
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.closed.check()?;
+        self.write(|replica| replica.set_many(entries.clone()))
+    }
+
+    fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+        self.closed.check()?;
+        self.write(|replica| replica.set_with_opts(key.clone(), value.clone(), opts))
+    }
+
+    // `Consistency::Eventual` returns the first replica that answers, without comparing or
+    // repairing. Otherwise every replica is asked.
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        opts.remaining()?;
+        let mut answered = Vec::with_capacity(self.replicas.len());
+        let mut answers = Vec::with_capacity(self.replicas.len());
+        let mut error = None;
+        for replica in &self.replicas {
+            match replica.get_with_opts(key.clone(), opts) {
+                Ok(value) if opts.consistency == Consistency::Eventual => return Ok(value),
+                Ok(value) => {
+                    answered.push(replica);
+                    answers.push(value);
+                }
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        if answers.is_empty() {
+            return Err(error.unwrap_or_else(|| "ReplicatedStorage has no replicas".into()));
+        }
+        if answers.iter().all(|value| *value == answers[0]) {
+            return Ok(answers.swap_remove(0));
+        }
+
+        let winner = self.resolution.resolve(&answers);
+        if self.repair_on_read {
+            // Best effort, a replica that cannot be repaired now is repaired on a later read.
+            // Repaired values are written without an expiry.
+            for (replica, value) in answered.into_iter().zip(&answers) {
+                if *value != winner {
+                    let _ = match &winner {
+                        Some(winner) => replica.set(key.clone(), winner.clone()),
+                        None => replica.del(key.clone()).map(|_| ()),
+                    };
+                }
+            }
+        }
+        Ok(winner)
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let mut removed = false;
+        self.write(|replica| {
+            removed |= replica.del_with_opts(key.clone(), opts)?.is_some();
+            Ok(())
+        })?;
+        Ok(if removed { Some(key) } else { None })
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        Ok(self.get_with_opts(key, opts)?.is_some())
+    }
+}
+
+// Merges into every replica in turn, use `Resolution::crdt` so reads merge diverged replicas.
+impl<K, V> MergeableStorage<K, V> for ReplicatedStorage<K, V>
+where
+    K: Clone,
+    V: Clone + PartialEq + Merge,
+{
+    fn merge(&self, key: K, value: V) -> Result<V, Err> {
+        self.closed.check()?;
+        let mut merged: Option<V> = None;
+        self.write(|replica| {
+            let mut value = match replica.get(key.clone())? {
+                Some(mut current) => {
+                    current.merge(value.clone());
+                    current
+                }
+                None => value.clone(),
+            };
+            replica.set(key.clone(), value.clone())?;
+            if let Some(merged) = merged.take() {
+                value.merge(merged);
+            }
+            merged = Some(value);
+            Ok(())
+        })?;
+        Ok(merged.unwrap_or(value))
+    }
+}
+
+pub struct ReplicatedStorageBuilder<K, V> {
+    replicas: Vec<Replica<K, V>>,
+    resolution: Resolution<V>,
+    write_policy: WritePolicy,
+    repair_on_read: bool,
+}
+
+impl<K, V> ReplicatedStorageBuilder<K, V> {
+    pub fn new() -> Self {
+        ReplicatedStorageBuilder::default()
+    }
+
+    // The first replica added is the primary.
+    pub fn replica<S: Storage<K, V> + Send + Sync + 'static>(mut self, replica: S) -> Self {
+        self.replicas.push(Box::new(replica));
+        self
+    }
+
+    pub fn resolution(mut self, resolution: Resolution<V>) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    pub fn write_policy(mut self, write_policy: WritePolicy) -> Self {
+        self.write_policy = write_policy;
+        self
+    }
+
+    // Writes the winner back to replicas that disagreed with it, on by default.
+    pub fn repair_on_read(mut self, repair_on_read: bool) -> Self {
+        self.repair_on_read = repair_on_read;
+        self
+    }
+
+    // # Panics
+    // Panics if no replica was added or the quorum is zero or larger than the replica count.
+    pub fn build(self) -> ReplicatedStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build replicated storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<ReplicatedStorage<K, V>, BuilderError> {
+        if self.replicas.is_empty() {
+            return Err(BuilderError::MissingField("replica"));
+        }
+        if let WritePolicy::Quorum(n) = self.write_policy {
+            if n == 0 || n > self.replicas.len() {
+                return Err(BuilderError::invalid(
+                    "write_policy",
+                    format!("quorum must be between 1 and {}", self.replicas.len()),
+                ));
+            }
+        }
+
+        Ok(ReplicatedStorage {
+            replicas: self.replicas,
+            resolution: self.resolution,
+            write_policy: self.write_policy,
+            repair_on_read: self.repair_on_read,
+            closed: CloseFlag::default(),
+        })
+    }
+}
+
+impl<K, V> Default for ReplicatedStorageBuilder<K, V> {
+    fn default() -> Self {
+        Self {
+            replicas: Vec::new(),
+            resolution: Resolution::PreferPrimary,
+            write_policy: WritePolicy::All,
+            repair_on_read: true,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::{DashMapStorage, DashMapStorageBuilder};
+    use std::sync::Arc;
+
+    type Shared = Arc<DashMapStorage<&'static str, String>>;
+
+    fn replicas() -> (Shared, Shared) {
+        (
+            Arc::new(DashMapStorageBuilder::new().build()),
+            Arc::new(DashMapStorageBuilder::new().build()),
+        )
+    }
+
+    #[test]
+    fn test_prefer_primary() {
+        let (primary, mirror) = replicas();
+        let storage = ReplicatedStorageBuilder::new()
+            .replica(primary.clone())
+            .replica(mirror.clone())
+            .build();
+
+        storage.set("name", "Ferris".to_string()).unwrap();
+        assert_eq!(mirror.get("name").unwrap(), Some("Ferris".to_string()));
+
+        mirror.set("name", "Corro".to_string()).unwrap();
+        mirror.set("stale", "Corro".to_string()).unwrap();
+        assert_eq!(storage.get("name").unwrap(), Some("Ferris".to_string()));
+        assert_eq!(mirror.get("name").unwrap(), Some("Ferris".to_string()));
+        assert!(!storage.contains("stale").unwrap());
+        assert!(!mirror.contains("stale").unwrap());
+    }
+
+    #[test]
+    fn test_last_write_wins() {
+        let (primary, mirror) = replicas();
+        let storage = ReplicatedStorageBuilder::new()
+            .replica(primary.clone())
+            .replica(mirror.clone())
+            .resolution(Resolution::last_write_wins(|value: &String| {
+                value.split_once('|').unwrap().0.parse().unwrap()
+            }))
+            .repair_on_read(false)
+            .build();
+
+        primary.set("name", "1|Ferris".to_string()).unwrap();
+        mirror.set("name", "2|Corro".to_string()).unwrap();
+        assert_eq!(storage.get("name").unwrap(), Some("2|Corro".to_string()));
+        assert_eq!(primary.get("name").unwrap(), Some("1|Ferris".to_string()));
+    }
+
+    #[test]
+    fn test_merge() {
+        let (primary, mirror) = replicas();
+        let storage = ReplicatedStorageBuilder::new()
+            .replica(primary.clone())
+            .replica(mirror.clone())
+            .resolution(Resolution::merge(|answers: &[Option<String>]| {
+                let mut tags: Vec<&str> = answers
+                    .iter()
+                    .flatten()
+                    .flat_map(|v| v.split(','))
+                    .collect();
+                tags.sort();
+                tags.dedup();
+                Some(tags.join(","))
+            }))
+            .build();
+
+        primary.set("tags", "a,b".to_string()).unwrap();
+        mirror.set("tags", "b,c".to_string()).unwrap();
+        assert_eq!(storage.get("tags").unwrap(), Some("a,b,c".to_string()));
+        assert_eq!(primary.get("tags").unwrap(), Some("a,b,c".to_string()));
+        assert_eq!(mirror.get("tags").unwrap(), Some("a,b,c".to_string()));
+    }
+
+    #[test]
+    fn test_eventual_read() {
+        let (primary, mirror) = replicas();
+        let storage = ReplicatedStorageBuilder::new()
+            .replica(primary.clone())
+            .replica(mirror.clone())
+            .build();
+
+        primary.set("name", "Ferris".to_string()).unwrap();
+        let opts = OpOptions::new().consistency(Consistency::Eventual);
+        assert_eq!(
+            storage.get_with_opts("name", &opts).unwrap(),
+            Some("Ferris".to_string())
+        );
+        assert!(!mirror.contains("name").unwrap());
+        assert_eq!(storage.get("name").unwrap(), Some("Ferris".to_string()));
+        assert!(mirror.contains("name").unwrap());
+    }
+
+    #[test]
+    fn test_crdt() {
+        use crate::merge::GCounter;
+
+        let primary = Arc::new(DashMapStorageBuilder::<&str, GCounter>::new().build());
+        let mirror = Arc::new(DashMapStorageBuilder::<&str, GCounter>::new().build());
+        let storage = ReplicatedStorageBuilder::new()
+            .replica(primary.clone())
+            .replica(mirror.clone())
+            .resolution(Resolution::crdt())
+            .build();
+
+        let mut eu = GCounter::new();
+        eu.increment("eu", 2);
+        let mut us = GCounter::new();
+        us.increment("us", 3);
+        primary.merge("visits", eu).unwrap();
+        mirror.merge("visits", us).unwrap();
+        assert_eq!(storage.get("visits").unwrap().unwrap().value(), 5);
+        assert_eq!(primary.get("visits").unwrap().unwrap().value(), 5);
+    }
+
+    // Rejects every call.
+    struct Down;
+
+    impl Storage<&'static str, String> for Down {
+        fn set(&self, _key: &'static str, _value: String) -> Result<(), Err> {
+            Err("replica is down".into())
+        }
+
+        fn set_ex(&self, _key: &'static str, _value: String, _expire: Duration) -> Result<(), Err> {
+            Err("replica is down".into())
+        }
+
+        fn get(&self, _key: &'static str) -> Result<Option<String>, Err> {
+            Err("replica is down".into())
+        }
+
+        fn del(&self, _key: &'static str) -> Result<Option<&'static str>, Err> {
+            Err("replica is down".into())
+        }
+
+        fn contains(&self, _key: &'static str) -> Result<bool, Err> {
+            Err("replica is down".into())
+        }
+
+        fn health_check(&self) -> Result<HealthStatus, Err> {
+            Err("replica is down".into())
+        }
+    }
+
+    #[test]
+    fn test_write_policy() {
+        let (primary, mirror) = replicas();
+        let strict = ReplicatedStorageBuilder::new()
+            .replica(primary.clone())
+            .replica(Down)
+            .build();
+        assert!(strict.set("name", "Ferris".to_string()).is_err());
+        assert!(primary.contains("name").unwrap());
+
+        let quorum = ReplicatedStorageBuilder::new()
+            .replica(primary.clone())
+            .replica(mirror.clone())
+            .replica(Down)
+            .write_policy(WritePolicy::Quorum(2))
+            .build();
+        quorum.set("name", "Corro".to_string()).unwrap();
+        assert_eq!(quorum.get("name").unwrap(), Some("Corro".to_string()));
+
+        let relaxed = ReplicatedStorageBuilder::new()
+            .replica(Down)
+            .replica(mirror.clone())
+            .write_policy(WritePolicy::BestEffort)
+            .build();
+        relaxed.set("name", "Rusty".to_string()).unwrap();
+        assert_eq!(mirror.get("name").unwrap(), Some("Rusty".to_string()));
+        let opts = OpOptions::new().consistency(Consistency::Eventual);
+        assert_eq!(
+            relaxed.get_with_opts("name", &opts).unwrap(),
+            Some("Rusty".to_string())
+        );
+    }
+
+    #[test]
+    fn test_health_check() {
+        let (primary, mirror) = replicas();
+        let quorum = ReplicatedStorageBuilder::new()
+            .replica(primary.clone())
+            .replica(Down)
+            .replica(mirror.clone())
+            .write_policy(WritePolicy::Quorum(2))
+            .build();
+        let status = quorum.health_check().unwrap();
+        assert_eq!(status.degraded, ["replica 1: replica is down"]);
+
+        let strict = ReplicatedStorageBuilder::new()
+            .replica(primary)
+            .replica(Down)
+            .build();
+        assert_eq!(
+            strict.health_check().unwrap_err().to_string(),
+            "replica 1: replica is down"
+        );
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = ReplicatedStorageBuilder::<&str, String>::new().try_build();
+        assert!(matches!(resp, Err(BuilderError::MissingField("replica"))));
+
+        let resp = ReplicatedStorageBuilder::new()
+            .replica(Down)
+            .write_policy(WritePolicy::Quorum(2))
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(_))));
+    }
+}
@@ -0,0 +1,335 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::BuilderError;
+use crate::failover_storage::is_connection_error;
+use crate::health::HealthStatus;
+use crate::jitter_storage::random_unit;
+use crate::options::OpOptions;
+use crate::storage::{Err, Storage};
+
+type RetryOn = Box<dyn Fn(&Err) -> bool + Send + Sync>;
+
+// Retries calls that fail with a transient error, sleeping an exponentially growing, jittered
+// backoff in between. Writes are retried too, so a write that timed out after reaching the
+// backend may be applied twice; `set` and `del` are idempotent, other uses should check.
+pub struct RetryStorage<S> {
+    inner: S,
+    max_attempts: usize,
+    backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: f64,
+    retry_on: RetryOn,
+}
+
+impl<S> RetryStorage<S> {
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    // The pause before retry number `retry`, counting from 0.
+    pub fn backoff(&self, retry: usize) -> Duration {
+        let exp = self.multiplier.powi(retry.min(i32::MAX as usize) as i32);
+        let delay = (self.backoff.as_secs_f64() * exp).min(self.max_backoff.as_secs_f64());
+        Duration::from_secs_f64(delay * (1.0 - self.jitter * random_unit()))
+    }
+
+    // Calls `op` until it succeeds, fails with an error `retry_on` rejects or runs out of
+    // attempts. A backoff that would end past `deadline` gives up early with the last error.
+    fn retry<T, F>(&self, deadline: Option<Instant>, mut op: F) -> Result<T, Err>
+    where
+        F: FnMut() -> Result<T, Err>,
+    {
+        let mut retry = 0;
+        loop {
+            let e = match op() {
+                Ok(resp) => return Ok(resp),
+                Err(e) => e,
+            };
+            if retry + 1 >= self.max_attempts || !(self.retry_on)(&e) {
+                return Err(e);
+            }
+            let pause = self.backoff(retry);
+            if deadline.is_some_and(|deadline| Instant::now() + pause >= deadline) {
+                return Err(e);
+            }
+            thread::sleep(pause);
+            retry += 1;
+        }
+    }
+}
+
+impl<K, V, S> Storage<K, V> for RetryStorage<S>
+where
+    K: Clone,
+    V: Clone,
+    S: Storage<K, V>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.retry(None, || self.inner.set(key.clone(), value.clone()))
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.retry(None, || {
+            self.inner.set_ex(key.clone(), value.clone(), expire)
+        })
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.retry(None, || self.inner.get(key.clone()))
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.retry(None, || self.inner.del(key.clone()))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.retry(None, || self.inner.contains(key.clone()))
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.inner.close()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.inner.health_check()
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        self.retry(None, || self.inner.get_many(keys.clone()))
+    }
+
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.retry(None, || self.inner.set_many(entries.clone()))
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        self.retry(None, || self.inner.del_many(keys.clone()))
+    }
+
+    fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+        self.retry(opts.deadline, || {
+            self.inner.set_with_opts(key.clone(), value.clone(), opts)
+        })
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        self.retry(opts.deadline, || {
+            self.inner.get_with_opts(key.clone(), opts)
+        })
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        self.retry(opts.deadline, || {
+            self.inner.del_with_opts(key.clone(), opts)
+        })
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        self.retry(opts.deadline, || {
+            self.inner.contains_with_opts(key.clone(), opts)
+        })
+    }
+}
+
+pub struct RetryStorageBuilder<S> {
+    inner: S,
+    max_attempts: usize,
+    backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: f64,
+    retry_on: Option<RetryOn>,
+}
+
+impl<S> RetryStorageBuilder<S> {
+    pub fn new(inner: S) -> Self {
+        RetryStorageBuilder {
+            inner,
+            max_attempts: 3,
+            backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+            multiplier: 2.0,
+            jitter: 0.5,
+            retry_on: None,
+        }
+    }
+
+    // Attempts in total, including the first call.
+    pub fn max_attempts(mut self, attempts: usize) -> Self {
+        self.max_attempts = attempts;
+        self
+    }
+
+    // The pause before the first retry, multiplied by `multiplier` for every later one and
+    // capped at `max_backoff`.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    // Shortens every pause by a random fraction of up to `jitter`, within `0.0..=1.0`, so
+    // callers that failed together don't retry together.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    // Only errors matching `retry_on` are retried. By default that's `is_connection_error`:
+    // io errors, timeouts and dropped or refused connections.
+    pub fn retry_on<F>(mut self, retry_on: F) -> Self
+    where
+        F: Fn(&Err) -> bool + Send + Sync + 'static,
+    {
+        self.retry_on = Some(Box::new(retry_on));
+        self
+    }
+
+    // # Panics
+    // Panics if `max_attempts` is zero, `multiplier` is below 1, `jitter` is outside
+    // `0.0..=1.0` or `max_backoff` is shorter than `backoff`.
+    pub fn build(self) -> RetryStorage<S> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build retry storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<RetryStorage<S>, BuilderError> {
+        if self.max_attempts == 0 {
+            return Err(BuilderError::invalid("max_attempts", "must be at least 1"));
+        }
+        if !(self.multiplier >= 1.0 && self.multiplier.is_finite()) {
+            return Err(BuilderError::invalid("multiplier", "must be at least 1"));
+        }
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return Err(BuilderError::invalid("jitter", "must be within 0.0..=1.0"));
+        }
+        if self.max_backoff < self.backoff {
+            return Err(BuilderError::invalid(
+                "max_backoff",
+                "must not be shorter than backoff",
+            ));
+        }
+
+        Ok(RetryStorage {
+            inner: self.inner,
+            max_attempts: self.max_attempts,
+            backoff: self.backoff,
+            max_backoff: self.max_backoff,
+            multiplier: self.multiplier,
+            jitter: self.jitter,
+            retry_on: self
+                .retry_on
+                .unwrap_or_else(|| Box::new(is_connection_error)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::StorageError;
+    use crate::mock_storage::{Call, MockStorage};
+
+    fn retrying(
+        mock: MockStorage<&'static str, u32>,
+    ) -> RetryStorage<MockStorage<&'static str, u32>> {
+        RetryStorageBuilder::new(mock)
+            .backoff(Duration::from_millis(1))
+            .build()
+    }
+
+    #[test]
+    fn test_retries_transient_errors() {
+        let mock = MockStorage::new().with_entry("a", 1);
+        mock.fail_nth(0, StorageError::Timeout);
+        mock.fail_nth(1, std::io::Error::from(std::io::ErrorKind::ConnectionReset));
+        let storage = retrying(mock);
+
+        assert_eq!(storage.get("a").unwrap(), Some(1));
+        assert_eq!(storage.inner().call_count(), 3);
+    }
+
+    #[test]
+    fn test_gives_up() {
+        let mock = MockStorage::new();
+        for n in 0..3 {
+            mock.fail_nth(n, StorageError::Timeout);
+        }
+        mock.fail_nth(3, "rejected");
+        let storage = retrying(mock);
+
+        let e = storage.set("a", 1).unwrap_err();
+        assert_eq!(e.downcast_ref(), Some(&StorageError::Timeout));
+        assert_eq!(storage.inner().call_count(), 3);
+
+        // Errors that aren't transient fail right away.
+        assert!(storage.set("a", 1).is_err());
+        assert_eq!(storage.inner().calls()[3], Call::Set("a", 1));
+        assert_eq!(storage.inner().call_count(), 4);
+    }
+
+    #[test]
+    fn test_deadline() {
+        let mock = MockStorage::<&str, u32>::new();
+        mock.fail_nth(0, StorageError::Timeout);
+        let storage = RetryStorageBuilder::new(mock)
+            .backoff(Duration::from_secs(1))
+            .build();
+
+        let opts = OpOptions::new().timeout(Duration::from_millis(100));
+        assert!(storage.get_with_opts("a", &opts).is_err());
+        assert_eq!(storage.inner().call_count(), 1);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let storage = RetryStorageBuilder::new(MockStorage::<&str, u32>::new())
+            .backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(300))
+            .jitter(0.0)
+            .build();
+        assert_eq!(storage.backoff(0), Duration::from_millis(100));
+        assert_eq!(storage.backoff(1), Duration::from_millis(200));
+        assert_eq!(storage.backoff(5), Duration::from_millis(300));
+
+        let jittered = RetryStorageBuilder::new(MockStorage::<&str, u32>::new())
+            .backoff(Duration::from_millis(100))
+            .build();
+        let pause = jittered.backoff(0);
+        assert!(pause > Duration::from_millis(49) && pause <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = RetryStorageBuilder::new(MockStorage::<&str, u32>::new())
+            .max_attempts(0)
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(e)) if e.field == "max_attempts"));
+
+        let resp = RetryStorageBuilder::new(MockStorage::<&str, u32>::new())
+            .jitter(1.5)
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(e)) if e.field == "jitter"));
+    }
+}
@@ -0,0 +1,835 @@
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rocksdb::{
+    ColumnFamily, ColumnFamilyDescriptor, DBCompactionStyle, Direction, IteratorMode, Options,
+    Transaction, TransactionDB, TransactionDBOptions, WriteBatchWithTransaction,
+    DEFAULT_COLUMN_FAMILY_NAME,
+};
+
+use crate::admin::AdminStorage;
+use crate::atomic::AtomicStorage;
+use crate::error::BuilderError;
+use crate::expiry::ExpiryStorage;
+use crate::export::{EntryIter, ExportEntry, ExportIter, ExportStorage};
+use crate::flush::{FlushConfirmation, FlushNamespace};
+use crate::gc::GarbageCollect;
+use crate::get_or_insert::GetOrInsertStorage;
+use crate::hooks::{Hooks, StorageHook};
+use crate::scan::{KeyIter, ScanStorage};
+use crate::storage::{CloseFlag, Err, Storage};
+use crate::versioned::{Version, VersionedStorage};
+
+// Keys and records in one column family of a RocksDB `TransactionDB`. Records use the layout
+// of `SledStorage`. The read-modify-write operations lock their key with `get_for_update` in
+// a transaction, so they hold against concurrent writers, plain writes included.
+#[derive(Clone)]
+pub struct RocksDbStorage<K, V>
+where
+    V: Into<String>,
+{
+    db: Arc<TransactionDB>,
+    cf: Arc<str>,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> RocksDbStorage<K, V>
+where
+    V: Into<String>,
+{
+    // Reuses a database the application already opened, `cf` names the column family to use.
+    pub fn from_db(db: Arc<TransactionDB>, cf: &str) -> Self {
+        RocksDbStorage {
+            db,
+            cf: cf.into(),
+            closed: CloseFlag::default(),
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn db(&self) -> &TransactionDB {
+        &self.db
+    }
+
+    pub fn column_family(&self) -> &str {
+        &self.cf
+    }
+
+    fn cf(&self) -> Result<&ColumnFamily, Err> {
+        self.db
+            .cf_handle(&self.cf)
+            .ok_or_else(|| format!("column family `{}` is not open", self.cf).into())
+    }
+
+    fn lookup(&self, key: &str) -> Result<Option<String>, Err> {
+        match self.db.get_cf(self.cf()?, key)? {
+            Some(record) => Ok(decode(&record)?.map(|(value, _)| value)),
+            None => Ok(None),
+        }
+    }
+
+    // Runs `f` on the live record under `key`, locked until the transaction commits.
+    fn locked<T, F>(&self, key: &str, f: F) -> Result<T, Err>
+    where
+        F: FnOnce(&Transaction<TransactionDB>, &ColumnFamily, Option<Live>) -> Result<T, Err>,
+    {
+        let cf = self.cf()?;
+        let txn = self.db.transaction();
+        let live = match txn.get_for_update_cf(cf, key, true)? {
+            Some(record) => decode(&record)?,
+            None => None,
+        };
+        let out = f(&txn, cf, live)?;
+        txn.commit()?;
+        Ok(out)
+    }
+
+    // Keys and records from `prefix` on in key order, until the first key without it.
+    fn prefixed(
+        &self,
+        prefix: String,
+    ) -> Result<impl Iterator<Item = Result<(String, Box<[u8]>), Err>> + '_, Err> {
+        let mode = IteratorMode::From(prefix.as_bytes(), Direction::Forward);
+        let entries = self
+            .db
+            .iterator_cf(self.cf()?, mode)
+            .map(|entry| -> Result<_, Err> {
+                let (key, record) = entry?;
+                Ok((String::from_utf8(key.into_vec())?, record))
+            });
+        Ok(entries.take_while(move |entry| match entry {
+            Ok((key, _)) => key.starts_with(&prefix),
+            Err(_) => true,
+        }))
+    }
+}
+
+// A decoded record: the value and its remaining TTL.
+type Live = (String, Option<Duration>);
+
+impl<K, V> Storage<K, V> for RocksDbStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        self.db
+            .put_cf(self.cf()?, key.as_ref(), encode(value.as_str(), 0))?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        let expires_at = now_millis() + expire.as_millis() as u64;
+        self.db
+            .put_cf(self.cf()?, key.as_ref(), encode(value.as_str(), expires_at))?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let value = self.lookup(key.as_ref())?.map(V::from);
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let found = self.locked(key.as_ref(), |txn, cf, live| {
+            txn.delete_cf(cf, key.as_ref())?;
+            Ok(live.is_some())
+        })?;
+        self.hooks.del(&key);
+        Ok(found.then_some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        Ok(self.lookup(key.as_ref())?.is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+
+    // One write batch, applied atomically.
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.closed.check()?;
+        let entries: Vec<(K, String)> = entries
+            .into_iter()
+            .map(|(key, value)| (key, value.into()))
+            .collect();
+        let cf = self.cf()?;
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        for (key, value) in &entries {
+            batch.put_cf(cf, key.as_ref(), encode(value.as_str(), 0));
+        }
+        self.db.write(batch)?;
+        for (key, value) in entries {
+            self.hooks.set_with(&key, || V::from(value));
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> ExportStorage<K, V> for RocksDbStorage<K, V>
+where
+    K: AsRef<str> + From<String>,
+    V: Into<String> + From<String>,
+{
+    fn export_iter(&self) -> Result<ExportIter<'_, K, V>, Err> {
+        Ok(Box::new(
+            self.export_entries()?
+                .map(|entry| entry.map(|entry| (entry.key, entry.value))),
+        ))
+    }
+
+    // One iterator in key order, which reads an implicit snapshot. Expired entries are skipped.
+    fn export_entries<'a>(&'a self) -> Result<EntryIter<'a, K, V>, Err>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.closed.check()?;
+        Ok(Box::new(self.prefixed(String::new())?.filter_map(
+            |entry| {
+                let entry = entry.and_then(|(key, record)| {
+                    Ok(decode(&record)?.map(|(value, ttl)| ExportEntry {
+                        key: K::from(key),
+                        value: V::from(value),
+                        ttl,
+                    }))
+                });
+                entry.transpose()
+            },
+        )))
+    }
+}
+
+impl<K, V> GarbageCollect for RocksDbStorage<K, V>
+where
+    V: Into<String>,
+{
+    // Each key is checked again under its lock, so a concurrent `set` survives.
+    fn gc_now(&self, batch_size: usize) -> Result<usize, Err> {
+        self.closed.check()?;
+        let mut expired = Vec::new();
+        for entry in self.prefixed(String::new())? {
+            if expired.len() >= batch_size {
+                break;
+            }
+            let (key, record) = entry?;
+            if decode(&record)?.is_none() {
+                expired.push(key);
+            }
+        }
+
+        let mut removed = 0;
+        for key in expired {
+            let cf = self.cf()?;
+            let txn = self.db.transaction();
+            if let Some(record) = txn.get_for_update_cf(cf, &key, true)? {
+                if decode(&record)?.is_none() {
+                    txn.delete_cf(cf, &key)?;
+                    removed += 1;
+                }
+            }
+            txn.commit()?;
+        }
+        Ok(removed)
+    }
+}
+
+// An expired record counts as missing. Writes clear the TTL, as `set` does.
+impl<K, V> AtomicStorage<K, V> for RocksDbStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String> + PartialEq,
+{
+    fn compare_and_swap(&self, key: K, expected: Option<V>, new: Option<V>) -> Result<bool, Err> {
+        self.closed.check()?;
+        let expected: Option<String> = expected.map(Into::into);
+        let new: Option<String> = new.map(Into::into);
+        self.locked(key.as_ref(), |txn, cf, live| {
+            if live.map(|(value, _)| value) != expected {
+                return Ok(false);
+            }
+            match new {
+                Some(new) => txn.put_cf(cf, key.as_ref(), encode(new, 0))?,
+                None => txn.delete_cf(cf, key.as_ref())?,
+            }
+            Ok(true)
+        })
+    }
+
+    fn swap(&self, key: K, value: V) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let old = self.locked(key.as_ref(), |txn, cf, live| {
+            txn.put_cf(cf, key.as_ref(), encode(value, 0))?;
+            Ok(live.map(|(value, _)| value))
+        })?;
+        Ok(old.map(V::from))
+    }
+
+    fn take(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let old = self.locked(key.as_ref(), |txn, cf, live| {
+            txn.delete_cf(cf, key.as_ref())?;
+            Ok(live.map(|(value, _)| value))
+        })?;
+        Ok(old.map(V::from))
+    }
+}
+
+// Versions hash the stored string, checked and written under the key's lock.
+impl<K, V> VersionedStorage<K, V> for RocksDbStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn get_versioned(&self, key: K) -> Result<Option<(V, Version)>, Err> {
+        self.closed.check()?;
+        Ok(self.lookup(key.as_ref())?.map(|value| {
+            let version = Version::of_bytes(value.as_bytes());
+            (V::from(value), version)
+        }))
+    }
+
+    fn set_if_version(&self, key: K, value: V, expected: Option<Version>) -> Result<bool, Err> {
+        self.closed.check()?;
+        self.locked(key.as_ref(), |txn, cf, live| {
+            let current = live.map(|(value, _)| Version::of_bytes(value.as_bytes()));
+            if current != expected {
+                return Ok(false);
+            }
+            txn.put_cf(cf, key.as_ref(), encode(value, 0))?;
+            Ok(true)
+        })
+    }
+}
+
+impl<K, V> GetOrInsertStorage<K, V> for RocksDbStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set_nx(&self, key: K, value: V, expire: Option<Duration>) -> Result<bool, Err> {
+        self.closed.check()?;
+        let expires_at = expire.map_or(0, |expire| now_millis() + expire.as_millis() as u64);
+        self.locked(key.as_ref(), |txn, cf, live| {
+            if live.is_some() {
+                return Ok(false);
+            }
+            txn.put_cf(cf, key.as_ref(), encode(value, expires_at))?;
+            Ok(true)
+        })
+    }
+}
+
+// `len` walks the column family to skip expired records, `clear` deletes every key in one
+// batch.
+impl<K, V> AdminStorage for RocksDbStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn clear(&self) -> Result<(), Err> {
+        self.closed.check()?;
+        let cf = self.cf()?;
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        for entry in self.prefixed(String::new())? {
+            let (key, _) = entry?;
+            batch.delete_cf(cf, key);
+        }
+        Ok(self.db.write(batch)?)
+    }
+
+    fn len(&self) -> Result<usize, Err> {
+        self.closed.check()?;
+        let mut len = 0;
+        for entry in self.prefixed(String::new())? {
+            let (_, record) = entry?;
+            if decode(&record)?.is_some() {
+                len += 1;
+            }
+        }
+        Ok(len)
+    }
+}
+
+impl<K, V> ExpiryStorage<K> for RocksDbStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String>,
+{
+    fn ttl(&self, key: K) -> Result<Option<Duration>, Err> {
+        self.closed.check()?;
+        match self.db.get_cf(self.cf()?, key.as_ref())? {
+            Some(record) => Ok(decode(&record)?.and_then(|(_, ttl)| ttl)),
+            None => Ok(None),
+        }
+    }
+
+    fn expire(&self, key: K, expire: Duration) -> Result<bool, Err> {
+        self.closed.check()?;
+        let expires_at = now_millis() + expire.as_millis() as u64;
+        self.retime(key.as_ref(), |_| Some(expires_at))
+    }
+
+    fn persist(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        self.retime(key.as_ref(), |ttl| ttl.map(|_| 0))
+    }
+}
+
+impl<K, V> RocksDbStorage<K, V>
+where
+    V: Into<String>,
+{
+    // `expires_at` maps the live record's TTL to its new expiry, `None` leaves it alone.
+    fn retime<F>(&self, key: &str, expires_at: F) -> Result<bool, Err>
+    where
+        F: FnOnce(Option<Duration>) -> Option<u64>,
+    {
+        self.locked(key, |txn, cf, live| {
+            let Some((value, ttl)) = live else {
+                return Ok(false);
+            };
+            let Some(expires_at) = expires_at(ttl) else {
+                return Ok(false);
+            };
+            txn.put_cf(cf, key, encode(value, expires_at))?;
+            Ok(true)
+        })
+    }
+}
+
+impl<K, V> ScanStorage<K> for RocksDbStorage<K, V>
+where
+    K: From<String>,
+    V: Into<String>,
+{
+    // A lazy iterator from the prefix on, in key order.
+    fn scan_prefix(&self, prefix: &str) -> Result<KeyIter<'_, K>, Err> {
+        self.closed.check()?;
+        Ok(Box::new(self.prefixed(prefix.to_string())?.filter_map(
+            |entry| {
+                let key = entry.and_then(|(key, record)| match decode(&record)? {
+                    Some(_) => Ok(Some(K::from(key))),
+                    None => Ok(None),
+                });
+                key.transpose()
+            },
+        )))
+    }
+}
+
+impl<K, V> FlushNamespace for RocksDbStorage<K, V>
+where
+    V: Into<String>,
+{
+    // A prefix scan, removed in one batch.
+    fn flush_namespace(&self, prefix: &str, confirm: &FlushConfirmation) -> Result<usize, Err> {
+        self.closed.check()?;
+        confirm.check(prefix)?;
+        let cf = self.cf()?;
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        let mut removed = 0;
+        for entry in self.prefixed(prefix.to_string())? {
+            let (key, _) = entry?;
+            batch.delete_cf(cf, key);
+            removed += 1;
+        }
+        self.db.write(batch)?;
+        Ok(removed)
+    }
+}
+
+// Records are the expiry in unix millis (0 for none) followed by the value, as in
+// `SledStorage`.
+fn encode<V: Into<String>>(value: V, expires_at: u64) -> Vec<u8> {
+    let value: String = value.into();
+    let mut record = expires_at.to_be_bytes().to_vec();
+    record.extend_from_slice(value.as_bytes());
+    record
+}
+
+// Returns the value and its remaining TTL, `None` once expired.
+fn decode(record: &[u8]) -> Result<Option<Live>, Err> {
+    if record.len() < 8 {
+        return Err(
+            "RocksDbStorage record is too short, was it written by another program?".into(),
+        );
+    }
+    let (expires_at, value) = record.split_at(8);
+    let expires_at = u64::from_be_bytes(expires_at.try_into()?);
+    let ttl = match expires_at {
+        0 => None,
+        expires_at => match expires_at.checked_sub(now_millis()) {
+            Some(left) if left > 0 => Some(Duration::from_millis(left)),
+            _ => return Ok(None),
+        },
+    };
+    Ok(Some((String::from_utf8(value.to_vec())?, ttl)))
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+// How RocksDB merges its sorted files, see its wiki for the trade-offs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RocksDbCompaction {
+    // Fewer files per read, RocksDB's default.
+    #[default]
+    Level,
+    // Less write amplification, for write-heavy loads.
+    Universal,
+    // Drops the oldest files once the column family is full, for caches whose entries all
+    // expire.
+    Fifo,
+}
+
+impl From<RocksDbCompaction> for DBCompactionStyle {
+    fn from(compaction: RocksDbCompaction) -> Self {
+        match compaction {
+            RocksDbCompaction::Level => DBCompactionStyle::Level,
+            RocksDbCompaction::Universal => DBCompactionStyle::Universal,
+            RocksDbCompaction::Fifo => DBCompactionStyle::Fifo,
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct RocksDbStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    path: Option<PathBuf>,
+    column_family: String,
+    write_buffer_size: Option<usize>,
+    compaction: RocksDbCompaction,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> RocksDbStorageBuilder<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    pub fn new() -> Self {
+        RocksDbStorageBuilder::default()
+    }
+
+    // The database directory, created if it doesn't exist.
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    // The column family keys live in, created if missing. Defaults to RocksDB's `default`.
+    pub fn column_family(mut self, column_family: &str) -> Self {
+        self.column_family = column_family.to_string();
+        self
+    }
+
+    // Bytes buffered in memory before they're written out as a file, RocksDB defaults to
+    // 64 MiB. Larger buffers absorb write bursts at the cost of memory and recovery time.
+    pub fn write_buffer_size(mut self, write_buffer_size: usize) -> Self {
+        self.write_buffer_size = Some(write_buffer_size);
+        self
+    }
+
+    pub fn compaction(mut self, compaction: RocksDbCompaction) -> Self {
+        self.compaction = compaction;
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their TTL and batch variants.
+    // Clones of the storage share them.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if the path is missing, the column family name is empty, the write buffer size
+    // is zero, or the database cannot be opened.
+    pub fn build(self) -> RocksDbStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build rocksdb storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<RocksDbStorage<K, V>, BuilderError> {
+        let path = self
+            .path
+            .clone()
+            .ok_or(BuilderError::MissingField("path"))?;
+        if self.column_family.is_empty() {
+            return Err(BuilderError::invalid("column_family", "must not be empty"));
+        }
+
+        let mut cf_options = Options::default();
+        if let Some(write_buffer_size) = self.write_buffer_size {
+            if write_buffer_size == 0 {
+                return Err(BuilderError::invalid("write_buffer_size", "must not be 0"));
+            }
+            cf_options.set_write_buffer_size(write_buffer_size);
+        }
+        cf_options.set_compaction_style(self.compaction.into());
+        let mut options = cf_options.clone();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+
+        let cf = ColumnFamilyDescriptor::new(self.column_family.as_str(), cf_options);
+        let db = TransactionDB::open_cf_descriptors(
+            &options,
+            &TransactionDBOptions::default(),
+            path,
+            [cf],
+        )
+        .map_err(BuilderError::backend)?;
+        Ok(RocksDbStorage {
+            hooks: self.hooks,
+            ..RocksDbStorage::from_db(Arc::new(db), &self.column_family)
+        })
+    }
+}
+
+impl<K, V> Default for RocksDbStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    fn default() -> Self {
+        Self {
+            path: None,
+            column_family: DEFAULT_COLUMN_FAMILY_NAME.to_string(),
+            write_buffer_size: None,
+            compaction: RocksDbCompaction::default(),
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::storage_conformance_tests!(
+        conformance,
+        RocksDbStorageBuilder::<String, String>::new()
+            .path(tempfile::tempdir().unwrap().keep())
+            .build()
+    );
+
+    fn build<K: AsRef<str>>(dir: &tempfile::TempDir) -> RocksDbStorage<K, String> {
+        RocksDbStorageBuilder::new().path(dir.path()).build()
+    }
+
+    #[test]
+    fn test_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = build::<&str>(&dir);
+
+        let (key, value) = ("name", "Ferris".to_string());
+        storage.set(key, value.clone()).unwrap();
+        assert_eq!(storage.get(key).unwrap(), Some(value));
+        assert_eq!(storage.del(key).unwrap(), Some(key));
+        assert_eq!(storage.get(key).unwrap(), None);
+        assert_eq!(storage.del(key).unwrap(), None);
+    }
+
+    #[test]
+    fn test_set_ex() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = build::<&str>(&dir);
+
+        let (key, value) = ("set_ex_test", "ok!".to_string());
+        storage
+            .set_ex(key, value.clone(), Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(storage.get(key).unwrap(), Some(value));
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(!storage.contains(key).unwrap());
+        assert!(storage.is_empty().unwrap());
+        assert_eq!(storage.gc_now(10).unwrap(), 1);
+        assert_eq!(storage.gc_now(10).unwrap(), 0);
+
+        storage.set("a", "ok!".to_string()).unwrap();
+        assert!(!storage.persist("a").unwrap());
+        assert!(storage.expire("a", Duration::from_secs(60)).unwrap());
+        assert!(storage.ttl("a").unwrap().unwrap() > Duration::from_secs(59));
+        assert!(storage.persist("a").unwrap());
+        assert_eq!(storage.ttl("a").unwrap(), None);
+    }
+
+    #[test]
+    fn test_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = |builder: RocksDbStorageBuilder<String, String>| {
+            builder
+                .path(dir.path())
+                .column_family("kv")
+                .write_buffer_size(1 << 20)
+                .compaction(RocksDbCompaction::Universal)
+        };
+        let storage = options(RocksDbStorageBuilder::new()).build();
+        storage
+            .set_many(vec![("a".into(), "1".into()), ("b".into(), "2".into())])
+            .unwrap();
+        storage.close().unwrap();
+        assert!(storage.get("a".into()).is_err());
+        drop(storage);
+
+        let storage = options(RocksDbStorageBuilder::new()).build();
+        let entries: Vec<(String, String)> =
+            storage.export_iter().unwrap().map(Result::unwrap).collect();
+        assert_eq!(
+            entries,
+            [
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "2".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flush_namespace() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = build::<String>(&dir);
+        for key in ["tenant:1:a", "tenant:1:b", "tenant:2:a", "tenant:1"] {
+            storage.set(key.to_string(), "ok!".to_string()).unwrap();
+        }
+
+        let keys: Vec<String> = storage
+            .scan_prefix("tenant:1:")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(keys, ["tenant:1:a", "tenant:1:b"]);
+
+        let confirm = FlushConfirmation::for_prefix("tenant:1:");
+        assert_eq!(storage.flush_namespace("tenant:1:", &confirm).unwrap(), 2);
+        assert_eq!(storage.len().unwrap(), 2);
+        storage.clear().unwrap();
+        assert!(storage.is_empty().unwrap());
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = build::<&str>(&dir);
+
+        assert!(storage
+            .compare_and_swap("cas", None, Some("1".to_string()))
+            .unwrap());
+        assert!(!storage
+            .compare_and_swap("cas", None, Some("2".to_string()))
+            .unwrap());
+        let seen = storage
+            .fetch_update("cas", |current| {
+                current.map(|n| (n.parse::<u32>().unwrap() + 1).to_string())
+            })
+            .unwrap();
+        assert_eq!(seen, Some("1".to_string()));
+        assert_eq!(storage.get("cas").unwrap(), Some("2".to_string()));
+
+        storage
+            .set_ex("a", "1".to_string(), Duration::from_millis(10))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        // An expired value was never there.
+        assert_eq!(storage.swap("a", "2".to_string()).unwrap(), None);
+        assert_eq!(storage.take("a").unwrap(), Some("2".to_string()));
+        assert_eq!(storage.take("a").unwrap(), None);
+
+        assert!(storage.set_nx("b", "1".to_string(), None).unwrap());
+        assert!(!storage.set_nx("b", "2".to_string(), None).unwrap());
+
+        assert!(storage.set_if_version("c", "1".to_string(), None).unwrap());
+        let (_, version) = storage.get_versioned("c").unwrap().unwrap();
+        assert!(storage
+            .set_if_version("c", "2".to_string(), Some(version))
+            .unwrap());
+        assert!(!storage
+            .set_if_version("c", "3".to_string(), Some(version))
+            .unwrap());
+    }
+
+    #[test]
+    fn test_concurrent_swaps() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = build::<String>(&dir);
+        // Every value written is returned by exactly one later swap or left at the end.
+        let seen: Vec<String> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|n| {
+                    let storage = &storage;
+                    scope.spawn(move || {
+                        (0..25)
+                            .filter_map(|i| {
+                                storage
+                                    .swap("key".to_string(), format!("{}-{}", n, i))
+                                    .unwrap()
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        let last = storage.get("key".to_string()).unwrap().unwrap();
+        let mut all: Vec<String> = seen.into_iter().chain([last]).collect();
+        all.sort();
+        all.dedup();
+        assert_eq!(all.len(), 100);
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = RocksDbStorageBuilder::<&str, String>::new().try_build();
+        assert!(matches!(resp, Err(BuilderError::MissingField("path"))));
+
+        let resp = RocksDbStorageBuilder::<&str, String>::new()
+            .path("data.rocksdb")
+            .write_buffer_size(0)
+            .try_build();
+        assert!(
+            matches!(resp, Err(BuilderError::InvalidConfig(e)) if e.field == "write_buffer_size")
+        );
+
+        let resp = RocksDbStorageBuilder::<&str, String>::new()
+            .path("data.rocksdb")
+            .column_family("")
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(e)) if e.field == "column_family"));
+    }
+}
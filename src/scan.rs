@@ -0,0 +1,26 @@
+use std::sync::Arc;
+
+use crate::storage::Err;
+
+pub type KeyIter<'a, K> = Box<dyn Iterator<Item = Result<K, Err>> + 'a>;
+
+// Backends that can list their keys under a prefix, e.g. for admin and cleanup jobs. Keys
+// written during the scan may or may not be yielded, expired keys are skipped.
+pub trait ScanStorage<K> {
+    // Every key starting with `prefix`, `""` lists them all.
+    fn scan_prefix(&self, prefix: &str) -> Result<KeyIter<'_, K>, Err>;
+}
+
+macro_rules! forward_scan {
+    ($($ty:ty),*) => {
+        $(
+            impl<K, S: ScanStorage<K> + ?Sized> ScanStorage<K> for $ty {
+                fn scan_prefix(&self, prefix: &str) -> Result<KeyIter<'_, K>, Err> {
+                    (**self).scan_prefix(prefix)
+                }
+            }
+        )*
+    };
+}
+
+forward_scan!(&S, Box<S>, Arc<S>);
@@ -0,0 +1,810 @@
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::Duration;
+
+use scylla::client::session::Session;
+use scylla::client::session_builder::SessionBuilder;
+use scylla::response::query_result::QueryResult;
+use scylla::response::PagingState;
+use scylla::statement::prepared::PreparedStatement;
+use scylla::value::{CqlValue, Row};
+use tokio::runtime::Runtime;
+
+use crate::admin::AdminStorage;
+use crate::atomic::AtomicStorage;
+use crate::env;
+use crate::error::BuilderError;
+use crate::expiry::ExpiryStorage;
+use crate::export::{EntryIter, ExportEntry, ExportIter, ExportStorage};
+use crate::flush::{FlushConfirmation, FlushNamespace};
+use crate::get_or_insert::GetOrInsertStorage;
+use crate::hooks::{Hooks, StorageHook};
+use crate::scan::{KeyIter, ScanStorage};
+use crate::storage::{CloseFlag, Err, Storage};
+
+// Rows per page of a full-table read.
+const PAGE: i32 = 1000;
+
+// The longest TTL the server accepts, 20 years in seconds.
+const MAX_TTL: u64 = 630_720_000;
+
+// One `(key text PRIMARY KEY, value text)` row per key in a ScyllaDB or Cassandra table.
+// `set_ex` writes with `USING TTL`, so the server expires the row itself; TTLs count in whole
+// seconds and are rounded up. Every statement is prepared once on build and shared by clones.
+// The atomic operations are lightweight transactions, which only see each other linearizably;
+// a plain `set` racing one of them may be lost.
+#[derive(Clone)]
+pub struct ScyllaStorage<K, V>
+where
+    V: Into<String>,
+{
+    runtime: Arc<Runtime>,
+    session: Arc<Session>,
+    statements: Arc<Statements>,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    _marker: PhantomData<(K, V)>,
+}
+
+struct Statements {
+    get: PreparedStatement,
+    ttl: PreparedStatement,
+    insert: PreparedStatement,
+    delete: PreparedStatement,
+    update_if: PreparedStatement,
+    update_if_null: PreparedStatement,
+    delete_if: PreparedStatement,
+    all: PreparedStatement,
+    count: PreparedStatement,
+    truncate: PreparedStatement,
+}
+
+impl Statements {
+    async fn prepare(session: &Session, table: &str) -> Result<Self, Err> {
+        let mut all = session
+            .prepare(format!("SELECT key, value, TTL(value) FROM {}", table))
+            .await?;
+        all.set_page_size(PAGE);
+        Ok(Statements {
+            get: session
+                .prepare(format!("SELECT value FROM {} WHERE key = ?", table))
+                .await?,
+            ttl: session
+                .prepare(format!(
+                    "SELECT value, TTL(value) FROM {} WHERE key = ?",
+                    table
+                ))
+                .await?,
+            // A TTL of 0 means none.
+            insert: session
+                .prepare(format!(
+                    "INSERT INTO {} (key, value) VALUES (?, ?) USING TTL ?",
+                    table
+                ))
+                .await?,
+            delete: session
+                .prepare(format!("DELETE FROM {} WHERE key = ?", table))
+                .await?,
+            update_if: session
+                .prepare(format!(
+                    "UPDATE {} USING TTL ? SET value = ? WHERE key = ? IF value = ?",
+                    table
+                ))
+                .await?,
+            // Also applies when the row doesn't exist.
+            update_if_null: session
+                .prepare(format!(
+                    "UPDATE {} USING TTL ? SET value = ? WHERE key = ? IF value = null",
+                    table
+                ))
+                .await?,
+            delete_if: session
+                .prepare(format!("DELETE FROM {} WHERE key = ? IF value = ?", table))
+                .await?,
+            all,
+            count: session
+                .prepare(format!("SELECT COUNT(value) FROM {}", table))
+                .await?,
+            truncate: session.prepare(format!("TRUNCATE {}", table)).await?,
+        })
+    }
+}
+
+// A live row: key, value and remaining TTL in seconds.
+type Entry = (String, String, Option<i32>);
+
+impl<K, V> ScyllaStorage<K, V>
+where
+    V: Into<String>,
+{
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    fn statements(&self) -> Result<&Statements, Err> {
+        self.closed.check()?;
+        Ok(&self.statements)
+    }
+
+    fn lookup(&self, key: &str) -> Result<Option<String>, Err> {
+        let result = self.runtime.block_on(
+            self.session
+                .execute_unpaged(&self.statements()?.get, (key,)),
+        )?;
+        let row = result
+            .into_rows_result()?
+            .maybe_first_row::<(Option<String>,)>()?;
+        Ok(row.and_then(|(value,)| value))
+    }
+
+    fn lookup_ttl(&self, key: &str) -> Result<Option<(String, Option<i32>)>, Err> {
+        let result = self.runtime.block_on(
+            self.session
+                .execute_unpaged(&self.statements()?.ttl, (key,)),
+        )?;
+        let row = result
+            .into_rows_result()?
+            .maybe_first_row::<(Option<String>, Option<i32>)>()?;
+        Ok(row.and_then(|(value, ttl)| value.map(|value| (value, ttl))))
+    }
+
+    fn insert(&self, key: &str, value: &str, ttl: i32) -> Result<(), Err> {
+        self.runtime.block_on(
+            self.session
+                .execute_unpaged(&self.statements()?.insert, (key, value, ttl)),
+        )?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<(), Err> {
+        self.runtime.block_on(
+            self.session
+                .execute_unpaged(&self.statements()?.delete, (key,)),
+        )?;
+        Ok(())
+    }
+
+    // Sets `value` if the current one is `expected`, `None` matching a missing row.
+    fn update_if(
+        &self,
+        key: &str,
+        expected: Option<&str>,
+        value: &str,
+        ttl: i32,
+    ) -> Result<bool, Err> {
+        let statements = self.statements()?;
+        let result = match expected {
+            Some(expected) => self.runtime.block_on(
+                self.session
+                    .execute_unpaged(&statements.update_if, (ttl, value, key, expected)),
+            ),
+            None => self.runtime.block_on(
+                self.session
+                    .execute_unpaged(&statements.update_if_null, (ttl, value, key)),
+            ),
+        }?;
+        applied(result)
+    }
+
+    fn page(&self, state: PagingState) -> Result<(Vec<Entry>, Option<PagingState>), Err> {
+        let (result, response) = self.runtime.block_on(self.session.execute_single_page(
+            &self.statements()?.all,
+            (),
+            state,
+        ))?;
+        let rows = result
+            .into_rows_result()?
+            .rows::<(String, Option<String>, Option<i32>)>()?
+            .filter_map(|row| match row {
+                Ok((key, Some(value), ttl)) => Some(Ok((key, value, ttl))),
+                Ok((_, None, _)) => None,
+                Err(e) => Some(Err(Err::from(e))),
+            })
+            .collect::<Result<Vec<Entry>, Err>>()?;
+        let next = match response.into_paging_control_flow() {
+            ControlFlow::Continue(state) => Some(state),
+            ControlFlow::Break(()) => None,
+        };
+        Ok((rows, next))
+    }
+
+    // Every live row in token order, not key order, a page at a time. Rows written during
+    // the read may or may not be yielded.
+    fn entries(&self) -> Result<impl Iterator<Item = Result<Entry, Err>> + '_, Err> {
+        self.closed.check()?;
+        let mut buffer = VecDeque::new();
+        let mut next = Some(PagingState::start());
+        Ok(std::iter::from_fn(move || loop {
+            if let Some(entry) = buffer.pop_front() {
+                return Some(Ok(entry));
+            }
+            match self.page(next.take()?) {
+                Ok((rows, state)) => {
+                    buffer.extend(rows);
+                    next = state;
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }))
+    }
+}
+
+impl<K, V> Storage<K, V> for ScyllaStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        let value: String = value.into();
+        self.insert(key.as_ref(), &value, 0)?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        let value: String = value.into();
+        self.insert(key.as_ref(), &value, ttl_secs(expire))?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        let value = self.lookup(key.as_ref())?.map(V::from);
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    // A read for the return value, then the delete.
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        let found = self.lookup(key.as_ref())?.is_some();
+        self.remove(key.as_ref())?;
+        self.hooks.del(&key);
+        Ok(found.then_some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        Ok(self.lookup(key.as_ref())?.is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+}
+
+impl<K, V> ExportStorage<K, V> for ScyllaStorage<K, V>
+where
+    K: AsRef<str> + From<String>,
+    V: Into<String> + From<String>,
+{
+    fn export_iter(&self) -> Result<ExportIter<'_, K, V>, Err> {
+        Ok(Box::new(
+            self.export_entries()?
+                .map(|entry| entry.map(|entry| (entry.key, entry.value))),
+        ))
+    }
+
+    fn export_entries<'a>(&'a self) -> Result<EntryIter<'a, K, V>, Err>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        Ok(Box::new(self.entries()?.map(|entry| {
+            entry.map(|(key, value, ttl)| ExportEntry {
+                key: K::from(key),
+                value: V::from(value),
+                ttl: ttl.map(|secs| Duration::from_secs(secs.max(0) as u64)),
+            })
+        })))
+    }
+}
+
+// Lightweight transactions on the value, so expired rows never match. Writes clear the TTL,
+// as `set` does.
+impl<K, V> AtomicStorage<K, V> for ScyllaStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String> + PartialEq,
+{
+    fn compare_and_swap(&self, key: K, expected: Option<V>, new: Option<V>) -> Result<bool, Err> {
+        let key = key.as_ref();
+        let expected: Option<String> = expected.map(Into::into);
+        match new.map(Into::into) {
+            Some(new) => self.update_if(key, expected.as_deref(), &new, 0),
+            None => match expected {
+                None => Ok(self.lookup(key)?.is_none()),
+                Some(expected) => {
+                    let result = self.runtime.block_on(
+                        self.session
+                            .execute_unpaged(&self.statements()?.delete_if, (key, expected)),
+                    )?;
+                    applied(result)
+                }
+            },
+        }
+    }
+}
+
+impl<K, V> GetOrInsertStorage<K, V> for ScyllaStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set_nx(&self, key: K, value: V, expire: Option<Duration>) -> Result<bool, Err> {
+        let value: String = value.into();
+        self.update_if(key.as_ref(), None, &value, expire.map_or(0, ttl_secs))
+    }
+}
+
+impl<K, V> AdminStorage for ScyllaStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn clear(&self) -> Result<(), Err> {
+        self.runtime.block_on(
+            self.session
+                .execute_unpaged(&self.statements()?.truncate, ()),
+        )?;
+        Ok(())
+    }
+
+    // A full-table count on the server, which can time out on large tables.
+    fn len(&self) -> Result<usize, Err> {
+        let result = self
+            .runtime
+            .block_on(self.session.execute_unpaged(&self.statements()?.count, ()))?;
+        let (count,) = result.into_rows_result()?.single_row::<(i64,)>()?;
+        Ok(count as usize)
+    }
+}
+
+// `expire` and `persist` rewrite the value with the new TTL, conditional on it being
+// unchanged, and retry when a concurrent write got there first.
+impl<K, V> ExpiryStorage<K> for ScyllaStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String>,
+{
+    fn ttl(&self, key: K) -> Result<Option<Duration>, Err> {
+        Ok(self
+            .lookup_ttl(key.as_ref())?
+            .and_then(|(_, ttl)| ttl)
+            .map(|secs| Duration::from_secs(secs.max(0) as u64)))
+    }
+
+    fn expire(&self, key: K, expire: Duration) -> Result<bool, Err> {
+        let key = key.as_ref();
+        loop {
+            let Some(value) = self.lookup(key)? else {
+                return Ok(false);
+            };
+            if self.update_if(key, Some(&value), &value, ttl_secs(expire))? {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn persist(&self, key: K) -> Result<bool, Err> {
+        let key = key.as_ref();
+        loop {
+            let Some((value, Some(_))) = self.lookup_ttl(key)? else {
+                return Ok(false);
+            };
+            if self.update_if(key, Some(&value), &value, 0)? {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+impl<K, V> ScanStorage<K> for ScyllaStorage<K, V>
+where
+    K: From<String>,
+    V: Into<String>,
+{
+    // Partition keys are hashed, so this pages through the whole table without a prefix
+    // index and yields keys in token order.
+    fn scan_prefix(&self, prefix: &str) -> Result<KeyIter<'_, K>, Err> {
+        let prefix = prefix.to_string();
+        Ok(Box::new(self.entries()?.filter_map(
+            move |entry| match entry {
+                Ok((key, _, _)) if key.starts_with(&prefix) => Some(Ok(K::from(key))),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            },
+        )))
+    }
+}
+
+impl<K, V> FlushNamespace for ScyllaStorage<K, V>
+where
+    V: Into<String>,
+{
+    // A delete per key found by a full-table read.
+    fn flush_namespace(&self, prefix: &str, confirm: &FlushConfirmation) -> Result<usize, Err> {
+        self.closed.check()?;
+        confirm.check(prefix)?;
+        let mut removed = 0;
+        for entry in self.entries()? {
+            let (key, _, _) = entry?;
+            if key.starts_with(prefix) {
+                self.remove(&key)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+// The `[applied]` column a lightweight transaction answers with.
+fn applied(result: QueryResult) -> Result<bool, Err> {
+    let row = result.into_rows_result()?.maybe_first_row::<Row>()?;
+    let column = row.and_then(|row| row.columns.into_iter().next().flatten());
+    Ok(matches!(column, Some(CqlValue::Boolean(true))))
+}
+
+// Whole seconds rounded up, at least 1 since a TTL of 0 means none.
+fn ttl_secs(expire: Duration) -> i32 {
+    let secs = expire
+        .as_secs()
+        .saturating_add(u64::from(expire.subsec_nanos() > 0));
+    secs.clamp(1, MAX_TTL) as i32
+}
+
+// Unquoted CQL names: a letter, then letters, digits and `_`, at most 48 characters.
+fn is_cql_name(name: &str) -> bool {
+    name.len() <= 48
+        && name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ScyllaStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    nodes: Vec<String>,
+    keyspace: String,
+    table: String,
+    replication_factor: u32,
+    user: Option<(String, String)>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::config::secs::option"))]
+    connect_timeout: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> ScyllaStorageBuilder<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    pub fn new() -> Self {
+        ScyllaStorageBuilder::default()
+    }
+
+    // Reads `SCYLLA_NODES`, comma separated, plus optional `SCYLLA_KEYSPACE` and
+    // `SCYLLA_TABLE`.
+    pub fn from_env() -> Result<Self, BuilderError> {
+        let mut builder = ScyllaStorageBuilder::new();
+        for node in env::required("SCYLLA_NODES")?.split(',') {
+            builder = builder.node(node.trim());
+        }
+        if let Some(keyspace) = env::var("SCYLLA_KEYSPACE")? {
+            builder = builder.keyspace(&keyspace);
+        }
+        if let Some(table) = env::var("SCYLLA_TABLE")? {
+            builder = builder.table(&table);
+        }
+        Ok(builder)
+    }
+
+    // Adds a contact point, `localhost:9042`. The driver discovers the rest of the cluster
+    // from it.
+    pub fn node(mut self, node: &str) -> Self {
+        self.nodes.push(node.to_string());
+        self
+    }
+
+    // Defaults to `storage_trait`, created with `replication_factor` if it doesn't exist.
+    pub fn keyspace(mut self, keyspace: &str) -> Self {
+        self.keyspace = keyspace.to_string();
+        self
+    }
+
+    // Defaults to `kv`, created if it doesn't exist.
+    pub fn table(mut self, table: &str) -> Self {
+        self.table = table.to_string();
+        self
+    }
+
+    // Only used when the keyspace is created, defaults to 1.
+    pub fn replication_factor(mut self, factor: u32) -> Self {
+        self.replication_factor = factor;
+        self
+    }
+
+    // Authenticates as this user on connect.
+    pub fn user(mut self, name: &str, password: &str) -> Self {
+        self.user = Some((name.to_string(), password.to_string()));
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their TTL and batch variants.
+    // Clones of the storage share them, writes from other clients don't fire them.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if no node is given, a name is invalid or the cluster cannot be reached. Use
+    // `try_build` to get the error instead.
+    pub fn build(self) -> ScyllaStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build scylla storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<ScyllaStorage<K, V>, BuilderError> {
+        if self.nodes.is_empty() {
+            return Err(BuilderError::MissingField("nodes"));
+        }
+        if self.nodes.iter().any(String::is_empty) {
+            return Err(BuilderError::invalid("nodes", "must not be empty"));
+        }
+        for (field, name) in [("keyspace", &self.keyspace), ("table", &self.table)] {
+            if !is_cql_name(name) {
+                return Err(BuilderError::invalid(
+                    field,
+                    "must start with a letter and only contain letters, digits and _, at most 48",
+                ));
+            }
+        }
+        if self.replication_factor == 0 {
+            return Err(BuilderError::invalid(
+                "replication_factor",
+                "must be at least 1",
+            ));
+        }
+        let mut builder = SessionBuilder::new().known_nodes(&self.nodes);
+        if let Some((name, password)) = &self.user {
+            builder = builder.user(name, password);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connection_timeout(timeout);
+        }
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(BuilderError::backend)?;
+        let session = runtime
+            .block_on(builder.build())
+            .map_err(BuilderError::unreachable)?;
+        let table = format!("{}.{}", self.keyspace, self.table);
+        let statements = runtime
+            .block_on(async {
+                session
+                    .query_unpaged(
+                        format!(
+                            "CREATE KEYSPACE IF NOT EXISTS {} WITH replication = \
+                             {{'class': 'NetworkTopologyStrategy', 'replication_factor': {}}}",
+                            self.keyspace, self.replication_factor
+                        ),
+                        (),
+                    )
+                    .await?;
+                session
+                    .query_unpaged(
+                        format!(
+                            "CREATE TABLE IF NOT EXISTS {} (key text PRIMARY KEY, value text)",
+                            table
+                        ),
+                        (),
+                    )
+                    .await?;
+                session.await_schema_agreement().await?;
+                Statements::prepare(&session, &table).await
+            })
+            .map_err(BuilderError::Backend)?;
+
+        Ok(ScyllaStorage {
+            runtime: Arc::new(runtime),
+            session: Arc::new(session),
+            statements: Arc::new(statements),
+            closed: CloseFlag::default(),
+            hooks: self.hooks,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<K, V> Default for ScyllaStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            keyspace: "storage_trait".to_string(),
+            table: "kv".to_string(),
+            replication_factor: 1,
+            user: None,
+            connect_timeout: None,
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+// No conformance tests, their 200ms TTLs are below the server's one-second resolution.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get() {
+        let storage = build_localhost::<&str, String>("get");
+        let (key, value) = ("name", "Ferris".to_string());
+        storage.set(key, value.clone()).unwrap();
+        assert_eq!(storage.get(key).unwrap(), Some(value));
+        assert!(storage.contains(key).unwrap());
+        assert_eq!(storage.del(key).unwrap(), Some(key));
+        assert_eq!(storage.get(key).unwrap(), None);
+        assert_eq!(storage.del(key).unwrap(), None);
+    }
+
+    #[test]
+    fn test_set_ex() {
+        let storage = build_localhost::<&str, String>("set_ex");
+        storage
+            .set_ex("a", "ok!".to_string(), Duration::from_millis(1500))
+            .unwrap();
+        assert_eq!(storage.get("a").unwrap(), Some("ok!".to_string()));
+        assert!(storage.ttl("a").unwrap().unwrap() <= Duration::from_secs(2));
+        std::thread::sleep(Duration::from_secs(3));
+        assert!(!storage.contains("a").unwrap());
+
+        storage.set("c", "ok!".to_string()).unwrap();
+        assert!(!storage.persist("c").unwrap());
+        assert!(storage.expire("c", Duration::from_secs(60)).unwrap());
+        assert!(storage.ttl("c").unwrap().unwrap() > Duration::from_secs(58));
+        assert_eq!(storage.get("c").unwrap(), Some("ok!".to_string()));
+        assert!(storage.persist("c").unwrap());
+        assert_eq!(storage.ttl("c").unwrap(), None);
+        assert!(!storage.expire("missing", Duration::from_secs(60)).unwrap());
+    }
+
+    #[test]
+    fn test_scan_pages() {
+        let storage = build_localhost::<String, String>("scan_pages");
+        for n in 0..PAGE + 10 {
+            storage
+                .set(format!("tenant:1:{:04}", n), n.to_string())
+                .unwrap();
+        }
+        storage.set("tenant:2:a".into(), "x".into()).unwrap();
+        storage.set("tenant:1".into(), "x".into()).unwrap();
+
+        let mut keys: Vec<String> = storage
+            .scan_prefix("tenant:1:")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        keys.sort();
+        assert_eq!(keys.len(), PAGE as usize + 10);
+        assert_eq!(keys[0], "tenant:1:0000");
+        assert_eq!(storage.export_iter().unwrap().count(), PAGE as usize + 12);
+
+        let confirm = FlushConfirmation::for_prefix("tenant:1:");
+        assert_eq!(
+            storage.flush_namespace("tenant:1:", &confirm).unwrap(),
+            PAGE as usize + 10
+        );
+        assert_eq!(storage.len().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let storage = build_localhost::<&str, String>("compare_and_swap");
+        assert!(storage.compare_and_swap("cas", None, None).unwrap());
+        assert!(storage
+            .compare_and_swap("cas", None, Some("1".to_string()))
+            .unwrap());
+        assert!(!storage
+            .compare_and_swap("cas", None, Some("2".to_string()))
+            .unwrap());
+        let seen = storage
+            .fetch_update("cas", |current| {
+                current.map(|n| (n.parse::<u32>().unwrap() + 1).to_string())
+            })
+            .unwrap();
+        assert_eq!(seen, Some("1".to_string()));
+        assert_eq!(storage.get("cas").unwrap(), Some("2".to_string()));
+        assert!(storage
+            .compare_and_swap("cas", Some("2".to_string()), None)
+            .unwrap());
+        assert!(!storage.contains("cas").unwrap());
+
+        assert_eq!(storage.swap("a", "1".to_string()).unwrap(), None);
+        assert_eq!(storage.take("a").unwrap(), Some("1".to_string()));
+        assert_eq!(storage.take("a").unwrap(), None);
+
+        assert!(storage.set_nx("b", "1".to_string(), None).unwrap());
+        assert!(!storage.set_nx("b", "2".to_string(), None).unwrap());
+        // A row whose value expired under `expire` counts as missing.
+        assert!(storage.expire("b", Duration::from_secs(1)).unwrap());
+        std::thread::sleep(Duration::from_secs(2));
+        assert!(storage.set_nx("b", "3".to_string(), None).unwrap());
+        assert_eq!(storage.get("b").unwrap(), Some("3".to_string()));
+    }
+
+    #[test]
+    fn test_ttl_secs() {
+        assert_eq!(ttl_secs(Duration::ZERO), 1);
+        assert_eq!(ttl_secs(Duration::from_millis(200)), 1);
+        assert_eq!(ttl_secs(Duration::from_millis(1001)), 2);
+        assert_eq!(ttl_secs(Duration::from_secs(60)), 60);
+        assert_eq!(ttl_secs(Duration::MAX), MAX_TTL as i32);
+        assert!(is_cql_name("storage_trait"));
+        assert!(!is_cql_name("1kv"));
+        assert!(!is_cql_name("kv; DROP TABLE kv"));
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = ScyllaStorageBuilder::<&str, String>::new().try_build();
+        assert!(matches!(resp, Err(BuilderError::MissingField("nodes"))));
+        let resp = ScyllaStorageBuilder::<&str, String>::new()
+            .node("")
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(e)) if e.field == "nodes"));
+        let resp = ScyllaStorageBuilder::<&str, String>::new()
+            .node("localhost:9042")
+            .keyspace("my-keyspace")
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(e)) if e.field == "keyspace"));
+        let resp = ScyllaStorageBuilder::<&str, String>::new()
+            .node("localhost:9042")
+            .table("")
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(e)) if e.field == "table"));
+        let resp = ScyllaStorageBuilder::<&str, String>::new()
+            .node("localhost:9042")
+            .replication_factor(0)
+            .try_build();
+        assert!(
+            matches!(resp, Err(BuilderError::InvalidConfig(e)) if e.field == "replication_factor")
+        );
+    }
+
+    // A table per test, emptied first, so tests can run in parallel.
+    fn build_localhost<K: AsRef<str>, V: Into<String> + From<String>>(
+        table: &str,
+    ) -> ScyllaStorage<K, V> {
+        let storage = ScyllaStorageBuilder::<K, V>::new()
+            .node("127.0.0.1:9042")
+            .table(&format!("kv_{}", table))
+            .connect_timeout(Duration::from_secs(2))
+            .build();
+        storage.clear().unwrap();
+        storage
+    }
+}
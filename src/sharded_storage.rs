@@ -0,0 +1,434 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::error::BuilderError;
+use crate::health::HealthStatus;
+use crate::options::OpOptions;
+use crate::storage::{CloseFlag, Err, Storage};
+
+pub type Shard<K, V> = Arc<dyn Storage<K, V> + Send + Sync>;
+// Items routed to one shard, with their position in the input.
+type Group<K, V, T> = (Shard<K, V>, Vec<(usize, T)>);
+
+// 64-bit FNV-1a with the murmur3 finalizer, so similar keys still land far apart on the
+// ring. Unlike `RandomState` it hashes the same in every process, which every client of the
+// same shards needs to agree on where a key lives.
+#[derive(Debug, Clone, Copy)]
+pub struct Fnv1aHasher(u64);
+
+impl Default for Fnv1aHasher {
+    fn default() -> Self {
+        Fnv1aHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1aHasher {
+    fn finish(&self) -> u64 {
+        let mut hash = self.0;
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        hash ^ (hash >> 33)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+pub type Fnv1a = BuildHasherDefault<Fnv1aHasher>;
+
+struct Ring<K, V> {
+    shards: BTreeMap<String, Shard<K, V>>,
+    points: BTreeMap<u64, String>,
+}
+
+// Spreads keys over named shards with a consistent hash ring, each shard owns `vnodes`
+// points on it. Adding or removing a shard only moves the keys between it and its ring
+// neighbours, moving the data itself is left to the caller.
+pub struct ShardedStorage<K, V, H = Fnv1a> {
+    ring: RwLock<Ring<K, V>>,
+    vnodes: usize,
+    hasher: H,
+    closed: CloseFlag,
+}
+
+impl<K, V, H> ShardedStorage<K, V, H>
+where
+    K: Hash,
+    H: BuildHasher,
+{
+    // Replaces and returns a shard already registered under `name`.
+    pub fn add_shard<S>(&self, name: &str, shard: S) -> Option<Shard<K, V>>
+    where
+        S: Storage<K, V> + Send + Sync + 'static,
+    {
+        let mut ring = self.ring.write().unwrap();
+        for point in self.points(name) {
+            ring.points.insert(point, name.to_string());
+        }
+        ring.shards.insert(name.to_string(), Arc::new(shard))
+    }
+
+    pub fn remove_shard(&self, name: &str) -> Option<Shard<K, V>> {
+        let mut ring = self.ring.write().unwrap();
+        let shard = ring.shards.remove(name)?;
+        ring.points.retain(|_, owner| owner != name);
+        Some(shard)
+    }
+
+    pub fn shard_names(&self) -> Vec<String> {
+        self.ring.read().unwrap().shards.keys().cloned().collect()
+    }
+
+    // The name of the shard `key` lives on, `None` when there are no shards.
+    pub fn shard_name(&self, key: &K) -> Option<String> {
+        let ring = self.ring.read().unwrap();
+        self.owner(&ring, key).map(|(name, _)| name.to_string())
+    }
+
+    fn points(&self, name: &str) -> Vec<u64> {
+        (0..self.vnodes)
+            .map(|vnode| self.hasher.hash_one((name, vnode)))
+            .collect()
+    }
+
+    fn owner<'a>(&self, ring: &'a Ring<K, V>, key: &K) -> Option<(&'a str, &'a Shard<K, V>)> {
+        let hash = self.hasher.hash_one(key);
+        let (_, name) = ring
+            .points
+            .range(hash..)
+            .next()
+            .or_else(|| ring.points.iter().next())?;
+        Some((name, &ring.shards[name]))
+    }
+
+    fn shard(&self, key: &K) -> Result<Shard<K, V>, Err> {
+        self.closed.check()?;
+        let ring = self.ring.read().unwrap();
+        match self.owner(&ring, key) {
+            Some((_, shard)) => Ok(shard.clone()),
+            None => Err("ShardedStorage has no shards".into()),
+        }
+    }
+
+    fn partition<T, F>(&self, items: Vec<T>, key: F) -> Result<Vec<Group<K, V, T>>, Err>
+    where
+        F: Fn(&T) -> &K,
+    {
+        self.closed.check()?;
+        let ring = self.ring.read().unwrap();
+        let mut groups: HashMap<&str, Group<K, V, T>> = HashMap::new();
+        for (i, item) in items.into_iter().enumerate() {
+            let (name, shard) = self
+                .owner(&ring, key(&item))
+                .ok_or("ShardedStorage has no shards")?;
+            groups
+                .entry(name)
+                .or_insert_with(|| (shard.clone(), Vec::new()))
+                .1
+                .push((i, item));
+        }
+        Ok(groups.into_values().collect())
+    }
+}
+
+impl<K, V, H> Storage<K, V> for ShardedStorage<K, V, H>
+where
+    K: Hash,
+    H: BuildHasher,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.shard(&key)?.set(key, value)
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.shard(&key)?.set_ex(key, value, expire)
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.shard(&key)?.get(key)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.shard(&key)?.del(key)
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.shard(&key)?.contains(key)
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        if !self.closed.close() {
+            return Ok(());
+        }
+        let ring = self.ring.read().unwrap();
+        let mut result = Ok(());
+        for shard in ring.shards.values() {
+            let resp = shard.close();
+            if result.is_ok() {
+                result = resp;
+            }
+        }
+        result
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+
+    // Every shard owns part of the keys, so any failing shard fails the check.
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.closed.check()?;
+        let shards: Vec<(String, Shard<K, V>)> = {
+            let ring = self.ring.read().unwrap();
+            ring.shards
+                .iter()
+                .map(|(name, shard)| (name.clone(), shard.clone()))
+                .collect()
+        };
+        if shards.is_empty() {
+            return Err("ShardedStorage has no shards".into());
+        }
+        let mut status = HealthStatus::default();
+        for (name, shard) in shards {
+            let name = format!("shard {}", name);
+            let part = shard
+                .health_check()
+                .map_err(|e| format!("{}: {}", name, e))?;
+            status.absorb(&name, part);
+        }
+        Ok(status)
+    }
+
+    // One `get_many` per shard.
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        let len = keys.len();
+        let mut values: Vec<Option<V>> = (0..len).map(|_| None).collect();
+        for (shard, keys) in self.partition(keys, |key| key)? {
+            let (positions, keys): (Vec<usize>, Vec<K>) = keys.into_iter().unzip();
+            for (i, value) in positions.into_iter().zip(shard.get_many(keys)?) {
+                values[i] = value;
+            }
+        }
+        Ok(values)
+    }
+
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        for (shard, entries) in self.partition(entries, |(key, _)| key)? {
+            shard.set_many(entries.into_iter().map(|(_, entry)| entry).collect())?;
+        }
+        Ok(())
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        let len = keys.len();
+        let mut removed: Vec<Option<K>> = (0..len).map(|_| None).collect();
+        for (shard, keys) in self.partition(keys, |key| key)? {
+            let (positions, keys): (Vec<usize>, Vec<K>) = keys.into_iter().unzip();
+            for (i, key) in positions.into_iter().zip(shard.del_many(keys)?) {
+                removed[i] = key;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+        self.shard(&key)?.set_with_opts(key, value, opts)
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        self.shard(&key)?.get_with_opts(key, opts)
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        self.shard(&key)?.del_with_opts(key, opts)
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        self.shard(&key)?.contains_with_opts(key, opts)
+    }
+}
+
+pub struct ShardedStorageBuilder<K, V, H = Fnv1a> {
+    shards: Vec<(String, Shard<K, V>)>,
+    vnodes: usize,
+    hasher: H,
+}
+
+impl<K, V> ShardedStorageBuilder<K, V> {
+    pub fn new() -> Self {
+        ShardedStorageBuilder::default()
+    }
+}
+
+impl<K, V, H> ShardedStorageBuilder<K, V, H>
+where
+    K: Hash,
+    H: BuildHasher,
+{
+    // The name places the shard on the ring, keep it stable across restarts and clients.
+    pub fn shard<S>(mut self, name: &str, shard: S) -> Self
+    where
+        S: Storage<K, V> + Send + Sync + 'static,
+    {
+        self.shards.push((name.to_string(), Arc::new(shard)));
+        self
+    }
+
+    // Points per shard on the ring, more points spread keys more evenly. Defaults to 160.
+    pub fn vnodes(mut self, vnodes: usize) -> Self {
+        self.vnodes = vnodes;
+        self
+    }
+
+    // Must hash the same in every process sharing the shards, the default is FNV-1a.
+    pub fn hasher<H2: BuildHasher>(self, hasher: H2) -> ShardedStorageBuilder<K, V, H2> {
+        ShardedStorageBuilder {
+            shards: self.shards,
+            vnodes: self.vnodes,
+            hasher,
+        }
+    }
+
+    // # Panics
+    // Panics if no shard was added, two shards share a name, or `vnodes` is zero.
+    pub fn build(self) -> ShardedStorage<K, V, H> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build sharded storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<ShardedStorage<K, V, H>, BuilderError> {
+        if self.shards.is_empty() {
+            return Err(BuilderError::MissingField("shard"));
+        }
+        if self.vnodes == 0 {
+            return Err(BuilderError::invalid("vnodes", "must be at least 1"));
+        }
+
+        let storage = ShardedStorage {
+            ring: RwLock::new(Ring {
+                shards: BTreeMap::new(),
+                points: BTreeMap::new(),
+            }),
+            vnodes: self.vnodes,
+            hasher: self.hasher,
+            closed: CloseFlag::default(),
+        };
+        for (name, shard) in self.shards {
+            let mut ring = storage.ring.write().unwrap();
+            if ring.shards.contains_key(&name) {
+                return Err(BuilderError::invalid(
+                    "shard",
+                    format!("`{}` was added twice", name),
+                ));
+            }
+            for point in storage.points(&name) {
+                ring.points.insert(point, name.clone());
+            }
+            ring.shards.insert(name, shard);
+        }
+        Ok(storage)
+    }
+}
+
+impl<K, V> Default for ShardedStorageBuilder<K, V> {
+    fn default() -> Self {
+        Self {
+            shards: Vec::new(),
+            vnodes: 160,
+            hasher: Fnv1a::default(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::{DashMapStorage, DashMapStorageBuilder};
+
+    type Map = Arc<DashMapStorage<String, u32>>;
+
+    fn shards(n: usize) -> Vec<Map> {
+        (0..n)
+            .map(|_| Arc::new(DashMapStorageBuilder::new().build()))
+            .collect()
+    }
+
+    fn keys() -> Vec<String> {
+        (0..1000).map(|i| format!("user:{}", i)).collect()
+    }
+
+    #[test]
+    fn test_distribution() {
+        let maps = shards(3);
+        let storage = maps
+            .iter()
+            .enumerate()
+            .fold(ShardedStorageBuilder::new(), |builder, (i, map)| {
+                builder.shard(&format!("redis-{}", i), map.clone())
+            })
+            .build();
+
+        for (i, key) in keys().into_iter().enumerate() {
+            storage.set(key, i as u32).unwrap();
+        }
+        for map in &maps {
+            let len = map.shared().len();
+            assert!(len > 200 && len < 470, "unbalanced shard: {}", len);
+        }
+        assert_eq!(storage.get("user:7".to_string()).unwrap(), Some(7));
+        assert_eq!(
+            storage
+                .get_many(vec!["user:1".to_string(), "missing".to_string()])
+                .unwrap(),
+            [Some(1), None]
+        );
+    }
+
+    #[test]
+    fn test_add_remove_shard() {
+        let maps = shards(4);
+        let storage = ShardedStorageBuilder::new()
+            .shard("a", maps[0].clone())
+            .shard("b", maps[1].clone())
+            .shard("c", maps[2].clone())
+            .build();
+        let before: Vec<_> = keys().iter().map(|k| storage.shard_name(k)).collect();
+
+        storage.add_shard("d", maps[3].clone());
+        let after: Vec<_> = keys().iter().map(|k| storage.shard_name(k)).collect();
+        let moved = before.iter().zip(&after).filter(|(b, a)| b != a).count();
+        assert!(after
+            .iter()
+            .zip(&before)
+            .all(|(a, b)| a == b || a.as_deref() == Some("d")));
+        assert!(moved > 100 && moved < 400, "moved {} keys", moved);
+
+        assert!(storage.remove_shard("d").is_some());
+        let restored: Vec<_> = keys().iter().map(|k| storage.shard_name(k)).collect();
+        assert_eq!(restored, before);
+        assert_eq!(storage.shard_names(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = ShardedStorageBuilder::<String, u32>::new().try_build();
+        assert!(matches!(resp, Err(BuilderError::MissingField("shard"))));
+
+        let maps = shards(2);
+        let resp = ShardedStorageBuilder::new()
+            .shard("a", maps[0].clone())
+            .shard("a", maps[1].clone())
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(_))));
+    }
+}
@@ -0,0 +1,836 @@
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::admin::AdminStorage;
+use crate::atomic::AtomicStorage;
+use crate::durability::{Durability, SyncState};
+use crate::error::BuilderError;
+use crate::expiry::ExpiryStorage;
+use crate::export::{EntryIter, ExportEntry, ExportIter, ExportStorage};
+use crate::flush::{FlushConfirmation, FlushNamespace};
+use crate::gc::GarbageCollect;
+use crate::get_or_insert::GetOrInsertStorage;
+use crate::hooks::{Hooks, StorageHook};
+use crate::scan::{KeyIter, ScanStorage};
+use crate::storage::{CloseFlag, Err, Storage};
+use crate::versioned::{Version, VersionedStorage};
+
+#[derive(Clone)]
+pub struct SledStorage<K, V>
+where
+    V: Into<String>,
+{
+    db: sled::Db,
+    // Flushes after writes for `Batch` and `EveryWrite`, sled's flusher thread covers the rest.
+    sync: Option<Arc<Mutex<SyncState>>>,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> SledStorage<K, V>
+where
+    V: Into<String>,
+{
+    // Reuses a database the application already opened, e.g. to share it across trees.
+    pub fn from_db(db: sled::Db) -> Self {
+        SledStorage {
+            db,
+            sync: None,
+            closed: CloseFlag::default(),
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn db(&self) -> &sled::Db {
+        &self.db
+    }
+
+    fn lookup(&self, key: &str) -> Result<Option<String>, Err> {
+        match self.db.get(key)? {
+            Some(record) => Ok(decode(&record)?.map(|(value, _)| value)),
+            None => Ok(None),
+        }
+    }
+
+    // Called after every write, flushes when the durability asks for it.
+    fn wrote(&self) -> Result<(), Err> {
+        if let Some(sync) = &self.sync {
+            if sync.lock().unwrap().record_write() {
+                self.db.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> Storage<K, V> for SledStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        self.db.insert(key.as_ref(), encode(value.as_str(), 0))?;
+        self.wrote()?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        let expires_at = now_millis() + expire.as_millis() as u64;
+        self.db
+            .insert(key.as_ref(), encode(value.as_str(), expires_at))?;
+        self.wrote()?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let value = self.lookup(key.as_ref())?.map(V::from);
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let found = match self.db.remove(key.as_ref())? {
+            Some(record) => decode(&record)?.is_some(),
+            None => false,
+        };
+        self.wrote()?;
+        self.hooks.del(&key);
+        Ok(found.then_some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        Ok(self.lookup(key.as_ref())?.is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        if self.closed.close() {
+            self.db.flush()?;
+        }
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+
+    // One `sled::Batch`, applied atomically.
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.closed.check()?;
+        let entries: Vec<(K, String)> = entries
+            .into_iter()
+            .map(|(key, value)| (key, value.into()))
+            .collect();
+        let mut batch = sled::Batch::default();
+        for (key, value) in &entries {
+            batch.insert(key.as_ref(), encode(value.as_str(), 0));
+        }
+        self.db.apply_batch(batch)?;
+        self.wrote()?;
+        for (key, value) in entries {
+            self.hooks.set_with(&key, || V::from(value));
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> ExportStorage<K, V> for SledStorage<K, V>
+where
+    K: AsRef<str> + From<String>,
+    V: Into<String> + From<String>,
+{
+    fn export_iter(&self) -> Result<ExportIter<'_, K, V>, Err> {
+        Ok(Box::new(
+            self.export_entries()?
+                .map(|entry| entry.map(|entry| (entry.key, entry.value))),
+        ))
+    }
+
+    // Follows the tree in key order, expired entries are skipped.
+    fn export_entries<'a>(&'a self) -> Result<EntryIter<'a, K, V>, Err>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.closed.check()?;
+        Ok(Box::new(self.db.iter().filter_map(|entry| {
+            let entry = entry.map_err(Err::from).and_then(|(key, record)| {
+                Ok(decode(&record)?.map(|(value, ttl)| (key, value, ttl)))
+            });
+            match entry {
+                Ok(Some((key, value, ttl))) => Some(match String::from_utf8(key.to_vec()) {
+                    Ok(key) => Ok(ExportEntry {
+                        key: K::from(key),
+                        value: V::from(value),
+                        ttl,
+                    }),
+                    Err(e) => Err(e.into()),
+                }),
+                Ok(None) => None,
+                Err(e) => Some(Err(e)),
+            }
+        })))
+    }
+}
+
+impl<K, V> GarbageCollect for SledStorage<K, V>
+where
+    V: Into<String>,
+{
+    // Removed with compare-and-swap, so a concurrent `set` survives.
+    fn gc_now(&self, batch_size: usize) -> Result<usize, Err> {
+        self.closed.check()?;
+        let mut expired = Vec::new();
+        for entry in self.db.iter() {
+            if expired.len() >= batch_size {
+                break;
+            }
+            let (key, record) = entry?;
+            if decode(&record)?.is_none() {
+                expired.push((key, record));
+            }
+        }
+
+        let mut removed = 0;
+        for (key, record) in expired {
+            if self
+                .db
+                .compare_and_swap(key, Some(record), None as Option<&[u8]>)?
+                .is_ok()
+            {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+// Built on sled's own compare-and-swap of the stored record, so an expired record and a
+// concurrent writer are both handled by the tree.
+impl<K, V> AtomicStorage<K, V> for SledStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String> + PartialEq,
+{
+    fn compare_and_swap(&self, key: K, expected: Option<V>, new: Option<V>) -> Result<bool, Err> {
+        self.closed.check()?;
+        let record = self.db.get(key.as_ref())?;
+        let current = match &record {
+            Some(record) => decode(record)?.map(|(value, _)| value),
+            None => None,
+        };
+        if current != expected.map(Into::into) {
+            return Ok(false);
+        }
+        let new = new.map(|new| encode(new, 0));
+        let swapped = self.db.compare_and_swap(key.as_ref(), record, new)?.is_ok();
+        if swapped {
+            self.wrote()?;
+        }
+        Ok(swapped)
+    }
+
+    // sled's own insert and remove hand back the record they replaced.
+    fn swap(&self, key: K, value: V) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let old = self.db.insert(key.as_ref(), encode(value, 0))?;
+        self.wrote()?;
+        Ok(live(old)?.map(V::from))
+    }
+
+    fn take(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let old = self.db.remove(key.as_ref())?;
+        self.wrote()?;
+        Ok(live(old)?.map(V::from))
+    }
+}
+
+fn live(record: Option<sled::IVec>) -> Result<Option<String>, Err> {
+    match record {
+        Some(record) => Ok(decode(&record)?.map(|(value, _)| value)),
+        None => Ok(None),
+    }
+}
+
+// Versions hash the stored string. The write swaps against the record that was read, so an
+// expired record counts as missing and a concurrent writer makes it fail.
+impl<K, V> VersionedStorage<K, V> for SledStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn get_versioned(&self, key: K) -> Result<Option<(V, Version)>, Err> {
+        self.closed.check()?;
+        let Some(record) = self.db.get(key.as_ref())? else {
+            return Ok(None);
+        };
+        Ok(decode(&record)?.map(|(value, _)| {
+            let version = Version::of_bytes(value.as_bytes());
+            (V::from(value), version)
+        }))
+    }
+
+    fn set_if_version(&self, key: K, value: V, expected: Option<Version>) -> Result<bool, Err> {
+        self.closed.check()?;
+        let record = self.db.get(key.as_ref())?;
+        let current = match &record {
+            Some(record) => decode(record)?.map(|(value, _)| Version::of_bytes(value.as_bytes())),
+            None => None,
+        };
+        if current != expected {
+            return Ok(false);
+        }
+        let new = encode(value, 0);
+        let swapped = self
+            .db
+            .compare_and_swap(key.as_ref(), record, Some(new))?
+            .is_ok();
+        if swapped {
+            self.wrote()?;
+        }
+        Ok(swapped)
+    }
+}
+
+// Compare-and-swap against the record that was read, an expired one counts as missing.
+impl<K, V> GetOrInsertStorage<K, V> for SledStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set_nx(&self, key: K, value: V, expire: Option<Duration>) -> Result<bool, Err> {
+        self.closed.check()?;
+        let expires_at = expire.map_or(0, |expire| now_millis() + expire.as_millis() as u64);
+        let new = encode(value, expires_at);
+        loop {
+            let record = self.db.get(key.as_ref())?;
+            if let Some(record) = &record {
+                if decode(record)?.is_some() {
+                    return Ok(false);
+                }
+            }
+            if self
+                .db
+                .compare_and_swap(key.as_ref(), record, Some(new.clone()))?
+                .is_ok()
+            {
+                self.wrote()?;
+                return Ok(true);
+            }
+        }
+    }
+}
+
+// `len` walks the tree to skip expired records.
+impl<K, V> AdminStorage for SledStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn clear(&self) -> Result<(), Err> {
+        self.closed.check()?;
+        self.db.clear()?;
+        self.wrote()
+    }
+
+    fn len(&self) -> Result<usize, Err> {
+        self.closed.check()?;
+        let mut len = 0;
+        for record in self.db.iter().values() {
+            if decode(&record?)?.is_some() {
+                len += 1;
+            }
+        }
+        Ok(len)
+    }
+}
+
+// The expiry is rewritten in place with compare-and-swap, retried if the record changed.
+impl<K, V> ExpiryStorage<K> for SledStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String>,
+{
+    fn ttl(&self, key: K) -> Result<Option<Duration>, Err> {
+        self.closed.check()?;
+        match self.db.get(key.as_ref())? {
+            Some(record) => Ok(decode(&record)?.and_then(|(_, ttl)| ttl)),
+            None => Ok(None),
+        }
+    }
+
+    fn expire(&self, key: K, expire: Duration) -> Result<bool, Err> {
+        self.closed.check()?;
+        let expires_at = now_millis() + expire.as_millis() as u64;
+        self.retime(key.as_ref(), |_| Some(expires_at))
+    }
+
+    fn persist(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        self.retime(key.as_ref(), |ttl| ttl.map(|_| 0))
+    }
+}
+
+impl<K, V> SledStorage<K, V>
+where
+    V: Into<String>,
+{
+    // `expires_at` maps the live record's TTL to its new expiry, `None` leaves it alone.
+    fn retime<F>(&self, key: &str, expires_at: F) -> Result<bool, Err>
+    where
+        F: Fn(Option<Duration>) -> Option<u64>,
+    {
+        loop {
+            let Some(record) = self.db.get(key)? else {
+                return Ok(false);
+            };
+            let Some((_, ttl)) = decode(&record)? else {
+                return Ok(false);
+            };
+            let Some(expires_at) = expires_at(ttl) else {
+                return Ok(false);
+            };
+            let mut retimed = expires_at.to_be_bytes().to_vec();
+            retimed.extend_from_slice(&record[8..]);
+            if self
+                .db
+                .compare_and_swap(key, Some(record), Some(retimed))?
+                .is_ok()
+            {
+                self.wrote()?;
+                return Ok(true);
+            }
+        }
+    }
+}
+
+impl<K, V> ScanStorage<K> for SledStorage<K, V>
+where
+    K: From<String>,
+    V: Into<String>,
+{
+    // A lazy prefix scan in key order.
+    fn scan_prefix(&self, prefix: &str) -> Result<KeyIter<'_, K>, Err> {
+        self.closed.check()?;
+        Ok(Box::new(self.db.scan_prefix(prefix).filter_map(|entry| {
+            let key = entry
+                .map_err(Err::from)
+                .and_then(|(key, record)| match decode(&record)? {
+                    Some(_) => Ok(Some(String::from_utf8(key.to_vec())?)),
+                    None => Ok(None),
+                });
+            key.map(|key| key.map(K::from)).transpose()
+        })))
+    }
+}
+
+impl<K, V> FlushNamespace for SledStorage<K, V>
+where
+    V: Into<String>,
+{
+    // A prefix scan, removed in one batch.
+    fn flush_namespace(&self, prefix: &str, confirm: &FlushConfirmation) -> Result<usize, Err> {
+        self.closed.check()?;
+        confirm.check(prefix)?;
+        let mut batch = sled::Batch::default();
+        let mut removed = 0;
+        for key in self.db.scan_prefix(prefix).keys() {
+            batch.remove(key?);
+            removed += 1;
+        }
+        self.db.apply_batch(batch)?;
+        self.wrote()?;
+        Ok(removed)
+    }
+}
+
+// Records are the expiry in unix millis (0 for none) followed by the value, as in
+// `PersyStorage`.
+fn encode<V: Into<String>>(value: V, expires_at: u64) -> Vec<u8> {
+    let value: String = value.into();
+    let mut record = expires_at.to_be_bytes().to_vec();
+    record.extend_from_slice(value.as_bytes());
+    record
+}
+
+// Returns the value and its remaining TTL, `None` once expired.
+fn decode(record: &[u8]) -> Result<Option<(String, Option<Duration>)>, Err> {
+    if record.len() < 8 {
+        return Err("SledStorage record is too short, was it written by another program?".into());
+    }
+    let (expires_at, value) = record.split_at(8);
+    let expires_at = u64::from_be_bytes(expires_at.try_into()?);
+    let ttl = match expires_at {
+        0 => None,
+        expires_at => match expires_at.checked_sub(now_millis()) {
+            Some(left) if left > 0 => Some(Duration::from_millis(left)),
+            _ => return Ok(None),
+        },
+    };
+    Ok(Some((String::from_utf8(value.to_vec())?, ttl)))
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SledStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    path: Option<PathBuf>,
+    cache_capacity: Option<u64>,
+    durability: Option<Durability>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> SledStorageBuilder<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    pub fn new() -> Self {
+        SledStorageBuilder::default()
+    }
+
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    // Page cache size in bytes, sled defaults to 1 GiB.
+    pub fn cache_capacity(mut self, cache_capacity: u64) -> Self {
+        self.cache_capacity = Some(cache_capacity);
+        self
+    }
+
+    // `Interval` sets sled's background flush period, `Never` turns the flusher off, and
+    // `Batch` and `EveryWrite` flush from the writing call. Unset, sled flushes every 500ms.
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.durability = Some(durability);
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their TTL and batch variants.
+    // Clones of the storage share them.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if the path is missing, the cache capacity is zero, the durability is invalid,
+    // or the database cannot be opened.
+    pub fn build(self) -> SledStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build sled storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<SledStorage<K, V>, BuilderError> {
+        let path = self
+            .path
+            .clone()
+            .ok_or(BuilderError::MissingField("path"))?;
+
+        let mut config = sled::Config::new().path(path);
+        if let Some(cache_capacity) = self.cache_capacity {
+            if cache_capacity == 0 {
+                return Err(BuilderError::invalid("cache_capacity", "must not be 0"));
+            }
+            config = config.cache_capacity(cache_capacity);
+        }
+        let mut sync = None;
+        if let Some(durability) = self.durability {
+            durability.validate()?;
+            config = match durability {
+                Durability::Interval(interval) => {
+                    config.flush_every_ms(Some((interval.as_millis() as u64).max(1)))
+                }
+                Durability::Never => config.flush_every_ms(None),
+                Durability::Batch(_) | Durability::EveryWrite => {
+                    sync = Some(Arc::new(Mutex::new(SyncState::new(durability))));
+                    config
+                }
+            };
+        }
+
+        let db = config.open().map_err(BuilderError::backend)?;
+        Ok(SledStorage {
+            sync,
+            hooks: self.hooks,
+            ..SledStorage::from_db(db)
+        })
+    }
+}
+
+impl<K, V> Default for SledStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    fn default() -> Self {
+        Self {
+            path: None,
+            cache_capacity: None,
+            durability: None,
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::storage_conformance_tests!(
+        conformance,
+        SledStorage::<String, String>::from_db(sled::Config::new().temporary(true).open().unwrap())
+    );
+
+    #[test]
+    fn test_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SledStorageBuilder::new()
+            .path(dir.path().join("data.sled"))
+            .build();
+
+        let (key, value) = ("name", "Ferris".to_string());
+        let _ = storage.set(key, value.clone());
+        let resp = storage.get(key).unwrap();
+        assert_eq!(resp, Some(value));
+
+        assert_eq!(storage.del(key).unwrap(), Some(key));
+        let resp = storage.get(key).unwrap();
+        assert_eq!(resp, None);
+    }
+
+    #[test]
+    fn test_set_ex() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SledStorageBuilder::new()
+            .path(dir.path().join("data.sled"))
+            .build();
+
+        let (key, value) = ("set_ex_test", "ok!".to_string());
+        storage
+            .set_ex(key, value.clone(), Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(storage.get(key).unwrap(), Some(value));
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(!storage.contains(key).unwrap());
+        assert!(storage.is_empty().unwrap());
+        assert_eq!(storage.gc_now(10).unwrap(), 1);
+        assert!(storage.db().is_empty());
+    }
+
+    #[test]
+    fn test_expire() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SledStorageBuilder::<&str, String>::new()
+            .path(dir.path().join("data.sled"))
+            .build();
+
+        storage.set("a", "ok!".to_string()).unwrap();
+        assert_eq!(storage.ttl("a").unwrap(), None);
+        assert!(!storage.persist("a").unwrap());
+        assert!(storage.expire("a", Duration::from_secs(60)).unwrap());
+        assert!(storage.ttl("a").unwrap().unwrap() > Duration::from_secs(59));
+        assert!(storage.persist("a").unwrap());
+        assert_eq!(storage.get("a").unwrap(), Some("ok!".to_string()));
+        assert!(!storage.expire("missing", Duration::from_secs(60)).unwrap());
+    }
+
+    #[test]
+    fn test_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.sled");
+        let storage = SledStorageBuilder::<&str, String>::new()
+            .path(&path)
+            .cache_capacity(1 << 20)
+            .build();
+        storage
+            .set_many(vec![("a", "1".to_string()), ("b", "2".to_string())])
+            .unwrap();
+        storage.close().unwrap();
+        drop(storage);
+
+        // sled's flusher thread lets go of the file lock shortly after the last handle is
+        // dropped, so the reopen retries for a while.
+        let mut attempts = 0;
+        let storage = loop {
+            match SledStorageBuilder::<String, String>::new()
+                .path(&path)
+                .try_build()
+            {
+                Ok(storage) => break storage,
+                Err(e) if attempts < 50 => {
+                    attempts += 1;
+                    assert!(e.to_string().contains("lock"), "{}", e);
+                    std::thread::sleep(Duration::from_millis(10 * attempts));
+                }
+                Err(e) => panic!("{}", e),
+            }
+        };
+        let entries: Vec<(String, String)> =
+            storage.export_iter().unwrap().map(Result::unwrap).collect();
+        assert_eq!(
+            entries,
+            [
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "2".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_durability() {
+        for durability in [
+            Durability::Never,
+            Durability::Interval(Duration::from_millis(10)),
+            Durability::Batch(2),
+            Durability::EveryWrite,
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            let storage = SledStorageBuilder::<&str, String>::new()
+                .path(dir.path().join("data.sled"))
+                .durability(durability)
+                .build();
+            for key in ["a", "b", "c"] {
+                storage.set(key, "ok!".to_string()).unwrap();
+            }
+            assert!(storage.compare_and_swap("a", None, None).is_ok());
+            assert_eq!(storage.take("b").unwrap(), Some("ok!".to_string()));
+            assert_eq!(storage.len().unwrap(), 2);
+        }
+    }
+
+    #[test]
+    fn test_flush_namespace() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SledStorageBuilder::<&str, String>::new()
+            .path(dir.path().join("data.sled"))
+            .build();
+        for key in ["tenant:1:a", "tenant:1:b", "tenant:2:a"] {
+            storage.set(key, "ok!".to_string()).unwrap();
+        }
+
+        let owned = SledStorage::<String, String>::from_db(storage.db().clone());
+        let keys: Vec<String> = owned
+            .scan_prefix("tenant:1:")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(keys, ["tenant:1:a", "tenant:1:b"]);
+
+        let confirm = FlushConfirmation::for_prefix("tenant:1:");
+        assert_eq!(storage.flush_namespace("tenant:1:", &confirm).unwrap(), 2);
+        assert!(!storage.contains("tenant:1:a").unwrap());
+        assert!(storage.contains("tenant:2:a").unwrap());
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SledStorageBuilder::<&str, String>::new()
+            .path(dir.path().join("data.sled"))
+            .build();
+
+        assert!(storage
+            .compare_and_swap("cas", None, Some("1".to_string()))
+            .unwrap());
+        assert!(!storage
+            .compare_and_swap("cas", None, Some("2".to_string()))
+            .unwrap());
+        let seen = storage
+            .fetch_update("cas", |current| {
+                current.map(|n| (n.parse::<u32>().unwrap() + 1).to_string())
+            })
+            .unwrap();
+        assert_eq!(seen, Some("1".to_string()));
+        assert_eq!(storage.get("cas").unwrap(), Some("2".to_string()));
+
+        let value = storage
+            .get_or_insert_with("cas", || "3".to_string())
+            .unwrap();
+        assert_eq!(value, "2");
+        assert!(storage
+            .set_nx("fresh", "1".to_string(), Some(Duration::from_secs(60)))
+            .unwrap());
+        assert!(storage.ttl("fresh").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_swap_take() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SledStorageBuilder::<&str, String>::new()
+            .path(dir.path().join("data.sled"))
+            .build();
+
+        storage
+            .set_ex("a", "1".to_string(), Duration::from_millis(10))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        // An expired value was never there.
+        assert_eq!(storage.swap("a", "2".to_string()).unwrap(), None);
+        assert_eq!(storage.take("a").unwrap(), Some("2".to_string()));
+        assert_eq!(storage.take("a").unwrap(), None);
+    }
+
+    #[test]
+    fn test_versioned() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SledStorageBuilder::<&str, String>::new()
+            .path(dir.path().join("data.sled"))
+            .build();
+
+        assert!(storage.set_if_version("a", "1".to_string(), None).unwrap());
+        let (value, version) = storage.get_versioned("a").unwrap().unwrap();
+        assert_eq!(value, "1");
+        assert!(!storage.set_if_version("a", "2".to_string(), None).unwrap());
+        assert!(storage
+            .set_if_version("a", "2".to_string(), Some(version))
+            .unwrap());
+        assert!(!storage
+            .set_if_version("a", "3".to_string(), Some(version))
+            .unwrap());
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = SledStorageBuilder::<&str, String>::new().try_build();
+        assert!(matches!(resp, Err(BuilderError::MissingField("path"))));
+
+        let resp = SledStorageBuilder::<&str, String>::new()
+            .path("data.sled")
+            .cache_capacity(0)
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(_))));
+
+        let resp = SledStorageBuilder::<&str, String>::new()
+            .path("data.sled")
+            .durability(Durability::Batch(0))
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(e)) if e.field == "durability"));
+    }
+}
@@ -0,0 +1,821 @@
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+
+use crate::admin::AdminStorage;
+use crate::atomic::AtomicStorage;
+use crate::error::BuilderError;
+use crate::expiry::ExpiryStorage;
+use crate::export::{EntryIter, ExportEntry, ExportIter, ExportStorage};
+use crate::flush::{FlushConfirmation, FlushNamespace};
+use crate::gc::GarbageCollect;
+use crate::get_or_insert::GetOrInsertStorage;
+use crate::hooks::{Hooks, StorageHook};
+use crate::scan::{KeyIter, ScanStorage};
+use crate::storage::{CloseFlag, Err, Storage};
+
+// Rows read per query by exports and scans, the connection is released between pages.
+const PAGE: usize = 500;
+
+// Keys and values in a `(key TEXT PRIMARY KEY, value TEXT, expires_at INTEGER)` table, the
+// expiry in unix millis and NULL for none. Expired rows are never returned and stay in the
+// table until they are overwritten, deleted or collected by `gc_now`. Clones share the
+// connection.
+#[derive(Clone)]
+pub struct SqliteStorage<K, V>
+where
+    V: Into<String>,
+{
+    conn: Arc<Mutex<Connection>>,
+    sql: Arc<Sql>,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    _marker: PhantomData<(K, V)>,
+}
+
+// The statements with the table name filled in, prepared once per connection through
+// rusqlite's statement cache.
+struct Sql {
+    table: String,
+    get: String,
+    upsert: String,
+    delete: String,
+    set_nx: String,
+    ttl: String,
+    expire: String,
+    persist: String,
+    page: String,
+}
+
+impl Sql {
+    fn new(table: &str) -> Self {
+        Sql {
+            table: table.to_string(),
+            get: format!(
+                "SELECT value FROM {} WHERE key = ?1 AND (expires_at IS NULL OR expires_at > ?2)",
+                table
+            ),
+            upsert: format!(
+                "INSERT INTO {} (key, value, expires_at) VALUES (?1, ?2, ?3) \
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value, \
+                 expires_at = excluded.expires_at",
+                table
+            ),
+            delete: format!(
+                "DELETE FROM {} WHERE key = ?1 RETURNING value, expires_at",
+                table
+            ),
+            set_nx: format!(
+                "INSERT INTO {0} (key, value, expires_at) VALUES (?1, ?2, ?3) \
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value, \
+                 expires_at = excluded.expires_at WHERE {0}.expires_at <= ?4",
+                table
+            ),
+            ttl: format!(
+                "SELECT expires_at FROM {} WHERE key = ?1 \
+                 AND (expires_at IS NULL OR expires_at > ?2)",
+                table
+            ),
+            expire: format!(
+                "UPDATE {} SET expires_at = ?3 WHERE key = ?1 \
+                 AND (expires_at IS NULL OR expires_at > ?2)",
+                table
+            ),
+            persist: format!(
+                "UPDATE {} SET expires_at = NULL WHERE key = ?1 AND expires_at > ?2",
+                table
+            ),
+            page: format!(
+                "SELECT key, value, expires_at FROM {} \
+                 WHERE (?1 IS NULL OR key > ?1) AND instr(key, ?2) = 1 \
+                 AND (expires_at IS NULL OR expires_at > ?3) ORDER BY key LIMIT ?4",
+                table
+            ),
+        }
+    }
+}
+
+type Row = (String, String, Option<Duration>);
+
+impl<K, V> SqliteStorage<K, V>
+where
+    V: Into<String>,
+{
+    // The path of the database file, `None` for an in-memory one.
+    pub fn path(&self) -> Option<PathBuf> {
+        self.conn()
+            .path()
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    }
+
+    pub fn table(&self) -> &str {
+        &self.sql.table
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap()
+    }
+
+    fn lookup(&self, key: &str) -> Result<Option<String>, Err> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare_cached(&self.sql.get)?;
+        Ok(stmt
+            .query_row(params![key, now_millis()], |row| row.get(0))
+            .optional()?)
+    }
+
+    fn upsert(&self, key: &str, value: &str, expires_at: Option<i64>) -> Result<(), Err> {
+        let conn = self.conn();
+        conn.prepare_cached(&self.sql.upsert)?
+            .execute(params![key, value, expires_at])?;
+        Ok(())
+    }
+
+    // Removes the row, expired or not, and returns its value if it was live.
+    fn remove(&self, key: &str) -> Result<Option<String>, Err> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare_cached(&self.sql.delete)?;
+        let row: Option<(String, Option<i64>)> = stmt
+            .query_row([key], |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()?;
+        Ok(row
+            .filter(|(_, expires_at)| is_live(*expires_at))
+            .map(|(value, _)| value))
+    }
+
+    // Live rows whose key starts with `prefix`, in key order, fetched a page at a time.
+    fn rows<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = Result<Row, Err>> + 'a {
+        let mut after: Option<String> = None;
+        let mut page = VecDeque::new();
+        let mut done = false;
+        std::iter::from_fn(move || {
+            if page.is_empty() && !done {
+                match self.page(after.as_deref(), prefix) {
+                    Ok(rows) => {
+                        done = rows.len() < PAGE;
+                        after = rows.last().map(|(key, _, _)| key.clone());
+                        page.extend(rows);
+                    }
+                    Err(e) => {
+                        done = true;
+                        return Some(Err(e));
+                    }
+                }
+            }
+            page.pop_front().map(Ok)
+        })
+    }
+
+    fn page(&self, after: Option<&str>, prefix: &str) -> Result<Vec<Row>, Err> {
+        self.closed.check()?;
+        let now = now_millis();
+        let conn = self.conn();
+        let mut stmt = conn.prepare_cached(&self.sql.page)?;
+        let rows = stmt.query_map(params![after, prefix, now, PAGE as i64], |row| {
+            let expires_at: Option<i64> = row.get(2)?;
+            Ok((row.get(0)?, row.get(1)?, remaining(expires_at, now)))
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+impl<K, V> Storage<K, V> for SqliteStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        self.upsert(key.as_ref(), &value, None)?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        self.upsert(key.as_ref(), &value, Some(expires_at(expire)))?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let value = self.lookup(key.as_ref())?.map(V::from);
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let found = self.remove(key.as_ref())?.is_some();
+        self.hooks.del(&key);
+        Ok(found.then_some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        Ok(self.lookup(key.as_ref())?.is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+
+    // One transaction, so either every entry is written or none.
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.closed.check()?;
+        let entries: Vec<(K, String)> = entries
+            .into_iter()
+            .map(|(key, value)| (key, value.into()))
+            .collect();
+        {
+            let mut conn = self.conn();
+            let tx = conn.transaction()?;
+            {
+                let mut stmt = tx.prepare_cached(&self.sql.upsert)?;
+                for (key, value) in &entries {
+                    stmt.execute(params![key.as_ref(), value, None::<i64>])?;
+                }
+            }
+            tx.commit()?;
+        }
+        for (key, value) in entries {
+            self.hooks.set_with(&key, || V::from(value));
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> ExportStorage<K, V> for SqliteStorage<K, V>
+where
+    K: AsRef<str> + From<String>,
+    V: Into<String> + From<String>,
+{
+    fn export_iter(&self) -> Result<ExportIter<'_, K, V>, Err> {
+        Ok(Box::new(
+            self.export_entries()?
+                .map(|entry| entry.map(|entry| (entry.key, entry.value))),
+        ))
+    }
+
+    // In key order, expired rows are skipped.
+    fn export_entries<'a>(&'a self) -> Result<EntryIter<'a, K, V>, Err>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.closed.check()?;
+        Ok(Box::new(self.rows("").map(|row| {
+            row.map(|(key, value, ttl)| ExportEntry {
+                key: K::from(key),
+                value: V::from(value),
+                ttl,
+            })
+        })))
+    }
+}
+
+impl<K, V> GarbageCollect for SqliteStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn gc_now(&self, batch_size: usize) -> Result<usize, Err> {
+        self.closed.check()?;
+        let conn = self.conn();
+        let sql = format!(
+            "DELETE FROM {0} WHERE key IN \
+             (SELECT key FROM {0} WHERE expires_at <= ?1 LIMIT ?2)",
+            self.sql.table
+        );
+        let removed = conn
+            .prepare_cached(&sql)?
+            .execute(params![now_millis(), batch_size as i64])?;
+        Ok(removed)
+    }
+}
+
+// Reads and writes in one immediate transaction, so other processes on the same file can't
+// write in between either.
+impl<K, V> AtomicStorage<K, V> for SqliteStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String> + PartialEq,
+{
+    fn compare_and_swap(&self, key: K, expected: Option<V>, new: Option<V>) -> Result<bool, Err> {
+        self.closed.check()?;
+        let mut conn = self.conn();
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let current: Option<String> = tx
+            .prepare_cached(&self.sql.get)?
+            .query_row(params![key.as_ref(), now_millis()], |row| row.get(0))
+            .optional()?;
+        if current != expected.map(Into::into) {
+            return Ok(false);
+        }
+        match new {
+            Some(new) => {
+                let new: String = new.into();
+                tx.prepare_cached(&self.sql.upsert)?.execute(params![
+                    key.as_ref(),
+                    new,
+                    None::<i64>
+                ])?;
+            }
+            None => {
+                tx.prepare_cached(&self.sql.delete)?
+                    .query_row([key.as_ref()], |_| Ok(()))
+                    .optional()?;
+            }
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+
+    fn swap(&self, key: K, value: V) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        let mut conn = self.conn();
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let old: Option<String> = tx
+            .prepare_cached(&self.sql.get)?
+            .query_row(params![key.as_ref(), now_millis()], |row| row.get(0))
+            .optional()?;
+        tx.prepare_cached(&self.sql.upsert)?
+            .execute(params![key.as_ref(), value, None::<i64>])?;
+        tx.commit()?;
+        Ok(old.map(V::from))
+    }
+
+    // A single DELETE .. RETURNING, so of concurrent takes only one sees the row.
+    fn take(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        Ok(self.remove(key.as_ref())?.map(V::from))
+    }
+}
+
+// An upsert that only overwrites an expired row, so a live one is left alone.
+impl<K, V> GetOrInsertStorage<K, V> for SqliteStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set_nx(&self, key: K, value: V, expire: Option<Duration>) -> Result<bool, Err> {
+        self.closed.check()?;
+        let value: String = value.into();
+        let conn = self.conn();
+        let changed = conn.prepare_cached(&self.sql.set_nx)?.execute(params![
+            key.as_ref(),
+            value,
+            expire.map(expires_at),
+            now_millis()
+        ])?;
+        Ok(changed > 0)
+    }
+}
+
+impl<K, V> AdminStorage for SqliteStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn clear(&self) -> Result<(), Err> {
+        self.closed.check()?;
+        let conn = self.conn();
+        conn.execute(&format!("DELETE FROM {}", self.sql.table), [])?;
+        Ok(())
+    }
+
+    fn len(&self) -> Result<usize, Err> {
+        self.closed.check()?;
+        let conn = self.conn();
+        let sql = format!(
+            "SELECT COUNT(*) FROM {} WHERE expires_at IS NULL OR expires_at > ?1",
+            self.sql.table
+        );
+        let len: i64 = conn.query_row(&sql, [now_millis()], |row| row.get(0))?;
+        Ok(len as usize)
+    }
+}
+
+impl<K, V> ExpiryStorage<K> for SqliteStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String>,
+{
+    fn ttl(&self, key: K) -> Result<Option<Duration>, Err> {
+        self.closed.check()?;
+        let now = now_millis();
+        let conn = self.conn();
+        let expires_at: Option<Option<i64>> = conn
+            .prepare_cached(&self.sql.ttl)?
+            .query_row(params![key.as_ref(), now], |row| row.get(0))
+            .optional()?;
+        Ok(expires_at.and_then(|expires_at| remaining(expires_at, now)))
+    }
+
+    fn expire(&self, key: K, expire: Duration) -> Result<bool, Err> {
+        self.closed.check()?;
+        let conn = self.conn();
+        let changed = conn.prepare_cached(&self.sql.expire)?.execute(params![
+            key.as_ref(),
+            now_millis(),
+            expires_at(expire)
+        ])?;
+        Ok(changed > 0)
+    }
+
+    fn persist(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        let conn = self.conn();
+        let changed = conn
+            .prepare_cached(&self.sql.persist)?
+            .execute(params![key.as_ref(), now_millis()])?;
+        Ok(changed > 0)
+    }
+}
+
+impl<K, V> ScanStorage<K> for SqliteStorage<K, V>
+where
+    K: From<String>,
+    V: Into<String>,
+{
+    // In key order, a page at a time like the export.
+    fn scan_prefix(&self, prefix: &str) -> Result<KeyIter<'_, K>, Err> {
+        self.closed.check()?;
+        let prefix = prefix.to_string();
+        let mut after: Option<String> = None;
+        let mut page = VecDeque::new();
+        let mut done = false;
+        Ok(Box::new(std::iter::from_fn(move || {
+            if page.is_empty() && !done {
+                match self.page(after.as_deref(), &prefix) {
+                    Ok(rows) => {
+                        done = rows.len() < PAGE;
+                        after = rows.last().map(|(key, _, _)| key.clone());
+                        page.extend(rows.into_iter().map(|(key, _, _)| K::from(key)));
+                    }
+                    Err(e) => {
+                        done = true;
+                        return Some(Err(e));
+                    }
+                }
+            }
+            page.pop_front().map(Ok)
+        })))
+    }
+}
+
+impl<K, V> FlushNamespace for SqliteStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn flush_namespace(&self, prefix: &str, confirm: &FlushConfirmation) -> Result<usize, Err> {
+        self.closed.check()?;
+        confirm.check(prefix)?;
+        let conn = self.conn();
+        let sql = format!("DELETE FROM {} WHERE instr(key, ?1) = 1", self.sql.table);
+        Ok(conn.execute(&sql, [prefix])?)
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+fn expires_at(expire: Duration) -> i64 {
+    now_millis() + expire.as_millis() as i64
+}
+
+fn is_live(expires_at: Option<i64>) -> bool {
+    expires_at.is_none_or(|expires_at| expires_at > now_millis())
+}
+
+fn remaining(expires_at: Option<i64>, now: i64) -> Option<Duration> {
+    expires_at.map(|expires_at| Duration::from_millis((expires_at - now).max(0) as u64))
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SqliteStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    path: Option<PathBuf>,
+    in_memory: bool,
+    table: String,
+    #[cfg_attr(feature = "serde", serde(with = "crate::config::secs::option"))]
+    busy_timeout: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> SqliteStorageBuilder<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    pub fn new() -> Self {
+        SqliteStorageBuilder::default()
+    }
+
+    // The database file, created with the table if it doesn't exist.
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self.in_memory = false;
+        self
+    }
+
+    // A private database that lives as long as the storage and its clones, e.g. for tests.
+    pub fn in_memory(mut self) -> Self {
+        self.in_memory = true;
+        self.path = None;
+        self
+    }
+
+    // Letters, digits and underscores, not starting with a digit. Defaults to `kv`.
+    pub fn table(mut self, table: &str) -> Self {
+        self.table = table.to_string();
+        self
+    }
+
+    // How long a write waits for another process holding the file's lock before it fails
+    // with SQLITE_BUSY. Defaults to 5 seconds.
+    pub fn busy_timeout(mut self, timeout: Duration) -> Self {
+        self.busy_timeout = Some(timeout);
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their TTL and batch variants.
+    // Clones of the storage share them.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if neither a path nor `in_memory` is set, the table name is invalid, or the
+    // database cannot be opened. Use `try_build` to get the error instead.
+    pub fn build(self) -> SqliteStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build sqlite storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<SqliteStorage<K, V>, BuilderError> {
+        let valid = self
+            .table
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid || !self.table.starts_with(|c: char| !c.is_ascii_digit()) {
+            return Err(BuilderError::invalid(
+                "table",
+                "must be letters, digits and underscores, not starting with a digit",
+            ));
+        }
+        let conn = match (&self.path, self.in_memory) {
+            (_, true) => Connection::open_in_memory(),
+            (Some(path), false) => Connection::open(path),
+            (None, false) => return Err(BuilderError::MissingField("path")),
+        }
+        .map_err(BuilderError::backend)?;
+        conn.busy_timeout(self.busy_timeout.unwrap_or(Duration::from_secs(5)))
+            .map_err(BuilderError::backend)?;
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {0} \
+             (key TEXT PRIMARY KEY NOT NULL, value TEXT NOT NULL, expires_at INTEGER) \
+             WITHOUT ROWID; \
+             CREATE INDEX IF NOT EXISTS {0}_expires_at ON {0} (expires_at) \
+             WHERE expires_at IS NOT NULL;",
+            self.table
+        ))
+        .map_err(BuilderError::backend)?;
+
+        Ok(SqliteStorage {
+            conn: Arc::new(Mutex::new(conn)),
+            sql: Arc::new(Sql::new(&self.table)),
+            closed: CloseFlag::default(),
+            hooks: self.hooks,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<K, V> Default for SqliteStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    fn default() -> Self {
+        Self {
+            path: None,
+            in_memory: false,
+            table: "kv".to_string(),
+            busy_timeout: None,
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::storage_conformance_tests!(
+        conformance,
+        SqliteStorageBuilder::<String, String>::new()
+            .in_memory()
+            .build()
+    );
+
+    fn memory() -> SqliteStorage<&'static str, String> {
+        SqliteStorageBuilder::new().in_memory().build()
+    }
+
+    #[test]
+    fn test_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteStorageBuilder::new()
+            .path(dir.path().join("data.db"))
+            .build();
+
+        let (key, value) = ("name", "Ferris".to_string());
+        storage.set(key, value.clone()).unwrap();
+        assert_eq!(storage.get(key).unwrap(), Some(value));
+        assert_eq!(storage.del(key).unwrap(), Some(key));
+        assert_eq!(storage.get(key).unwrap(), None);
+        assert_eq!(storage.del(key).unwrap(), None);
+    }
+
+    #[test]
+    fn test_set_ex() {
+        let storage = memory();
+        storage
+            .set_ex("a", "ok!".to_string(), Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(storage.get("a").unwrap(), Some("ok!".to_string()));
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(!storage.contains("a").unwrap());
+        assert!(storage.is_empty().unwrap());
+        // An expired row is there until something removes it.
+        assert_eq!(storage.del("a").unwrap(), None);
+        storage
+            .set_ex("b", "ok!".to_string(), Duration::from_millis(1))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(storage.gc_now(10).unwrap(), 1);
+        assert_eq!(storage.gc_now(10).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_expire() {
+        let storage = memory();
+        storage.set("a", "ok!".to_string()).unwrap();
+        assert_eq!(storage.ttl("a").unwrap(), None);
+        assert!(!storage.persist("a").unwrap());
+        assert!(storage.expire("a", Duration::from_secs(60)).unwrap());
+        assert!(storage.ttl("a").unwrap().unwrap() > Duration::from_secs(59));
+        assert!(storage.persist("a").unwrap());
+        assert_eq!(storage.get("a").unwrap(), Some("ok!".to_string()));
+        assert!(!storage.expire("missing", Duration::from_secs(60)).unwrap());
+    }
+
+    #[test]
+    fn test_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.db");
+        let storage = SqliteStorageBuilder::<&str, String>::new()
+            .path(&path)
+            .table("sessions")
+            .build();
+        storage
+            .set_many(vec![("a", "1".to_string()), ("b", "2".to_string())])
+            .unwrap();
+        storage
+            .set_ex("c", "3".to_string(), Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(storage.path(), Some(path.clone()));
+        storage.close().unwrap();
+        drop(storage);
+
+        let storage = SqliteStorageBuilder::<String, String>::new()
+            .path(&path)
+            .table("sessions")
+            .build();
+        let entries: Vec<ExportEntry<String, String>> = storage
+            .export_entries()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(entries.len(), 3);
+        assert_eq!((entries[0].key.as_str(), entries[0].ttl), ("a", None));
+        assert!(entries[2].ttl.unwrap() > Duration::from_secs(59));
+    }
+
+    #[test]
+    fn test_scan_pages() {
+        let storage = SqliteStorageBuilder::<String, String>::new()
+            .in_memory()
+            .build();
+        let entries = (0..PAGE + 10)
+            .map(|n| (format!("tenant:1:{:04}", n), n.to_string()))
+            .collect();
+        storage.set_many(entries).unwrap();
+        storage.set("tenant:2:a".into(), "x".into()).unwrap();
+        storage.set("tenant_1".into(), "x".into()).unwrap();
+
+        let keys: Vec<String> = storage
+            .scan_prefix("tenant:1:")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(keys.len(), PAGE + 10);
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(storage.export_iter().unwrap().count(), PAGE + 12);
+
+        let confirm = FlushConfirmation::for_prefix("tenant:1:");
+        assert_eq!(
+            storage.flush_namespace("tenant:1:", &confirm).unwrap(),
+            PAGE + 10
+        );
+        assert_eq!(storage.len().unwrap(), 2);
+        storage.clear().unwrap();
+        assert!(storage.is_empty().unwrap());
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let storage = memory();
+        assert!(storage
+            .compare_and_swap("cas", None, Some("1".to_string()))
+            .unwrap());
+        assert!(!storage
+            .compare_and_swap("cas", None, Some("2".to_string()))
+            .unwrap());
+        let seen = storage
+            .fetch_update("cas", |current| {
+                current.map(|n| (n.parse::<u32>().unwrap() + 1).to_string())
+            })
+            .unwrap();
+        assert_eq!(seen, Some("1".to_string()));
+        assert_eq!(storage.get("cas").unwrap(), Some("2".to_string()));
+        assert!(storage
+            .compare_and_swap("cas", Some("2".to_string()), None)
+            .unwrap());
+        assert!(!storage.contains("cas").unwrap());
+
+        storage
+            .set_ex("a", "1".to_string(), Duration::from_millis(10))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        // An expired value was never there.
+        assert_eq!(storage.swap("a", "2".to_string()).unwrap(), None);
+        assert_eq!(storage.take("a").unwrap(), Some("2".to_string()));
+        assert_eq!(storage.take("a").unwrap(), None);
+    }
+
+    #[test]
+    fn test_set_nx() {
+        let storage = memory();
+        assert!(storage
+            .set_nx("a", "1".to_string(), Some(Duration::from_millis(10)))
+            .unwrap());
+        assert!(!storage.set_nx("a", "2".to_string(), None).unwrap());
+        std::thread::sleep(Duration::from_millis(20));
+        // Takes over the expired row.
+        assert!(storage.set_nx("a", "3".to_string(), None).unwrap());
+        assert_eq!(storage.get("a").unwrap(), Some("3".to_string()));
+        assert_eq!(storage.ttl("a").unwrap(), None);
+        let value = storage.get_or_insert_with("a", || "4".to_string()).unwrap();
+        assert_eq!(value, "3");
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = SqliteStorageBuilder::<&str, String>::new().try_build();
+        assert!(matches!(resp, Err(BuilderError::MissingField("path"))));
+
+        for table in ["", "1kv", "kv; DROP TABLE kv", "k-v"] {
+            let resp = SqliteStorageBuilder::<&str, String>::new()
+                .in_memory()
+                .table(table)
+                .try_build();
+            assert!(matches!(resp, Err(BuilderError::InvalidConfig(e)) if e.field == "table"));
+        }
+    }
+}
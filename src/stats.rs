@@ -0,0 +1,46 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::storage::Err;
+
+// A point-in-time view of a backend for capacity planning. Figures are read while writers
+// keep going, so they are approximate, and lazily expired entries may still count.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageInfo {
+    pub keys: u64,
+    // Bytes the backend reports using, `None` where it can't tell.
+    pub memory_bytes: Option<u64>,
+    // Backend specific figures, e.g. redis INFO fields or DashMap shard sizes.
+    pub details: BTreeMap<String, String>,
+}
+
+impl StorageInfo {
+    // Sets a backend specific figure, for backends outside this crate too.
+    pub fn detail<T: ToString>(&mut self, name: &str, value: T) {
+        self.details.insert(name.to_string(), value.to_string());
+    }
+}
+
+// Introspection for capacity planning and dashboards, not for request paths: backends may
+// walk their whole state to answer.
+pub trait StorageStats {
+    fn stats(&self) -> Result<StorageInfo, Err>;
+}
+
+impl<S: StorageStats + ?Sized> StorageStats for &S {
+    fn stats(&self) -> Result<StorageInfo, Err> {
+        (**self).stats()
+    }
+}
+
+impl<S: StorageStats + ?Sized> StorageStats for Box<S> {
+    fn stats(&self) -> Result<StorageInfo, Err> {
+        (**self).stats()
+    }
+}
+
+impl<S: StorageStats + ?Sized> StorageStats for Arc<S> {
+    fn stats(&self) -> Result<StorageInfo, Err> {
+        (**self).stats()
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use crate::storage::{Err, Storage};
+
+fn poisoned<T>(_: T) -> Err {
+    "lock poisoned by a panicking writer".into()
+}
+
+macro_rules! impl_locked_map {
+    ($lock:ident, $read:ident, $write:ident, $map:ident, $($bound:tt)+) => {
+        impl<K: $($bound)+, V: Clone> Storage<K, V> for $lock<$map<K, V>> {
+            fn set(&self, key: K, value: V) -> Result<(), Err> {
+                self.$write().map_err(poisoned)?.insert(key, value);
+                Ok(())
+            }
+
+            fn set_ex(&self, _key: K, _value: V, _expire: Duration) -> Result<(), Err> {
+                Err(concat!(stringify!($lock), "<", stringify!($map), "> does not support expiration").into())
+            }
+
+            fn get(&self, key: K) -> Result<Option<V>, Err> {
+                Ok(self.$read().map_err(poisoned)?.get(&key).cloned())
+            }
+
+            fn del(&self, key: K) -> Result<Option<K>, Err> {
+                Ok(self
+                    .$write()
+                    .map_err(poisoned)?
+                    .remove(&key)
+                    .map(|_| key))
+            }
+
+            fn contains(&self, key: K) -> Result<bool, Err> {
+                Ok(self.$read().map_err(poisoned)?.contains_key(&key))
+            }
+        }
+    };
+}
+
+impl_locked_map!(RwLock, read, write, HashMap, Hash + Eq);
+impl_locked_map!(RwLock, read, write, BTreeMap, Ord);
+impl_locked_map!(Mutex, lock, lock, HashMap, Hash + Eq);
+impl_locked_map!(Mutex, lock, lock, BTreeMap, Ord);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::storage_conformance_tests!(
+        conformance,
+        RwLock::new(HashMap::<String, String>::new()),
+        no_ttl
+    );
+
+    fn set_get_del<S: Storage<&'static str, String>>(storage: S) {
+        let (key, value) = ("name", "Ferris".to_string());
+        storage.set(key, value.clone()).unwrap();
+        assert!(storage.contains(key).unwrap());
+        assert_eq!(storage.get(key).unwrap(), Some(value));
+
+        assert_eq!(storage.del(key).unwrap(), Some(key));
+        assert_eq!(storage.del(key).unwrap(), None);
+        assert_eq!(storage.get(key).unwrap(), None);
+        assert!(storage
+            .set_ex(key, "ok!".to_string(), Duration::from_secs(1))
+            .is_err());
+    }
+
+    #[test]
+    fn test_locked_maps() {
+        set_get_del(RwLock::new(HashMap::new()));
+        set_get_del(RwLock::new(BTreeMap::new()));
+        set_get_del(Mutex::new(HashMap::new()));
+        set_get_del(Mutex::new(BTreeMap::new()));
+    }
+}
@@ -1,11 +1,332 @@
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
+use crate::bulk::{chunked, ExtendOptions, ExtendReport, ValueSize};
+use crate::error::StorageError;
+use crate::export::ExportStorage;
+use crate::health::HealthStatus;
+use crate::options::OpOptions;
+
 pub type Err = Box<dyn std::error::Error>;
+// Async callers need errors that can cross `.await` points in spawned tasks.
+#[cfg(feature = "async")]
+pub type AsyncErr = Box<dyn std::error::Error + Send + Sync>;
+
+// A backend chosen at runtime, e.g. from configuration, without naming it in every type.
+pub type DynStorage<K = String, V = Vec<u8>> = Arc<dyn Storage<K, V> + Send + Sync>;
 
+// Object safe: the generic helpers are `Self: Sized`, and the `Box`/`Arc` forwarding impls
+// bring them back to `DynStorage` and other trait objects.
 pub trait Storage<K, V> {
     fn set(&self, key: K, value: V) -> Result<(), Err>;
     fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err>;
     fn get(&self, key: K) -> Result<Option<V>, Err>;
     fn del(&self, key: K) -> Result<Option<K>, Err>;
     fn contains(&self, key: K) -> Result<bool, Err>;
+
+    // Flushes and releases whatever the backend holds, later calls fail with `StorageError::Closed`.
+    fn close(&self) -> Result<(), Err> {
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        false
+    }
+
+    // Whether the storage can serve requests, e.g. for readiness probes. The default only
+    // looks at `is_closed`; backends behind a network probe them, wrappers ask their inner
+    // storages.
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        if self.is_closed() {
+            return Err(StorageError::Closed.into());
+        }
+        Ok(HealthStatus::default())
+    }
+
+    // Batch variants, results are in the order of `keys`. The defaults call the single-key
+    // methods one by one, backends with native batching override them.
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        keys.into_iter().map(|key| self.get(key)).collect()
+    }
+
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        for (key, value) in entries {
+            self.set(key, value)?;
+        }
+        Ok(())
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        keys.into_iter().map(|key| self.del(key)).collect()
+    }
+
+    // Per-call variants taking `OpOptions`. The defaults only check the deadline before the
+    // call and apply the TTL override, backends that can do better override them.
+    fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+        opts.remaining()?;
+        match opts.ttl {
+            Some(ttl) => self.set_ex(key, value, ttl),
+            None => self.set(key, value),
+        }
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        opts.remaining()?;
+        self.get(key)
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        opts.remaining()?;
+        self.del(key)
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        opts.remaining()?;
+        self.contains(key)
+    }
+
+    // Bulk load with the default `ExtendOptions`, see `extend_with`.
+    fn extend<I>(&self, entries: I) -> ExtendReport
+    where
+        Self: Sized,
+        I: IntoIterator<Item = (K, V)>,
+        V: ValueSize,
+    {
+        self.extend_with(entries, ExtendOptions::default())
+    }
+
+    // Replaces entries of `self` with the full state of `source`, see `snapshot_to`.
+    fn restore_from<S: ExportStorage<K, V>>(&self, source: &S) -> Result<usize, Err>
+    where
+        Self: Sized,
+    {
+        source.snapshot_to(self)
+    }
+
+    // Writes `entries` one `set_many` per chunk, a failing chunk is reported as a whole and
+    // the rest are still written.
+    fn extend_with<I>(&self, entries: I, options: ExtendOptions) -> ExtendReport
+    where
+        Self: Sized,
+        I: IntoIterator<Item = (K, V)>,
+        V: ValueSize,
+    {
+        chunked(entries, options, |chunk| {
+            self.set_many(chunk).map_err(|e| (0, e))
+        })
+    }
+}
+
+// `Storage` for async code, every future is `Send` so handlers can await it inside spawned
+// tasks without `spawn_blocking`.
+#[cfg(feature = "async")]
+pub trait AsyncStorage<K, V> {
+    fn set(&self, key: K, value: V) -> impl Future<Output = Result<(), AsyncErr>> + Send;
+    fn set_ex(
+        &self,
+        key: K,
+        value: V,
+        expire: Duration,
+    ) -> impl Future<Output = Result<(), AsyncErr>> + Send;
+    fn get(&self, key: K) -> impl Future<Output = Result<Option<V>, AsyncErr>> + Send;
+    fn del(&self, key: K) -> impl Future<Output = Result<Option<K>, AsyncErr>> + Send;
+    fn contains(&self, key: K) -> impl Future<Output = Result<bool, AsyncErr>> + Send;
+
+    fn close(&self) -> impl Future<Output = Result<(), AsyncErr>> + Send {
+        async { Ok(()) }
+    }
+
+    fn is_closed(&self) -> bool {
+        false
+    }
+}
+
+// Shared by clones of a storage, so closing one handle closes them all.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CloseFlag(Arc<AtomicBool>);
+
+impl CloseFlag {
+    pub(crate) fn check(&self) -> Result<(), Err> {
+        if self.is_closed() {
+            return Err(StorageError::Closed.into());
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn check_async(&self) -> Result<(), AsyncErr> {
+        if self.is_closed() {
+            return Err(StorageError::Closed.into());
+        }
+        Ok(())
+    }
+
+    // Returns true only for the call that actually closed it.
+    pub(crate) fn close(&self) -> bool {
+        !self.0.swap(true, Ordering::SeqCst)
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+macro_rules! forward_storage {
+    ($($ty:ty),*) => {
+        $(
+            impl<K, V, S> Storage<K, V> for $ty
+            where
+                S: Storage<K, V> + ?Sized,
+            {
+                fn set(&self, key: K, value: V) -> Result<(), Err> {
+                    (**self).set(key, value)
+                }
+
+                fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+                    (**self).set_ex(key, value, expire)
+                }
+
+                fn get(&self, key: K) -> Result<Option<V>, Err> {
+                    (**self).get(key)
+                }
+
+                fn del(&self, key: K) -> Result<Option<K>, Err> {
+                    (**self).del(key)
+                }
+
+                fn contains(&self, key: K) -> Result<bool, Err> {
+                    (**self).contains(key)
+                }
+
+                fn close(&self) -> Result<(), Err> {
+                    (**self).close()
+                }
+
+                fn is_closed(&self) -> bool {
+                    (**self).is_closed()
+                }
+
+                fn health_check(&self) -> Result<HealthStatus, Err> {
+                    (**self).health_check()
+                }
+
+                fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+                    (**self).get_many(keys)
+                }
+
+                fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+                    (**self).set_many(entries)
+                }
+
+                fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+                    (**self).del_many(keys)
+                }
+
+                fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+                    (**self).set_with_opts(key, value, opts)
+                }
+
+                fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+                    (**self).get_with_opts(key, opts)
+                }
+
+                fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+                    (**self).del_with_opts(key, opts)
+                }
+
+                fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+                    (**self).contains_with_opts(key, opts)
+                }
+            }
+        )*
+    };
+}
+
+forward_storage!(&S, Box<S>, Arc<S>);
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::DashMapStorageBuilder;
+
+    fn set_get<S: Storage<&'static str, String>>(storage: S) -> Option<String> {
+        storage.set("name", "Ferris".to_string()).unwrap();
+        storage.get("name").unwrap()
+    }
+
+    #[test]
+    fn test_forwarding_impls() {
+        let storage = Arc::new(DashMapStorageBuilder::new().build());
+        assert_eq!(set_get(storage.clone()), Some("Ferris".to_string()));
+        assert_eq!(set_get(&storage), Some("Ferris".to_string()));
+        assert_eq!(set_get(storage.as_ref()), Some("Ferris".to_string()));
+
+        let boxed: Box<dyn Storage<&'static str, String>> =
+            Box::new(DashMapStorageBuilder::new().build());
+        assert_eq!(set_get(boxed), Some("Ferris".to_string()));
+    }
+
+    #[test]
+    fn test_dyn_storage() {
+        let pick = |name: &str| -> DynStorage {
+            match name {
+                "memory" => Arc::new(DashMapStorageBuilder::new().build()),
+                _ => Arc::new(crate::mock_storage::MockStorage::new()),
+            }
+        };
+        let storage = pick("memory");
+        storage.set("a".to_string(), vec![1]).unwrap();
+        let report = storage.extend(vec![("b".to_string(), vec![2])]);
+        assert!(report.failed.is_empty());
+
+        let other = storage.clone();
+        let values = std::thread::spawn(move || {
+            other
+                .get_many(vec!["a".to_string(), "b".to_string()])
+                .unwrap()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(values, [Some(vec![1]), Some(vec![2])]);
+        assert_eq!(pick("mock").get("a".to_string()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_opts() {
+        let storage = DashMapStorageBuilder::<&str, String>::new().build();
+        let opts = OpOptions::new().timeout(Duration::from_secs(60));
+        storage
+            .set_with_opts("name", "Ferris".to_string(), &opts)
+            .unwrap();
+        assert_eq!(
+            storage.get_with_opts("name", &opts).unwrap(),
+            Some("Ferris".to_string())
+        );
+
+        let expired = OpOptions::new().deadline(std::time::Instant::now());
+        let e = storage.get_with_opts("name", &expired).unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<StorageError>(),
+            Some(&StorageError::Timeout)
+        );
+    }
+
+    #[test]
+    fn test_close() {
+        let storage = Arc::new(DashMapStorageBuilder::<&str, String>::new().build());
+        let handle = storage.clone();
+        handle.close().unwrap();
+        handle.close().unwrap();
+
+        assert!(storage.is_closed());
+        assert!(storage.health_check().is_err());
+        let e = storage.get("name").unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<StorageError>(),
+            Some(&StorageError::Closed)
+        );
+    }
 }
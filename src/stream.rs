@@ -0,0 +1,357 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::storage::Err;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamEntry {
+    // `<milliseconds>-<sequence>`, increasing along the stream.
+    pub id: String,
+    pub fields: Vec<(String, String)>,
+}
+
+// Append-only logs like redis streams, read by position or through consumer groups. IDs are
+// redis stream ids, `0` stands for the start of a stream. A group hands each entry to one of
+// its consumers and keeps it pending until it is acked, so a consumer that crashed can read
+// its pending entries again with `read_pending`.
+pub trait StreamStorage<K> {
+    // Returns the new entry's id.
+    fn append(&self, stream: K, fields: Vec<(String, String)>) -> Result<String, Err>;
+
+    // Up to `count` entries with ids after `after`, oldest first.
+    fn read_from(&self, stream: K, after: &str, count: usize) -> Result<Vec<StreamEntry>, Err>;
+
+    fn stream_len(&self, stream: K) -> Result<usize, Err>;
+
+    // Drops the oldest entries beyond `max_len`, returns how many.
+    fn trim(&self, stream: K, max_len: usize) -> Result<usize, Err>;
+
+    // Creates the stream if needed. The group starts after `from`, `$` for only new entries.
+    // `false` if the group already exists.
+    fn create_group(&self, stream: K, group: &str, from: &str) -> Result<bool, Err>;
+
+    // Up to `count` entries no consumer of the group was given yet, they stay pending for
+    // `consumer` until acked.
+    fn read_group(
+        &self,
+        stream: K,
+        group: &str,
+        consumer: &str,
+        count: usize,
+    ) -> Result<Vec<StreamEntry>, Err>;
+
+    // Entries given to `consumer` and not acked yet, oldest first.
+    fn read_pending(
+        &self,
+        stream: K,
+        group: &str,
+        consumer: &str,
+        count: usize,
+    ) -> Result<Vec<StreamEntry>, Err>;
+
+    // Returns how many of `ids` were pending.
+    fn ack(&self, stream: K, group: &str, ids: Vec<String>) -> Result<usize, Err>;
+}
+
+// A stream kept in memory, e.g. as the values of a `DashMapStorage<K, Stream>`. Trimmed
+// entries that were pending are acked.
+#[derive(Debug, Clone, Default)]
+pub struct Stream {
+    entries: VecDeque<(StreamId, Vec<(String, String)>)>,
+    last_id: StreamId,
+    groups: HashMap<String, Group>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct StreamId(u64, u64);
+
+impl StreamId {
+    fn parse(id: &str) -> Result<StreamId, Err> {
+        let (ms, seq) = id.split_once('-').unwrap_or((id, "0"));
+        match (ms.parse(), seq.parse()) {
+            (Ok(ms), Ok(seq)) => Ok(StreamId(ms, seq)),
+            _ => Err(format!("invalid stream id {:?}", id).into()),
+        }
+    }
+}
+
+impl Display for StreamId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.0, self.1)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Group {
+    delivered: StreamId,
+    // Consumer of each pending entry.
+    pending: BTreeMap<StreamId, String>,
+}
+
+impl Stream {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // IDs come from the clock, or follow the last one if the clock didn't move past it.
+    pub fn append(&mut self, fields: Vec<(String, String)>) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let id = if now > self.last_id.0 {
+            StreamId(now, 0)
+        } else {
+            StreamId(self.last_id.0, self.last_id.1 + 1)
+        };
+        self.last_id = id;
+        self.entries.push_back((id, fields));
+        id.to_string()
+    }
+
+    pub fn read_from(&self, after: &str, count: usize) -> Result<Vec<StreamEntry>, Err> {
+        let after = StreamId::parse(after)?;
+        Ok(self.after(after).take(count).map(entry).collect())
+    }
+
+    pub fn trim(&mut self, max_len: usize) -> usize {
+        let trimmed = self.entries.len().saturating_sub(max_len);
+        for (id, _) in self.entries.drain(..trimmed) {
+            for group in self.groups.values_mut() {
+                group.pending.remove(&id);
+            }
+        }
+        trimmed
+    }
+
+    pub fn create_group(&mut self, group: &str, from: &str) -> Result<bool, Err> {
+        if self.groups.contains_key(group) {
+            return Ok(false);
+        }
+        let delivered = match from {
+            "$" => self.last_id,
+            from => StreamId::parse(from)?,
+        };
+        let state = Group {
+            delivered,
+            pending: BTreeMap::new(),
+        };
+        self.groups.insert(group.to_string(), state);
+        Ok(true)
+    }
+
+    pub fn read_group(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        count: usize,
+    ) -> Result<Vec<StreamEntry>, Err> {
+        let state = self.groups.get_mut(group).ok_or_else(|| no_group(group))?;
+        let start = self
+            .entries
+            .partition_point(|(id, _)| *id <= state.delivered);
+        let read: Vec<StreamEntry> = self.entries.range(start..).take(count).map(entry).collect();
+        for (id, _) in self.entries.range(start..start + read.len()) {
+            state.pending.insert(*id, consumer.to_string());
+            state.delivered = *id;
+        }
+        Ok(read)
+    }
+
+    pub fn read_pending(
+        &self,
+        group: &str,
+        consumer: &str,
+        count: usize,
+    ) -> Result<Vec<StreamEntry>, Err> {
+        let state = self.groups.get(group).ok_or_else(|| no_group(group))?;
+        Ok(state
+            .pending
+            .iter()
+            .filter(|(_, owner)| owner.as_str() == consumer)
+            .filter_map(|(id, _)| {
+                let index = self.entries.binary_search_by_key(id, |(id, _)| *id).ok()?;
+                self.entries.get(index).map(entry)
+            })
+            .take(count)
+            .collect())
+    }
+
+    pub fn ack(&mut self, group: &str, ids: &[String]) -> Result<usize, Err> {
+        let state = self.groups.get_mut(group).ok_or_else(|| no_group(group))?;
+        let mut acked = 0;
+        for id in ids {
+            if state.pending.remove(&StreamId::parse(id)?).is_some() {
+                acked += 1;
+            }
+        }
+        Ok(acked)
+    }
+
+    fn after(&self, after: StreamId) -> impl Iterator<Item = &(StreamId, Vec<(String, String)>)> {
+        let start = self.entries.partition_point(|(id, _)| *id <= after);
+        self.entries.range(start..)
+    }
+}
+
+fn entry((id, fields): &(StreamId, Vec<(String, String)>)) -> StreamEntry {
+    StreamEntry {
+        id: id.to_string(),
+        fields: fields.clone(),
+    }
+}
+
+pub(crate) fn no_group(group: &str) -> Err {
+    format!("no consumer group {:?} on this stream", group).into()
+}
+
+impl<K, S: StreamStorage<K> + ?Sized> StreamStorage<K> for &S {
+    fn append(&self, stream: K, fields: Vec<(String, String)>) -> Result<String, Err> {
+        (**self).append(stream, fields)
+    }
+
+    fn read_from(&self, stream: K, after: &str, count: usize) -> Result<Vec<StreamEntry>, Err> {
+        (**self).read_from(stream, after, count)
+    }
+
+    fn stream_len(&self, stream: K) -> Result<usize, Err> {
+        (**self).stream_len(stream)
+    }
+
+    fn trim(&self, stream: K, max_len: usize) -> Result<usize, Err> {
+        (**self).trim(stream, max_len)
+    }
+
+    fn create_group(&self, stream: K, group: &str, from: &str) -> Result<bool, Err> {
+        (**self).create_group(stream, group, from)
+    }
+
+    fn read_group(
+        &self,
+        stream: K,
+        group: &str,
+        consumer: &str,
+        count: usize,
+    ) -> Result<Vec<StreamEntry>, Err> {
+        (**self).read_group(stream, group, consumer, count)
+    }
+
+    fn read_pending(
+        &self,
+        stream: K,
+        group: &str,
+        consumer: &str,
+        count: usize,
+    ) -> Result<Vec<StreamEntry>, Err> {
+        (**self).read_pending(stream, group, consumer, count)
+    }
+
+    fn ack(&self, stream: K, group: &str, ids: Vec<String>) -> Result<usize, Err> {
+        (**self).ack(stream, group, ids)
+    }
+}
+
+impl<K, S: StreamStorage<K> + ?Sized> StreamStorage<K> for Arc<S> {
+    fn append(&self, stream: K, fields: Vec<(String, String)>) -> Result<String, Err> {
+        (**self).append(stream, fields)
+    }
+
+    fn read_from(&self, stream: K, after: &str, count: usize) -> Result<Vec<StreamEntry>, Err> {
+        (**self).read_from(stream, after, count)
+    }
+
+    fn stream_len(&self, stream: K) -> Result<usize, Err> {
+        (**self).stream_len(stream)
+    }
+
+    fn trim(&self, stream: K, max_len: usize) -> Result<usize, Err> {
+        (**self).trim(stream, max_len)
+    }
+
+    fn create_group(&self, stream: K, group: &str, from: &str) -> Result<bool, Err> {
+        (**self).create_group(stream, group, from)
+    }
+
+    fn read_group(
+        &self,
+        stream: K,
+        group: &str,
+        consumer: &str,
+        count: usize,
+    ) -> Result<Vec<StreamEntry>, Err> {
+        (**self).read_group(stream, group, consumer, count)
+    }
+
+    fn read_pending(
+        &self,
+        stream: K,
+        group: &str,
+        consumer: &str,
+        count: usize,
+    ) -> Result<Vec<StreamEntry>, Err> {
+        (**self).read_pending(stream, group, consumer, count)
+    }
+
+    fn ack(&self, stream: K, group: &str, ids: Vec<String>) -> Result<usize, Err> {
+        (**self).ack(stream, group, ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(value: &str) -> Vec<(String, String)> {
+        vec![("event".to_string(), value.to_string())]
+    }
+
+    #[test]
+    fn test_append_read() {
+        let mut stream = Stream::default();
+        let first = stream.append(fields("a"));
+        let second = stream.append(fields("b"));
+        assert!(StreamId::parse(&first).unwrap() < StreamId::parse(&second).unwrap());
+
+        let all = stream.read_from("0", 10).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[1].fields, fields("b"));
+        assert_eq!(stream.read_from(&first, 10).unwrap()[0].id, second);
+        assert!(stream.read_from("bogus", 10).is_err());
+
+        assert_eq!(stream.trim(1), 1);
+        assert_eq!(stream.read_from("0", 10).unwrap()[0].id, second);
+    }
+
+    #[test]
+    fn test_group() {
+        let mut stream = Stream::default();
+        stream.append(fields("old"));
+        assert!(stream.create_group("workers", "$").unwrap());
+        assert!(!stream.create_group("workers", "0").unwrap());
+        let id = stream.append(fields("new"));
+        stream.append(fields("newer"));
+
+        let read = stream.read_group("workers", "alice", 1).unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].id, id);
+        // Each entry goes to one consumer.
+        assert_eq!(
+            stream.read_group("workers", "bob", 10).unwrap()[0].fields,
+            fields("newer")
+        );
+        assert!(stream.read_group("workers", "bob", 10).unwrap().is_empty());
+
+        assert_eq!(stream.read_pending("workers", "alice", 10).unwrap(), read);
+        assert_eq!(stream.ack("workers", &[id.clone(), id]).unwrap(), 1);
+        assert!(stream
+            .read_pending("workers", "alice", 10)
+            .unwrap()
+            .is_empty());
+        assert!(stream.read_group("nobody", "alice", 1).is_err());
+    }
+}
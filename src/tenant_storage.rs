@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::admin::AdminStorage;
+use crate::error::StorageError;
+use crate::flush::FlushNamespace;
+use crate::health::HealthStatus;
+use crate::namespaced_storage::NamespacedStorage;
+use crate::scan::{KeyIter, ScanStorage};
+use crate::storage::{Err, Storage};
+
+// Calls a tenant may make until its usage is reset, `None` for no limit. Deletes are never
+// limited, so a tenant over its quota can still free space.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TenantQuota {
+    pub max_reads: Option<u64>,
+    pub max_writes: Option<u64>,
+}
+
+// Calls made since the last reset, batch calls count once per key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TenantUsage {
+    pub reads: u64,
+    pub writes: u64,
+    pub deletes: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    reads: AtomicU64,
+    writes: AtomicU64,
+    deletes: AtomicU64,
+}
+
+// Hands out per-tenant handles to one shared backend. Each tenant's keys live under
+// `<tenant>:`, so tenants can't read or clear each other's keys, and tenant ids containing
+// `:` are rejected as they could reach into another tenant's keys. Usage is counted in this
+// process and shared by every handle of a tenant, processes sharing a backend count
+// separately.
+pub struct TenantStorage<S> {
+    inner: Arc<S>,
+    quota: TenantQuota,
+    usage: Mutex<HashMap<String, Arc<Counters>>>,
+}
+
+impl<S> TenantStorage<S> {
+    pub fn new(inner: S) -> Self {
+        TenantStorage {
+            inner: Arc::new(inner),
+            quota: TenantQuota::default(),
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Applies to every tenant, unlimited by default.
+    pub fn quota(mut self, quota: TenantQuota) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    // A handle for `tenant`, cheap enough to make per request.
+    pub fn scoped(&self, tenant: &str) -> Result<TenantScope<S>, Err> {
+        if tenant.is_empty() || tenant.contains(':') {
+            return Err(format!("invalid tenant id {:?}", tenant).into());
+        }
+        let counters = self.lock().entry(tenant.to_string()).or_default().clone();
+        Ok(TenantScope {
+            storage: NamespacedStorage::new(self.inner.clone(), &format!("{}:", tenant)),
+            tenant: tenant.to_string(),
+            quota: self.quota,
+            counters,
+        })
+    }
+
+    pub fn usage(&self, tenant: &str) -> TenantUsage {
+        self.lock()
+            .get(tenant)
+            .map_or_else(TenantUsage::default, |c| c.usage())
+    }
+
+    // Starts a new quota period for `tenant`, e.g. from a daily job.
+    pub fn reset_usage(&self, tenant: &str) {
+        if let Some(counters) = self.lock().get(tenant) {
+            counters.reads.store(0, Ordering::SeqCst);
+            counters.writes.store(0, Ordering::SeqCst);
+            counters.deletes.store(0, Ordering::SeqCst);
+        }
+    }
+
+    // Tenants scoped in this process, in no particular order.
+    pub fn tenants(&self) -> Vec<String> {
+        self.lock().keys().cloned().collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Arc<Counters>>> {
+        self.usage
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Counters {
+    fn usage(&self) -> TenantUsage {
+        TenantUsage {
+            reads: self.reads.load(Ordering::SeqCst),
+            writes: self.writes.load(Ordering::SeqCst),
+            deletes: self.deletes.load(Ordering::SeqCst),
+        }
+    }
+}
+
+// Counts `n` calls unless that would go over `max`, a denied batch doesn't count.
+fn charge(counter: &AtomicU64, n: usize, max: Option<u64>) -> Result<(), Err> {
+    let n = n as u64;
+    counter
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| match max {
+            Some(max) if used + n > max => None,
+            _ => Some(used + n),
+        })
+        .map(|_| ())
+        .map_err(|_| StorageError::QuotaExceeded.into())
+}
+
+// One tenant's view of a `TenantStorage`. Calls over the tenant's quota fail with
+// `StorageError::QuotaExceeded`. Closing a handle doesn't close the shared backend.
+pub struct TenantScope<S> {
+    storage: NamespacedStorage<Arc<S>>,
+    tenant: String,
+    quota: TenantQuota,
+    counters: Arc<Counters>,
+}
+
+impl<S> Clone for TenantScope<S> {
+    fn clone(&self) -> Self {
+        TenantScope {
+            storage: NamespacedStorage::new(self.storage.inner().clone(), self.storage.prefix()),
+            tenant: self.tenant.clone(),
+            quota: self.quota,
+            counters: self.counters.clone(),
+        }
+    }
+}
+
+impl<S> TenantScope<S> {
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
+    pub fn usage(&self) -> TenantUsage {
+        self.counters.usage()
+    }
+
+    fn read(&self, n: usize) -> Result<(), Err> {
+        charge(&self.counters.reads, n, self.quota.max_reads)
+    }
+
+    fn write(&self, n: usize) -> Result<(), Err> {
+        charge(&self.counters.writes, n, self.quota.max_writes)
+    }
+
+    fn delete(&self, n: usize) {
+        self.counters.deletes.fetch_add(n as u64, Ordering::SeqCst);
+    }
+}
+
+impl<K, V, S> Storage<K, V> for TenantScope<S>
+where
+    K: AsRef<str>,
+    S: Storage<String, V>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.write(1)?;
+        self.storage.set(key, value)
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.write(1)?;
+        self.storage.set_ex(key, value, expire)
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.read(1)?;
+        self.storage.get(key)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.delete(1);
+        self.storage.del(key)
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.read(1)?;
+        self.storage.contains(key)
+    }
+
+    fn is_closed(&self) -> bool {
+        Storage::<K, V>::is_closed(&self.storage)
+    }
+
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        Storage::<K, V>::health_check(&self.storage)
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        self.read(keys.len())?;
+        self.storage.get_many(keys)
+    }
+
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.write(entries.len())?;
+        self.storage.set_many(entries)
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        self.delete(keys.len());
+        self.storage.del_many(keys)
+    }
+}
+
+// Lists only this tenant's keys, without its prefix.
+impl<K, S> ScanStorage<K> for TenantScope<S>
+where
+    K: From<String>,
+    S: ScanStorage<String>,
+{
+    fn scan_prefix(&self, prefix: &str) -> Result<KeyIter<'_, K>, Err> {
+        self.storage.scan_prefix(prefix)
+    }
+}
+
+// `clear` removes every key of this tenant and nobody else's.
+impl<S> AdminStorage for TenantScope<S>
+where
+    S: FlushNamespace + ScanStorage<String>,
+{
+    fn clear(&self) -> Result<(), Err> {
+        self.storage.clear()
+    }
+
+    fn len(&self) -> Result<usize, Err> {
+        self.storage.len()
+    }
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::{DashMapStorage, DashMapStorageBuilder};
+
+    fn tenants() -> TenantStorage<DashMapStorage<String, u32>> {
+        TenantStorage::new(DashMapStorageBuilder::new().build())
+    }
+
+    #[test]
+    fn test_isolation() {
+        let storage = tenants();
+        let acme = storage.scoped("acme").unwrap();
+        let globex = storage.scoped("globex").unwrap();
+        acme.set("a", 1).unwrap();
+        acme.set("b", 2).unwrap();
+        globex.set("a", 3).unwrap();
+
+        assert_eq!(acme.get("a").unwrap(), Some(1));
+        assert_eq!(globex.get("a").unwrap(), Some(3));
+        assert_eq!(storage.inner().get("acme:b".to_string()).unwrap(), Some(2));
+
+        acme.clear().unwrap();
+        assert!(acme.is_empty().unwrap());
+        assert_eq!(globex.len().unwrap(), 1);
+        assert!(storage.scoped("acme:b").is_err());
+        assert!(storage.scoped("").is_err());
+    }
+
+    #[test]
+    fn test_quota() {
+        let storage = tenants().quota(TenantQuota {
+            max_reads: None,
+            max_writes: Some(2),
+        });
+        let acme = storage.scoped("acme").unwrap();
+        acme.set("a", 1).unwrap();
+        // Handles of one tenant share its usage, a batch over the quota is denied whole.
+        let again = storage.scoped("acme").unwrap();
+        let resp = again.set_many(vec![("b", 2), ("c", 3)]);
+        let e = resp.unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<StorageError>(),
+            Some(&StorageError::QuotaExceeded)
+        );
+        again.set("b", 2).unwrap();
+        assert!(acme.set("c", 3).is_err());
+        acme.del("a").unwrap();
+        storage.scoped("globex").unwrap().set("a", 1).unwrap();
+
+        assert_eq!(
+            storage.usage("acme"),
+            TenantUsage {
+                reads: 0,
+                writes: 2,
+                deletes: 1,
+            }
+        );
+        storage.reset_usage("acme");
+        acme.set("c", 3).unwrap();
+        assert_eq!(acme.usage().writes, 1);
+    }
+}
@@ -0,0 +1,156 @@
+// The checks `storage_conformance_tests!` runs, for backends that want to pick their own.
+// They use `conformance:`-prefixed keys, so a shared server only needs that prefix free.
+use std::thread;
+use std::time::Duration;
+
+use crate::storage::Storage;
+
+const TTL: Duration = Duration::from_millis(200);
+
+fn key(test: &str, n: usize) -> String {
+    format!("conformance:{}:{}", test, n)
+}
+
+pub fn set_get_del<S: Storage<String, String>>(storage: &S) {
+    let k = key("set_get_del", 0);
+    assert_eq!(storage.get(k.clone()).unwrap(), None);
+    assert!(!storage.contains(k.clone()).unwrap());
+
+    storage.set(k.clone(), "Ferris".to_string()).unwrap();
+    assert!(storage.contains(k.clone()).unwrap());
+    assert_eq!(storage.get(k.clone()).unwrap().as_deref(), Some("Ferris"));
+
+    storage.set(k.clone(), "Corro".to_string()).unwrap();
+    assert_eq!(storage.get(k.clone()).unwrap().as_deref(), Some("Corro"));
+
+    assert_eq!(storage.del(k.clone()).unwrap(), Some(k.clone()));
+    assert_eq!(storage.del(k.clone()).unwrap(), None);
+    assert_eq!(storage.get(k.clone()).unwrap(), None);
+    assert!(!storage.contains(k).unwrap());
+}
+
+pub fn set_ex<S: Storage<String, String>>(storage: &S) {
+    let (short, overwritten) = (key("set_ex", 0), key("set_ex", 1));
+    storage
+        .set_ex(short.clone(), "short".to_string(), TTL)
+        .unwrap();
+    storage
+        .set_ex(overwritten.clone(), "short".to_string(), TTL)
+        .unwrap();
+    assert_eq!(
+        storage.get(short.clone()).unwrap().as_deref(),
+        Some("short")
+    );
+    // A plain `set` drops the expiry.
+    storage
+        .set(overwritten.clone(), "kept".to_string())
+        .unwrap();
+
+    thread::sleep(TTL * 2);
+    assert_eq!(storage.get(short.clone()).unwrap(), None);
+    assert!(!storage.contains(short.clone()).unwrap());
+    assert_eq!(storage.del(short).unwrap(), None);
+    assert_eq!(
+        storage.get(overwritten.clone()).unwrap().as_deref(),
+        Some("kept")
+    );
+    storage.del(overwritten).unwrap();
+}
+
+pub fn many<S: Storage<String, String>>(storage: &S) {
+    let keys: Vec<String> = (0..3).map(|n| key("many", n)).collect();
+    storage
+        .set_many(vec![
+            (keys[0].clone(), "a".to_string()),
+            (keys[1].clone(), "b".to_string()),
+        ])
+        .unwrap();
+    assert_eq!(
+        storage.get_many(keys.clone()).unwrap(),
+        [Some("a".to_string()), Some("b".to_string()), None]
+    );
+    assert_eq!(
+        storage.del_many(keys.clone()).unwrap(),
+        [Some(keys[0].clone()), Some(keys[1].clone()), None]
+    );
+    assert_eq!(storage.get_many(keys).unwrap(), [None, None, None]);
+}
+
+// Writers on distinct keys all land, writers racing on one key leave one of their values.
+pub fn concurrent<S: Storage<String, String> + Sync>(storage: &S) {
+    const THREADS: usize = 8;
+    const KEYS: usize = 32;
+    let shared = key("concurrent", usize::MAX);
+    thread::scope(|scope| {
+        for t in 0..THREADS {
+            let shared = shared.clone();
+            scope.spawn(move || {
+                for n in 0..KEYS {
+                    storage
+                        .set(key("concurrent", t * KEYS + n), t.to_string())
+                        .unwrap();
+                    storage.set(shared.clone(), t.to_string()).unwrap();
+                }
+            });
+        }
+    });
+
+    for t in 0..THREADS {
+        for n in 0..KEYS {
+            let k = key("concurrent", t * KEYS + n);
+            assert_eq!(storage.get(k.clone()).unwrap(), Some(t.to_string()));
+            storage.del(k).unwrap();
+        }
+    }
+    let last: usize = storage
+        .get(shared.clone())
+        .unwrap()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(last < THREADS);
+    storage.del(shared).unwrap();
+}
+
+// Generates a test module `$name` running every check against a fresh `$storage` expression,
+// which must build a `Storage<String, String> + Sync`. Pass `no_ttl` for backends without
+// expiry.
+//
+//     storage_conformance_tests!(my_storage, MyStorage::open_temp());
+#[macro_export]
+macro_rules! storage_conformance_tests {
+    (@module $name:ident, $storage:expr, { $($extra:tt)* }) => {
+        mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #[test]
+            fn set_get_del() {
+                $crate::testkit::set_get_del(&$storage);
+            }
+
+            #[test]
+            fn many() {
+                $crate::testkit::many(&$storage);
+            }
+
+            #[test]
+            fn concurrent() {
+                $crate::testkit::concurrent(&$storage);
+            }
+
+            $($extra)*
+        }
+    };
+    ($name:ident, $storage:expr, no_ttl) => {
+        $crate::storage_conformance_tests!(@module $name, $storage, {});
+    };
+    ($name:ident, $storage:expr) => {
+        $crate::storage_conformance_tests!(@module $name, $storage, {
+            #[test]
+            fn set_ex() {
+                $crate::testkit::set_ex(&$storage);
+            }
+        });
+    };
+}
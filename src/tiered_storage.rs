@@ -0,0 +1,309 @@
+use std::time::Duration;
+
+use crate::error::BuilderError;
+use crate::health::HealthStatus;
+use crate::options::{Consistency, OpOptions};
+use crate::storage::{CloseFlag, Err, Storage};
+
+// Read-through cache: reads try L1 first and fill it from L2 on a miss, writes go to L2 and
+// then L1. L2 is the source of truth, L1 failures never fail a call. L1 copies filled on a
+// miss don't know the TTL left in L2, set `l1_ttl` when L2 entries expire or other writers
+// share L2. `Consistency::Strong` reads skip L1.
+pub struct TieredStorage<L1, L2> {
+    l1: L1,
+    l2: L2,
+    l1_ttl: Option<Duration>,
+    closed: CloseFlag,
+}
+
+impl<L1, L2> TieredStorage<L1, L2> {
+    pub fn l1(&self) -> &L1 {
+        &self.l1
+    }
+
+    pub fn l2(&self) -> &L2 {
+        &self.l2
+    }
+
+    // The TTL of an L1 copy, given the TTL written to L2.
+    fn l1_expire(&self, expire: Option<Duration>) -> Option<Duration> {
+        match (expire, self.l1_ttl) {
+            (Some(expire), Some(ttl)) => Some(expire.min(ttl)),
+            (expire, ttl) => expire.or(ttl),
+        }
+    }
+
+    // Best effort: a copy that can't be written is dropped so L1 never serves a stale value.
+    fn fill<K: Clone, V>(&self, key: K, value: V, expire: Option<Duration>)
+    where
+        L1: Storage<K, V>,
+    {
+        let resp = match self.l1_expire(expire) {
+            Some(expire) => self.l1.set_ex(key.clone(), value, expire),
+            None => self.l1.set(key.clone(), value),
+        };
+        if resp.is_err() {
+            let _ = self.l1.del(key);
+        }
+    }
+}
+
+impl<K, V, L1, L2> Storage<K, V> for TieredStorage<L1, L2>
+where
+    K: Clone,
+    V: Clone,
+    L1: Storage<K, V>,
+    L2: Storage<K, V>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.set_with_opts(key, value, &OpOptions::default())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.set_with_opts(key, value, &OpOptions::new().ttl(expire))
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.get_with_opts(key, &OpOptions::default())
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.del_with_opts(key, &OpOptions::default())
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.contains_with_opts(key, &OpOptions::default())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        if !self.closed.close() {
+            return Ok(());
+        }
+        let l1 = self.l1.close();
+        self.l2.close()?;
+        l1
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+
+    // L2 decides, a failing L1 only degrades the storage.
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.closed.check()?;
+        let mut status = self.l2.health_check()?;
+        status.part("l1", self.l1.health_check());
+        Ok(status)
+    }
+
+    // Only the L1 misses are fetched from L2, with one `get_many`.
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        self.closed.check()?;
+        let mut values = self
+            .l1
+            .get_many(keys.clone())
+            .unwrap_or_else(|_| vec![None; keys.len()]);
+        let misses: Vec<usize> = (0..keys.len()).filter(|i| values[*i].is_none()).collect();
+        if misses.is_empty() {
+            return Ok(values);
+        }
+
+        let fetched = self
+            .l2
+            .get_many(misses.iter().map(|i| keys[*i].clone()).collect())?;
+        for (i, value) in misses.into_iter().zip(fetched) {
+            if let Some(value) = &value {
+                self.fill(keys[i].clone(), value.clone(), None);
+            }
+            values[i] = value;
+        }
+        Ok(values)
+    }
+
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.closed.check()?;
+        self.l2.set_many(entries.clone())?;
+        for (key, value) in entries {
+            self.fill(key, value, None);
+        }
+        Ok(())
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        self.closed.check()?;
+        let removed = self.l2.del_many(keys.clone())?;
+        let _ = self.l1.del_many(keys);
+        Ok(removed)
+    }
+
+    fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+        self.closed.check()?;
+        self.l2.set_with_opts(key.clone(), value.clone(), opts)?;
+        self.fill(key, value, opts.ttl);
+        Ok(())
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        if opts.consistency != Consistency::Strong {
+            if let Ok(Some(value)) = self.l1.get_with_opts(key.clone(), opts) {
+                return Ok(Some(value));
+            }
+        }
+
+        let value = self.l2.get_with_opts(key.clone(), opts)?;
+        match &value {
+            Some(value) => self.fill(key, value.clone(), None),
+            None => {
+                let _ = self.l1.del(key);
+            }
+        }
+        Ok(value)
+    }
+
+    // L2 first, so a concurrent miss can't copy the old value back into L1 afterwards.
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let removed = self.l2.del_with_opts(key.clone(), opts)?;
+        let _ = self.l1.del(key);
+        Ok(removed)
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        self.closed.check()?;
+        if opts.consistency != Consistency::Strong
+            && matches!(self.l1.contains_with_opts(key.clone(), opts), Ok(true))
+        {
+            return Ok(true);
+        }
+        self.l2.contains_with_opts(key, opts)
+    }
+}
+
+pub struct TieredStorageBuilder<L1, L2> {
+    l1: L1,
+    l2: L2,
+    l1_ttl: Option<Duration>,
+}
+
+impl<L1, L2> TieredStorageBuilder<L1, L2> {
+    pub fn new(l1: L1, l2: L2) -> Self {
+        TieredStorageBuilder {
+            l1,
+            l2,
+            l1_ttl: None,
+        }
+    }
+
+    // Upper bound on how long L1 keeps a copy, shorter L2 TTLs still win.
+    pub fn l1_ttl(mut self, ttl: Duration) -> Self {
+        self.l1_ttl = Some(ttl);
+        self
+    }
+
+    // # Panics
+    // Panics if the L1 TTL is zero.
+    pub fn build(self) -> TieredStorage<L1, L2> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build tiered storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<TieredStorage<L1, L2>, BuilderError> {
+        if self.l1_ttl.is_some_and(|ttl| ttl.is_zero()) {
+            return Err(BuilderError::invalid("l1_ttl", "must not be zero"));
+        }
+
+        Ok(TieredStorage {
+            l1: self.l1,
+            l2: self.l2,
+            l1_ttl: self.l1_ttl,
+            closed: CloseFlag::default(),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::{DashMapStorage, DashMapStorageBuilder};
+
+    type Map = DashMapStorage<&'static str, u32>;
+
+    fn tiered() -> TieredStorage<Map, Map> {
+        TieredStorageBuilder::new(
+            DashMapStorageBuilder::new().build(),
+            DashMapStorageBuilder::new().build(),
+        )
+        .l1_ttl(Duration::from_millis(50))
+        .build()
+    }
+
+    #[test]
+    fn test_read_through() {
+        let storage = tiered();
+        storage.l2().set("a", 1).unwrap();
+        assert_eq!(storage.l1().get("a").unwrap(), None);
+
+        assert_eq!(storage.get("a").unwrap(), Some(1));
+        assert_eq!(storage.l1().get("a").unwrap(), Some(1));
+
+        storage.l2().set("a", 2).unwrap();
+        assert_eq!(storage.get("a").unwrap(), Some(1));
+        let strong = OpOptions::new().consistency(Consistency::Strong);
+        assert_eq!(storage.get_with_opts("a", &strong).unwrap(), Some(2));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(storage.l1().get("a").unwrap(), None);
+    }
+
+    #[test]
+    fn test_write_through() {
+        let storage = tiered();
+        storage.set("a", 1).unwrap();
+        storage.set_ex("b", 2, Duration::from_secs(60)).unwrap();
+        assert_eq!(storage.l1().get("a").unwrap(), Some(1));
+        assert_eq!(storage.l2().get("b").unwrap(), Some(2));
+
+        assert_eq!(storage.del("a").unwrap(), Some("a"));
+        assert!(!storage.l1().contains("a").unwrap());
+        assert!(!storage.contains("a").unwrap());
+    }
+
+    #[test]
+    fn test_get_many() {
+        let storage = tiered();
+        storage.l1().set("a", 1).unwrap();
+        storage.l2().set("b", 2).unwrap();
+
+        assert_eq!(
+            storage.get_many(vec!["a", "b", "c"]).unwrap(),
+            [Some(1), Some(2), None]
+        );
+        assert_eq!(storage.l1().get("b").unwrap(), Some(2));
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = TieredStorageBuilder::new(
+            DashMapStorageBuilder::<&str, u32>::new().build(),
+            DashMapStorageBuilder::<&str, u32>::new().build(),
+        )
+        .l1_ttl(Duration::ZERO)
+        .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_health_check() {
+        let storage = tiered();
+        assert!(!storage.health_check().unwrap().is_degraded());
+
+        storage.l1().close().unwrap();
+        let status = storage.health_check().unwrap();
+        assert_eq!(status.degraded, ["l1: storage is closed"]);
+        storage.l2().close().unwrap();
+        assert!(storage.health_check().is_err());
+    }
+}
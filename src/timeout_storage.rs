@@ -0,0 +1,227 @@
+use std::error::Error;
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+
+use crate::error::StorageError;
+use crate::health::HealthStatus;
+use crate::options::OpOptions;
+use crate::storage::{Err, Storage};
+
+// Gives every call a deadline `timeout` from when it starts, or keeps the caller's deadline
+// if that is earlier. Calls go through the `*_with_opts` methods, so the bound is as tight
+// as the backend makes it: redis turns the deadline into socket timeouts, backends on the
+// default methods only check it before calling and can still block past it. Batch methods
+// take no options, so batches run as single-key calls sharing one deadline, giving up MGET
+// and MSET. Timeouts reported by the backend, redis and io ones, come back as
+// `StorageError::Timeout` like a passed deadline. `health_check` is forwarded unbounded.
+pub struct TimeoutStorage<S> {
+    inner: S,
+    timeout: Duration,
+}
+
+impl<S> TimeoutStorage<S> {
+    pub fn new(inner: S, timeout: Duration) -> Self {
+        TimeoutStorage { inner, timeout }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn bounded(&self, opts: &OpOptions) -> OpOptions {
+        let deadline = Instant::now() + self.timeout;
+        OpOptions {
+            deadline: Some(opts.deadline.map_or(deadline, |d| d.min(deadline))),
+            ..opts.clone()
+        }
+    }
+}
+
+fn timed_out<T>(resp: Result<T, Err>) -> Result<T, Err> {
+    resp.map_err(|e| match is_timeout(e.as_ref()) {
+        true => StorageError::Timeout.into(),
+        false => e,
+    })
+}
+
+fn is_timeout(e: &(dyn Error + 'static)) -> bool {
+    #[cfg(feature = "redis")]
+    if let Some(e) = e.downcast_ref::<redis::RedisError>() {
+        return e.is_timeout();
+    }
+    e.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock))
+}
+
+impl<K, V, S> Storage<K, V> for TimeoutStorage<S>
+where
+    S: Storage<K, V>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.set_with_opts(key, value, &OpOptions::default())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.set_with_opts(key, value, &OpOptions::new().ttl(expire))
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.get_with_opts(key, &OpOptions::default())
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.del_with_opts(key, &OpOptions::default())
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.contains_with_opts(key, &OpOptions::default())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.inner.close()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.inner.health_check()
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        let opts = self.bounded(&OpOptions::default());
+        let values = keys
+            .into_iter()
+            .map(|key| self.inner.get_with_opts(key, &opts))
+            .collect();
+        timed_out(values)
+    }
+
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        let opts = self.bounded(&OpOptions::default());
+        for (key, value) in entries {
+            timed_out(self.inner.set_with_opts(key, value, &opts))?;
+        }
+        Ok(())
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        let opts = self.bounded(&OpOptions::default());
+        let keys = keys
+            .into_iter()
+            .map(|key| self.inner.del_with_opts(key, &opts))
+            .collect();
+        timed_out(keys)
+    }
+
+    fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+        timed_out(self.inner.set_with_opts(key, value, &self.bounded(opts)))
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        timed_out(self.inner.get_with_opts(key, &self.bounded(opts)))
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        timed_out(self.inner.del_with_opts(key, &self.bounded(opts)))
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        timed_out(self.inner.contains_with_opts(key, &self.bounded(opts)))
+    }
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::DashMapStorageBuilder;
+    use std::sync::Mutex;
+
+    // Remembers the deadline of the last call.
+    #[derive(Default)]
+    struct Recorder {
+        deadline: Mutex<Option<Instant>>,
+    }
+
+    impl Storage<&'static str, u32> for Recorder {
+        fn set(&self, _key: &'static str, _value: u32) -> Result<(), Err> {
+            Ok(())
+        }
+
+        fn set_ex(&self, _key: &'static str, _value: u32, _expire: Duration) -> Result<(), Err> {
+            Ok(())
+        }
+
+        fn get(&self, _key: &'static str) -> Result<Option<u32>, Err> {
+            Ok(None)
+        }
+
+        fn del(&self, _key: &'static str) -> Result<Option<&'static str>, Err> {
+            Ok(None)
+        }
+
+        fn contains(&self, _key: &'static str) -> Result<bool, Err> {
+            Ok(false)
+        }
+
+        fn get_with_opts(&self, key: &'static str, opts: &OpOptions) -> Result<Option<u32>, Err> {
+            *self.deadline.lock().unwrap() = opts.deadline;
+            match key {
+                "slow" => Err(std::io::Error::from(ErrorKind::TimedOut).into()),
+                key => self.get(key),
+            }
+        }
+    }
+
+    #[test]
+    fn test_deadline() {
+        let storage = TimeoutStorage::new(Recorder::default(), Duration::from_secs(10));
+        let before = Instant::now();
+        storage.get("a").unwrap();
+        let deadline = storage.inner().deadline.lock().unwrap().unwrap();
+        assert!(deadline >= before + Duration::from_secs(10));
+
+        // An earlier deadline from the caller wins, a later one doesn't.
+        let soon = Instant::now() + Duration::from_secs(1);
+        storage
+            .get_with_opts("a", &OpOptions::new().deadline(soon))
+            .unwrap();
+        assert_eq!(*storage.inner().deadline.lock().unwrap(), Some(soon));
+
+        let later = Instant::now() + Duration::from_secs(60);
+        storage
+            .get_with_opts("a", &OpOptions::new().deadline(later))
+            .unwrap();
+        assert!(storage.inner().deadline.lock().unwrap().unwrap() < later);
+    }
+
+    #[test]
+    fn test_batches() {
+        let storage = TimeoutStorage::new(Recorder::default(), Duration::from_secs(10));
+        assert_eq!(storage.get_many(vec!["a", "b"]).unwrap(), [None, None]);
+        assert!(storage.inner().deadline.lock().unwrap().is_some());
+
+        // The backend's own timeout is reported like a passed deadline.
+        let e = storage.get_many(vec!["a", "slow"]).unwrap_err();
+        assert_eq!(e.downcast_ref(), Some(&StorageError::Timeout));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let storage = TimeoutStorage::new(
+            DashMapStorageBuilder::<&str, u32>::new().build(),
+            Duration::ZERO,
+        );
+        let e = storage.set("a", 1).unwrap_err();
+        assert_eq!(e.downcast_ref(), Some(&StorageError::Timeout));
+
+        let storage = TimeoutStorage::new(storage.inner, Duration::from_secs(1));
+        storage.set_ex("a", 1, Duration::from_secs(60)).unwrap();
+        assert_eq!(storage.get("a").unwrap(), Some(1));
+    }
+}
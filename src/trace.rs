@@ -0,0 +1,210 @@
+// Spans for backend operations with the `tracing` feature. Every operation of `RedisStorage`
+// and `DashMapStorage` runs in a `storage` span at debug level carrying `backend`, `op`, `key`,
+// `result` (`ok`, `hit`, `miss` or `error`) and `elapsed_us`; redis adds `acquire_us`, the
+// time spent checking a connection out of the pool. Without the feature `traced!` is just
+// its body.
+#[cfg(feature = "tracing")]
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "tracing")]
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[cfg(feature = "tracing")]
+use crate::sharded_storage::Fnv1aHasher;
+#[cfg(feature = "tracing")]
+use crate::storage::Err;
+
+// How keys show up in spans. Keys often hold user ids or emails, so they are hashed unless
+// asked otherwise.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceKeys {
+    Omit,
+    #[default]
+    Hashed,
+    // The key itself where the backend sees its bytes (redis), hashed elsewhere.
+    Plain,
+}
+
+#[cfg(feature = "tracing")]
+static TRACE_KEYS: AtomicU8 = AtomicU8::new(TraceKeys::Hashed as u8);
+
+// Applies to every storage in the process.
+#[cfg(feature = "tracing")]
+pub fn set_trace_keys(mode: TraceKeys) {
+    TRACE_KEYS.store(mode as u8, Ordering::Relaxed);
+}
+
+#[cfg(feature = "tracing")]
+pub fn trace_keys() -> TraceKeys {
+    match TRACE_KEYS.load(Ordering::Relaxed) {
+        0 => TraceKeys::Omit,
+        2 => TraceKeys::Plain,
+        _ => TraceKeys::Hashed,
+    }
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn hashed_key<K: Hash + ?Sized>(key: &K) -> Option<String> {
+    if trace_keys() == TraceKeys::Omit {
+        return None;
+    }
+    let mut hasher = Fnv1aHasher::default();
+    key.hash(&mut hasher);
+    Some(format!("{:016x}", hasher.finish()))
+}
+
+#[cfg(all(feature = "tracing", feature = "redis"))]
+pub(crate) fn redis_key<K: redis::ToRedisArgs>(key: &K) -> Option<String> {
+    let bytes = key.to_redis_args().concat();
+    match trace_keys() {
+        TraceKeys::Omit => None,
+        TraceKeys::Hashed => hashed_key(bytes.as_slice()),
+        TraceKeys::Plain => Some(String::from_utf8_lossy(&bytes).into_owned()),
+    }
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn outcome<T>(resp: &Result<T, Err>, ok: fn(&T) -> &'static str) -> &'static str {
+    match resp {
+        Ok(value) => ok(value),
+        Err(_) => "error",
+    }
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn found<V>(value: &Option<V>) -> &'static str {
+    if value.is_some() {
+        "hit"
+    } else {
+        "miss"
+    }
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn ok<T>(_: &T) -> &'static str {
+    "ok"
+}
+
+// `traced!(backend, op, key, outcome, body)`: runs `body` in a span, `key` is an
+// `Option<String>` only computed when the span is enabled and `outcome` names a successful
+// result.
+#[cfg(feature = "tracing")]
+macro_rules! traced {
+    ($backend:literal, $op:literal, $key:expr, $outcome:expr, $body:expr) => {{
+        let span = tracing::debug_span!(
+            "storage",
+            backend = $backend,
+            op = $op,
+            key = tracing::field::Empty,
+            result = tracing::field::Empty,
+            elapsed_us = tracing::field::Empty,
+            acquire_us = tracing::field::Empty,
+        );
+        if !span.is_disabled() {
+            if let Some(key) = $key {
+                span.record("key", key.as_str());
+            }
+        }
+        let _entered = span.enter();
+        let start = std::time::Instant::now();
+        let resp: Result<_, crate::storage::Err> = (|| $body)();
+        span.record("elapsed_us", start.elapsed().as_micros() as u64);
+        span.record("result", crate::trace::outcome(&resp, $outcome));
+        resp
+    }};
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! traced {
+    ($backend:literal, $op:literal, $key:expr, $outcome:expr, $body:expr) => {
+        $body
+    };
+}
+
+pub(crate) use traced;
+
+#[cfg(all(test, feature = "tracing", feature = "dashmap", feature = "redis"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::DashMapStorageBuilder;
+    use crate::storage::Storage;
+    use std::fmt::Debug;
+    use std::sync::atomic::AtomicU64;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata};
+
+    // Collects `name=value` for every field recorded on any span.
+    #[derive(Clone, Default)]
+    struct Fields {
+        fields: Arc<Mutex<Vec<String>>>,
+        next: Arc<AtomicU64>,
+    }
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            let value = format!("{:?}", value).trim_matches('"').to_string();
+            self.fields
+                .lock()
+                .unwrap()
+                .push(format!("{}={}", field.name(), value));
+        }
+    }
+
+    impl tracing::Subscriber for Fields {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            span.record(&mut self.clone());
+            Id::from_u64(self.next.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, _: &Id, values: &Record<'_>) {
+            values.record(&mut self.clone());
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event<'_>) {}
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn test_dashmap_span() {
+        let subscriber = Fields::default();
+        let storage = DashMapStorageBuilder::<&str, u32>::new().build();
+        tracing::subscriber::with_default(subscriber.clone(), || {
+            storage.get("user:1").unwrap();
+        });
+
+        let fields = subscriber.fields.lock().unwrap();
+        for field in [
+            "backend=dashmap",
+            "op=get",
+            "result=miss",
+            &format!("key={}", hashed_key("user:1").unwrap()),
+        ] {
+            assert!(
+                fields.iter().any(|f| f == field),
+                "{} in {:?}",
+                field,
+                fields
+            );
+        }
+        assert!(fields.iter().any(|f| f.starts_with("elapsed_us=")));
+    }
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(trace_keys(), TraceKeys::Hashed);
+        assert_eq!(redis_key(&"user:1"), hashed_key(b"user:1".as_slice()));
+        assert_ne!(hashed_key("user:1"), hashed_key("user:2"));
+        assert_eq!(hashed_key("user:1").unwrap().len(), 16);
+    }
+}
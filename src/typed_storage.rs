@@ -0,0 +1,299 @@
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::health::HealthStatus;
+use crate::merge::{Merge, MergeableStorage};
+use crate::options::OpOptions;
+use crate::storage::{Err, Storage};
+
+// Turns values into the representation `R` a backend stores, usually `Vec<u8>` or `String`.
+pub trait Codec<R> {
+    fn encode<T: Serialize>(&self, value: &T) -> Result<R, Err>;
+    fn decode<T: DeserializeOwned>(&self, raw: R) -> Result<T, Err>;
+}
+
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl Codec<Vec<u8>> for Json {
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Err> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, raw: Vec<u8>) -> Result<T, Err> {
+        Ok(serde_json::from_slice(&raw)?)
+    }
+}
+
+#[cfg(feature = "json")]
+impl Codec<String> for Json {
+    fn encode<T: Serialize>(&self, value: &T) -> Result<String, Err> {
+        Ok(serde_json::to_string(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, raw: String) -> Result<T, Err> {
+        Ok(serde_json::from_str(&raw)?)
+    }
+}
+
+// Compact and fast, but without field names: values written before a struct changed shape
+// don't decode after it.
+#[cfg(feature = "bincode")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bincode;
+
+#[cfg(feature = "bincode")]
+impl Codec<Vec<u8>> for Bincode {
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Err> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, raw: Vec<u8>) -> Result<T, Err> {
+        Ok(bincode::deserialize(&raw)?)
+    }
+}
+
+// MessagePack with struct fields written by name, so fields can be added like with `Json`.
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MsgPack;
+
+#[cfg(feature = "msgpack")]
+impl Codec<Vec<u8>> for MsgPack {
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Err> {
+        Ok(rmp_serde::to_vec_named(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, raw: Vec<u8>) -> Result<T, Err> {
+        Ok(rmp_serde::from_slice(&raw)?)
+    }
+}
+
+// Stores any `T: Serialize + DeserializeOwned` in a backend holding `R`s, encoded with `C`.
+// Values that fail to decode are returned as errors, not as misses.
+pub struct TypedStorage<T, S, C, R = Vec<u8>> {
+    inner: S,
+    codec: C,
+    _marker: PhantomData<(T, R)>,
+}
+
+impl<T, S, C: Default, R> TypedStorage<T, S, C, R> {
+    pub fn new(inner: S) -> Self {
+        TypedStorage::with_codec(inner, C::default())
+    }
+}
+
+impl<T, S, C, R> TypedStorage<T, S, C, R> {
+    pub fn with_codec(inner: S, codec: C) -> Self {
+        TypedStorage {
+            inner,
+            codec,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn decode(&self, raw: Option<R>) -> Result<Option<T>, Err>
+    where
+        T: DeserializeOwned,
+        C: Codec<R>,
+    {
+        raw.map(|raw| self.codec.decode(raw)).transpose()
+    }
+}
+
+impl<K, T, S, C, R> Storage<K, T> for TypedStorage<T, S, C, R>
+where
+    T: Serialize + DeserializeOwned,
+    S: Storage<K, R>,
+    C: Codec<R>,
+{
+    fn set(&self, key: K, value: T) -> Result<(), Err> {
+        self.inner.set(key, self.codec.encode(&value)?)
+    }
+
+    fn set_ex(&self, key: K, value: T, expire: Duration) -> Result<(), Err> {
+        self.inner.set_ex(key, self.codec.encode(&value)?, expire)
+    }
+
+    fn get(&self, key: K) -> Result<Option<T>, Err> {
+        self.decode(self.inner.get(key)?)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.inner.del(key)
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.inner.contains(key)
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.inner.close()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.inner.health_check()
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<T>>, Err> {
+        self.inner
+            .get_many(keys)?
+            .into_iter()
+            .map(|raw| self.decode(raw))
+            .collect()
+    }
+
+    fn set_many(&self, entries: Vec<(K, T)>) -> Result<(), Err> {
+        let entries = entries
+            .into_iter()
+            .map(|(key, value)| Ok((key, self.codec.encode(&value)?)))
+            .collect::<Result<_, Err>>()?;
+        self.inner.set_many(entries)
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        self.inner.del_many(keys)
+    }
+
+    fn set_with_opts(&self, key: K, value: T, opts: &OpOptions) -> Result<(), Err> {
+        self.inner
+            .set_with_opts(key, self.codec.encode(&value)?, opts)
+    }
+
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<T>, Err> {
+        self.decode(self.inner.get_with_opts(key, opts)?)
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        self.inner.del_with_opts(key, opts)
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        self.inner.contains_with_opts(key, opts)
+    }
+}
+
+// Read-merge-write through the codec.
+impl<K, T, S, C, R> MergeableStorage<K, T> for TypedStorage<T, S, C, R>
+where
+    T: Serialize + DeserializeOwned + Merge,
+    S: Storage<K, R>,
+    C: Codec<R>,
+{
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::DashMapStorageBuilder;
+    #[cfg(any(feature = "json", feature = "bincode", feature = "msgpack"))]
+    use serde::Deserialize;
+
+    #[cfg(any(feature = "json", feature = "bincode", feature = "msgpack"))]
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Session {
+        user_id: u64,
+        roles: Vec<String>,
+    }
+
+    // Writes a fixed tag and refuses to decode anything.
+    struct Tagged;
+
+    impl Codec<String> for Tagged {
+        fn encode<T: Serialize>(&self, _: &T) -> Result<String, Err> {
+            Ok("tagged".to_string())
+        }
+
+        fn decode<T: DeserializeOwned>(&self, raw: String) -> Result<T, Err> {
+            Err(format!("cannot decode {}", raw).into())
+        }
+    }
+
+    #[test]
+    fn test_custom_codec() {
+        let storage: TypedStorage<u32, _, _, String> =
+            TypedStorage::with_codec(DashMapStorageBuilder::<&str, String>::new().build(), Tagged);
+        storage.set("a", 1).unwrap();
+        assert_eq!(
+            storage.inner().get("a").unwrap(),
+            Some("tagged".to_string())
+        );
+        assert!(storage.get("a").is_err());
+        assert_eq!(storage.get("b").unwrap(), None);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json() {
+        let storage: TypedStorage<Session, _, Json> =
+            TypedStorage::new(DashMapStorageBuilder::<&str, Vec<u8>>::new().build());
+
+        let session = Session {
+            user_id: 7,
+            roles: vec!["admin".to_string()],
+        };
+        storage.set("session", session.clone()).unwrap();
+        assert_eq!(storage.get("session").unwrap(), Some(session.clone()));
+        assert_eq!(
+            storage.get_many(vec!["session", "missing"]).unwrap(),
+            [Some(session), None]
+        );
+        assert_eq!(
+            storage.inner().get("session").unwrap(),
+            Some(br#"{"user_id":7,"roles":["admin"]}"#.to_vec())
+        );
+    }
+
+    #[cfg(any(feature = "bincode", feature = "msgpack"))]
+    fn round_trip<C: Codec<Vec<u8>> + Default>() {
+        let storage: TypedStorage<Session, _, C> =
+            TypedStorage::new(DashMapStorageBuilder::<&str, Vec<u8>>::new().build());
+
+        let session = Session {
+            user_id: 7,
+            roles: vec!["admin".to_string()],
+        };
+        storage.set("session", session.clone()).unwrap();
+        assert_eq!(storage.get("session").unwrap(), Some(session));
+        storage.inner().set("broken", vec![0xc1]).unwrap();
+        assert!(storage.get("broken").is_err());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_bincode() {
+        round_trip::<Bincode>();
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack() {
+        round_trip::<MsgPack>();
+        let raw = MsgPack.encode(&Session {
+            user_id: 7,
+            roles: Vec::new(),
+        });
+        assert!(raw.unwrap().windows(7).any(|field| field == b"user_id"));
+    }
+}
@@ -0,0 +1,30 @@
+use std::sync::Arc;
+
+use crate::storage::{Err, Storage};
+
+// Changes a stored value in place, e.g. a field of a session, without the race of a `get`
+// followed by a `set`. Unlike `fetch_update`, missing keys stay missing and the TTL is kept.
+pub trait UpdateStorage<K, V>: Storage<K, V> {
+    // Applies `f` to the key's value and stores the result. Returns whether there was a value
+    // to update. Backends that retry on contention may run `f` more than once.
+    fn update<F>(&self, key: K, f: F) -> Result<bool, Err>
+    where
+        F: FnMut(&mut V);
+}
+
+macro_rules! forward_update {
+    ($($ty:ty),*) => {
+        $(
+            impl<K, V, S: UpdateStorage<K, V> + ?Sized> UpdateStorage<K, V> for $ty {
+                fn update<F>(&self, key: K, f: F) -> Result<bool, Err>
+                where
+                    F: FnMut(&mut V),
+                {
+                    (**self).update(key, f)
+                }
+            }
+        )*
+    };
+}
+
+forward_update!(&S, Box<S>, Arc<S>);
@@ -0,0 +1,437 @@
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::env;
+use crate::error::BuilderError;
+use crate::hooks::{Hooks, StorageHook};
+use crate::secret::SecretString;
+use crate::storage::{CloseFlag, Err, Storage};
+
+#[derive(Clone)]
+pub struct VaultStorage<K, V> {
+    agent: ureq::Agent,
+    addr: String,
+    mount: String,
+    namespace: Option<String>,
+    token: SecretString,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> VaultStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Serialize + DeserializeOwned,
+{
+    pub fn get_version(&self, key: K, version: u64) -> Result<Option<V>, Err> {
+        self.read(key.as_ref(), Some(version))?
+            .map_or(Ok(None), |(value, _)| Ok(Some(value)))
+    }
+
+    pub fn current_version(&self, key: K) -> Result<Option<u64>, Err> {
+        self.read(key.as_ref(), None)?
+            .map_or(Ok(None), |(_, version)| Ok(Some(version)))
+    }
+
+    pub fn set_cas(&self, key: K, value: V, version: u64) -> Result<u64, Err> {
+        self.write(key.as_ref(), &value, Some(version))
+    }
+
+    pub fn destroy(&self, key: K) -> Result<(), Err> {
+        match self
+            .request("DELETE", &self.metadata_url(key.as_ref()))
+            .call()
+        {
+            Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn read(&self, key: &str, version: Option<u64>) -> Result<Option<(V, u64)>, Err> {
+        let mut request = self.request("GET", &self.data_url(key));
+        if let Some(version) = version {
+            request = request.query("version", &version.to_string());
+        }
+
+        let body: Value = match request.call() {
+            Ok(resp) => resp.into_json()?,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let version = body["data"]["metadata"]["version"].as_u64().unwrap_or(0);
+        match body["data"]["data"].clone() {
+            Value::Null => Ok(None),
+            data => Ok(Some((decode(data)?, version))),
+        }
+    }
+
+    fn write(&self, key: &str, value: &V, cas: Option<u64>) -> Result<u64, Err> {
+        let mut body = json!({ "data": encode(value)? });
+        if let Some(cas) = cas {
+            body["options"] = json!({ "cas": cas });
+        }
+
+        let resp: Value = self
+            .request("POST", &self.data_url(key))
+            .send_json(body)?
+            .into_json()?;
+        Ok(resp["data"]["version"].as_u64().unwrap_or(0))
+    }
+
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        let request = self
+            .agent
+            .request(method, url)
+            .set("X-Vault-Token", self.token.expose_secret());
+        match &self.namespace {
+            Some(namespace) => request.set("X-Vault-Namespace", namespace),
+            None => request,
+        }
+    }
+
+    fn data_url(&self, key: &str) -> String {
+        format!("{}/v1/{}/data/{}", self.addr, self.mount, key)
+    }
+
+    fn metadata_url(&self, key: &str) -> String {
+        format!("{}/v1/{}/metadata/{}", self.addr, self.mount, key)
+    }
+}
+
+impl<K, V> Storage<K, V> for VaultStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Serialize + DeserializeOwned,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        self.write(key.as_ref(), &value, None)?;
+        self.hooks.set(&key, &value);
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        self.request("POST", &self.metadata_url(key.as_ref()))
+            .send_json(json!({ "delete_version_after": format!("{}s", expire.as_secs()) }))?;
+        self.write(key.as_ref(), &value, None)?;
+        self.hooks.set(&key, &value);
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        let value = self.read(key.as_ref(), None)?.map(|(value, _)| value);
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    // Soft-deletes the latest version, older versions stay recoverable until `destroy`.
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let found = self.read(key.as_ref(), None)?.is_some();
+        if found {
+            self.request("DELETE", &self.data_url(key.as_ref()))
+                .call()?;
+        }
+        self.hooks.del(&key);
+        Ok(found.then_some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        Ok(self.read(key.as_ref(), None)?.is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+}
+
+impl<K, V> Debug for VaultStorage<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VaultStorage")
+            .field("addr", &self.addr)
+            .field("mount", &self.mount)
+            .field("namespace", &self.namespace)
+            .field("token", &self.token)
+            .finish()
+    }
+}
+
+// KV v2 only stores JSON objects, so scalar values are kept under a `value` field.
+fn encode<V: Serialize>(value: V) -> Result<Value, Err> {
+    match serde_json::to_value(value)? {
+        Value::Object(map) => Ok(Value::Object(map)),
+        value => Ok(json!({ "value": value })),
+    }
+}
+
+fn decode<V: DeserializeOwned>(data: Value) -> Result<V, Err> {
+    match serde_json::from_value(data.clone()) {
+        Ok(value) => Ok(value),
+        Err(e) => match data.get("value") {
+            Some(value) => Ok(serde_json::from_value(value.clone())?),
+            None => Err(e.into()),
+        },
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+enum VaultAuth {
+    Token(String),
+    AppRole { role_id: String, secret_id: String },
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct VaultStorageBuilder<K, V> {
+    addr: Option<String>,
+    mount: String,
+    namespace: Option<String>,
+    auth: Option<VaultAuth>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::config::secs::option"))]
+    timeout: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> VaultStorageBuilder<K, V>
+where
+    K: AsRef<str>,
+    V: Serialize + DeserializeOwned,
+{
+    pub fn new() -> Self {
+        VaultStorageBuilder::default()
+    }
+
+    // Reads `VAULT_ADDR` and either `VAULT_TOKEN` or `VAULT_ROLE_ID`/`VAULT_SECRET_ID`,
+    // plus optional `VAULT_NAMESPACE` and `VAULT_MOUNT`.
+    pub fn from_env() -> Result<Self, BuilderError> {
+        let mut builder = VaultStorageBuilder::new().addr(&env::required("VAULT_ADDR")?);
+        builder = match env::var("VAULT_TOKEN")? {
+            Some(token) => builder.token(&token),
+            None => builder.approle(
+                &env::var("VAULT_ROLE_ID")?.ok_or(BuilderError::MissingEnv("VAULT_TOKEN"))?,
+                &env::required("VAULT_SECRET_ID")?,
+            ),
+        };
+        if let Some(namespace) = env::var("VAULT_NAMESPACE")? {
+            builder = builder.namespace(&namespace);
+        }
+        if let Some(mount) = env::var("VAULT_MOUNT")? {
+            builder = builder.mount(&mount);
+        }
+        Ok(builder)
+    }
+
+    pub fn addr(mut self, addr: &str) -> Self {
+        self.addr = Some(addr.trim_end_matches('/').to_string());
+        self
+    }
+
+    pub fn mount(mut self, mount: &str) -> Self {
+        self.mount = mount.trim_matches('/').to_string();
+        self
+    }
+
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.namespace = Some(namespace.to_string());
+        self
+    }
+
+    pub fn token(mut self, token: &str) -> Self {
+        self.auth = Some(VaultAuth::Token(token.to_string()));
+        self
+    }
+
+    pub fn approle(mut self, role_id: &str, secret_id: &str) -> Self {
+        self.auth = Some(VaultAuth::AppRole {
+            role_id: role_id.to_string(),
+            secret_id: secret_id.to_string(),
+        });
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their TTL variants. Clones of
+    // the storage share them, versioned reads and `set_cas` don't fire them.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics if the address or auth method is missing or invalid, or the AppRole login fails.
+    pub fn build(self) -> VaultStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build vault storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<VaultStorage<K, V>, BuilderError> {
+        let addr = self
+            .addr
+            .clone()
+            .ok_or(BuilderError::MissingField("addr"))?;
+        if !addr.starts_with("http://") && !addr.starts_with("https://") {
+            return Err(BuilderError::invalid(
+                "addr",
+                "must start with `http://` or `https://`",
+            ));
+        }
+        if self.mount.is_empty() {
+            return Err(BuilderError::invalid("mount", "must not be empty"));
+        }
+
+        let mut agent = ureq::AgentBuilder::new();
+        if let Some(timeout) = self.timeout {
+            agent = agent.timeout(timeout);
+        }
+        let agent = agent.build();
+
+        let token = match self.auth {
+            Some(VaultAuth::Token(token)) if token.is_empty() => {
+                return Err(BuilderError::invalid("token", "must not be empty"))
+            }
+            Some(VaultAuth::Token(token)) => token,
+            Some(VaultAuth::AppRole { role_id, secret_id }) => {
+                let mut request = agent.post(&format!("{}/v1/auth/approle/login", addr));
+                if let Some(namespace) = &self.namespace {
+                    request = request.set("X-Vault-Namespace", namespace);
+                }
+                let resp: Value = request
+                    .send_json(json!({ "role_id": role_id, "secret_id": secret_id }))
+                    .map_err(BuilderError::backend)?
+                    .into_json()
+                    .map_err(BuilderError::backend)?;
+                resp["auth"]["client_token"]
+                    .as_str()
+                    .ok_or_else(|| BuilderError::backend("AppRole login returned no client token"))?
+                    .to_string()
+            }
+            None => return Err(BuilderError::MissingField("auth")),
+        };
+
+        Ok(VaultStorage {
+            agent,
+            addr,
+            mount: self.mount,
+            namespace: self.namespace,
+            token: token.into(),
+            closed: CloseFlag::default(),
+            hooks: self.hooks,
+            _marker: self._marker,
+        })
+    }
+}
+
+impl<K, V> Default for VaultStorageBuilder<K, V> {
+    fn default() -> Self {
+        Self {
+            addr: None,
+            mount: "secret".to_string(),
+            namespace: None,
+            auth: None,
+            timeout: None,
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_encode_decode() {
+        let data = encode("Ferris".to_string()).unwrap();
+        assert_eq!(data, json!({ "value": "Ferris" }));
+        assert_eq!(decode::<String>(data).unwrap(), "Ferris");
+
+        let secret = HashMap::from([("password".to_string(), "hunter2".to_string())]);
+        let data = encode(secret.clone()).unwrap();
+        assert_eq!(decode::<HashMap<String, String>>(data).unwrap(), secret);
+    }
+
+    #[test]
+    fn test_get() {
+        let storage = build_localhost();
+
+        let (key, value) = ("name", "Ferris".to_string());
+        let _ = storage.set(key, value.clone());
+        let resp = storage.get(key).unwrap();
+        assert_eq!(resp, Some(value));
+
+        let _ = storage.del(key).unwrap();
+        let resp = storage.get(key).unwrap();
+        assert_eq!(resp, None);
+    }
+
+    #[test]
+    fn test_versions() {
+        let storage = build_localhost::<&str, String>();
+
+        storage.set("versioned", "v1".to_string()).unwrap();
+        let version = storage.current_version("versioned").unwrap().unwrap();
+        storage
+            .set_cas("versioned", "v2".to_string(), version)
+            .unwrap();
+        assert!(storage
+            .set_cas("versioned", "v3".to_string(), version)
+            .is_err());
+
+        let resp = storage.get_version("versioned", version).unwrap();
+        assert_eq!(resp, Some("v1".to_string()));
+        storage.destroy("versioned").unwrap();
+    }
+
+    #[test]
+    fn test_debug_redacts_token() {
+        let storage = build_localhost::<&str, String>();
+        assert!(!format!("{:?}", storage).contains("root"));
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp = VaultStorageBuilder::<&str, String>::new()
+            .addr("http://127.0.0.1:8200")
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::MissingField("auth"))));
+
+        let resp = VaultStorageBuilder::<&str, String>::new()
+            .addr("127.0.0.1:8200")
+            .token("root")
+            .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(_))));
+    }
+
+    fn build_localhost<K: AsRef<str>, V: Serialize + DeserializeOwned>() -> VaultStorage<K, V> {
+        VaultStorageBuilder::<K, V>::new()
+            .addr("http://127.0.0.1:8200")
+            .token("root")
+            .build()
+    }
+}
@@ -0,0 +1,95 @@
+use std::hash::Hasher;
+use std::sync::Arc;
+
+use crate::storage::{Err, Storage};
+
+// Identifies what a key held when it was read. DashMap and Redis count writes, so a version
+// only grows and writing an equal value again still changes it; which writes count differs,
+// see their impls. Sled fingerprints the stored bytes instead. Either way it can round-trip
+// through something like an HTTP `ETag`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Version(u64);
+
+impl Version {
+    pub fn from_u64(version: u64) -> Self {
+        Version(version)
+    }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    // FNV-1a of the stored bytes, as `DefaultHasher` may change between Rust releases.
+    pub fn of_bytes(bytes: &[u8]) -> Self {
+        let mut hasher = Fnv::default();
+        hasher.write(bytes);
+        Version(hasher.finish())
+    }
+}
+
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+// Optimistic concurrency without comparing whole values: read a value with its version, and
+// write back only if nobody changed it since. Like `compare_and_swap`, a successful write
+// drops the key's TTL.
+pub trait VersionedStorage<K, V>: Storage<K, V> {
+    fn get_versioned(&self, key: K) -> Result<Option<(V, Version)>, Err>;
+
+    // Writes `value` only if the key is still at `expected`, `None` for a key that must not
+    // exist yet. Returns whether it was written.
+    fn set_if_version(&self, key: K, value: V, expected: Option<Version>) -> Result<bool, Err>;
+}
+
+macro_rules! forward_versioned {
+    ($($ty:ty),*) => {
+        $(
+            impl<K, V, S: VersionedStorage<K, V> + ?Sized> VersionedStorage<K, V> for $ty {
+                fn get_versioned(&self, key: K) -> Result<Option<(V, Version)>, Err> {
+                    (**self).get_versioned(key)
+                }
+
+                fn set_if_version(
+                    &self,
+                    key: K,
+                    value: V,
+                    expected: Option<Version>,
+                ) -> Result<bool, Err> {
+                    (**self).set_if_version(key, value, expected)
+                }
+            }
+        )*
+    };
+}
+
+forward_versioned!(&S, Box<S>, Arc<S>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_of() {
+        // Pinned, versions handed out by one release must still match in the next.
+        assert_eq!(Version::of_bytes(b"abc").as_u64(), 0xe71f_a219_0541_574b);
+        assert_eq!(Version::from_u64(7).as_u64(), 7);
+    }
+}
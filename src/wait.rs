@@ -0,0 +1,65 @@
+#[cfg(feature = "dashmap")]
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "dashmap")]
+use std::time::Instant;
+
+use crate::storage::{Err, Storage};
+#[cfg(feature = "dashmap")]
+use crate::watch::WatchableStorage;
+
+// How often a waiter re-reads the key when no event came, in case one was missed.
+#[cfg(any(feature = "dashmap", feature = "redis"))]
+pub(crate) const WAIT_POLL: Duration = Duration::from_secs(1);
+
+// Blocks until a key has a value, e.g. a job's completion marker, instead of polling for it.
+pub trait WaitStorage<K, V>: Storage<K, V> {
+    // The key's value as soon as it has one, `None` once `timeout` passed without one.
+    fn wait_for(&self, key: K, timeout: Duration) -> Result<Option<V>, Err>;
+}
+
+macro_rules! forward_wait {
+    ($($ty:ty),*) => {
+        $(
+            impl<K, V, S: WaitStorage<K, V> + ?Sized> WaitStorage<K, V> for $ty {
+                fn wait_for(&self, key: K, timeout: Duration) -> Result<Option<V>, Err> {
+                    (**self).wait_for(key, timeout)
+                }
+            }
+        )*
+    };
+}
+
+forward_wait!(&S, Box<S>, Arc<S>);
+
+// `wait_for` on top of `watch`: subscribes before the first read, so a write right after it
+// still wakes the waiter, and re-reads the key on every event. Without events, e.g. writes
+// the backend can't see, the key is re-read every `WAIT_POLL`, and a watch that ended is set
+// up again.
+#[cfg(feature = "dashmap")]
+pub(crate) fn wait_watched<K, V, S>(
+    storage: &S,
+    key: K,
+    timeout: Duration,
+) -> Result<Option<V>, Err>
+where
+    K: Clone,
+    S: Storage<K, V> + WatchableStorage<K> + ?Sized,
+{
+    let deadline = Instant::now() + timeout;
+    let mut events = storage.watch(key.clone())?;
+    loop {
+        if let Some(value) = storage.get(key.clone())? {
+            return Ok(Some(value));
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(None);
+        }
+        match events.recv_timeout(left.min(WAIT_POLL)) {
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => events = storage.watch(key.clone())?,
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::ops::Deref;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Weak};
+
+use crate::storage::Err;
+
+// What happened to a watched key. Events carry no value, read the key again for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeEvent {
+    Set,
+    Del,
+    Expired,
+}
+
+// The events of one `watch`, used like the `Receiver` it wraps. Dropping it is seen by the
+// backend without waiting for the next event, so a backend that runs a thread per watch can
+// end it while the key is quiet.
+#[derive(Debug)]
+pub struct Changes {
+    rx: Receiver<ChangeEvent>,
+    alive: Arc<()>,
+}
+
+impl Changes {
+    // For backends outside the crate too.
+    pub fn new(rx: Receiver<ChangeEvent>) -> Self {
+        Changes {
+            rx,
+            alive: Arc::new(()),
+        }
+    }
+
+    // Has no strong count left once the `Changes` is dropped.
+    pub fn alive(&self) -> Weak<()> {
+        Arc::downgrade(&self.alive)
+    }
+}
+
+impl Deref for Changes {
+    type Target = Receiver<ChangeEvent>;
+
+    fn deref(&self) -> &Receiver<ChangeEvent> {
+        &self.rx
+    }
+}
+
+// Blocks for each event, ends when the receiver disconnects.
+impl Iterator for Changes {
+    type Item = ChangeEvent;
+
+    fn next(&mut self) -> Option<ChangeEvent> {
+        self.rx.recv().ok()
+    }
+}
+
+// Subscribes to changes of single keys, e.g. to reload configuration when it is rewritten.
+pub trait WatchableStorage<K> {
+    // Every change of `key` from now on, in order. The receiver disconnects when the backend
+    // can no longer deliver events, watch again after that and re-read the key.
+    fn watch(&self, key: K) -> Result<Changes, Err>;
+}
+
+impl<K, S: WatchableStorage<K> + ?Sized> WatchableStorage<K> for &S {
+    fn watch(&self, key: K) -> Result<Changes, Err> {
+        (**self).watch(key)
+    }
+}
+
+impl<K, S: WatchableStorage<K> + ?Sized> WatchableStorage<K> for Box<S> {
+    fn watch(&self, key: K) -> Result<Changes, Err> {
+        (**self).watch(key)
+    }
+}
+
+impl<K, S: WatchableStorage<K> + ?Sized> WatchableStorage<K> for Arc<S> {
+    fn watch(&self, key: K) -> Result<Changes, Err> {
+        (**self).watch(key)
+    }
+}
@@ -0,0 +1,475 @@
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use web_sys::js_sys::Date;
+use web_sys::wasm_bindgen::JsValue;
+
+use crate::admin::AdminStorage;
+use crate::atomic::AtomicStorage;
+use crate::error::BuilderError;
+use crate::expiry::ExpiryStorage;
+use crate::export::{EntryIter, ExportEntry, ExportIter, ExportStorage};
+use crate::flush::{FlushConfirmation, FlushNamespace};
+use crate::gc::GarbageCollect;
+use crate::get_or_insert::GetOrInsertStorage;
+use crate::hooks::{Hooks, StorageHook};
+use crate::scan::{KeyIter, ScanStorage};
+use crate::storage::{CloseFlag, Err, Storage};
+
+// Deadlines live in their own entries under this prefix, so values stay readable as they are
+// from other scripts on the page.
+const TTL_PREFIX: &str = "\u{0}ttl:";
+
+// Keys and values in the browser's `localStorage`, or `sessionStorage`, under a key prefix.
+// Values are stored as they are. A key with a TTL also gets a `"\0ttl:<key>"` entry holding
+// its deadline in milliseconds since the epoch, and expired keys read as absent until they
+// are read again or `gc_now` removes them. Each call is synchronous and runs to completion
+// before any other script on the page, so the atomic operations are atomic within the page;
+// other tabs of the same origin share the storage and may interleave with them.
+#[derive(Clone)]
+pub struct WebStorage<K, V>
+where
+    V: Into<String>,
+{
+    storage: web_sys::Storage,
+    prefix: String,
+    closed: CloseFlag,
+    hooks: Hooks<K, V>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> WebStorage<K, V>
+where
+    V: Into<String>,
+{
+    pub fn storage(&self) -> &web_sys::Storage {
+        &self.storage
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    fn path(&self, key: &str) -> Result<String, Err> {
+        self.closed.check()?;
+        let path = format!("{}{}", self.prefix, key);
+        if path.starts_with(TTL_PREFIX) {
+            return Err(format!("key {:?} starts with the reserved {:?}", path, TTL_PREFIX).into());
+        }
+        Ok(path)
+    }
+
+    fn read(&self, name: &str) -> Result<Option<String>, Err> {
+        self.storage.get_item(name).map_err(js_err)
+    }
+
+    fn write(&self, name: &str, value: &str) -> Result<(), Err> {
+        self.storage.set_item(name, value).map_err(js_err)
+    }
+
+    fn remove(&self, name: &str) -> Result<(), Err> {
+        self.storage.remove_item(name).map_err(js_err)
+    }
+
+    // The value at `path` and its deadline, `None` once expired. Expired keys are removed.
+    fn lookup(&self, path: &str) -> Result<Option<(String, Option<f64>)>, Err> {
+        let Some(value) = self.read(path)? else {
+            return Ok(None);
+        };
+        let deadline = match self.read(&ttl_path(path))? {
+            Some(deadline) => Some(
+                deadline
+                    .parse::<f64>()
+                    .map_err(|e| format!("deadline of {:?}: {}", path, e))?,
+            ),
+            None => None,
+        };
+        match deadline {
+            Some(deadline) if deadline <= Date::now() => {
+                self.unset(path)?;
+                Ok(None)
+            }
+            _ => Ok(Some((value, deadline))),
+        }
+    }
+
+    // Writes the value first, so a full quota leaves the old deadline in place.
+    fn put(&self, path: &str, value: &str, expire: Option<Duration>) -> Result<(), Err> {
+        self.write(path, value)?;
+        match expire {
+            Some(expire) => self.write(&ttl_path(path), &deadline(expire).to_string()),
+            None => self.remove(&ttl_path(path)),
+        }
+    }
+
+    fn unset(&self, path: &str) -> Result<(), Err> {
+        self.remove(path)?;
+        self.remove(&ttl_path(path))
+    }
+
+    // Every stored name, snapshotted first since removing entries renumbers them.
+    fn names(&self) -> Result<Vec<String>, Err> {
+        self.closed.check()?;
+        let len = self.storage.length().map_err(js_err)?;
+        let mut names = Vec::with_capacity(len as usize);
+        for index in 0..len {
+            if let Some(name) = self.storage.key(index).map_err(js_err)? {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    // Live keys starting with `prefix`, without the storage prefix, in the browser's order.
+    fn keys(&self, prefix: &str) -> Result<Vec<String>, Err> {
+        let full = format!("{}{}", self.prefix, prefix);
+        let mut keys = Vec::new();
+        for name in self.names()? {
+            if name.starts_with(TTL_PREFIX) || !name.starts_with(&full) {
+                continue;
+            }
+            if self.lookup(&name)?.is_some() {
+                keys.push(name[self.prefix.len()..].to_string());
+            }
+        }
+        Ok(keys)
+    }
+}
+
+impl<K, V> Storage<K, V> for WebStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        let value: String = value.into();
+        self.put(&self.path(key.as_ref())?, &value, None)?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        let value: String = value.into();
+        self.put(&self.path(key.as_ref())?, &value, Some(expire))?;
+        self.hooks.set_with(&key, || V::from(value));
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        let value = self
+            .lookup(&self.path(key.as_ref())?)?
+            .map(|(value, _)| V::from(value));
+        self.hooks.get(&key, value.as_ref());
+        Ok(value)
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        let path = self.path(key.as_ref())?;
+        let found = self.lookup(&path)?.is_some();
+        self.unset(&path)?;
+        self.hooks.del(&key);
+        Ok(found.then_some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        Ok(self.lookup(&self.path(key.as_ref())?)?.is_some())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        self.closed.close();
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+}
+
+impl<K, V> ExportStorage<K, V> for WebStorage<K, V>
+where
+    K: AsRef<str> + From<String>,
+    V: Into<String> + From<String>,
+{
+    fn export_iter(&self) -> Result<ExportIter<'_, K, V>, Err> {
+        Ok(Box::new(
+            self.export_entries()?
+                .map(|entry| entry.map(|entry| (entry.key, entry.value))),
+        ))
+    }
+
+    fn export_entries<'a>(&'a self) -> Result<EntryIter<'a, K, V>, Err>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        let keys = self.keys("")?;
+        Ok(Box::new(keys.into_iter().filter_map(move |key| {
+            let path = format!("{}{}", self.prefix, key);
+            match self.lookup(&path) {
+                Ok(Some((value, deadline))) => Some(Ok(ExportEntry {
+                    key: K::from(key),
+                    value: V::from(value),
+                    ttl: deadline.map(remaining),
+                })),
+                Ok(None) => None,
+                Err(e) => Some(Err(e)),
+            }
+        })))
+    }
+}
+
+impl<K, V> GarbageCollect for WebStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn gc_now(&self, batch_size: usize) -> Result<usize, Err> {
+        let sidecars = format!("{}{}", TTL_PREFIX, self.prefix);
+        let mut removed = 0;
+        for name in self.names()? {
+            if removed == batch_size {
+                break;
+            }
+            let Some(path) = name.strip_prefix(TTL_PREFIX) else {
+                continue;
+            };
+            if name.starts_with(&sidecars) && self.lookup(path)?.is_none() {
+                // Also drops deadlines whose value was removed by other scripts.
+                self.remove(&name)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+// Nothing else on the page runs during a call, so a read and a write are enough. Writes clear
+// the TTL, as `set` does.
+impl<K, V> AtomicStorage<K, V> for WebStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String> + PartialEq,
+{
+    fn compare_and_swap(&self, key: K, expected: Option<V>, new: Option<V>) -> Result<bool, Err> {
+        let path = self.path(key.as_ref())?;
+        let current = self.lookup(&path)?.map(|(value, _)| value);
+        if current != expected.map(Into::into) {
+            return Ok(false);
+        }
+        match new {
+            Some(new) => self.put(&path, &new.into(), None)?,
+            None => self.unset(&path)?,
+        }
+        Ok(true)
+    }
+}
+
+impl<K, V> GetOrInsertStorage<K, V> for WebStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    fn set_nx(&self, key: K, value: V, expire: Option<Duration>) -> Result<bool, Err> {
+        let path = self.path(key.as_ref())?;
+        if self.lookup(&path)?.is_some() {
+            return Ok(false);
+        }
+        self.put(&path, &value.into(), expire)?;
+        Ok(true)
+    }
+}
+
+// Only the entries under the prefix, the rest of the origin's storage is left alone.
+impl<K, V> AdminStorage for WebStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn clear(&self) -> Result<(), Err> {
+        let sidecars = format!("{}{}", TTL_PREFIX, self.prefix);
+        for name in self.names()? {
+            let data = !name.starts_with(TTL_PREFIX) && name.starts_with(&self.prefix);
+            if data || name.starts_with(&sidecars) {
+                self.remove(&name)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn len(&self) -> Result<usize, Err> {
+        Ok(self.keys("")?.len())
+    }
+}
+
+impl<K, V> ExpiryStorage<K> for WebStorage<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String>,
+{
+    fn ttl(&self, key: K) -> Result<Option<Duration>, Err> {
+        let path = self.path(key.as_ref())?;
+        Ok(self
+            .lookup(&path)?
+            .and_then(|(_, deadline)| deadline)
+            .map(remaining))
+    }
+
+    fn expire(&self, key: K, expire: Duration) -> Result<bool, Err> {
+        let path = self.path(key.as_ref())?;
+        if self.lookup(&path)?.is_none() {
+            return Ok(false);
+        }
+        self.write(&ttl_path(&path), &deadline(expire).to_string())?;
+        Ok(true)
+    }
+
+    fn persist(&self, key: K) -> Result<bool, Err> {
+        let path = self.path(key.as_ref())?;
+        match self.lookup(&path)? {
+            Some((_, Some(_))) => {
+                self.remove(&ttl_path(&path))?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+impl<K, V> ScanStorage<K> for WebStorage<K, V>
+where
+    K: From<String>,
+    V: Into<String>,
+{
+    fn scan_prefix(&self, prefix: &str) -> Result<KeyIter<'_, K>, Err> {
+        Ok(Box::new(
+            self.keys(prefix)?.into_iter().map(|key| Ok(K::from(key))),
+        ))
+    }
+}
+
+impl<K, V> FlushNamespace for WebStorage<K, V>
+where
+    V: Into<String>,
+{
+    fn flush_namespace(&self, prefix: &str, confirm: &FlushConfirmation) -> Result<usize, Err> {
+        self.closed.check()?;
+        confirm.check(prefix)?;
+        let keys = self.keys(prefix)?;
+        for key in &keys {
+            self.unset(&format!("{}{}", self.prefix, key))?;
+        }
+        Ok(keys.len())
+    }
+}
+
+fn ttl_path(path: &str) -> String {
+    format!("{}{}", TTL_PREFIX, path)
+}
+
+// Milliseconds since the epoch on the browser's clock.
+fn deadline(expire: Duration) -> f64 {
+    Date::now() + expire.as_millis() as f64
+}
+
+fn remaining(deadline: f64) -> Duration {
+    Duration::from_millis((deadline - Date::now()).max(0.0) as u64)
+}
+
+// Browsers throw `DOMException`s, e.g. a `QuotaExceededError` once the origin's storage is
+// full; their message is all that is kept.
+fn js_err(e: JsValue) -> Err {
+    match e.as_string() {
+        Some(message) => message.into(),
+        None => format!("{:?}", e).into(),
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct WebStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    prefix: String,
+    session: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Hooks<K, V>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> WebStorageBuilder<K, V>
+where
+    K: AsRef<str>,
+    V: Into<String> + From<String>,
+{
+    pub fn new() -> Self {
+        WebStorageBuilder::default()
+    }
+
+    // Put in front of every key, e.g. `app/`. Defaults to none, so `clear` and `len` cover
+    // the whole origin's storage.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    // Uses `sessionStorage`, which lasts as long as the tab, instead of `localStorage`.
+    pub fn session(mut self) -> Self {
+        self.session = true;
+        self
+    }
+
+    // Called after every successful `set`, `get` and `del` and their TTL and batch variants.
+    // Clones of the storage share them, writes from other tabs don't fire them.
+    pub fn hook<H: StorageHook<K, V> + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // # Panics
+    // Panics outside a browser window or if the storage is disabled, e.g. by privacy
+    // settings. Use `try_build` to get the error instead.
+    pub fn build(self) -> WebStorage<K, V> {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build web storage: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<WebStorage<K, V>, BuilderError> {
+        if self.prefix.starts_with('\u{0}') {
+            return Err(BuilderError::invalid("prefix", "must not start with \\0"));
+        }
+        // Workers have no `window`, and no synchronous storage either.
+        let window = web_sys::window()
+            .ok_or_else(|| BuilderError::backend("no `window`, not running in a browser tab"))?;
+        let storage = match self.session {
+            true => window.session_storage(),
+            false => window.local_storage(),
+        };
+        let storage = storage
+            .map_err(|e| BuilderError::Backend(js_err(e)))?
+            .ok_or_else(|| BuilderError::backend("web storage is not available"))?;
+
+        Ok(WebStorage {
+            storage,
+            prefix: self.prefix,
+            closed: CloseFlag::default(),
+            hooks: self.hooks,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<K, V> Default for WebStorageBuilder<K, V>
+where
+    V: Into<String>,
+{
+    fn default() -> Self {
+        Self {
+            prefix: String::new(),
+            session: false,
+            hooks: Hooks::default(),
+            _marker: PhantomData,
+        }
+    }
+}
@@ -0,0 +1,369 @@
+// Reproducible operation mixes for comparing backends through `Storage`, used by the benches
+// and usable from an application's own. Keys are `workload:<n>` strings and values are
+// strings of a fixed size, so any `Storage<String, String>` can run them.
+use std::time::{Duration, Instant};
+
+use crate::storage::{Err, Storage};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    Get(String),
+    Set(String, String),
+    Del(String),
+    GetMany(Vec<String>),
+    SetMany(Vec<(String, String)>),
+}
+
+// Relative weights of each operation, e.g. 90 gets to 10 sets. Batches have `batch_size` keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mix {
+    pub get: u32,
+    pub set: u32,
+    pub del: u32,
+    pub get_many: u32,
+    pub set_many: u32,
+}
+
+impl Mix {
+    pub const READ_HEAVY: Mix = Mix {
+        get: 90,
+        set: 10,
+        del: 0,
+        get_many: 0,
+        set_many: 0,
+    };
+
+    pub const WRITE_HEAVY: Mix = Mix {
+        get: 10,
+        set: 80,
+        del: 10,
+        get_many: 0,
+        set_many: 0,
+    };
+
+    pub fn only_get() -> Self {
+        Mix::weights(1, 0, 0, 0, 0)
+    }
+
+    pub fn only_set() -> Self {
+        Mix::weights(0, 1, 0, 0, 0)
+    }
+
+    pub fn only_del() -> Self {
+        Mix::weights(0, 0, 1, 0, 0)
+    }
+
+    pub fn only_batches() -> Self {
+        Mix::weights(0, 0, 0, 1, 1)
+    }
+
+    fn weights(get: u32, set: u32, del: u32, get_many: u32, set_many: u32) -> Self {
+        Mix {
+            get,
+            set,
+            del,
+            get_many,
+            set_many,
+        }
+    }
+
+    fn total(&self) -> u64 {
+        [self.get, self.set, self.del, self.get_many, self.set_many]
+            .iter()
+            .map(|weight| *weight as u64)
+            .sum()
+    }
+}
+
+// Generates operations over a fixed key space. With `hot_keys`, a share of the operations
+// goes to the first keys only, like a cache's popular entries.
+#[derive(Debug, Clone)]
+pub struct Workload {
+    keys: usize,
+    value_size: usize,
+    batch_size: usize,
+    mix: Mix,
+    hot: Option<(f64, f64)>,
+    seed: u64,
+}
+
+impl Default for Workload {
+    fn default() -> Self {
+        Workload::new()
+    }
+}
+
+impl Workload {
+    // 10 000 keys, 100 byte values, batches of 16 and `Mix::READ_HEAVY`.
+    pub fn new() -> Self {
+        Workload {
+            keys: 10_000,
+            value_size: 100,
+            batch_size: 16,
+            mix: Mix::READ_HEAVY,
+            hot: None,
+            seed: 0,
+        }
+    }
+
+    pub fn keys(mut self, keys: usize) -> Self {
+        self.keys = keys.max(1);
+        self
+    }
+
+    pub fn value_size(mut self, value_size: usize) -> Self {
+        self.value_size = value_size;
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn mix(mut self, mix: Mix) -> Self {
+        self.mix = mix;
+        self
+    }
+
+    // Sends `share` of the operations to the first `fraction` of the keys, both clamped to
+    // `0.0..=1.0`, e.g. `hot_keys(0.2, 0.8)`.
+    pub fn hot_keys(mut self, fraction: f64, share: f64) -> Self {
+        self.hot = Some((fraction.clamp(0.0, 1.0), share.clamp(0.0, 1.0)));
+        self
+    }
+
+    // The same seed generates the same operations.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn key(&self, n: usize) -> String {
+        format!("workload:{}", n % self.keys)
+    }
+
+    pub fn value(&self) -> String {
+        "v".repeat(self.value_size)
+    }
+
+    // Writes every key once, so reads hit, in batches of `batch_size`.
+    pub fn preload<S: Storage<String, String> + ?Sized>(&self, storage: &S) -> Result<(), Err> {
+        let value = self.value();
+        let keys: Vec<String> = (0..self.keys).map(|n| self.key(n)).collect();
+        for batch in keys.chunks(self.batch_size) {
+            let entries = batch.iter().map(|key| (key.clone(), value.clone()));
+            storage.set_many(entries.collect())?;
+        }
+        Ok(())
+    }
+
+    // Deletes every key, e.g. to leave a shared server clean.
+    pub fn cleanup<S: Storage<String, String> + ?Sized>(&self, storage: &S) -> Result<(), Err> {
+        let keys: Vec<String> = (0..self.keys).map(|n| self.key(n)).collect();
+        for batch in keys.chunks(self.batch_size) {
+            storage.del_many(batch.to_vec())?;
+        }
+        Ok(())
+    }
+
+    // `count` operations drawn from the mix. Generated up front, so timing them doesn't
+    // include their generation.
+    pub fn ops(&self, count: usize) -> Vec<Op> {
+        let mut rng = SplitMix64(self.seed);
+        let total = self.mix.total();
+        if total == 0 {
+            return Vec::new();
+        }
+        let value = self.value();
+        (0..count)
+            .map(|_| {
+                let mut pick = rng.next() % total;
+                let mut kind = 0;
+                for (i, weight) in [
+                    self.mix.get,
+                    self.mix.set,
+                    self.mix.del,
+                    self.mix.get_many,
+                    self.mix.set_many,
+                ]
+                .into_iter()
+                .enumerate()
+                {
+                    if pick < weight as u64 {
+                        kind = i;
+                        break;
+                    }
+                    pick -= weight as u64;
+                }
+                let mut key = || self.key(self.draw_key(&mut rng));
+                match kind {
+                    0 => Op::Get(key()),
+                    1 => Op::Set(key(), value.clone()),
+                    2 => Op::Del(key()),
+                    3 => Op::GetMany((0..self.batch_size).map(|_| key()).collect()),
+                    _ => Op::SetMany(
+                        (0..self.batch_size)
+                            .map(|_| (key(), value.clone()))
+                            .collect(),
+                    ),
+                }
+            })
+            .collect()
+    }
+
+    fn draw_key(&self, rng: &mut SplitMix64) -> usize {
+        let n = rng.next() as usize;
+        match self.hot {
+            Some((fraction, share)) => {
+                let hot = ((self.keys as f64 * fraction) as usize).max(1);
+                if rng.unit() < share {
+                    n % hot
+                } else {
+                    n % self.keys
+                }
+            }
+            None => n % self.keys,
+        }
+    }
+}
+
+// Runs one operation, keys and values are cloned so `ops` can be replayed.
+pub fn apply<S: Storage<String, String> + ?Sized>(storage: &S, op: &Op) -> Result<(), Err> {
+    match op {
+        Op::Get(key) => storage.get(key.clone()).map(drop),
+        Op::Set(key, value) => storage.set(key.clone(), value.clone()),
+        Op::Del(key) => storage.del(key.clone()).map(drop),
+        Op::GetMany(keys) => storage.get_many(keys.clone()).map(drop),
+        Op::SetMany(entries) => storage.set_many(entries.clone()),
+    }
+}
+
+// Runs `ops` in order and times each, stopping at the first error.
+pub fn run<S: Storage<String, String> + ?Sized>(storage: &S, ops: &[Op]) -> Result<Report, Err> {
+    let mut latencies = Vec::with_capacity(ops.len());
+    let start = Instant::now();
+    for op in ops {
+        let op_start = Instant::now();
+        apply(storage, op)?;
+        latencies.push(op_start.elapsed());
+    }
+    let elapsed = start.elapsed();
+    latencies.sort_unstable();
+    Ok(Report { elapsed, latencies })
+}
+
+// Timings of one `run`. The timing of each call is included, which matters little next to a
+// network round trip but is a share of an in-memory call.
+#[derive(Debug, Clone)]
+pub struct Report {
+    elapsed: Duration,
+    // Sorted.
+    latencies: Vec<Duration>,
+}
+
+impl Report {
+    pub fn ops(&self) -> usize {
+        self.latencies.len()
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn ops_per_sec(&self) -> f64 {
+        self.ops() as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+
+    // The latency below which `p` of the operations finished, e.g. `0.99`.
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (p.clamp(0.0, 1.0) * (self.latencies.len() - 1) as f64).round() as usize;
+        self.latencies[rank]
+    }
+}
+
+// Seeded like `ChaosStorage`, so runs are reproducible without a rand dependency.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_storage::MockStorage;
+
+    #[test]
+    fn test_ops() {
+        let workload = Workload::new().keys(100).seed(3);
+        let ops = workload.ops(1000);
+        assert_eq!(ops, workload.ops(1000));
+        assert_ne!(ops, workload.clone().seed(4).ops(1000));
+
+        let gets = ops.iter().filter(|op| matches!(op, Op::Get(_))).count();
+        assert!((850..950).contains(&gets), "{} gets", gets);
+        assert!(ops
+            .iter()
+            .all(|op| matches!(op, Op::Get(_) | Op::Set(_, _))));
+
+        let batches = workload.mix(Mix::only_batches()).batch_size(4).ops(10);
+        assert!(batches.iter().all(|op| match op {
+            Op::GetMany(keys) => keys.len() == 4,
+            Op::SetMany(entries) => entries.len() == 4,
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn test_hot_keys() {
+        let workload = Workload::new()
+            .keys(1000)
+            .mix(Mix::only_get())
+            .hot_keys(0.01, 0.9);
+        let hot = workload
+            .ops(1000)
+            .iter()
+            .filter(|op| match op {
+                Op::Get(key) => key["workload:".len()..].parse::<usize>().unwrap() < 10,
+                _ => false,
+            })
+            .count();
+        assert!(hot > 850, "{} hot reads", hot);
+    }
+
+    #[test]
+    fn test_run() {
+        let storage = MockStorage::<String, String>::new();
+        let workload = Workload::new().keys(10).batch_size(4).value_size(3);
+        workload.preload(&storage).unwrap();
+        assert_eq!(storage.get("workload:9".into()).unwrap().unwrap(), "vvv");
+        storage.clear_calls();
+
+        let ops = workload.ops(50);
+        let report = run(&storage, &ops).unwrap();
+        assert_eq!(report.ops(), 50);
+        assert_eq!(storage.call_count(), 50);
+        assert!(report.percentile(0.5) <= report.percentile(0.99));
+        assert!(report.ops_per_sec() > 0.0);
+
+        // Stops at the first failure.
+        storage.clear_calls();
+        storage.fail_nth(2, "down");
+        assert!(run(&storage, &ops).is_err());
+        assert_eq!(storage.call_count(), 3);
+    }
+}
@@ -0,0 +1,614 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::error::BuilderError;
+use crate::health::HealthStatus;
+use crate::storage::{CloseFlag, Err, Storage};
+
+type ErrorHook = Box<dyn Fn(&Err) + Send + Sync>;
+
+// A write waiting for the backing storage, the latest one per key.
+enum Pending<V> {
+    Set(V, Option<Instant>),
+    Del,
+}
+
+struct Queued<V> {
+    write: Pending<V>,
+    // Tells a write that was replaced while in flight from the one that was flushed.
+    seq: u64,
+    queued: bool,
+}
+
+struct Queue<K, V> {
+    writes: HashMap<K, Queued<V>>,
+    // Keys waiting for the flusher, in the order they were first written.
+    order: VecDeque<K>,
+    next_seq: u64,
+    flushing: usize,
+    stopping: bool,
+}
+
+struct Shared<K, V, B> {
+    backing: B,
+    queue: Mutex<Queue<K, V>>,
+    changed: Condvar,
+    batch_size: usize,
+    flush_interval: Duration,
+    max_pending: usize,
+    max_attempts: usize,
+    retry_backoff: Duration,
+    on_error: Option<ErrorHook>,
+    dropped: AtomicU64,
+}
+
+// Acknowledges writes once the cache has them and copies them to the backing storage from a
+// background thread, in batches of up to `batch_size` keys. Writes to a key that is still
+// queued replace each other, so only the last one is sent. Reads see queued writes first,
+// then the cache, then the backing storage.
+//
+// A batch that fails is retried `max_attempts` times with a doubling backoff, then dropped:
+// the error goes to `on_error` and the writes count in `dropped()`. Queued writes are lost if
+// the process dies, call `flush` at checkpoints and `close` on shutdown to wait for them.
+// Once `max_pending` keys are queued, writes wait for the flusher.
+pub struct WriteBehindStorage<K, V, C, B> {
+    cache: C,
+    shared: Arc<Shared<K, V, B>>,
+    flusher: Mutex<Option<JoinHandle<()>>>,
+    closed: CloseFlag,
+}
+
+impl<K, V, B> Shared<K, V, B> {
+    fn lock(&self) -> MutexGuard<'_, Queue<K, V>> {
+        self.queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<K, V, B> Shared<K, V, B>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    B: Storage<K, V>,
+{
+    fn run(&self) {
+        loop {
+            let Some(batch) = self.next_batch() else {
+                return;
+            };
+            let written = self.write_batch(&batch);
+            let mut queue = self.lock();
+            for (key, seq, _) in batch {
+                if queue
+                    .writes
+                    .get(&key)
+                    .is_some_and(|queued| queued.seq == seq)
+                {
+                    queue.writes.remove(&key);
+                }
+            }
+            drop(queue);
+            if !written {
+                self.dropped.fetch_add(1, Ordering::SeqCst);
+            }
+            self.changed.notify_all();
+        }
+    }
+
+    // Waits until a batch is full, a flush waits or `flush_interval` passed since the first
+    // write came in. `None` once stopped with nothing left to write.
+    fn next_batch(&self) -> Option<Vec<(K, u64, Pending<V>)>> {
+        let mut queue = self.lock();
+        while queue.order.is_empty() {
+            if queue.stopping {
+                return None;
+            }
+            queue = self.changed.wait(queue).unwrap_or_else(|p| p.into_inner());
+        }
+        let deadline = Instant::now() + self.flush_interval;
+        while queue.order.len() < self.batch_size && queue.flushing == 0 && !queue.stopping {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            queue = self
+                .changed
+                .wait_timeout(queue, left)
+                .unwrap_or_else(|p| p.into_inner())
+                .0;
+        }
+
+        let take = queue.order.len().min(self.batch_size);
+        let keys: Vec<K> = queue.order.drain(..take).collect();
+        let batch = keys
+            .into_iter()
+            .filter_map(|key| {
+                let queued = queue.writes.get_mut(&key)?;
+                queued.queued = false;
+                let write = match &queued.write {
+                    Pending::Set(value, deadline) => Pending::Set(value.clone(), *deadline),
+                    Pending::Del => Pending::Del,
+                };
+                Some((key, queued.seq, write))
+            })
+            .collect();
+        Some(batch)
+    }
+
+    // `false` if the batch was dropped after its last attempt.
+    fn write_batch(&self, batch: &[(K, u64, Pending<V>)]) -> bool {
+        let mut backoff = self.retry_backoff;
+        for attempt in 1..=self.max_attempts {
+            match self.write(batch) {
+                Ok(()) => return true,
+                Err(e) => {
+                    if let Some(on_error) = &self.on_error {
+                        on_error(&e);
+                    }
+                    if attempt < self.max_attempts {
+                        thread::sleep(backoff);
+                        backoff = backoff.saturating_mul(2);
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    // Plain sets go out with one `set_many` and deletes with one `del_many`. A TTL that ran
+    // out while queued deletes the key.
+    fn write(&self, batch: &[(K, u64, Pending<V>)]) -> Result<(), Err> {
+        let now = Instant::now();
+        let mut sets = Vec::new();
+        let mut dels = Vec::new();
+        for (key, _, write) in batch {
+            match write {
+                Pending::Set(value, None) => sets.push((key.clone(), value.clone())),
+                Pending::Set(value, Some(deadline)) if *deadline > now => {
+                    self.backing
+                        .set_ex(key.clone(), value.clone(), *deadline - now)?;
+                }
+                Pending::Set(_, Some(_)) | Pending::Del => dels.push(key.clone()),
+            }
+        }
+        if !sets.is_empty() {
+            self.backing.set_many(sets)?;
+        }
+        if !dels.is_empty() {
+            self.backing.del_many(dels)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K, V, C, B> WriteBehindStorage<K, V, C, B> {
+    pub fn cache(&self) -> &C {
+        &self.cache
+    }
+
+    pub fn backing(&self) -> &B {
+        &self.shared.backing
+    }
+
+    // Keys whose write hasn't reached the backing storage yet.
+    pub fn pending(&self) -> usize {
+        self.shared.lock().writes.len()
+    }
+
+    // Batches given up on after their last attempt.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::SeqCst)
+    }
+
+    // Writes out everything queued so far and waits for it. Fails if a batch was dropped
+    // meanwhile, writes made during the flush may or may not be included.
+    pub fn flush(&self) -> Result<(), Err> {
+        let dropped = self.dropped();
+        let mut queue = self.shared.lock();
+        let target = queue.next_seq;
+        queue.flushing += 1;
+        self.shared.changed.notify_all();
+        while queue.writes.values().any(|queued| queued.seq < target) {
+            queue = self
+                .shared
+                .changed
+                .wait(queue)
+                .unwrap_or_else(|p| p.into_inner());
+        }
+        queue.flushing -= 1;
+        drop(queue);
+        match self.dropped() - dropped {
+            0 => Ok(()),
+            batches => Err(format!("{} write-behind batches were dropped", batches).into()),
+        }
+    }
+
+    // Lets the flusher write out what is left and waits for it to end.
+    fn stop_flusher(&self) {
+        self.shared.lock().stopping = true;
+        self.shared.changed.notify_all();
+        let handle = self
+            .flusher
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .take();
+        if let Some(handle) = handle {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl<K, V, C, B> WriteBehindStorage<K, V, C, B>
+where
+    K: Hash + Eq + Clone,
+{
+    fn enqueue(&self, key: K, write: Pending<V>) {
+        let mut queue = self.shared.lock();
+        while queue.writes.len() >= self.shared.max_pending && !queue.writes.contains_key(&key) {
+            queue = self
+                .shared
+                .changed
+                .wait(queue)
+                .unwrap_or_else(|p| p.into_inner());
+        }
+        let seq = queue.next_seq;
+        queue.next_seq += 1;
+        let requeue = match queue.writes.get_mut(&key) {
+            Some(queued) => {
+                queued.write = write;
+                queued.seq = seq;
+                !std::mem::replace(&mut queued.queued, true)
+            }
+            None => {
+                let queued = Queued {
+                    write,
+                    seq,
+                    queued: true,
+                };
+                queue.writes.insert(key.clone(), queued);
+                true
+            }
+        };
+        if requeue {
+            queue.order.push_back(key);
+            // Woken for the first write and for a full batch, writes in between wait.
+            let len = queue.order.len();
+            if len == 1 || len == self.shared.batch_size {
+                self.shared.changed.notify_all();
+            }
+        }
+    }
+
+    // `Some` with the queued write's answer, `None` if the key isn't queued.
+    fn queued<T>(&self, key: &K, f: impl FnOnce(Option<&V>) -> T) -> Option<T> {
+        let queue = self.shared.lock();
+        let queued = queue.writes.get(key)?;
+        Some(match &queued.write {
+            Pending::Set(value, deadline) if deadline.is_none_or(|d| d > Instant::now()) => {
+                f(Some(value))
+            }
+            _ => f(None),
+        })
+    }
+}
+
+impl<K, V, C, B> Storage<K, V> for WriteBehindStorage<K, V, C, B>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    C: Storage<K, V>,
+    B: Storage<K, V>,
+{
+    // A cache that can't take the write and can't drop its old copy fails the call, the write
+    // isn't queued then.
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.closed.check()?;
+        if let Err(e) = self.cache.set(key.clone(), value.clone()) {
+            self.cache.del(key.clone()).map_err(|_| e)?;
+        }
+        self.enqueue(key, Pending::Set(value, None));
+        Ok(())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.closed.check()?;
+        if let Err(e) = self.cache.set_ex(key.clone(), value.clone(), expire) {
+            self.cache.del(key.clone()).map_err(|_| e)?;
+        }
+        self.enqueue(key, Pending::Set(value, Some(Instant::now() + expire)));
+        Ok(())
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        if let Some(value) = self.queued(&key, |value| value.cloned()) {
+            return Ok(value);
+        }
+        if let Ok(Some(value)) = self.cache.get(key.clone()) {
+            return Ok(Some(value));
+        }
+        self.shared.backing.get(key)
+    }
+
+    // Returns the key as the backing storage would answer once the delete is written.
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        self.cache.del(key.clone())?;
+        self.enqueue(key.clone(), Pending::Del);
+        Ok(Some(key))
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.closed.check()?;
+        if let Some(found) = self.queued(&key, |value| value.is_some()) {
+            return Ok(found);
+        }
+        if let Ok(true) = self.cache.contains(key.clone()) {
+            return Ok(true);
+        }
+        self.shared.backing.contains(key)
+    }
+
+    // Writes out the queue before closing the cache and the backing storage.
+    fn close(&self) -> Result<(), Err> {
+        if !self.closed.close() {
+            return Ok(());
+        }
+        let dropped = self.dropped();
+        self.stop_flusher();
+        let cache = self.cache.close();
+        self.shared.backing.close()?;
+        cache?;
+        match self.dropped() - dropped {
+            0 => Ok(()),
+            batches => Err(format!("{} write-behind batches were dropped", batches).into()),
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+
+    // Writes are acknowledged by the cache, a failing backing storage only degrades.
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.closed.check()?;
+        let mut status = self.cache.health_check()?;
+        status.part("backing", self.shared.backing.health_check());
+        Ok(status)
+    }
+}
+
+impl<K, V, C, B> Drop for WriteBehindStorage<K, V, C, B> {
+    fn drop(&mut self) {
+        self.stop_flusher();
+    }
+}
+
+pub struct WriteBehindStorageBuilder<C, B> {
+    cache: C,
+    backing: B,
+    batch_size: usize,
+    flush_interval: Duration,
+    max_pending: usize,
+    max_attempts: usize,
+    retry_backoff: Duration,
+    on_error: Option<ErrorHook>,
+}
+
+impl<C, B> WriteBehindStorageBuilder<C, B> {
+    pub fn new(cache: C, backing: B) -> Self {
+        WriteBehindStorageBuilder {
+            cache,
+            backing,
+            batch_size: 100,
+            flush_interval: Duration::from_millis(100),
+            max_pending: 10_000,
+            max_attempts: 3,
+            retry_backoff: Duration::from_millis(100),
+            on_error: None,
+        }
+    }
+
+    // Keys written per round trip, 100 by default.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    // How long the first write of a batch waits for others, 100ms by default.
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    // Queued keys before writes wait for the flusher, 10000 by default.
+    pub fn max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = max_pending;
+        self
+    }
+
+    // Tries per batch, 3 by default, the first retry after `retry_backoff`.
+    pub fn max_attempts(mut self, attempts: usize) -> Self {
+        self.max_attempts = attempts;
+        self
+    }
+
+    pub fn retry_backoff(mut self, backoff: Duration) -> Self {
+        self.retry_backoff = backoff;
+        self
+    }
+
+    // Called on the flusher's thread with every failed attempt.
+    pub fn on_error<F: Fn(&Err) + Send + Sync + 'static>(mut self, hook: F) -> Self {
+        self.on_error = Some(Box::new(hook));
+        self
+    }
+
+    // # Panics
+    // Panics if a size or the number of attempts is zero, or the flusher can't be started.
+    pub fn build<K, V>(self) -> WriteBehindStorage<K, V, C, B>
+    where
+        K: Hash + Eq + Clone + Send + 'static,
+        V: Clone + Send + 'static,
+        B: Storage<K, V> + Send + Sync + 'static,
+    {
+        match self.try_build() {
+            Ok(storage) => storage,
+            Err(e) => panic!("Failed to build write-behind storage: {}", e),
+        }
+    }
+
+    pub fn try_build<K, V>(self) -> Result<WriteBehindStorage<K, V, C, B>, BuilderError>
+    where
+        K: Hash + Eq + Clone + Send + 'static,
+        V: Clone + Send + 'static,
+        B: Storage<K, V> + Send + Sync + 'static,
+    {
+        if self.batch_size == 0 {
+            return Err(BuilderError::invalid("batch_size", "must be at least 1"));
+        }
+        if self.max_pending == 0 {
+            return Err(BuilderError::invalid("max_pending", "must be at least 1"));
+        }
+        if self.max_attempts == 0 {
+            return Err(BuilderError::invalid("max_attempts", "must be at least 1"));
+        }
+
+        let shared = Arc::new(Shared {
+            backing: self.backing,
+            queue: Mutex::new(Queue {
+                writes: HashMap::new(),
+                order: VecDeque::new(),
+                next_seq: 0,
+                flushing: 0,
+                stopping: false,
+            }),
+            changed: Condvar::new(),
+            batch_size: self.batch_size,
+            flush_interval: self.flush_interval,
+            max_pending: self.max_pending,
+            max_attempts: self.max_attempts,
+            retry_backoff: self.retry_backoff,
+            on_error: self.on_error,
+            dropped: AtomicU64::new(0),
+        });
+        let flusher = shared.clone();
+        let handle = thread::Builder::new()
+            .name("storage-write-behind".to_string())
+            .spawn(move || flusher.run())
+            .map_err(BuilderError::backend)?;
+
+        Ok(WriteBehindStorage {
+            cache: self.cache,
+            shared,
+            flusher: Mutex::new(Some(handle)),
+            closed: CloseFlag::default(),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::{DashMapStorage, DashMapStorageBuilder};
+    use crate::mock_storage::{Call, MockStorage};
+
+    type Map = DashMapStorage<&'static str, u32>;
+
+    fn write_behind() -> WriteBehindStorage<&'static str, u32, Map, Map> {
+        WriteBehindStorageBuilder::new(
+            DashMapStorageBuilder::new().build(),
+            DashMapStorageBuilder::new().build(),
+        )
+        .flush_interval(Duration::from_secs(60))
+        .build()
+    }
+
+    #[test]
+    fn test_write_behind() {
+        let storage = write_behind();
+        storage.set("a", 1).unwrap();
+        storage.set("a", 2).unwrap();
+        storage.set_ex("b", 3, Duration::from_secs(60)).unwrap();
+        assert_eq!(storage.cache().get("a").unwrap(), Some(2));
+        assert_eq!(storage.backing().get("a").unwrap(), None);
+        assert_eq!(storage.pending(), 2);
+
+        storage.flush().unwrap();
+        assert_eq!(storage.pending(), 0);
+        assert_eq!(storage.backing().get("a").unwrap(), Some(2));
+        assert_eq!(storage.backing().get("b").unwrap(), Some(3));
+    }
+
+    #[test]
+    fn test_queued_reads() {
+        let storage = write_behind();
+        storage.backing().set("a", 1).unwrap();
+        assert_eq!(storage.get("a").unwrap(), Some(1));
+
+        // The backing storage still has the old value, the queued delete hides it.
+        storage.del("a").unwrap();
+        assert_eq!(storage.get("a").unwrap(), None);
+        assert!(!storage.contains("a").unwrap());
+        storage.flush().unwrap();
+        assert!(!storage.backing().contains("a").unwrap());
+    }
+
+    #[test]
+    fn test_close_drains() {
+        let storage = WriteBehindStorageBuilder::new(
+            DashMapStorageBuilder::new().build(),
+            MockStorage::<&str, u32>::new(),
+        )
+        .flush_interval(Duration::from_secs(60))
+        .build();
+        storage.set("a", 1).unwrap();
+        storage.set("b", 2).unwrap();
+        storage.close().unwrap();
+        assert_eq!(
+            storage.backing().calls(),
+            [Call::Set("a", 1), Call::Set("b", 2), Call::Close]
+        );
+        assert!(storage.set("c", 3).is_err());
+    }
+
+    #[test]
+    fn test_dropped_batch() {
+        let errors = Arc::new(AtomicU64::new(0));
+        let seen = errors.clone();
+        let backing = MockStorage::<&str, u32>::new();
+        backing.fail_nth(0, "backend is down");
+        backing.fail_nth(1, "backend is down");
+        let storage = WriteBehindStorageBuilder::new(DashMapStorageBuilder::new().build(), backing)
+            .max_attempts(2)
+            .retry_backoff(Duration::from_millis(1))
+            .on_error(move |_| {
+                seen.fetch_add(1, Ordering::SeqCst);
+            })
+            .build();
+
+        storage.set("a", 1).unwrap();
+        assert!(storage.flush().is_err());
+        assert_eq!(storage.dropped(), 1);
+        assert_eq!(errors.load(Ordering::SeqCst), 2);
+        storage.set("b", 2).unwrap();
+        storage.flush().unwrap();
+        assert_eq!(storage.backing().get("b").unwrap(), Some(2));
+    }
+
+    #[test]
+    fn test_try_build_invalid() {
+        let resp: Result<WriteBehindStorage<_, _, Map, Map>, _> = WriteBehindStorageBuilder::new(
+            DashMapStorageBuilder::new().build(),
+            DashMapStorageBuilder::new().build(),
+        )
+        .batch_size(0)
+        .try_build();
+        assert!(matches!(resp, Err(BuilderError::InvalidConfig(_))));
+    }
+}
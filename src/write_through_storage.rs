@@ -0,0 +1,252 @@
+use std::time::Duration;
+
+use crate::health::HealthStatus;
+use crate::options::{Consistency, OpOptions};
+use crate::storage::{CloseFlag, Err, Storage};
+
+// Writes go to the backing storage and then to the cache before they return, reads try the
+// cache first. Unlike `TieredStorage`, a cache that can't take a write and can't drop its old
+// copy either fails the call, so a caller never reads its own write back stale. Misses are
+// read from the backing storage and only copied into the cache with `fill_on_miss`, as a copy
+// taken during someone else's write could bring back the old value. Concurrent writers of one
+// key race, the cache keeps whichever write reaches it last.
+pub struct WriteThroughStorage<C, B> {
+    cache: C,
+    backing: B,
+    fill_on_miss: bool,
+    closed: CloseFlag,
+}
+
+impl<C, B> WriteThroughStorage<C, B> {
+    pub fn new(cache: C, backing: B) -> Self {
+        WriteThroughStorage {
+            cache,
+            backing,
+            fill_on_miss: false,
+            closed: CloseFlag::default(),
+        }
+    }
+
+    // Copies values read from the backing storage into the cache, for caches that lose
+    // entries, e.g. to eviction or a restart.
+    pub fn fill_on_miss(mut self) -> Self {
+        self.fill_on_miss = true;
+        self
+    }
+
+    pub fn cache(&self) -> &C {
+        &self.cache
+    }
+
+    pub fn backing(&self) -> &B {
+        &self.backing
+    }
+
+    // A failed write is fine once the old copy is gone, reads then go to the backing storage.
+    fn write_cache<K: Clone, V>(&self, key: K, value: V, ttl: Option<Duration>) -> Result<(), Err>
+    where
+        C: Storage<K, V>,
+    {
+        let resp = match ttl {
+            Some(ttl) => self.cache.set_ex(key.clone(), value, ttl),
+            None => self.cache.set(key.clone(), value),
+        };
+        match resp {
+            Ok(()) => Ok(()),
+            Err(e) => self.cache.del(key).map(|_| ()).map_err(|_| e),
+        }
+    }
+}
+
+impl<K, V, C, B> Storage<K, V> for WriteThroughStorage<C, B>
+where
+    K: Clone,
+    V: Clone,
+    C: Storage<K, V>,
+    B: Storage<K, V>,
+{
+    fn set(&self, key: K, value: V) -> Result<(), Err> {
+        self.set_with_opts(key, value, &OpOptions::default())
+    }
+
+    fn set_ex(&self, key: K, value: V, expire: Duration) -> Result<(), Err> {
+        self.set_with_opts(key, value, &OpOptions::new().ttl(expire))
+    }
+
+    fn get(&self, key: K) -> Result<Option<V>, Err> {
+        self.get_with_opts(key, &OpOptions::default())
+    }
+
+    fn del(&self, key: K) -> Result<Option<K>, Err> {
+        self.del_with_opts(key, &OpOptions::default())
+    }
+
+    fn contains(&self, key: K) -> Result<bool, Err> {
+        self.contains_with_opts(key, &OpOptions::default())
+    }
+
+    fn close(&self) -> Result<(), Err> {
+        if !self.closed.close() {
+            return Ok(());
+        }
+        let cache = self.cache.close();
+        self.backing.close()?;
+        cache
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_closed()
+    }
+
+    // Both are needed, writes fail while the cache is down.
+    fn health_check(&self) -> Result<HealthStatus, Err> {
+        self.closed.check()?;
+        let mut status = self.backing.health_check()?;
+        status.absorb("cache", self.cache.health_check()?);
+        Ok(status)
+    }
+
+    fn get_many(&self, keys: Vec<K>) -> Result<Vec<Option<V>>, Err> {
+        self.closed.check()?;
+        let mut values = self
+            .cache
+            .get_many(keys.clone())
+            .unwrap_or_else(|_| vec![None; keys.len()]);
+        let misses: Vec<usize> = (0..keys.len()).filter(|i| values[*i].is_none()).collect();
+        if misses.is_empty() {
+            return Ok(values);
+        }
+
+        let fetched = self
+            .backing
+            .get_many(misses.iter().map(|i| keys[*i].clone()).collect())?;
+        for (i, value) in misses.into_iter().zip(fetched) {
+            if let (true, Some(value)) = (self.fill_on_miss, &value) {
+                let _ = self.write_cache(keys[i].clone(), value.clone(), None);
+            }
+            values[i] = value;
+        }
+        Ok(values)
+    }
+
+    // The cache's copies are dropped if it can't take them all.
+    fn set_many(&self, entries: Vec<(K, V)>) -> Result<(), Err> {
+        self.closed.check()?;
+        self.backing.set_many(entries.clone())?;
+        let keys: Vec<K> = entries.iter().map(|(key, _)| key.clone()).collect();
+        match self.cache.set_many(entries) {
+            Ok(()) => Ok(()),
+            Err(e) => self.cache.del_many(keys).map(|_| ()).map_err(|_| e),
+        }
+    }
+
+    fn del_many(&self, keys: Vec<K>) -> Result<Vec<Option<K>>, Err> {
+        self.closed.check()?;
+        let removed = self.backing.del_many(keys.clone())?;
+        self.cache.del_many(keys)?;
+        Ok(removed)
+    }
+
+    fn set_with_opts(&self, key: K, value: V, opts: &OpOptions) -> Result<(), Err> {
+        self.closed.check()?;
+        self.backing
+            .set_with_opts(key.clone(), value.clone(), opts)?;
+        self.write_cache(key, value, opts.ttl)
+    }
+
+    // A failing cache is read around, `Consistency::Strong` skips it.
+    fn get_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<V>, Err> {
+        self.closed.check()?;
+        if opts.consistency != Consistency::Strong {
+            if let Ok(Some(value)) = self.cache.get_with_opts(key.clone(), opts) {
+                return Ok(Some(value));
+            }
+        }
+
+        let value = self.backing.get_with_opts(key.clone(), opts)?;
+        if let (true, Some(value)) = (self.fill_on_miss, &value) {
+            let _ = self.write_cache(key, value.clone(), None);
+        }
+        Ok(value)
+    }
+
+    fn del_with_opts(&self, key: K, opts: &OpOptions) -> Result<Option<K>, Err> {
+        self.closed.check()?;
+        let removed = self.backing.del_with_opts(key.clone(), opts)?;
+        self.cache.del(key)?;
+        Ok(removed)
+    }
+
+    fn contains_with_opts(&self, key: K, opts: &OpOptions) -> Result<bool, Err> {
+        self.closed.check()?;
+        if opts.consistency != Consistency::Strong
+            && matches!(self.cache.contains_with_opts(key.clone(), opts), Ok(true))
+        {
+            return Ok(true);
+        }
+        self.backing.contains_with_opts(key, opts)
+    }
+}
+
+#[cfg(all(test, feature = "dashmap"))]
+mod tests {
+    use super::*;
+    use crate::dashmap_storage::{DashMapStorage, DashMapStorageBuilder};
+    use crate::expiry::ExpiryStorage;
+    use crate::mock_storage::MockStorage;
+
+    type Map = DashMapStorage<&'static str, u32>;
+
+    fn write_through() -> WriteThroughStorage<Map, Map> {
+        WriteThroughStorage::new(
+            DashMapStorageBuilder::new().build(),
+            DashMapStorageBuilder::new().build(),
+        )
+    }
+
+    #[test]
+    fn test_write_through() {
+        let storage = write_through();
+        storage.set("a", 1).unwrap();
+        storage.set_ex("b", 2, Duration::from_secs(60)).unwrap();
+        assert_eq!(storage.cache().get("a").unwrap(), Some(1));
+        assert_eq!(storage.backing().get("a").unwrap(), Some(1));
+        assert!(storage.cache().ttl("b").unwrap().is_some());
+
+        storage.set("a", 3).unwrap();
+        assert_eq!(storage.get("a").unwrap(), Some(3));
+        storage.del("a").unwrap();
+        assert!(!storage.cache().contains("a").unwrap());
+        assert!(!storage.contains("a").unwrap());
+    }
+
+    #[test]
+    fn test_fill_on_miss() {
+        let storage = write_through();
+        storage.backing().set("a", 1).unwrap();
+        assert_eq!(storage.get("a").unwrap(), Some(1));
+        assert!(!storage.cache().contains("a").unwrap());
+
+        let storage = write_through().fill_on_miss();
+        storage.backing().set("a", 1).unwrap();
+        assert_eq!(storage.get_many(vec!["a", "b"]).unwrap(), [Some(1), None]);
+        assert_eq!(storage.cache().get("a").unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_failing_cache() {
+        let cache = MockStorage::<&str, u32>::new();
+        let storage = WriteThroughStorage::new(cache, DashMapStorageBuilder::new().build());
+        // The set fails but the old copy is dropped, nothing stale is left to read.
+        storage.cache().fail_nth(0, "cache is full");
+        storage.set("a", 1).unwrap();
+        assert_eq!(storage.backing().get("a").unwrap(), Some(1));
+        assert_eq!(storage.cache().get("a").unwrap(), None);
+
+        storage.cache().clear_calls();
+        storage.cache().fail_nth(0, "cache is down");
+        storage.cache().fail_nth(1, "cache is down");
+        assert!(storage.set("a", 2).is_err());
+        assert_eq!(storage.backing().get("a").unwrap(), Some(2));
+    }
+}